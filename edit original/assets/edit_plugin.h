@@ -0,0 +1,67 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+// The C API for editor plugins. It mirrors `src/plugin.rs`, which documents it in detail.
+//
+// A plugin is a shared library that exports:
+//   uint32_t edit_plugin_api_version(void);          // return EDIT_PLUGIN_API_VERSION
+//   bool edit_plugin_init(const EditApi* api, EditHost* host);
+
+#pragma once
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#define EDIT_PLUGIN_API_VERSION 1
+
+#define EDIT_EVENT_BUFFER_CHANGED 1
+#define EDIT_EVENT_SAVED 2
+
+#define EDIT_LIST_UNCHANGED 0
+#define EDIT_LIST_SELECTED 1
+#define EDIT_LIST_ACTIVATED 2
+
+typedef struct EditHost EditHost;
+
+// A borrowed UTF-8 string. It's not NUL-terminated.
+typedef struct EditStr {
+    const char* ptr;
+    size_t len;
+} EditStr;
+
+typedef struct EditEvent {
+    uint32_t kind;
+    EditStr path;
+    size_t offset;
+    EditStr deleted;
+    EditStr inserted;
+} EditEvent;
+
+typedef void (*EditCommandFn)(EditHost* host, void* user);
+typedef void (*EditDrawFn)(EditHost* host, void* user);
+typedef void (*EditEventFn)(EditHost* host, const EditEvent* event, void* user);
+
+typedef struct EditApi {
+    uint32_t version;
+
+    bool (*register_command)(EditHost* host, EditStr id, EditStr label, EditCommandFn run, void* user);
+    bool (*register_panel)(EditHost* host, EditStr id, EditStr title, EditDrawFn draw, void* user);
+    void (*subscribe)(EditHost* host, EditEventFn handler, void* user);
+    void (*log_error)(EditHost* host, EditStr message);
+
+    bool (*buffer_available)(EditHost* host);
+    size_t (*buffer_length)(EditHost* host);
+    size_t (*buffer_read)(EditHost* host, size_t offset, char* dst, size_t cap);
+    void (*buffer_selection)(EditHost* host, size_t* beg, size_t* end);
+    bool (*buffer_replace)(EditHost* host, size_t beg, size_t end, EditStr text);
+
+    void (*ui_block_begin)(EditHost* host, EditStr name);
+    void (*ui_block_end)(EditHost* host);
+    void (*ui_label)(EditHost* host, EditStr name, EditStr text);
+    bool (*ui_button)(EditHost* host, EditStr name, EditStr text);
+    void (*ui_list_begin)(EditHost* host, EditStr name);
+    uint32_t (*ui_list_item)(EditHost* host, bool select, EditStr text);
+    void (*ui_list_end)(EditHost* host);
+    bool (*ui_editline)(EditHost* host, EditStr name, char* buf, size_t* len, size_t cap);
+} EditApi;
@@ -69,8 +69,21 @@ fn bench_buffer(c: &mut Criterion) {
         buf
     };
 
-    let bench_text_buffer = || {
-        let mut tb = buffer::TextBuffer::new(false).unwrap();
+    let bench_piece_table = || {
+        let mut buf = buffer::PieceTable::new();
+        buf.replace(0..usize::MAX, data.start_content.as_bytes());
+
+        for t in &data.txns {
+            for p in &t.patches {
+                buf.replace(p.0..p.0 + p.1, p.2.as_bytes());
+            }
+        }
+
+        buf
+    };
+
+    let bench_text_buffer = |storage: fn() -> buffer::TextStorage| {
+        let mut tb = buffer::TextBuffer::with_storage(storage());
         tb.set_crlf(false);
         tb.write(data.start_content.as_bytes(), true);
 
@@ -83,6 +96,9 @@ fn bench_buffer(c: &mut Criterion) {
         tb
     };
 
+    let gap_buffer = || buffer::TextStorage::GapBuffer(buffer::GapBuffer::new(false).unwrap());
+    let piece_table = || buffer::TextStorage::PieceTable(buffer::PieceTable::new());
+
     // Sanity check: If this fails, the implementation is incorrect.
     {
         let buf = bench_gap_buffer();
@@ -91,7 +107,13 @@ fn bench_buffer(c: &mut Criterion) {
         assert_eq!(actual, data.end_content.as_bytes());
     }
     {
-        let mut tb = bench_text_buffer();
+        let buf = bench_piece_table();
+        let mut actual = Vec::new();
+        buf.extract_raw(0..usize::MAX, &mut actual, 0);
+        assert_eq!(actual, data.end_content.as_bytes());
+    }
+    for storage in [gap_buffer, piece_table] {
+        let mut tb = bench_text_buffer(storage);
         let mut actual = String::new();
         tb.save_as_string(&mut actual);
         assert_eq!(actual, data.end_content);
@@ -101,8 +123,14 @@ fn bench_buffer(c: &mut Criterion) {
         .bench_function(BenchmarkId::new("GapBuffer", "rustcode"), |b| {
             b.iter(bench_gap_buffer);
         })
+        .bench_function(BenchmarkId::new("PieceTable", "rustcode"), |b| {
+            b.iter(bench_piece_table);
+        })
         .bench_function(BenchmarkId::new("TextBuffer", "rustcode"), |b| {
-            b.iter(bench_text_buffer);
+            b.iter(|| bench_text_buffer(gap_buffer));
+        })
+        .bench_function(BenchmarkId::new("TextBuffer+PieceTable", "rustcode"), |b| {
+            b.iter(|| bench_text_buffer(piece_table));
         });
 }
 
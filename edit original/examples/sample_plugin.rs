@@ -0,0 +1,175 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! A sample plugin which exercises the whole [`edit::plugin`] API.
+//! Build it with `cargo build --example sample_plugin` and copy the resulting
+//! shared library into the `plugins` directory next to your settings file.
+//!
+//! It registers these commands:
+//! * `sample.count_words`: Counts the words in the document.
+//! * `sample.uppercase`: Uppercases the selection.
+//! * `sample.panel`: Toggles a panel that shows the word count, the number of changes
+//!   and the last saved path. It can also insert a greeting and pick a case for it.
+
+#![allow(clippy::missing_safety_doc)]
+
+use std::ffi::c_void;
+
+use edit::plugin::*;
+
+const GREETING_CAPACITY: usize = 64;
+const CASES: [&str; 2] = ["As typed", "Uppercase"];
+
+struct Sample {
+    api: &'static Api,
+    words: Option<usize>,
+    changes: usize,
+    saved: String,
+    greeting: [u8; GREETING_CAPACITY],
+    greeting_len: usize,
+    case: usize,
+}
+
+impl Sample {
+    unsafe fn from<'a>(user: *mut c_void) -> &'a mut Self {
+        unsafe { &mut *user.cast::<Self>() }
+    }
+
+    unsafe fn read_range(&self, host: *mut Host, beg: usize, end: usize) -> Vec<u8> {
+        let mut text = vec![0; end.saturating_sub(beg)];
+        let len = unsafe { (self.api.buffer_read)(host, beg, text.as_mut_ptr(), text.len()) };
+        text.truncate(len);
+        text
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn edit_plugin_api_version() -> u32 {
+    API_VERSION
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn edit_plugin_init(api: *const Api, host: *mut Host) -> bool {
+    let api = unsafe { &*api };
+    let user = Box::into_raw(Box::new(Sample {
+        api,
+        words: None,
+        changes: 0,
+        saved: String::new(),
+        greeting: [0; GREETING_CAPACITY],
+        greeting_len: 0,
+        case: 0,
+    }))
+    .cast::<c_void>();
+
+    let greeting = b"Hello";
+    let sample = unsafe { Sample::from(user) };
+    sample.greeting[..greeting.len()].copy_from_slice(greeting);
+    sample.greeting_len = greeting.len();
+
+    unsafe {
+        (api.register_command)(
+            host,
+            Str::new("sample.count_words"),
+            Str::new("Count Words"),
+            count_words,
+            user,
+        ) && (api.register_command)(
+            host,
+            Str::new("sample.uppercase"),
+            Str::new("Uppercase Selection"),
+            uppercase,
+            user,
+        ) && (api.register_panel)(host, Str::new("sample.panel"), Str::new("Sample"), draw, user)
+            && {
+                (api.subscribe)(host, on_event, user);
+                true
+            }
+    }
+}
+
+unsafe extern "C" fn count_words(host: *mut Host, user: *mut c_void) {
+    unsafe {
+        let sample = Sample::from(user);
+        if !(sample.api.buffer_available)(host) {
+            (sample.api.log_error)(host, Str::new("there's no document"));
+            return;
+        }
+        let len = (sample.api.buffer_length)(host);
+        let text = sample.read_range(host, 0, len);
+        sample.words = Some(text.split(u8::is_ascii_whitespace).filter(|w| !w.is_empty()).count());
+    }
+}
+
+unsafe extern "C" fn uppercase(host: *mut Host, user: *mut c_void) {
+    unsafe {
+        let sample = Sample::from(user);
+        let (mut beg, mut end) = (0, 0);
+        (sample.api.buffer_selection)(host, &mut beg, &mut end);
+        let text = sample.read_range(host, beg, end);
+        let text = String::from_utf8_lossy(&text).to_uppercase();
+        (sample.api.buffer_replace)(host, beg, end, Str::new(&text));
+    }
+}
+
+unsafe extern "C" fn on_event(_host: *mut Host, event: *const Event, user: *mut c_void) {
+    unsafe {
+        let sample = Sample::from(user);
+        let event = &*event;
+        match event.kind {
+            EVENT_BUFFER_CHANGED => sample.changes += 1,
+            EVENT_SAVED => sample.saved = event.path.as_str().unwrap_or_default().to_string(),
+            _ => {}
+        }
+    }
+}
+
+unsafe extern "C" fn draw(host: *mut Host, user: *mut c_void) {
+    unsafe {
+        let sample = Sample::from(user);
+        let api = sample.api;
+
+        let words = match sample.words {
+            Some(words) => format!("Words: {words}"),
+            None => "Words: ?".to_string(),
+        };
+        (api.ui_label)(host, Str::new("words"), Str::new(&words));
+        (api.ui_label)(
+            host,
+            Str::new("changes"),
+            Str::new(&format!("Changes: {}", sample.changes)),
+        );
+        (api.ui_label)(host, Str::new("saved"), Str::new(&format!("Saved: {}", sample.saved)));
+
+        (api.ui_block_begin)(host, Str::new("greeting"));
+        {
+            (api.ui_editline)(
+                host,
+                Str::new("text"),
+                sample.greeting.as_mut_ptr(),
+                &mut sample.greeting_len,
+                GREETING_CAPACITY,
+            );
+
+            (api.ui_list_begin)(host, Str::new("case"));
+            for (i, case) in CASES.iter().enumerate() {
+                if (api.ui_list_item)(host, i == sample.case, Str::new(case)) != LIST_UNCHANGED {
+                    sample.case = i;
+                }
+            }
+            (api.ui_list_end)(host);
+
+            if (api.ui_button)(host, Str::new("insert"), Str::new("Insert Greeting")) {
+                let greeting = &sample.greeting[..sample.greeting_len];
+                let mut greeting = String::from_utf8_lossy(greeting).into_owned();
+                if sample.case == 1 {
+                    greeting = greeting.to_uppercase();
+                }
+                let (mut beg, mut end) = (0, 0);
+                (api.buffer_selection)(host, &mut beg, &mut end);
+                (api.buffer_replace)(host, beg, end, Str::new(&greeting));
+            }
+        }
+        (api.ui_block_end)(host);
+    }
+}
@@ -3,22 +3,62 @@
 
 use std::collections::LinkedList;
 use std::ffi::OsStr;
-use std::fs::File;
+use std::fs::{self, File};
+use std::io;
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::SystemTime;
 
-use edit::buffer::{RcTextBuffer, TextBuffer};
+use edit::buffer::{RcTextBuffer, TextBuffer, UndoFile};
 use edit::helpers::{CoordType, Point};
-use edit::{apperr, path, sys};
+use edit::{apperr, path, syntax, sys};
 
+use crate::settings::Settings;
 use crate::state::DisplayablePathBuf;
 
+/// Identifies a version of a file on disk.
+#[derive(Clone, PartialEq, Eq)]
+pub struct DiskStamp {
+    // Differs if the file got replaced, e.g. by an editor that saves to a temporary file.
+    id: sys::FileId,
+    modified: SystemTime,
+    size: u64,
+}
+
+impl DiskStamp {
+    pub fn of(path: &Path) -> io::Result<Self> {
+        let metadata = fs::metadata(path)?;
+        Ok(Self {
+            id: sys::file_id(None, path).map_err(|_| io::ErrorKind::Other)?,
+            modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            size: metadata.len(),
+        })
+    }
+}
+
+/// How the file of a [`Document`] changed on disk.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DiskChange {
+    Modified,
+    Deleted,
+}
+
 pub struct Document {
+    /// Unique among the documents of a [`DocumentManager`] and never reused.
+    pub id: u64,
     pub buffer: RcTextBuffer,
     pub path: Option<PathBuf>,
     pub dir: Option<DisplayablePathBuf>,
     pub filename: String,
     pub file_id: Option<sys::FileId>,
     pub new_file_counter: usize,
+    pub save_generation: u32, // Bumped every time the document is saved.
+    /// The file as it was when it was last read or written. `None` if there's no file yet.
+    disk_stamp: Option<DiskStamp>,
+    /// The file as it was during the last [`Document::check_disk`]. `None` if it was missing.
+    disk_seen: Option<DiskStamp>,
+    settings: Rc<Settings>,
+    undo_dir: Option<Rc<Path>>,
 }
 
 impl Document {
@@ -28,36 +68,87 @@ impl Document {
 
         {
             let mut tb = self.buffer.borrow_mut();
-            tb.write_file(&mut file)?;
+            let undo_file = tb.undo_file().cloned();
+            if new_path.is_some() {
+                // The history belongs to the new path from now on.
+                tb.set_undo_file(undo_file_for(self.undo_dir.as_deref(), &self.settings, path));
+            }
+            if let Err(err) = tb.write_file(&mut file) {
+                tb.set_undo_file(undo_file);
+                return Err(err);
+            }
         }
 
         if let Ok(id) = sys::file_id(None, path) {
             self.file_id = Some(id);
         }
+        self.disk_stamp = DiskStamp::of(path).ok();
+        self.disk_seen = self.disk_stamp.clone();
 
         if let Some(path) = new_path {
             self.set_path(path);
         }
 
+        self.save_generation = self.save_generation.wrapping_add(1);
         Ok(())
     }
 
+    /// Reads the file again, keeping the cursor on the same line and column.
     pub fn reread(&mut self, encoding: Option<&'static str>) -> apperr::Result<()> {
         let path = self.path.as_ref().unwrap().as_path();
         let mut file = DocumentManager::open_for_reading(path)?;
 
         {
             let mut tb = self.buffer.borrow_mut();
+            let pos = tb.cursor_logical_pos();
             tb.read_file(&mut file, encoding)?;
+            tb.cursor_move_to_logical(pos);
+            tb.make_cursor_visible();
         }
 
         if let Ok(id) = sys::file_id(None, path) {
             self.file_id = Some(id);
         }
+        self.disk_stamp = DiskStamp::of(path).ok();
+        self.disk_seen = self.disk_stamp.clone();
 
         Ok(())
     }
 
+    /// Returns how the file changed on disk since the last call, if it did.
+    /// Each change gets reported once. Documents without a file are never changed.
+    pub fn check_disk(&mut self) -> Option<DiskChange> {
+        let path = self.path.as_deref()?;
+        self.disk_stamp.as_ref()?;
+
+        let current = match DiskStamp::of(path) {
+            Ok(stamp) => Some(stamp),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            // Can't tell, e.g. due to permissions. Let's not bother the user with it.
+            Err(_) => return None,
+        };
+        if current == self.disk_seen {
+            return None;
+        }
+
+        self.disk_seen = current;
+        match &self.disk_seen {
+            None => Some(DiskChange::Deleted),
+            // Changed back to what it was? Then there's no need to reload.
+            Some(stamp) if Some(stamp) == self.disk_stamp.as_ref() => None,
+            Some(_) => Some(DiskChange::Modified),
+        }
+    }
+
+    /// Returns true if saving would overwrite changes that were made to the file
+    /// on disk since it was last read or written.
+    pub fn changed_on_disk(&self) -> bool {
+        let (Some(path), Some(stamp)) = (&self.path, &self.disk_stamp) else {
+            return false;
+        };
+        DiskStamp::of(path).is_ok_and(|current| current != *stamp)
+    }
+
     fn set_path(&mut self, path: PathBuf) {
         let filename = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
         let dir = path.parent().map(ToOwned::to_owned).unwrap_or_default();
@@ -69,13 +160,37 @@ impl Document {
 
     fn update_file_mode(&mut self) {
         let mut tb = self.buffer.borrow_mut();
-        tb.set_ruler(if self.filename == "COMMIT_EDITMSG" { 72 } else { 0 });
+
+        let chunk = tb.read_forward(0);
+        let first_line = chunk.split(|&c| c == b'\n').next().unwrap_or_default();
+        let language = syntax::detect_language(&self.filename, first_line);
+        tb.set_language(language);
+
+        let settings = self.settings.resolve(&self.filename, language);
+        settings.apply(&mut tb, self.file_id.is_none());
+
+        let undo_file = self
+            .path
+            .as_deref()
+            .and_then(|path| undo_file_for(self.undo_dir.as_deref(), &self.settings, path));
+        tb.set_undo_file(undo_file);
     }
 }
 
+/// Where the undo history of `path` is kept, if the settings ask for it.
+fn undo_file_for(undo_dir: Option<&Path>, settings: &Settings, path: &Path) -> Option<UndoFile> {
+    undo_dir.filter(|_| settings.persistent_undo).map(|dir| UndoFile::new(dir, path))
+}
+
 #[derive(Default)]
 pub struct DocumentManager {
+    /// Ordered by most recent use. The first one is the active document.
     list: LinkedList<Document>,
+    /// The document IDs in the order the user arranged them, as shown by the tab bar.
+    tab_order: Vec<u64>,
+    next_id: u64,
+    settings: Rc<Settings>,
+    undo_dir: Option<Rc<Path>>,
 }
 
 impl DocumentManager {
@@ -84,6 +199,16 @@ impl DocumentManager {
         self.list.len()
     }
 
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = &Document> {
+        self.list.iter()
+    }
+
+    #[inline]
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Document> {
+        self.list.iter_mut()
+    }
+
     #[inline]
     pub fn active(&self) -> Option<&Document> {
         self.list.front()
@@ -108,24 +233,83 @@ impl DocumentManager {
         false
     }
 
+    /// Replaces the settings and applies them to all open documents.
+    pub fn set_settings(&mut self, settings: Settings) {
+        self.settings = Rc::new(settings);
+        for doc in &mut self.list {
+            doc.settings = self.settings.clone();
+            doc.update_file_mode();
+        }
+    }
+
+    /// Sets the directory for the undo history of files,
+    /// which is kept if [`Settings::persistent_undo`] is enabled.
+    pub fn set_undo_dir(&mut self, dir: &Path) {
+        self.undo_dir = Some(dir.into());
+        for doc in &mut self.list {
+            doc.undo_dir = self.undo_dir.clone();
+            doc.update_file_mode();
+        }
+    }
+
     pub fn remove_active(&mut self) {
-        self.list.pop_front();
+        if let Some(doc) = self.list.pop_front() {
+            self.tab_order.retain(|&id| id != doc.id);
+        }
+    }
+
+    /// Returns the documents in tab order.
+    pub fn tabs(&self) -> impl Iterator<Item = &Document> {
+        self.tab_order.iter().filter_map(|&id| self.list.iter().find(|doc| doc.id == id))
+    }
+
+    /// Moves the tab at index `from` to index `to`.
+    pub fn move_tab(&mut self, from: usize, to: usize) {
+        if from < self.tab_order.len() && to < self.tab_order.len() {
+            let id = self.tab_order.remove(from);
+            self.tab_order.insert(to, id);
+        }
+    }
+
+    /// Activates the next document in tab order, or the previous one if `delta` is negative.
+    pub fn activate_next_tab(&mut self, delta: isize) {
+        let Some(active) = self.active() else {
+            return;
+        };
+        let len = self.tab_order.len() as isize;
+        let idx = self.tab_order.iter().position(|&id| id == active.id).unwrap_or(0) as isize;
+        let id = self.tab_order[(idx + delta).rem_euclid(len) as usize];
+        self.update_active(|doc| doc.id == id);
+    }
+
+    fn push(&mut self, mut doc: Document) -> &mut Document {
+        self.next_id += 1;
+        doc.id = self.next_id;
+        self.tab_order.push(doc.id);
+        self.list.push_front(doc);
+        self.list.front_mut().unwrap()
     }
 
     pub fn add_untitled(&mut self) -> apperr::Result<&mut Document> {
-        let buffer = Self::create_buffer()?;
+        let buffer = TextBuffer::new_rc(false)?;
         let mut doc = Document {
+            id: 0,
             buffer,
             path: None,
             dir: Default::default(),
             filename: Default::default(),
             file_id: None,
             new_file_counter: 0,
+            save_generation: 0,
+            disk_stamp: None,
+            disk_seen: None,
+            settings: self.settings.clone(),
+            undo_dir: self.undo_dir.clone(),
         };
         self.gen_untitled_name(&mut doc);
+        doc.update_file_mode();
 
-        self.list.push_front(doc);
-        Ok(self.list.front_mut().unwrap())
+        Ok(self.push(doc))
     }
 
     pub fn gen_untitled_name(&self, doc: &mut Document) {
@@ -160,10 +344,15 @@ impl DocumentManager {
             return Ok(doc);
         }
 
-        let buffer = Self::create_buffer()?;
+        let buffer = TextBuffer::new_rc(false)?;
         {
             if let Some(file) = &mut file {
                 let mut tb = buffer.borrow_mut();
+                // Only the filename is known yet, but that's enough for the default encoding.
+                let filename = path.file_name().unwrap_or_default().to_string_lossy();
+                let language = syntax::detect_language(&filename, b"");
+                tb.set_default_encoding(self.settings.resolve(&filename, language).encoding);
+                tb.set_undo_file(undo_file_for(self.undo_dir.as_deref(), &self.settings, &path));
                 tb.read_file(file, None)?;
 
                 if let Some(goto) = goto
@@ -175,26 +364,41 @@ impl DocumentManager {
         }
 
         let mut doc = Document {
+            id: 0,
             buffer,
             path: None,
             dir: None,
             filename: Default::default(),
             file_id,
             new_file_counter: 0,
+            save_generation: 0,
+            disk_stamp: None,
+            disk_seen: None,
+            settings: self.settings.clone(),
+            undo_dir: self.undo_dir.clone(),
         };
+        if doc.file_id.is_some() {
+            doc.disk_stamp = DiskStamp::of(&path).ok();
+            doc.disk_seen = doc.disk_stamp.clone();
+        }
         doc.set_path(path);
 
+        let mut replaced_tab = None;
         if let Some(active) = self.active()
             && active.path.is_none()
             && active.file_id.is_none()
             && !active.buffer.borrow().is_dirty()
         {
             // If the current document is a pristine Untitled document with no
-            // name and no ID, replace it with the new document.
+            // name and no ID, replace it with the new document, including its tab.
+            replaced_tab = self.tab_order.iter().position(|&id| id == active.id);
             self.remove_active();
         }
 
-        self.list.push_front(doc);
+        self.push(doc);
+        if let Some(idx) = replaced_tab {
+            self.move_tab(self.tab_order.len() - 1, idx);
+        }
         Ok(self.list.front_mut().unwrap())
     }
 
@@ -213,17 +417,6 @@ impl DocumentManager {
         File::create(path).map_err(apperr::Error::from)
     }
 
-    fn create_buffer() -> apperr::Result<RcTextBuffer> {
-        let buffer = TextBuffer::new_rc(false)?;
-        {
-            let mut tb = buffer.borrow_mut();
-            tb.set_insert_final_newline(!cfg!(windows)); // As mandated by POSIX.
-            tb.set_margin_enabled(true);
-            tb.set_line_highlight_enabled(true);
-        }
-        Ok(buffer)
-    }
-
     // Parse a filename in the form of "filename:line:char".
     // Returns the position of the first colon and the line/char coordinates.
     fn parse_filename_goto(path: &Path) -> (&Path, Option<Point>) {
@@ -2,16 +2,26 @@
 // Licensed under the MIT License.
 
 use std::num::ParseIntError;
+use std::time::Duration;
 
+use edit::buffer::SearchOptions;
 use edit::framebuffer::IndexedColor;
 use edit::helpers::*;
 use edit::icu;
 use edit::input::{kbmod, vk};
 use edit::tui::*;
 
+use crate::documents::DiskChange;
+use crate::draw_tabs::tab_bar_visible;
+use crate::find_in_files;
 use crate::localization::*;
+use crate::panes::{Layout, Pane, Split, make_cursor_live};
+use crate::plugins::PANEL_HEIGHT;
 use crate::state::*;
 
+/// How long each frame may spend on finding the search matches to highlight.
+const SEARCH_MATCHES_TIME_SLICE: Duration = Duration::from_millis(8);
+
 pub fn draw_editor(ctx: &mut Context, state: &mut State) {
     if !matches!(state.wants_search.kind, StateSearchKind::Hidden | StateSearchKind::Disabled) {
         draw_search(ctx, state);
@@ -19,21 +29,220 @@ pub fn draw_editor(ctx: &mut Context, state: &mut State) {
 
     let size = ctx.size();
     // TODO: The layout code should be able to just figure out the height on its own.
-    let height_reduction = match state.wants_search.kind {
+    let mut height_reduction = match state.wants_search.kind {
         StateSearchKind::Search => 4,
         StateSearchKind::Replace => 5,
         _ => 2,
     };
+    if state.plugins.is_panel_visible() {
+        height_reduction += PANEL_HEIGHT;
+    }
+    if state.find_in_files.is_visible() {
+        height_reduction += find_in_files::PANEL_HEIGHT;
+    }
+    if tab_bar_visible(state) {
+        height_reduction += 1;
+    }
 
-    if let Some(doc) = state.documents.active() {
-        ctx.textarea("textarea", doc.buffer.clone());
-        ctx.inherit_focus();
+    let size = Size { width: size.width, height: size.height - height_reduction };
+    state.panes.sync(&mut state.documents);
+
+    if let Some(layout) = &mut state.panes.layout {
+        let mut pass = PanesPass {
+            panes: &state.panes.panes,
+            active: state.panes.active,
+            wants_focus: state.panes.wants_focus,
+            activated: None,
+            divider_color: state.menubar_color_bg,
+        };
+        draw_layout(ctx, &mut pass, layout, size, true);
+
+        state.panes.wants_focus = false;
+        if let Some(id) = pass.activated {
+            state.panes.activate(id, &mut state.documents);
+            ctx.needs_rerender();
+        }
     } else {
         ctx.block_begin("empty");
         ctx.block_end();
+        ctx.attr_intrinsic_size(size);
     }
 
-    ctx.attr_intrinsic_size(Size { width: 0, height: size.height - height_reduction });
+    sync_search_matches(ctx, state);
+}
+
+/// Highlights the matches of the search bar in the active document, and only there.
+/// This comes after drawing the documents, so that the match count reflects any edits.
+/// Each call searches for a bit, and the main loop keeps calling it until it's done.
+fn sync_search_matches(ctx: &mut Context, state: &mut State) {
+    let visible =
+        !matches!(state.wants_search.kind, StateSearchKind::Hidden | StateSearchKind::Disabled);
+    let active = state.documents.active().map(|doc| doc.id);
+    let mut count = None;
+
+    for doc in state.documents.iter() {
+        let mut tb = doc.buffer.borrow_mut();
+        if visible && Some(doc.id) == active {
+            tb.set_search_matches(&state.search_needle, state.search_options);
+            if tb.search_matches_pending() {
+                tb.search_matches_update(SEARCH_MATCHES_TIME_SLICE);
+            }
+            count = tb.search_match_count();
+        } else {
+            tb.set_search_matches("", SearchOptions::default());
+        }
+    }
+
+    if count != state.search_match_count {
+        state.search_match_count = count;
+        ctx.needs_rerender();
+    }
+}
+
+struct PanesPass<'a> {
+    panes: &'a [Pane],
+    active: u64,
+    wants_focus: bool,
+    /// The pane that got focused by the user (e.g. by clicking into it).
+    activated: Option<u64>,
+    divider_color: u32,
+}
+
+/// Draws the `layout` into an area of the given `size`.
+/// `focus_path` is true if it contains the active pane.
+fn draw_layout(
+    ctx: &mut Context,
+    pass: &mut PanesPass,
+    layout: &mut Layout,
+    size: Size,
+    focus_path: bool,
+) {
+    let split = match layout {
+        Layout::Pane(id) => {
+            draw_pane(ctx, pass, *id, size);
+            return;
+        }
+        Layout::Split(split) => split,
+    };
+
+    let focus_first = focus_path && split.first.contains(pass.active);
+    let focus_second = focus_path && !focus_first;
+    let total = if split.vertical { size.width } else { size.height } - 1;
+    let first = ((total as f32 * split.ratio).round() as CoordType).clamp(1, (total - 1).max(1));
+    let second = total - first;
+
+    if split.vertical {
+        ctx.table_begin("split");
+        ctx.table_set_columns(&[first, 1, second]);
+        if focus_path {
+            ctx.inherit_focus();
+        }
+        ctx.table_next_row();
+        if focus_path {
+            ctx.inherit_focus();
+        }
+        draw_layout(
+            ctx,
+            pass,
+            &mut split.first,
+            Size { width: first, height: size.height },
+            focus_first,
+        );
+        draw_divider(ctx, pass, split, Size { width: 1, height: size.height }, total);
+        draw_layout(
+            ctx,
+            pass,
+            &mut split.second,
+            Size { width: second, height: size.height },
+            focus_second,
+        );
+        ctx.table_end();
+    } else {
+        ctx.block_begin("split");
+        if focus_path {
+            ctx.inherit_focus();
+        }
+        draw_layout(
+            ctx,
+            pass,
+            &mut split.first,
+            Size { width: size.width, height: first },
+            focus_first,
+        );
+        draw_divider(ctx, pass, split, Size { width: size.width, height: 1 }, total);
+        draw_layout(
+            ctx,
+            pass,
+            &mut split.second,
+            Size { width: size.width, height: second },
+            focus_second,
+        );
+        ctx.block_end();
+    }
+}
+
+fn draw_pane(ctx: &mut Context, pass: &mut PanesPass, id: u64, size: Size) {
+    let Some(pane) = pass.panes.iter().find(|p| p.id == id) else {
+        return;
+    };
+    let active = id == pass.active;
+
+    ctx.next_block_id_mixin(id);
+    ctx.block_begin("pane");
+    ctx.attr_intrinsic_size(size);
+    if active {
+        ctx.inherit_focus();
+    }
+    {
+        // The user focused another pane. Its cursor must be in the buffer before
+        // the textarea handles the input (e.g. the click that focused it).
+        if !active && !pass.wants_focus && ctx.contains_focus() {
+            make_cursor_live(pass.panes, id);
+            pass.activated = Some(id);
+        }
+
+        ctx.textarea_view("textarea", pane.buffer.clone(), pane.view.clone());
+        ctx.attr_intrinsic_size(size);
+        if active {
+            ctx.inherit_focus();
+            if pass.wants_focus {
+                ctx.steal_focus();
+            }
+        }
+    }
+    ctx.block_end();
+}
+
+/// Draws the divider between the two halves of the `split`, which can be dragged
+/// with the mouse to resize them. `total` is the space they share.
+fn draw_divider(
+    ctx: &mut Context,
+    pass: &mut PanesPass,
+    split: &mut Split,
+    size: Size,
+    total: CoordType,
+) {
+    ctx.block_begin("divider");
+    ctx.attr_intrinsic_size(size);
+    ctx.attr_background_rgba(pass.divider_color);
+
+    if ctx.was_mouse_down() {
+        // Clicking the divider shouldn't leave the editor without focus.
+        pass.wants_focus = true;
+    }
+    if let Some(delta) = ctx.mouse_drag_delta() {
+        let start = *split.drag_start.get_or_insert(split.ratio);
+        let delta = if split.vertical { delta.x } else { delta.y };
+        let ratio = (start + delta as f32 / total.max(1) as f32).clamp(0.0, 1.0);
+        if ratio != split.ratio {
+            split.ratio = ratio;
+            ctx.needs_rerender();
+        }
+    } else {
+        split.drag_start = None;
+    }
+
+    ctx.block_end();
 }
 
 fn draw_search(ctx: &mut Context, state: &mut State) {
@@ -154,6 +363,20 @@ fn draw_search(ctx: &mut Context, state: &mut State) {
             if ctx.button("close", loc(LocId::SearchClose), ButtonStyle::default()) {
                 state.wants_search.kind = StateSearchKind::Hidden;
             }
+            if let Some(count) = state.search_match_count {
+                // The total keeps growing while large documents are searched.
+                let mut total = count.total.to_string();
+                if !count.complete {
+                    total.push('+');
+                }
+                let text = match count.index {
+                    Some(index) => loc(LocId::SearchMatchIndex)
+                        .replace("{index}", &(index + 1).to_string())
+                        .replace("{count}", &total),
+                    None => loc(LocId::SearchMatchCount).replace("{count}", &total),
+                };
+                ctx.label("match-count", &text);
+            }
 
             if change {
                 action = change_action;
@@ -189,6 +412,17 @@ fn draw_search(ctx: &mut Context, state: &mut State) {
 pub fn draw_handle_save(ctx: &mut Context, state: &mut State) {
     if let Some(doc) = state.documents.active_mut() {
         if doc.path.is_some() {
+            if doc.changed_on_disk() {
+                match draw_save_conflict(ctx) {
+                    None => return,
+                    Some(true) => {}
+                    Some(false) => {
+                        state.wants_save = false;
+                        ctx.needs_rerender();
+                        return;
+                    }
+                }
+            }
             if let Err(err) = doc.save(None) {
                 error_log_add(ctx, state, err);
             }
@@ -203,7 +437,156 @@ pub fn draw_handle_save(ctx: &mut Context, state: &mut State) {
     state.wants_save = false;
 }
 
+/// Asks whether to save over changes another program made to the file.
+/// Returns `None` while the question is still open.
+fn draw_save_conflict(ctx: &mut Context) -> Option<bool> {
+    let mut overwrite = None;
+
+    ctx.modal_begin("save-conflict", loc(LocId::DiskChangeDialogTitle));
+    ctx.attr_background_rgba(ctx.indexed(IndexedColor::Red));
+    ctx.attr_foreground_rgba(ctx.indexed(IndexedColor::BrightWhite));
+    {
+        let contains_focus = ctx.contains_focus();
+
+        ctx.label("description", loc(LocId::SaveConflictDialogDescription));
+        ctx.attr_padding(Rect::three(1, 2, 1));
+
+        ctx.table_begin("choices");
+        ctx.inherit_focus();
+        ctx.attr_padding(Rect::three(0, 2, 1));
+        ctx.attr_position(Position::Center);
+        ctx.table_set_cell_gap(Size { width: 2, height: 0 });
+        {
+            ctx.table_next_row();
+            ctx.inherit_focus();
+
+            if ctx.button("yes", loc(LocId::Yes), ButtonStyle::default()) {
+                overwrite = Some(true);
+            }
+            ctx.inherit_focus();
+            if ctx.button("no", loc(LocId::No), ButtonStyle::default()) {
+                overwrite = Some(false);
+            }
+        }
+        ctx.table_end();
+
+        if contains_focus {
+            if ctx.consume_shortcut(vk::Y) {
+                overwrite = Some(true);
+            } else if ctx.consume_shortcut(vk::N) {
+                overwrite = Some(false);
+            }
+        }
+    }
+    if ctx.modal_end() {
+        overwrite = Some(false);
+    }
+
+    overwrite
+}
+
+/// Asks what to do about the first of the documents whose file changed on disk.
+pub fn draw_handle_disk_change(ctx: &mut Context, state: &mut State) {
+    let (id, change) = state.disk_changes[0];
+    let Some(doc) = state.documents.iter_mut().find(|doc| doc.id == id) else {
+        // It got closed in the meantime.
+        state.disk_changes.remove(0);
+        ctx.needs_rerender();
+        return;
+    };
+
+    enum Action {
+        None,
+        Reload,
+        Keep,
+    }
+    let mut action = Action::None;
+
+    ctx.modal_begin("disk-change", loc(LocId::DiskChangeDialogTitle));
+    {
+        let contains_focus = ctx.contains_focus();
+
+        ctx.label("filename", &doc.filename);
+        ctx.attr_overflow(Overflow::TruncateMiddle);
+        ctx.attr_padding(Rect::three(1, 2, 0));
+
+        ctx.label(
+            "description",
+            loc(match change {
+                DiskChange::Modified => LocId::DiskChangeDialogModified,
+                DiskChange::Deleted => LocId::DiskChangeDialogDeleted,
+            }),
+        );
+        ctx.attr_padding(Rect::three(1, 2, 1));
+
+        ctx.table_begin("choices");
+        ctx.inherit_focus();
+        ctx.attr_padding(Rect::three(0, 2, 1));
+        ctx.attr_position(Position::Center);
+        ctx.table_set_cell_gap(Size { width: 2, height: 0 });
+        {
+            ctx.table_next_row();
+            ctx.inherit_focus();
+
+            // There's nothing to reload if the file is gone.
+            if change == DiskChange::Modified {
+                if ctx.button(
+                    "reload",
+                    loc(LocId::DiskChangeDialogReload),
+                    ButtonStyle::default().accelerator('R'),
+                ) {
+                    action = Action::Reload;
+                }
+                ctx.inherit_focus();
+            }
+            if ctx.button(
+                "keep",
+                loc(LocId::DiskChangeDialogKeep),
+                ButtonStyle::default().accelerator('K'),
+            ) {
+                action = Action::Keep;
+            }
+        }
+        ctx.table_end();
+
+        if contains_focus {
+            if change == DiskChange::Modified && ctx.consume_shortcut(vk::R) {
+                action = Action::Reload;
+            } else if ctx.consume_shortcut(vk::K) {
+                action = Action::Keep;
+            }
+        }
+    }
+    if ctx.modal_end() {
+        action = Action::Keep;
+    }
+
+    match action {
+        Action::None => return,
+        Action::Reload => {
+            let encoding = doc.buffer.borrow().encoding();
+            if let Err(err) = doc.reread(Some(encoding)) {
+                error_log_add(ctx, state, err);
+            }
+        }
+        Action::Keep => {
+            if change == DiskChange::Deleted {
+                // Closing it would otherwise lose the contents without asking.
+                doc.buffer.borrow_mut().mark_as_dirty();
+            }
+        }
+    }
+
+    state.disk_changes.remove(0);
+    ctx.needs_rerender();
+}
+
 pub fn draw_handle_wants_close(ctx: &mut Context, state: &mut State) {
+    if state.wants_save {
+        // The save got held up by a prompt. It'll be back once that's answered.
+        return;
+    }
+
     let Some(doc) = state.documents.active() else {
         state.wants_close = false;
         return;
@@ -337,7 +720,7 @@ pub fn draw_goto_menu(ctx: &mut Context, state: &mut State) {
     }
 }
 
-fn validate_goto_point(line: &str) -> Result<Point, ParseIntError> {
+pub fn validate_goto_point(line: &str) -> Result<Point, ParseIntError> {
     let mut coords = [0; 2];
     let (y, x) = line.split_once(':').unwrap_or((line, "0"));
     // Using a loop here avoids 2 copies of the str->int code.
@@ -0,0 +1,230 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The undo history browser: Lists the states of the undo tree, including
+//! the branches that were undone, and previews the text at each of them.
+
+use std::time::{Duration, SystemTime};
+
+use edit::buffer::{HistoryState, HistoryStateKind};
+use edit::diff::split_lines;
+use edit::framebuffer::{Attributes, IndexedColor};
+use edit::helpers::*;
+use edit::input::vk;
+use edit::tui::*;
+
+use crate::localization::*;
+use crate::state::*;
+
+/// The number of lines shown above and below the edit in the preview.
+const PREVIEW_CONTEXT: usize = 4;
+
+pub fn draw_history(ctx: &mut Context, state: &mut State) {
+    enum Action {
+        None,
+        Goto,
+        Select(usize),
+    }
+    let mut action = Action::None;
+
+    let Some(doc) = state.documents.active() else {
+        state.wants_history = false;
+        return;
+    };
+    let buffer = doc.buffer.clone();
+    let history = buffer.borrow().history();
+    // Opening the dialog resets the preview. It starts out at the current state.
+    if state.history_preview.is_none() {
+        let current = history.iter().find(|s| s.kind == HistoryStateKind::Current);
+        state.history_selected = current.map_or(0, |s| s.id);
+    }
+    if !history.iter().any(|s| s.id == state.history_selected) {
+        state.history_selected = 0;
+    }
+    if state.history_preview.as_ref().is_none_or(|(id, _)| *id != state.history_selected) {
+        let selected = history.iter().find(|s| s.id == state.history_selected).unwrap();
+        let text = buffer.borrow().history_text(selected.id);
+        state.history_preview = Some((selected.id, preview_lines(&text, selected)));
+    }
+
+    let width = (ctx.size().width - 20).max(20);
+    let now = SystemTime::now();
+    let saved = history.iter().find(|s| s.saved).map_or(state.history_selected, |s| s.id);
+    let ten_minutes_ago = buffer.borrow().history_at_time(now - Duration::from_secs(10 * 60));
+
+    ctx.modal_begin("history", loc(LocId::EditUndoHistory));
+    {
+        let contains_focus = ctx.contains_focus();
+
+        ctx.scrollarea_begin(
+            "states",
+            Size { width, height: (history.len() as CoordType).clamp(1, 10) },
+        );
+        ctx.attr_background_rgba(ctx.indexed_alpha(IndexedColor::Black, 1, 4));
+        ctx.attr_padding(Rect::three(1, 2, 0));
+        ctx.inherit_focus();
+        {
+            ctx.list_begin("states");
+            ctx.inherit_focus();
+            // Newest first, like the history of a shell.
+            for s in history.iter().rev() {
+                ctx.next_block_id_mixin(s.id as u64);
+                ctx.styled_list_item_begin();
+                ctx.attr_overflow(Overflow::TruncateTail);
+                ctx.styled_label_add_text(if s.kind == HistoryStateKind::Current {
+                    "• "
+                } else {
+                    "  "
+                });
+                match s.time {
+                    Some(time) => ctx.styled_label_add_text(&format!(
+                        "{:>4}  ",
+                        format_age(now.duration_since(time).unwrap_or_default())
+                    )),
+                    None => ctx.styled_label_add_text("      "),
+                }
+
+                // Undone edits are dimmed, those on other branches in italics as well.
+                let fg = match s.kind {
+                    HistoryStateKind::Current | HistoryStateKind::Undo => None,
+                    HistoryStateKind::Redo => Some(ctx.indexed(IndexedColor::BrightBlack)),
+                    HistoryStateKind::Branch => {
+                        ctx.styled_label_set_attributes(Attributes::Italic);
+                        Some(ctx.indexed(IndexedColor::BrightBlack))
+                    }
+                };
+                if let Some(fg) = fg {
+                    ctx.styled_label_set_foreground(fg);
+                }
+                if s.id == 0 {
+                    ctx.styled_label_add_text(loc(LocId::HistoryDialogInitial));
+                } else {
+                    ctx.styled_label_add_text(&format!(
+                        "{}:{}  +{} -{}  {}",
+                        s.position.y + 1,
+                        s.position.x + 1,
+                        s.inserted_len,
+                        s.deleted_len,
+                        s.excerpt
+                    ));
+                }
+                if s.saved {
+                    ctx.styled_label_add_text("  ");
+                    ctx.styled_label_set_attributes(Attributes::Italic);
+                    ctx.styled_label_add_text(loc(LocId::HistoryDialogSaved));
+                }
+
+                match ctx.styled_list_item_end(s.id == state.history_selected) {
+                    ListSelection::Unchanged => {}
+                    ListSelection::Selected => action = Action::Select(s.id),
+                    ListSelection::Activated => {
+                        state.history_selected = s.id;
+                        action = Action::Goto;
+                    }
+                }
+            }
+            ctx.list_end();
+        }
+        ctx.scrollarea_end();
+
+        // The text of the selected state, around the spot that was edited.
+        ctx.scrollarea_begin(
+            "preview",
+            Size { width, height: 2 * PREVIEW_CONTEXT as CoordType + 1 },
+        );
+        ctx.attr_padding(Rect::three(1, 2, 1));
+        if let Some((_, lines)) = &state.history_preview {
+            for (idx, (line, edited)) in lines.iter().enumerate() {
+                ctx.next_block_id_mixin(idx as u64);
+                ctx.label("line", line);
+                ctx.attr_overflow(Overflow::TruncateTail);
+                if *edited {
+                    ctx.attr_background_rgba(ctx.indexed_alpha(IndexedColor::BrightBlue, 1, 4));
+                }
+            }
+        }
+        ctx.scrollarea_end();
+
+        ctx.table_begin("choices");
+        ctx.inherit_focus();
+        ctx.attr_padding(Rect::three(0, 2, 1));
+        ctx.attr_position(Position::Center);
+        ctx.table_set_cell_gap(Size { width: 2, height: 0 });
+        {
+            ctx.table_next_row();
+
+            if ctx.button(
+                "goto",
+                loc(LocId::HistoryDialogGoto),
+                ButtonStyle::default().accelerator('G'),
+            ) {
+                action = Action::Goto;
+            }
+            if ctx.button(
+                "saved",
+                loc(LocId::HistoryDialogLastSave),
+                ButtonStyle::default().accelerator('S'),
+            ) {
+                action = Action::Select(saved);
+            }
+            if ctx.button(
+                "ten-minutes",
+                loc(LocId::HistoryDialogTenMinutesAgo),
+                ButtonStyle::default().accelerator('M'),
+            ) {
+                action = Action::Select(ten_minutes_ago);
+            }
+        }
+        ctx.table_end();
+
+        if contains_focus {
+            if ctx.consume_shortcut(vk::G) {
+                action = Action::Goto;
+            } else if ctx.consume_shortcut(vk::S) {
+                action = Action::Select(saved);
+            } else if ctx.consume_shortcut(vk::M) {
+                action = Action::Select(ten_minutes_ago);
+            }
+        }
+    }
+    if ctx.modal_end() {
+        state.wants_history = false;
+    }
+
+    match action {
+        Action::None => return,
+        Action::Select(id) => state.history_selected = id,
+        Action::Goto => {
+            buffer.borrow_mut().history_goto(state.history_selected);
+            state.wants_history = false;
+        }
+    }
+    ctx.needs_rerender();
+}
+
+/// Returns the lines around the edit that led to the given state,
+/// and whether each one is the line where it took place.
+fn preview_lines(text: &[u8], s: &HistoryState) -> Vec<(String, bool)> {
+    let lines = split_lines(text);
+    let y = (s.position.y.max(0) as usize).min(lines.len().saturating_sub(1));
+    let beg = y.saturating_sub(PREVIEW_CONTEXT);
+    let end = (y + PREVIEW_CONTEXT + 1).min(lines.len());
+    lines[beg..end]
+        .iter()
+        .enumerate()
+        .map(|(idx, line)| {
+            let line = line.strip_suffix(b"\n").unwrap_or(line);
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            (String::from_utf8_lossy(line).into_owned(), s.id != 0 && beg + idx == y)
+        })
+        .collect()
+}
+
+fn format_age(age: Duration) -> String {
+    match age.as_secs() {
+        s @ 0..60 => format!("{s}s"),
+        s @ 60..3600 => format!("{}m", s / 60),
+        s @ 3600..86400 => format!("{}h", s / 3600),
+        s => format!("{}d", s / 86400),
+    }
+}
@@ -3,9 +3,10 @@
 
 use edit::arena_format;
 use edit::helpers::*;
-use edit::input::{kbmod, vk};
+use edit::keymap::cmd as textarea;
 use edit::tui::*;
 
+use crate::keybindings::cmd;
 use crate::localization::*;
 use crate::state::*;
 
@@ -19,7 +20,7 @@ pub fn draw_menubar(ctx: &mut Context, state: &mut State) {
         if ctx.menubar_menu_begin(loc(LocId::File), 'F') {
             draw_menu_file(ctx, state);
         }
-        if !contains_focus && ctx.consume_shortcut(vk::F10) {
+        if !contains_focus && ctx.consume_command(cmd::VIEW_FOCUS_MENUBAR) {
             ctx.steal_focus();
         }
         if state.documents.active().is_some() && ctx.menubar_menu_begin(loc(LocId::Edit), 'E') {
@@ -36,24 +37,24 @@ pub fn draw_menubar(ctx: &mut Context, state: &mut State) {
 }
 
 fn draw_menu_file(ctx: &mut Context, state: &mut State) {
-    if ctx.menubar_menu_button(loc(LocId::FileNew), 'N', kbmod::CTRL | vk::N) {
+    if ctx.menubar_menu_button(loc(LocId::FileNew), 'N', cmd::FILE_NEW) {
         draw_add_untitled_document(ctx, state);
     }
-    if ctx.menubar_menu_button(loc(LocId::FileOpen), 'O', kbmod::CTRL | vk::O) {
+    if ctx.menubar_menu_button(loc(LocId::FileOpen), 'O', cmd::FILE_OPEN) {
         state.wants_file_picker = StateFilePicker::Open;
     }
     if state.documents.active().is_some() {
-        if ctx.menubar_menu_button(loc(LocId::FileSave), 'S', kbmod::CTRL | vk::S) {
+        if ctx.menubar_menu_button(loc(LocId::FileSave), 'S', cmd::FILE_SAVE) {
             state.wants_save = true;
         }
-        if ctx.menubar_menu_button(loc(LocId::FileSaveAs), 'A', vk::NULL) {
+        if ctx.menubar_menu_button(loc(LocId::FileSaveAs), 'A', cmd::FILE_SAVE_AS) {
             state.wants_file_picker = StateFilePicker::SaveAs;
         }
-        if ctx.menubar_menu_button(loc(LocId::FileClose), 'C', kbmod::CTRL | vk::W) {
+        if ctx.menubar_menu_button(loc(LocId::FileClose), 'C', cmd::FILE_CLOSE) {
             state.wants_close = true;
         }
     }
-    if ctx.menubar_menu_button(loc(LocId::FileExit), 'X', kbmod::CTRL | vk::Q) {
+    if ctx.menubar_menu_button(loc(LocId::FileExit), 'X', cmd::FILE_EXIT) {
         state.wants_exit = true;
     }
     ctx.menubar_menu_end();
@@ -63,35 +64,50 @@ fn draw_menu_edit(ctx: &mut Context, state: &mut State) {
     let doc = state.documents.active().unwrap();
     let mut tb = doc.buffer.borrow_mut();
 
-    if ctx.menubar_menu_button(loc(LocId::EditUndo), 'U', kbmod::CTRL | vk::Z) {
+    if ctx.menubar_menu_button(loc(LocId::EditUndo), 'U', textarea::EDIT_UNDO) {
         tb.undo();
         ctx.needs_rerender();
     }
-    if ctx.menubar_menu_button(loc(LocId::EditRedo), 'R', kbmod::CTRL | vk::Y) {
+    if ctx.menubar_menu_button(loc(LocId::EditRedo), 'R', textarea::EDIT_REDO) {
         tb.redo();
         ctx.needs_rerender();
     }
-    if ctx.menubar_menu_button(loc(LocId::EditCut), 'T', kbmod::CTRL | vk::X) {
-        ctx.set_clipboard(tb.extract_selection(true));
+    if ctx.menubar_menu_button(loc(LocId::EditUndoHistory), 'H', cmd::EDIT_UNDO_HISTORY) {
+        state.wants_history = true;
+        state.history_preview = None;
     }
-    if ctx.menubar_menu_button(loc(LocId::EditCopy), 'C', kbmod::CTRL | vk::C) {
-        ctx.set_clipboard(tb.extract_selection(false));
+    if ctx.menubar_menu_button(loc(LocId::EditCut), 'T', textarea::EDIT_CUT) {
+        ctx.set_clipboard_from_selection(&mut tb, true);
     }
-    if ctx.menubar_menu_button(loc(LocId::EditPaste), 'P', kbmod::CTRL | vk::V) {
-        tb.write(ctx.clipboard(), true);
+    if ctx.menubar_menu_button(loc(LocId::EditCopy), 'C', textarea::EDIT_COPY) {
+        ctx.set_clipboard_from_selection(&mut tb, false);
+    }
+    if ctx.menubar_menu_button(loc(LocId::EditPaste), 'P', textarea::EDIT_PASTE) {
+        ctx.paste_clipboard(&mut tb);
         ctx.needs_rerender();
     }
     if state.wants_search.kind != StateSearchKind::Disabled {
-        if ctx.menubar_menu_button(loc(LocId::EditFind), 'F', kbmod::CTRL | vk::F) {
+        if ctx.menubar_menu_button(loc(LocId::EditFind), 'F', cmd::EDIT_FIND) {
             state.wants_search.kind = StateSearchKind::Search;
             state.wants_search.focus = true;
         }
-        if ctx.menubar_menu_button(loc(LocId::EditReplace), 'L', kbmod::CTRL | vk::R) {
+        if ctx.menubar_menu_button(loc(LocId::EditReplace), 'L', cmd::EDIT_REPLACE) {
             state.wants_search.kind = StateSearchKind::Replace;
             state.wants_search.focus = true;
         }
     }
-    if ctx.menubar_menu_button(loc(LocId::EditSelectAll), 'A', kbmod::CTRL | vk::A) {
+    if state.find_in_files.kind != StateSearchKind::Disabled {
+        if ctx.menubar_menu_button(loc(LocId::EditFindInFiles), 'I', cmd::EDIT_FIND_IN_FILES) {
+            state.find_in_files.kind = StateSearchKind::Search;
+            state.find_in_files.focus = true;
+        }
+        if ctx.menubar_menu_button(loc(LocId::EditReplaceInFiles), 'E', cmd::EDIT_REPLACE_IN_FILES)
+        {
+            state.find_in_files.kind = StateSearchKind::Replace;
+            state.find_in_files.focus = true;
+        }
+    }
+    if ctx.menubar_menu_button(loc(LocId::EditSelectAll), 'A', textarea::SELECT_ALL) {
         tb.select_all();
         ctx.needs_rerender();
     }
@@ -99,31 +115,55 @@ fn draw_menu_edit(ctx: &mut Context, state: &mut State) {
 }
 
 fn draw_menu_view(ctx: &mut Context, state: &mut State) {
-    if ctx.menubar_menu_button(loc(LocId::ViewFocusStatusbar), 'S', vk::NULL) {
+    if ctx.menubar_menu_button(loc(LocId::ViewFocusStatusbar), 'S', cmd::VIEW_FOCUS_STATUSBAR) {
         state.wants_statusbar_focus = true;
     }
+    if ctx.menubar_menu_button(loc(LocId::ViewCommandPalette), 'C', cmd::VIEW_COMMAND_PALETTE) {
+        state.wants_command_palette = true;
+    }
 
     if let Some(doc) = state.documents.active() {
         let mut tb = doc.buffer.borrow_mut();
         let word_wrap = tb.is_word_wrap_enabled();
 
-        if ctx.menubar_menu_button(loc(LocId::ViewDocumentPicker), 'P', kbmod::CTRL | vk::P) {
+        if ctx.menubar_menu_button(loc(LocId::ViewDocumentPicker), 'P', cmd::VIEW_DOCUMENT_PICKER) {
             state.wants_document_picker = true;
         }
-        if ctx.menubar_menu_button(loc(LocId::FileGoto), 'G', kbmod::CTRL | vk::G) {
+        if ctx.menubar_menu_button(loc(LocId::FileGoto), 'G', cmd::VIEW_GOTO) {
             state.wants_goto = true;
         }
-        if ctx.menubar_menu_checkbox(loc(LocId::ViewWordWrap), 'W', kbmod::ALT | vk::Z, word_wrap) {
+        if ctx.menubar_menu_checkbox(
+            loc(LocId::ViewWordWrap),
+            'W',
+            textarea::VIEW_WORD_WRAP,
+            word_wrap,
+        ) {
             tb.set_word_wrap(!word_wrap);
             ctx.needs_rerender();
         }
+        drop(tb);
+
+        if ctx.menubar_menu_button(loc(LocId::ViewSplitRight), 'R', cmd::VIEW_SPLIT_RIGHT) {
+            state.panes.split(true, &mut state.documents);
+            ctx.needs_rerender();
+        }
+        if ctx.menubar_menu_button(loc(LocId::ViewSplitDown), 'D', cmd::VIEW_SPLIT_DOWN) {
+            state.panes.split(false, &mut state.documents);
+            ctx.needs_rerender();
+        }
+        if state.panes.len() > 1
+            && ctx.menubar_menu_button(loc(LocId::ViewClosePane), 'L', cmd::VIEW_CLOSE_PANE)
+        {
+            state.panes.close_active(&mut state.documents);
+            ctx.needs_rerender();
+        }
     }
 
     ctx.menubar_menu_end();
 }
 
 fn draw_menu_help(ctx: &mut Context, state: &mut State) {
-    if ctx.menubar_menu_button(loc(LocId::HelpAbout), 'A', vk::NULL) {
+    if ctx.menubar_menu_button(loc(LocId::HelpAbout), 'A', cmd::HELP_ABOUT) {
         state.wants_about = true;
     }
     ctx.menubar_menu_end();
@@ -0,0 +1,167 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use edit::arena::scratch_arena;
+use edit::arena_format;
+use edit::framebuffer::{Attributes, IndexedColor};
+use edit::fuzzy::score_fuzzy;
+use edit::helpers::*;
+use edit::input::vk;
+use edit::keymap::cmd as textarea;
+use edit::tui::*;
+
+use crate::keybindings::cmd;
+use crate::localization::*;
+use crate::run_command;
+use crate::state::*;
+
+/// The entries of the command palette: The menu they belong to, their label and command.
+const PALETTE: &[(LocId, LocId, &str)] = &[
+    (LocId::File, LocId::FileNew, cmd::FILE_NEW),
+    (LocId::File, LocId::FileOpen, cmd::FILE_OPEN),
+    (LocId::File, LocId::FileSave, cmd::FILE_SAVE),
+    (LocId::File, LocId::FileSaveAs, cmd::FILE_SAVE_AS),
+    (LocId::File, LocId::FileClose, cmd::FILE_CLOSE),
+    (LocId::File, LocId::EncodingReopen, cmd::FILE_REOPEN_WITH_ENCODING),
+    (LocId::File, LocId::FileExit, cmd::FILE_EXIT),
+    (LocId::Edit, LocId::EditUndo, textarea::EDIT_UNDO),
+    (LocId::Edit, LocId::EditRedo, textarea::EDIT_REDO),
+    (LocId::Edit, LocId::EditUndoHistory, cmd::EDIT_UNDO_HISTORY),
+    (LocId::Edit, LocId::EditCut, textarea::EDIT_CUT),
+    (LocId::Edit, LocId::EditCopy, textarea::EDIT_COPY),
+    (LocId::Edit, LocId::EditPaste, textarea::EDIT_PASTE),
+    (LocId::Edit, LocId::EditFind, cmd::EDIT_FIND),
+    (LocId::Edit, LocId::EditReplace, cmd::EDIT_REPLACE),
+    (LocId::Edit, LocId::EditFindInFiles, cmd::EDIT_FIND_IN_FILES),
+    (LocId::Edit, LocId::EditReplaceInFiles, cmd::EDIT_REPLACE_IN_FILES),
+    (LocId::Edit, LocId::EditSelectAll, textarea::SELECT_ALL),
+    (LocId::Edit, LocId::CommandToggleOvertype, textarea::EDIT_TOGGLE_OVERTYPE),
+    (LocId::Edit, LocId::CommandConvertToLf, cmd::EDIT_CONVERT_TO_LF),
+    (LocId::Edit, LocId::CommandConvertToCrlf, cmd::EDIT_CONVERT_TO_CRLF),
+    (LocId::Edit, LocId::CommandChangeTabSize, cmd::EDIT_CHANGE_TAB_SIZE),
+    (LocId::View, LocId::ViewFocusStatusbar, cmd::VIEW_FOCUS_STATUSBAR),
+    (LocId::View, LocId::ViewDocumentPicker, cmd::VIEW_DOCUMENT_PICKER),
+    (LocId::View, LocId::FileGoto, cmd::VIEW_GOTO),
+    (LocId::View, LocId::ViewWordWrap, textarea::VIEW_WORD_WRAP),
+    (LocId::View, LocId::ViewSplitRight, cmd::VIEW_SPLIT_RIGHT),
+    (LocId::View, LocId::ViewSplitDown, cmd::VIEW_SPLIT_DOWN),
+    (LocId::View, LocId::ViewClosePane, cmd::VIEW_CLOSE_PANE),
+    (LocId::View, LocId::CommandFocusNextPane, cmd::VIEW_FOCUS_NEXT_PANE),
+    (LocId::View, LocId::CommandFocusPreviousPane, cmd::VIEW_FOCUS_PREVIOUS_PANE),
+    (LocId::View, LocId::CommandNextTab, cmd::VIEW_NEXT_TAB),
+    (LocId::View, LocId::CommandPreviousTab, cmd::VIEW_PREVIOUS_TAB),
+    (LocId::Help, LocId::HelpAbout, cmd::HELP_ABOUT),
+];
+
+pub fn draw_command_palette(ctx: &mut Context, state: &mut State) {
+    let width = (ctx.size().width - 20).max(10);
+    let height = (ctx.size().height - 10).max(10);
+    let mut run = None;
+
+    ctx.modal_begin("command-palette", loc(LocId::ViewCommandPalette));
+    {
+        let scratch = scratch_arena(None);
+        let mut entries = Vec::new_in(&*scratch);
+        let needle = state.command_palette_needle.trim_ascii();
+
+        let builtin = PALETTE
+            .iter()
+            .filter(|e| is_available(state, e.2))
+            .map(|&(menu, label, command)| (loc(menu), loc(label), command));
+        // Plugin commands are listed under the name of their plugin.
+        let plugins = state.plugins.palette_entries();
+
+        for (menu, label, command) in builtin.chain(plugins) {
+            let text = arena_format!(&*scratch, "{}: {}", menu, label);
+            let score = if needle.is_empty() {
+                1
+            } else {
+                let local_scratch = scratch_arena(Some(&scratch));
+                score_fuzzy(&local_scratch, &text, needle, true).0
+            };
+            if score > 0 {
+                entries.push((score, text, command));
+            }
+        }
+
+        // A stable sort, so that equally good matches stay in menu order.
+        entries.sort_by_key(|e| std::cmp::Reverse(e.0));
+
+        ctx.table_begin("palette-search");
+        ctx.table_set_columns(&[0, COORD_TYPE_SAFE_MAX]);
+        ctx.table_set_cell_gap(Size { width: 1, height: 0 });
+        ctx.inherit_focus();
+        {
+            ctx.table_next_row();
+            ctx.inherit_focus();
+
+            ctx.label("needle-label", loc(LocId::SearchNeedleLabel));
+
+            ctx.editline("needle", &mut state.command_palette_needle);
+            ctx.inherit_focus();
+            if ctx.is_focused() && ctx.consume_shortcut(vk::RETURN) {
+                run = entries.first().map(|e| e.2);
+            }
+        }
+        ctx.table_end();
+
+        ctx.scrollarea_begin("scrollarea", Size { width, height });
+        ctx.attr_background_rgba(ctx.indexed_alpha(IndexedColor::Black, 1, 4));
+        {
+            ctx.list_begin("commands");
+
+            for (_, text, command) in &entries {
+                ctx.styled_list_item_begin();
+                ctx.attr_overflow(Overflow::TruncateTail);
+                ctx.styled_label_add_text(text);
+
+                if let Some(keys) = ctx.keymap().keys_for(command) {
+                    let keys = ctx.keys_to_string(keys);
+                    ctx.styled_label_add_text("   ");
+                    ctx.styled_label_set_attributes(Attributes::Italic);
+                    ctx.styled_label_add_text(&keys);
+                }
+
+                if ctx.styled_list_item_end(false) == ListSelection::Activated {
+                    run = Some(command);
+                }
+            }
+
+            ctx.list_end();
+        }
+        ctx.scrollarea_end();
+    }
+    let done = ctx.modal_end() || run.is_some();
+
+    if done {
+        state.wants_command_palette = false;
+        state.command_palette_needle.clear();
+        ctx.needs_rerender();
+    }
+    if let Some(command) = run {
+        run_command(ctx, state, command);
+    }
+}
+
+/// Whether the command can be run right now, mirroring the conditions in the menus.
+fn is_available(state: &State, command: &str) -> bool {
+    match command {
+        cmd::FILE_NEW | cmd::FILE_OPEN | cmd::FILE_EXIT => true,
+        cmd::VIEW_FOCUS_STATUSBAR | cmd::HELP_ABOUT => true,
+        cmd::EDIT_FIND | cmd::EDIT_REPLACE => {
+            state.documents.active().is_some()
+                && state.wants_search.kind != StateSearchKind::Disabled
+        }
+        cmd::EDIT_FIND_IN_FILES | cmd::EDIT_REPLACE_IN_FILES => {
+            state.find_in_files.kind != StateSearchKind::Disabled
+        }
+        cmd::VIEW_CLOSE_PANE | cmd::VIEW_FOCUS_NEXT_PANE | cmd::VIEW_FOCUS_PREVIOUS_PANE => {
+            state.panes.len() > 1
+        }
+        cmd::VIEW_NEXT_TAB | cmd::VIEW_PREVIOUS_TAB => state.documents.len() > 1,
+        cmd::FILE_REOPEN_WITH_ENCODING => {
+            state.documents.active().is_some_and(|doc| doc.path.is_some())
+        }
+        _ => state.documents.active().is_some(),
+    }
+}
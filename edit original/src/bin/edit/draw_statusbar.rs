@@ -163,6 +163,11 @@ pub fn draw_statusbar(ctx: &mut Context, state: &mut State) {
             &arena_format!(ctx.arena(), "{}/{}", tb.logical_line_count(), tb.visual_line_count(),),
         );
 
+        if !ctx.pending_keys().is_empty() {
+            let keys = ctx.keys_to_string(ctx.pending_keys());
+            ctx.label("chord", &keys);
+        }
+
         if tb.is_overtype() && ctx.button("overtype", "OVR", ButtonStyle::default()) {
             tb.set_overtype(false);
             ctx.needs_rerender();
@@ -0,0 +1,196 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The tab bar below the menubar, with a tab for each open document.
+
+use edit::arena::{ArenaString, scratch_arena};
+use edit::arena_format;
+use edit::helpers::*;
+use edit::input::InputMouseState;
+use edit::tui::*;
+use edit::unicode::MeasurementConfig;
+
+use crate::state::*;
+
+const CLOSE_BUTTON: &str = "× ";
+const SCROLL_LEFT: &str = "◀ ";
+const SCROLL_RIGHT: &str = " ▶";
+
+struct Tab<'a> {
+    id: u64,
+    label: ArenaString<'a>,
+    /// The width including the close button.
+    width: CoordType,
+}
+
+enum TabAction {
+    None,
+    Activate(u64),
+    Close(u64),
+    Move(usize, usize),
+    Scroll(isize),
+}
+
+pub fn tab_bar_visible(state: &State) -> bool {
+    state.tab_bar && state.documents.len() != 0
+}
+
+pub fn draw_tabs(ctx: &mut Context, state: &mut State) {
+    if !tab_bar_visible(state) {
+        return;
+    }
+
+    let scratch = scratch_arena(None);
+    let active = state.documents.active().map_or(0, |doc| doc.id);
+    let mut tabs = Vec::new_in(&*scratch);
+    for doc in state.documents.tabs() {
+        let dirty = if doc.buffer.borrow().is_dirty() { "*" } else { "" };
+        let label = arena_format!(&*scratch, " {}{} ", doc.filename, dirty);
+        let width = text_width(&label) + text_width(CLOSE_BUTTON);
+        tabs.push(Tab { id: doc.id, label, width });
+    }
+    let active_idx = tabs.iter().position(|tab| tab.id == active).unwrap_or(0);
+
+    // If the tabs don't fit, only a range of them is shown, with buttons to scroll it.
+    let width = ctx.size().width;
+    let overflow = tabs.iter().map(|tab| tab.width).sum::<CoordType>() > width;
+    let available =
+        if overflow { width - text_width(SCROLL_LEFT) - text_width(SCROLL_RIGHT) } else { width };
+    let fits = |beg: usize, end: usize| {
+        end <= beg || tabs[beg..=end].iter().map(|tab| tab.width).sum::<CoordType>() <= available
+    };
+
+    if !overflow {
+        state.tab_scroll = 0;
+    } else if state.tab_scroll_active != active {
+        // Scroll the newly activated tab into view.
+        state.tab_scroll_active = active;
+        state.tab_scroll = state.tab_scroll.min(active_idx);
+        while !fits(state.tab_scroll, active_idx) {
+            state.tab_scroll += 1;
+        }
+    }
+    state.tab_scroll = state.tab_scroll.min(tabs.len() - 1);
+
+    let beg = state.tab_scroll;
+    let mut end = beg + 1;
+    while end < tabs.len() && fits(beg, end) {
+        end += 1;
+    }
+
+    let mut columns = Vec::new_in(&*scratch);
+    if overflow {
+        columns.push(text_width(SCROLL_LEFT));
+    }
+    let mut used = 0;
+    for tab in &tabs[beg..end] {
+        let label_width = (tab.width - text_width(CLOSE_BUTTON)).min(available);
+        columns.push(label_width);
+        columns.push(text_width(CLOSE_BUTTON));
+        used += label_width + text_width(CLOSE_BUTTON);
+    }
+    if overflow {
+        // Pushes the right scroll button to the edge.
+        columns.push((available - used).max(0));
+        columns.push(text_width(SCROLL_RIGHT));
+    }
+
+    let mut action = TabAction::None;
+    let mut dragging = false;
+
+    ctx.table_begin("tabs");
+    ctx.attr_background_rgba(state.menubar_color_bg);
+    ctx.attr_foreground_rgba(state.menubar_color_fg);
+    ctx.attr_intrinsic_size(Size { width: COORD_TYPE_SAFE_MAX, height: 1 });
+    ctx.table_set_columns(&columns);
+    {
+        ctx.table_next_row();
+
+        if overflow {
+            ctx.label("scroll-left", SCROLL_LEFT);
+            if ctx.was_clicked(InputMouseState::Left) {
+                action = TabAction::Scroll(-1);
+            }
+        }
+
+        for (idx, tab) in tabs.iter().enumerate().take(end).skip(beg) {
+            ctx.next_block_id_mixin(tab.id);
+            ctx.label("tab", &tab.label);
+            ctx.attr_overflow(Overflow::TruncateTail);
+            if tab.id == active {
+                ctx.attr_reverse();
+            }
+
+            if ctx.was_clicked(InputMouseState::Left) {
+                action = TabAction::Activate(tab.id);
+            } else if ctx.was_clicked(InputMouseState::Middle) {
+                action = TabAction::Close(tab.id);
+            } else if let Some(delta) = ctx.mouse_drag_delta() {
+                // The tab gets moved to wherever its center is among the other tabs.
+                // Their positions don't depend on where the dragged one is, which is
+                // what keeps it from jumping back and forth between two spots.
+                let center = *state.tab_drag_start.get_or_insert_with(|| {
+                    tabs[..idx].iter().map(|t| t.width).sum::<CoordType>() + tab.width / 2
+                }) + delta.x;
+                let mut target = 0;
+                let mut x = 0;
+                for other in tabs.iter().filter(|t| t.id != tab.id) {
+                    if x + other.width / 2 < center {
+                        target += 1;
+                    }
+                    x += other.width;
+                }
+                if target != idx {
+                    action = TabAction::Move(idx, target);
+                }
+                dragging = true;
+            }
+
+            ctx.next_block_id_mixin(tab.id);
+            ctx.label("close", CLOSE_BUTTON);
+            if tab.id == active {
+                ctx.attr_reverse();
+            }
+            if ctx.was_clicked(InputMouseState::Left) {
+                action = TabAction::Close(tab.id);
+            }
+        }
+
+        if overflow {
+            ctx.label("filler", "");
+            ctx.label("scroll-right", SCROLL_RIGHT);
+            if ctx.was_clicked(InputMouseState::Left) {
+                action = TabAction::Scroll(1);
+            }
+        }
+    }
+    ctx.table_end();
+
+    if !dragging {
+        state.tab_drag_start = None;
+    }
+
+    match action {
+        TabAction::None => return,
+        TabAction::Activate(id) => {
+            state.documents.update_active(|doc| doc.id == id);
+        }
+        TabAction::Close(id) => {
+            // Goes through the usual prompt for unsaved changes.
+            state.documents.update_active(|doc| doc.id == id);
+            state.wants_close = true;
+        }
+        TabAction::Move(from, to) => state.documents.move_tab(from, to),
+        TabAction::Scroll(delta) => {
+            state.tab_scroll = state.tab_scroll.saturating_add_signed(delta).min(tabs.len() - 1);
+        }
+    }
+    ctx.needs_rerender();
+}
+
+fn text_width(text: &str) -> CoordType {
+    MeasurementConfig::new(&text.as_bytes())
+        .goto_visual(Point { x: CoordType::MAX, y: 0 })
+        .visual_pos
+        .x
+}
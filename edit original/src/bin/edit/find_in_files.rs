@@ -0,0 +1,659 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The Find in Files panel: Searches all files in a directory tree with the
+//! same options as the search bar, and replaces the matches across them.
+//!
+//! Files are searched in time slices in between frames, so that the results
+//! stream in while the UI stays responsive. Open documents are searched as
+//! they are, including unsaved changes. Replacing changes open documents as a
+//! single undo step each, which the user saves as usual. Other files aren't
+//! opened, but changed and saved right away. Either can be undone per file.
+//! Files that changed since they were searched are marked as outdated and
+//! left alone, because their matches may no longer be where they were.
+
+use std::fs::{self, File};
+use std::io::{Read as _, Seek as _};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use std::{env, mem};
+
+use edit::buffer::{SearchOptions, TextBuffer};
+use edit::framebuffer::{Attributes, IndexedColor};
+use edit::helpers::*;
+use edit::input::vk;
+use edit::tui::*;
+use edit::walk::Walker;
+use edit::{apperr, icu};
+
+use crate::documents::{DiskStamp, Document, DocumentManager};
+use crate::localization::*;
+use crate::state::*;
+
+/// The height of the panel, including its title.
+pub const PANEL_HEIGHT: CoordType = 14;
+/// How long each frame may spend on searching files.
+const TIME_SLICE: Duration = Duration::from_millis(20);
+/// The search stops after this many matches.
+const MAX_MATCHES: usize = 1000;
+/// Larger files are skipped, as are those that look binary.
+const MAX_FILE_SIZE: u64 = 16 * MEBI as u64;
+/// The number of characters shown in front of a match.
+const PREVIEW_CONTEXT: usize = 32;
+
+pub struct FindInFiles {
+    pub kind: StateSearchKind,
+    pub focus: bool,
+    pub dir: String,
+    pub needle: String,
+    pub replacement: String,
+    pub options: SearchOptions,
+    /// False if the needle isn't a valid regex.
+    pub success: bool,
+    pub files: Vec<FileMatches>,
+    /// The selected list item: A file and optionally one of its matches.
+    pub selected: (usize, Option<usize>),
+    search: Option<Search>,
+    /// Whether the search stopped at [`MAX_MATCHES`].
+    truncated: bool,
+}
+
+struct Search {
+    walker: Walker,
+    root: PathBuf,
+    needle: String,
+    options: SearchOptions,
+}
+
+pub struct FileMatches {
+    pub path: PathBuf,
+    /// The path relative to the searched directory.
+    pub name: String,
+    pub matches: Vec<Match>,
+    /// What the matches were found in, to tell whether the file changed since.
+    source: Source,
+    /// Whether the file changed since it was searched. Such files aren't replaced in.
+    pub outdated: bool,
+    pub replaced: Option<Replaced>,
+}
+
+enum Source {
+    /// The open document, as of the given generation of its buffer.
+    Document(u32),
+    /// The file on disk, because it wasn't open.
+    Disk(DiskStamp),
+}
+
+pub struct Replaced {
+    /// The buffer the matches were replaced in, if the file wasn't open.
+    /// Kept to undo the replacement with its history.
+    buffer: Option<TextBuffer>,
+    /// The undo history states before and after replacing the matches.
+    before: usize,
+    after: usize,
+}
+
+pub struct Match {
+    pub beg: Point,
+    pub end: Point,
+    /// The line of the match, split into the text before, of and after the match.
+    pub before: String,
+    pub text: String,
+    pub after: String,
+}
+
+impl Default for FindInFiles {
+    fn default() -> Self {
+        Self {
+            kind: StateSearchKind::Hidden,
+            focus: false,
+            dir: String::new(),
+            needle: String::new(),
+            replacement: String::new(),
+            options: SearchOptions::default(),
+            success: true,
+            files: Vec::new(),
+            selected: (0, None),
+            search: None,
+            truncated: false,
+        }
+    }
+}
+
+impl FindInFiles {
+    pub fn is_visible(&self) -> bool {
+        !matches!(self.kind, StateSearchKind::Hidden | StateSearchKind::Disabled)
+    }
+
+    pub fn is_searching(&self) -> bool {
+        self.search.is_some()
+    }
+
+    /// Discards the results and starts searching for the current needle.
+    pub fn start(&mut self) -> apperr::Result<()> {
+        self.search = None;
+        self.files.clear();
+        self.selected = (0, None);
+        self.truncated = false;
+        self.success = true;
+        if self.needle.is_empty() {
+            return Ok(());
+        }
+
+        icu::init()?;
+        // Invalid patterns would otherwise fail for every single file.
+        if TextBuffer::new(true)?.find_all(&self.needle, self.options).is_err() {
+            self.success = false;
+            return Ok(());
+        }
+
+        let cwd = env::current_dir()?;
+        let root = edit::path::normalize(&cwd.join(self.dir.trim()));
+        self.search = Some(Search {
+            walker: Walker::new(&root),
+            root,
+            needle: self.needle.clone(),
+            options: self.options,
+        });
+        Ok(())
+    }
+
+    /// Marks changed files as outdated and searches files until the time slice
+    /// is used up. To be called once per frame.
+    pub fn update(&mut self, documents: &DocumentManager) {
+        for file in &mut self.files {
+            file.outdated |= file.changed(documents);
+        }
+
+        let Some(search) = &mut self.search else {
+            return;
+        };
+        let beg = Instant::now();
+        let mut count: usize = self.files.iter().map(|f| f.matches.len()).sum();
+
+        while beg.elapsed() < TIME_SLICE {
+            let Some(path) = search.walker.next() else {
+                self.search = None;
+                return;
+            };
+            // Files that can't be read are skipped, like binary ones.
+            let Ok((matches, source)) =
+                search_file(documents, &path, &search.needle, search.options)
+            else {
+                continue;
+            };
+            if matches.is_empty() {
+                continue;
+            }
+
+            count += matches.len();
+            let name = path.strip_prefix(&search.root).unwrap_or(&path).to_string_lossy();
+            let name = name.replace('\\', "/");
+            self.files.push(FileMatches {
+                path,
+                name,
+                matches,
+                source,
+                outdated: false,
+                replaced: None,
+            });
+
+            if count >= MAX_MATCHES {
+                self.search = None;
+                self.truncated = true;
+                return;
+            }
+        }
+    }
+
+    fn status(&self) -> String {
+        let count: usize = self.files.iter().map(|f| f.matches.len()).sum();
+        let mut count = count.to_string();
+        if self.truncated {
+            count.push('+');
+        }
+        let mut status = loc(LocId::FindInFilesStatus)
+            .replace("{matches}", &count)
+            .replace("{files}", &self.files.len().to_string());
+        if self.is_searching() {
+            status.push_str("  ");
+            status.push_str(loc(LocId::FindInFilesSearching));
+        }
+        status
+    }
+}
+
+impl FileMatches {
+    /// Whether the file changed since it was searched or replaced in, going by its document.
+    /// Changes on disk are only checked right before they'd matter, because it's slow.
+    fn changed(&self, documents: &DocumentManager) -> bool {
+        match (&self.source, find_document(documents, &self.path)) {
+            (Source::Document(generation), Some(doc)) => {
+                doc.buffer.borrow().generation() != *generation
+            }
+            // It's unknown whether the changes got saved before it was closed.
+            (Source::Document(_), None) => true,
+            (Source::Disk(_), Some(doc)) => doc.buffer.borrow().is_dirty(),
+            (Source::Disk(_), None) => false,
+        }
+    }
+
+    /// Whether the file on disk is still the one that was searched or written.
+    fn unchanged_on_disk(&self) -> bool {
+        match &self.source {
+            Source::Document(_) => true,
+            Source::Disk(stamp) => DiskStamp::of(&self.path).is_ok_and(|s| s == *stamp),
+        }
+    }
+}
+
+fn find_document<'a>(documents: &'a DocumentManager, path: &Path) -> Option<&'a Document> {
+    documents.iter().find(|doc| doc.path.as_deref() == Some(path))
+}
+
+/// Returns the matches in the given file. If it's open, the document is searched instead.
+fn search_file(
+    documents: &DocumentManager,
+    path: &Path,
+    needle: &str,
+    options: SearchOptions,
+) -> apperr::Result<(Vec<Match>, Source)> {
+    if let Some(doc) = find_document(documents, path) {
+        let tb = doc.buffer.borrow();
+        return Ok((find_matches(&tb, needle, options)?, Source::Document(tb.generation())));
+    }
+
+    // Taken first, so that a change while reading makes the results outdated.
+    let stamp = DiskStamp::of(path)?;
+    if fs::metadata(path)?.len() > MAX_FILE_SIZE {
+        return Ok((Vec::new(), Source::Disk(stamp)));
+    }
+    let mut file = File::open(path)?;
+    let mut head = [0; 1024];
+    let len = file.read(&mut head)?;
+    if head[..len].contains(&0) {
+        return Ok((Vec::new(), Source::Disk(stamp)));
+    }
+    file.rewind()?;
+
+    let mut tb = TextBuffer::new(false)?;
+    tb.read_file(&mut file, None)?;
+    Ok((find_matches(&tb, needle, options)?, Source::Disk(stamp)))
+}
+
+fn find_matches(
+    tb: &TextBuffer,
+    needle: &str,
+    options: SearchOptions,
+) -> apperr::Result<Vec<Match>> {
+    let ranges = tb.find_all(needle, options)?;
+    Ok(ranges
+        .into_iter()
+        .map(|range| {
+            let beg = tb.logical_pos_at(range.start);
+            let end = tb.logical_pos_at(range.end);
+            let line = tb.line(beg.y);
+            let line_start = tb.offset_at(Point { x: 0, y: beg.y });
+            // Matches across multiple lines are shown up to the end of the first one.
+            let start = (range.start - line_start).min(line.len());
+            let end_in_line = (range.end - line_start).min(line.len());
+
+            let before = String::from_utf8_lossy(&line[..start]);
+            let before = before.trim_start();
+            let skip = before.chars().count().saturating_sub(PREVIEW_CONTEXT);
+            let before = match before.char_indices().nth(skip) {
+                Some((idx, _)) if skip > 0 => format!("…{}", &before[idx..]),
+                _ => before.to_string(),
+            };
+
+            Match {
+                beg,
+                end,
+                before,
+                text: String::from_utf8_lossy(&line[start..end_in_line]).into_owned(),
+                after: String::from_utf8_lossy(&line[end_in_line..]).into_owned(),
+            }
+        })
+        .collect())
+}
+
+pub fn draw_find_in_files(ctx: &mut Context, state: &mut State) {
+    enum Action {
+        None,
+        Search,
+        Open(usize, Option<usize>),
+        ReplaceAll,
+        UndoFile,
+    }
+    let mut action = Action::None;
+
+    if let Err(err) = icu::init() {
+        error_log_add(ctx, state, err);
+        state.find_in_files.kind = StateSearchKind::Disabled;
+        return;
+    }
+
+    let fif = &mut state.find_in_files;
+    let replace = fif.kind == StateSearchKind::Replace;
+    let focus = mem::take(&mut fif.focus);
+    if focus {
+        if fif.dir.is_empty() {
+            fif.dir = env::current_dir().map(|d| d.display().to_string()).unwrap_or_default();
+        }
+        if let Some(doc) = state.documents.active()
+            && let Some(selection) = doc.buffer.borrow_mut().extract_user_selection(false)
+        {
+            fif.needle = String::from_utf8_lossy_owned(selection);
+        }
+    }
+
+    ctx.block_begin("find-in-files");
+    ctx.attr_intrinsic_size(Size { width: COORD_TYPE_SAFE_MAX, height: PANEL_HEIGHT });
+    {
+        if ctx.contains_focus() && ctx.consume_shortcut(vk::ESCAPE) {
+            fif.kind = StateSearchKind::Hidden;
+            ctx.needs_rerender();
+        }
+
+        let title = format!(
+            "{}  {}",
+            loc(if replace { LocId::EditReplaceInFiles } else { LocId::EditFindInFiles }),
+            fif.status()
+        );
+        ctx.label("title", &title);
+        ctx.attr_background_rgba(state.menubar_color_bg);
+        ctx.attr_foreground_rgba(state.menubar_color_fg);
+        ctx.attr_padding(Rect::two(0, 1));
+
+        ctx.table_begin("inputs");
+        ctx.table_set_cell_gap(Size { width: 1, height: 0 });
+        ctx.attr_padding(Rect::two(0, 1));
+        {
+            ctx.table_next_row();
+            ctx.label("label", loc(LocId::FindInFilesFolderLabel));
+            ctx.editline("dir", &mut fif.dir);
+            ctx.attr_intrinsic_size(Size { width: COORD_TYPE_SAFE_MAX, height: 1 });
+            if ctx.is_focused() && ctx.consume_shortcut(vk::RETURN) {
+                action = Action::Search;
+            }
+
+            ctx.table_next_row();
+            ctx.label("label", loc(LocId::SearchNeedleLabel));
+            ctx.editline("needle", &mut fif.needle);
+            if !fif.success {
+                ctx.attr_background_rgba(ctx.indexed(IndexedColor::Red));
+                ctx.attr_foreground_rgba(ctx.indexed(IndexedColor::BrightWhite));
+            }
+            ctx.attr_intrinsic_size(Size { width: COORD_TYPE_SAFE_MAX, height: 1 });
+            if focus {
+                ctx.steal_focus();
+            }
+            if ctx.is_focused() && ctx.consume_shortcut(vk::RETURN) {
+                action = Action::Search;
+            }
+
+            if replace {
+                ctx.table_next_row();
+                ctx.label("label", loc(LocId::SearchReplacementLabel));
+                ctx.editline("replacement", &mut fif.replacement);
+                ctx.attr_intrinsic_size(Size { width: COORD_TYPE_SAFE_MAX, height: 1 });
+                if ctx.is_focused() && ctx.consume_shortcut(vk::RETURN) {
+                    action = Action::Search;
+                }
+            }
+        }
+        ctx.table_end();
+
+        ctx.table_begin("options");
+        ctx.table_set_cell_gap(Size { width: 2, height: 0 });
+        ctx.attr_padding(Rect::two(0, 1));
+        {
+            ctx.table_next_row();
+
+            let mut change = false;
+            change |= ctx.checkbox(
+                "match-case",
+                loc(LocId::SearchMatchCase),
+                &mut fif.options.match_case,
+            );
+            change |= ctx.checkbox(
+                "whole-word",
+                loc(LocId::SearchWholeWord),
+                &mut fif.options.whole_word,
+            );
+            change |=
+                ctx.checkbox("use-regex", loc(LocId::SearchUseRegex), &mut fif.options.use_regex);
+            if change {
+                action = Action::Search;
+            }
+
+            if replace {
+                if ctx.button("replace-all", loc(LocId::SearchReplaceAll), ButtonStyle::default()) {
+                    action = Action::ReplaceAll;
+                }
+                if ctx.button("undo-file", loc(LocId::FindInFilesUndoFile), ButtonStyle::default())
+                {
+                    action = Action::UndoFile;
+                }
+            }
+            if ctx.button("close", loc(LocId::SearchClose), ButtonStyle::default()) {
+                fif.kind = StateSearchKind::Hidden;
+                ctx.needs_rerender();
+            }
+        }
+        ctx.table_end();
+
+        let inputs = if replace { 3 } else { 2 };
+        ctx.scrollarea_begin("results", Size { width: 0, height: PANEL_HEIGHT - 2 - inputs });
+        ctx.attr_padding(Rect::two(0, 1));
+        {
+            ctx.list_begin("results");
+            for (file_idx, file) in fif.files.iter().enumerate() {
+                ctx.styled_list_item_begin();
+                ctx.attr_overflow(Overflow::TruncateHead);
+                ctx.styled_label_add_text(&file.name);
+                ctx.styled_label_set_foreground(ctx.indexed(IndexedColor::BrightBlack));
+                ctx.styled_label_add_text(&format!("  ({})", file.matches.len()));
+                if file.replaced.is_some() {
+                    ctx.styled_label_add_text("  ");
+                    ctx.styled_label_set_attributes(Attributes::Italic);
+                    ctx.styled_label_add_text(loc(LocId::FindInFilesReplaced));
+                } else if file.outdated {
+                    ctx.styled_label_add_text("  ");
+                    ctx.styled_label_set_attributes(Attributes::Italic);
+                    ctx.styled_label_add_text(loc(LocId::FindInFilesOutdated));
+                }
+                match ctx.styled_list_item_end(fif.selected == (file_idx, None)) {
+                    ListSelection::Unchanged => {}
+                    ListSelection::Selected => fif.selected = (file_idx, None),
+                    ListSelection::Activated => action = Action::Open(file_idx, None),
+                }
+
+                for (idx, m) in file.matches.iter().enumerate() {
+                    ctx.styled_list_item_begin();
+                    ctx.attr_overflow(Overflow::TruncateTail);
+                    ctx.styled_label_set_foreground(ctx.indexed(IndexedColor::BrightBlack));
+                    ctx.styled_label_add_text(&format!("  {:>5}: ", m.beg.y + 1));
+                    ctx.styled_label_set_foreground(ctx.indexed(IndexedColor::Foreground));
+                    ctx.styled_label_add_text(&m.before);
+                    // In replace mode the match is shown the way it will be replaced.
+                    ctx.styled_label_set_attributes(Attributes::Underlined);
+                    if replace && file.replaced.is_none() && !file.outdated {
+                        ctx.styled_label_set_foreground(ctx.indexed(IndexedColor::Red));
+                        ctx.styled_label_add_text(&m.text);
+                        ctx.styled_label_set_foreground(ctx.indexed(IndexedColor::Green));
+                        ctx.styled_label_add_text(&fif.replacement);
+                        ctx.styled_label_set_foreground(ctx.indexed(IndexedColor::Foreground));
+                    } else {
+                        ctx.styled_label_add_text(&m.text);
+                    }
+                    ctx.styled_label_set_attributes(Attributes::None);
+                    ctx.styled_label_add_text(&m.after);
+
+                    match ctx.styled_list_item_end(fif.selected == (file_idx, Some(idx))) {
+                        ListSelection::Unchanged => {}
+                        ListSelection::Selected => fif.selected = (file_idx, Some(idx)),
+                        ListSelection::Activated => action = Action::Open(file_idx, Some(idx)),
+                    }
+                }
+            }
+            ctx.list_end();
+        }
+        ctx.scrollarea_end();
+    }
+    ctx.block_end();
+
+    match action {
+        Action::None => return,
+        Action::Search => {
+            if let Err(err) = state.find_in_files.start() {
+                error_log_add(ctx, state, err);
+            }
+        }
+        Action::Open(file, idx) => open_match(ctx, state, file, idx),
+        Action::ReplaceAll => replace_all(ctx, state),
+        Action::UndoFile => undo_file(ctx, state, state.find_in_files.selected.0),
+    }
+    ctx.needs_rerender();
+}
+
+/// Opens the file and selects the given match, or the first one.
+fn open_match(ctx: &mut Context, state: &mut State, file: usize, idx: Option<usize>) {
+    let file = &state.find_in_files.files[file];
+    let m = &file.matches[idx.unwrap_or(0)];
+    let (beg, end) = (m.beg, m.end);
+
+    match state.documents.add_file_path(&file.path.clone()) {
+        Ok(doc) => {
+            let mut tb = doc.buffer.borrow_mut();
+            tb.clear_selection();
+            tb.cursor_move_to_logical(beg);
+            tb.start_selection();
+            tb.selection_update_logical(end);
+            state.panes.wants_focus = true;
+        }
+        Err(err) => error_log_add(ctx, state, err),
+    }
+}
+
+/// Replaces all matches in all files that haven't been replaced yet, one undo step per file.
+fn replace_all(ctx: &mut Context, state: &mut State) {
+    let fif = &mut state.find_in_files;
+    let documents = &state.documents;
+    let mut errors = Vec::new();
+
+    for file in &mut fif.files {
+        if file.replaced.is_some() || file.outdated {
+            continue;
+        }
+        if file.changed(documents) || !file.unchanged_on_disk() {
+            file.outdated = true;
+            continue;
+        }
+        let result = match find_document(documents, &file.path) {
+            Some(doc) => replace_in_document(file, doc, &fif.needle, fif.options, &fif.replacement),
+            None => replace_on_disk(file, &fif.needle, fif.options, &fif.replacement),
+        };
+        if let Err(err) = result {
+            errors.push(err);
+        }
+    }
+
+    for err in errors {
+        error_log_add(ctx, state, err);
+    }
+}
+
+fn replace_in_document(
+    file: &mut FileMatches,
+    doc: &Document,
+    needle: &str,
+    options: SearchOptions,
+    replacement: &str,
+) -> apperr::Result<()> {
+    let mut tb = doc.buffer.borrow_mut();
+    let before = replace_matches(&mut tb, needle, options, replacement)?;
+    file.replaced = Some(Replaced { buffer: None, before, after: tb.history_current() });
+    file.source = Source::Document(tb.generation());
+    Ok(())
+}
+
+fn replace_on_disk(
+    file: &mut FileMatches,
+    needle: &str,
+    options: SearchOptions,
+    replacement: &str,
+) -> apperr::Result<()> {
+    let mut tb = TextBuffer::new(false)?;
+    tb.read_file(&mut DocumentManager::open_for_reading(&file.path)?, None)?;
+    let before = replace_matches(&mut tb, needle, options, replacement)?;
+    tb.write_file(&mut DocumentManager::open_for_writing(&file.path)?)?;
+
+    let after = tb.history_current();
+    file.replaced = Some(Replaced { buffer: Some(tb), before, after });
+    file.source = Source::Disk(DiskStamp::of(&file.path)?);
+    Ok(())
+}
+
+/// Replaces all matches as a single undo step and returns the history state from before.
+fn replace_matches(
+    tb: &mut TextBuffer,
+    needle: &str,
+    options: SearchOptions,
+    replacement: &str,
+) -> apperr::Result<usize> {
+    let before = tb.history_current();
+    tb.transaction(|tb| {
+        let ranges = tb.find_all(needle, options)?;
+        // Back to front, so that the ranges stay valid.
+        for range in ranges.into_iter().rev() {
+            tb.replace_range(range, replacement.as_bytes());
+        }
+        Ok(before)
+    })
+}
+
+/// Undoes the replacements in the given file, unless it has been changed since.
+fn undo_file(ctx: &mut Context, state: &mut State, file: usize) {
+    let Some(file) = state.find_in_files.files.get_mut(file) else {
+        return;
+    };
+    if file.replaced.is_none() {
+        return;
+    }
+    if file.changed(&state.documents) || !file.unchanged_on_disk() {
+        file.outdated = true;
+        return;
+    }
+    let Some(replaced) = &mut file.replaced else {
+        return;
+    };
+
+    let result = match &mut replaced.buffer {
+        Some(tb) => {
+            tb.history_goto(replaced.before);
+            DocumentManager::open_for_writing(&file.path)
+                .and_then(|mut f| tb.write_file(&mut f))
+                .and_then(|()| Ok(Source::Disk(DiskStamp::of(&file.path)?)))
+        }
+        None => {
+            let Some(doc) = find_document(&state.documents, &file.path) else {
+                return;
+            };
+            let mut tb = doc.buffer.borrow_mut();
+            if tb.history_current() != replaced.after {
+                return;
+            }
+            tb.history_goto(replaced.before);
+            Ok(Source::Document(tb.generation()))
+        }
+    };
+
+    match result {
+        Ok(source) => {
+            file.source = source;
+            file.replaced = None;
+        }
+        Err(err) => error_log_add(ctx, state, err),
+    }
+}
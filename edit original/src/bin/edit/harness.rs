@@ -0,0 +1,1143 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! An in-process harness for end-to-end tests of the UI.
+//!
+//! It feeds recorded [`Input`] events through [`process_input`], the same path
+//! the terminal input takes, without involving `sys` at all. Afterwards the
+//! resulting [`State`], the document contents and the layout can be inspected.
+
+use std::sync::{Mutex, MutexGuard, Once, PoisonError};
+
+use edit::arena::{self, scratch_arena};
+use edit::helpers::{Point, Size};
+use edit::input::{self, Input, InputKey, InputMouse, InputMouseState, InputText, kbmod};
+use edit::tui::Tui;
+use edit::vt;
+
+use crate::serve::read_range;
+use crate::state::State;
+use crate::{keybindings, localization, process_input, setup_theme};
+
+/// The scratch arenas are global and not thread-safe,
+/// which is why tests that use them must not run concurrently.
+/// Hold onto the returned guard for the duration of the test.
+pub fn lock() -> MutexGuard<'static, ()> {
+    static LOCK: Mutex<()> = Mutex::new(());
+    static INIT: Once = Once::new();
+
+    let guard = LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    INIT.call_once(|| {
+        arena::init(16 * 1024 * 1024).unwrap();
+        localization::init();
+    });
+    guard
+}
+
+pub struct Harness {
+    pub tui: Tui,
+    pub state: State,
+    vt_parser: vt::Parser,
+    input_parser: input::Parser,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl Harness {
+    /// Creates a UI without any documents. Like in the real application, its
+    /// first input must be a [`Harness::resize`]. Set up the [`State`] before that.
+    pub fn new() -> Self {
+        let lock = lock();
+        let mut tui = Tui::new().unwrap();
+        let mut state = State::new().unwrap();
+        setup_theme(&mut tui, &mut state);
+        tui.set_keymap(keybindings::default_keymap());
+
+        Self {
+            tui,
+            state,
+            vt_parser: vt::Parser::new(),
+            input_parser: input::Parser::new(),
+            _lock: lock,
+        }
+    }
+
+    /// Runs the given events as a single batch, just like a single read from stdin.
+    pub fn send<'a>(&mut self, inputs: impl IntoIterator<Item = Input<'a>>) {
+        process_input(&mut self.tui, &mut self.state, inputs);
+    }
+
+    /// Runs the given terminal input (as it would arrive on stdin) through the VT parser.
+    pub fn send_vt(&mut self, text: &str) {
+        let vt_iter = self.vt_parser.parse(text);
+        let input_iter = self.input_parser.parse(vt_iter);
+        process_input(&mut self.tui, &mut self.state, input_iter);
+    }
+
+    pub fn resize(&mut self, size: Size) {
+        self.send([Input::Resize(size)]);
+    }
+
+    pub fn key(&mut self, key: InputKey) {
+        self.send([Input::Keyboard(key)]);
+    }
+
+    pub fn text(&mut self, text: &str) {
+        self.send([Input::Text(InputText { text, bracketed: false })]);
+    }
+
+    /// Presses and releases the left mouse button at the given position.
+    pub fn click(&mut self, position: Point) {
+        let mouse = |state| {
+            Input::Mouse(InputMouse {
+                state,
+                modifiers: kbmod::NONE,
+                position,
+                scroll: Point::default(),
+            })
+        };
+        self.send([mouse(InputMouseState::Left)]);
+        self.send([mouse(InputMouseState::None)]);
+    }
+
+    /// Returns the contents of the last frame as plain text.
+    pub fn screen(&mut self) -> String {
+        self.tui.render_snapshot().to_text()
+    }
+
+    /// Returns the output of [`Tui::debug_layout`] for the last frame.
+    pub fn layout(&mut self) -> String {
+        let scratch = scratch_arena(None);
+        self.tui.debug_layout(&scratch).to_string()
+    }
+
+    /// Returns the contents of the active document, with CRLF normalized to LF.
+    pub fn active_text(&self) -> String {
+        let doc = self.state.documents.active().unwrap();
+        let tb = doc.buffer.borrow();
+        read_range(&tb, 0..tb.text_length()).replace("\r\n", "\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use edit::diff::DiffOp;
+    use edit::framebuffer::{Attributes, Snapshot};
+    use edit::helpers::CoordType;
+    use edit::input::vk;
+
+    use super::*;
+    use crate::localization::{LocId, loc};
+    use crate::panes::Layout;
+    use crate::settings::{ConfigFile, Settings};
+    use crate::state::{DisplayablePathBuf, StateFilePicker, StateSearchKind};
+    use crate::swap::{self, SwapFiles};
+    use crate::{plugins, reload_keybindings, reload_settings};
+
+    const SIZE: Size = Size { width: 80, height: 24 };
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = env::temp_dir().join(format!("edit-harness-{}-{name}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_search_bar() {
+        let mut h = Harness::new();
+        h.state.documents.add_untitled().unwrap();
+        h.resize(SIZE);
+        h.text("foo bar baz");
+
+        h.key(kbmod::CTRL | vk::F);
+        assert!(h.state.wants_search.kind == StateSearchKind::Search);
+        assert!(h.screen().contains(loc(LocId::SearchNeedleLabel)));
+
+        // The search bar has the focus now, so text goes into it and not into the document.
+        h.text("bar");
+        assert_eq!(h.state.search_needle, "bar");
+        assert!(h.state.search_success);
+        assert_eq!(h.active_text().trim_end(), "foo bar baz");
+        {
+            let doc = h.state.documents.active().unwrap();
+            let (beg, end) = doc.buffer.borrow().selection_range().unwrap();
+            assert_eq!(
+                (beg.logical_pos, end.logical_pos),
+                (Point { x: 4, y: 0 }, Point { x: 7, y: 0 })
+            );
+        }
+
+        h.text("x");
+
+        // Clicking behind the first line (below the search bar) moves the focus back to the document.
+        h.click(Point { x: 30, y: 3 });
+        h.text("!");
+        assert_eq!(h.state.search_needle, "barx");
+        assert!(h.active_text().starts_with("foo bar baz!"));
+
+        h.key(kbmod::CTRL | vk::F);
+        h.key(vk::ESCAPE);
+        assert!(h.state.wants_search.kind == StateSearchKind::Hidden);
+        assert!(!h.screen().contains(loc(LocId::SearchNeedleLabel)));
+    }
+
+    #[test]
+    fn test_search_matches() {
+        let mut h = Harness::new();
+        h.state.documents.add_untitled().unwrap();
+        h.resize(SIZE);
+        let text: String =
+            (0..100).map(|i| if [2, 3, 90].contains(&i) { "foo x\n" } else { "bar\n" }).collect();
+        h.text(&text);
+        h.key(kbmod::CTRL | vk::HOME);
+
+        // Each UI pass finds more of the matches. Like the main loop, another frame picks them up.
+        let frame = |h: &mut Harness| {
+            h.tui.render_snapshot();
+            h.send(None);
+            h.tui.render_snapshot()
+        };
+        // Returns the background color at the start of the given line of the document.
+        let bg = |snapshot: &Snapshot, line: &str| {
+            let y = (0..SIZE.height).find(|&y| snapshot.line(y).contains(line)).unwrap();
+            let text = snapshot.line(y);
+            let x = text[..text.find(line).unwrap() + line.find("│ ").unwrap()].chars().count() + 2;
+            snapshot.cell(Point { x: x as CoordType, y }).unwrap().bg
+        };
+
+        h.key(kbmod::CTRL | vk::F);
+        h.text("foo");
+        let snapshot = frame(&mut h);
+        assert!(snapshot.to_text().contains("1 of 3"));
+        let selected = bg(&snapshot, "3 │ foo x");
+        let highlighted = bg(&snapshot, "4 │ foo x");
+        let plain = bg(&snapshot, "5 │ bar");
+        assert_ne!(highlighted, plain);
+        assert_ne!(highlighted, selected);
+        // The match far below the viewport shows up on the scrollbar.
+        assert!(snapshot.to_text().lines().skip(4).any(|l| l.ends_with('━')));
+
+        h.key(vk::RETURN);
+        assert!(frame(&mut h).to_text().contains("2 of 3"));
+
+        // Edits are taken into account.
+        {
+            let mut tb = h.state.documents.active().unwrap().buffer.borrow_mut();
+            tb.clear_selection();
+            tb.write(b"foo", true);
+        }
+        assert!(frame(&mut h).to_text().contains("4 matches"));
+
+        h.key(vk::ESCAPE);
+        let snapshot = frame(&mut h);
+        assert!(h.state.search_match_count.is_none());
+        assert_eq!(bg(&snapshot, "3 │ foo x"), plain);
+        assert!(!snapshot.to_text().contains('━'));
+    }
+
+    #[test]
+    fn test_file_picker() {
+        let dir = temp_dir("picker");
+        fs::write(dir.join("a.txt"), "hello").unwrap();
+
+        let mut h = Harness::new();
+        h.state.file_picker_pending_dir = DisplayablePathBuf::from_path(dir.clone());
+        h.resize(SIZE);
+
+        h.key(kbmod::CTRL | vk::O);
+        assert!(h.state.wants_file_picker == StateFilePicker::Open);
+        let screen = h.screen();
+        assert!(screen.contains(loc(LocId::FileOpen)));
+        assert!(screen.contains("a.txt"));
+        assert!(h.layout().contains("focus_path"));
+
+        h.text("a.txt");
+        h.key(vk::RETURN);
+        assert!(h.state.wants_file_picker == StateFilePicker::None);
+        assert_eq!(h.state.documents.active().unwrap().filename, "a.txt");
+        assert!(h.active_text().starts_with("hello"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_save_and_close() {
+        let dir = temp_dir("close");
+        let path = dir.join("b.txt");
+        fs::write(&path, "").unwrap();
+
+        let mut h = Harness::new();
+        h.state.documents.add_file_path(&path).unwrap();
+        h.resize(SIZE);
+        h.send_vt("abc");
+        assert_eq!(h.active_text(), "abc");
+
+        h.key(kbmod::CTRL | vk::S);
+        assert!(!h.state.wants_save);
+        assert!(!h.state.documents.active().unwrap().buffer.borrow().is_dirty());
+        assert_eq!(fs::read_to_string(&path).unwrap().trim_end(), "abc");
+
+        // Closing a dirty document asks whether to save it first. Cancel, then discard.
+        h.text("d");
+        h.key(kbmod::CTRL | vk::W);
+        assert!(h.state.wants_close);
+        assert!(h.screen().contains(loc(LocId::UnsavedChangesDialogTitle)));
+
+        h.key(vk::ESCAPE);
+        assert!(!h.state.wants_close);
+        assert_eq!(h.state.documents.len(), 1);
+
+        h.key(kbmod::CTRL | vk::W);
+        h.key(vk::N);
+        assert!(!h.state.wants_close);
+        assert_eq!(h.state.documents.len(), 0);
+        assert_eq!(fs::read_to_string(&path).unwrap().trim_end(), "abc");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_multiple_cursors() {
+        let mut h = Harness::new();
+        h.state.documents.add_untitled().unwrap();
+        h.resize(SIZE);
+        h.send_vt("one\rtwo\rsix");
+
+        h.key(kbmod::CTRL | vk::HOME);
+        h.key(kbmod::CTRL_ALT | vk::DOWN);
+        h.key(kbmod::CTRL_ALT | vk::DOWN);
+        h.key(vk::END);
+        h.text("!");
+        assert!(h.active_text().starts_with("one!\ntwo!\nsix!"));
+
+        // A single Ctrl+Z reverts the edits of all cursors and Escape drops them.
+        h.key(kbmod::CTRL | vk::Z);
+        assert!(h.active_text().starts_with("one\ntwo\nsix"));
+        h.key(kbmod::CTRL | vk::D);
+        h.key(vk::ESCAPE);
+        h.text("?");
+        assert!(h.active_text().starts_with("one\ntwo\nsix?"));
+    }
+
+    #[test]
+    fn test_block_selection() {
+        let mut h = Harness::new();
+        h.state.documents.add_untitled().unwrap();
+        h.resize(SIZE);
+        h.send_vt("ab\rcd\r");
+
+        h.key(kbmod::CTRL | vk::HOME);
+        h.key(kbmod::ALT_SHIFT | vk::RIGHT);
+        h.key(kbmod::ALT_SHIFT | vk::DOWN);
+        h.key(kbmod::CTRL | vk::X);
+        assert!(h.active_text().starts_with("b\nd\n"));
+
+        // The cut block gets pasted as a column.
+        h.key(kbmod::CTRL | vk::HOME);
+        h.key(vk::END);
+        h.key(kbmod::CTRL | vk::V);
+        assert!(h.active_text().starts_with("ba\ndc\n"));
+    }
+
+    #[test]
+    fn test_syntax_highlighting() {
+        let dir = temp_dir("syntax");
+        let path = dir.join("main.rs");
+        fs::write(&path, "fn main() {} // hi\n").unwrap();
+
+        let mut h = Harness::new();
+        h.state.documents.add_file_path(&path).unwrap();
+        h.resize(SIZE);
+
+        // Returns the cell at the start of the first occurrence of `needle` on screen.
+        let find = |h: &mut Harness, needle: &str| {
+            let snapshot = h.tui.render_snapshot();
+            let y = (0..SIZE.height).find(|&y| snapshot.line(y).contains(needle)).unwrap();
+            let line = snapshot.line(y);
+            let x = line[..line.find(needle).unwrap()].chars().count() as CoordType;
+            let cell = snapshot.cell(Point { x, y }).unwrap();
+            (cell.fg, cell.attributes)
+        };
+
+        let (keyword, _) = find(&mut h, "fn");
+        let (plain, plain_attr) = find(&mut h, "main");
+        let (comment, comment_attr) = find(&mut h, "// hi");
+        assert_ne!(keyword, plain);
+        assert_ne!(comment, plain);
+        assert_eq!(plain_attr, Attributes::None);
+        assert_eq!(comment_attr, Attributes::Italic);
+
+        // Opening a block comment in front turns the rest of the file into a comment.
+        h.key(kbmod::CTRL | vk::HOME);
+        h.text("/* ");
+        assert_eq!(find(&mut h, "main"), (comment, Attributes::Italic));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_settings_reload() {
+        let dir = temp_dir("settings");
+        let settings = dir.join("settings.json");
+        let path = dir.join("notes.md");
+        fs::write(&path, "hello\n").unwrap();
+        let mut file = ConfigFile::new(settings.clone());
+
+        let mut h = Harness::new();
+        h.state.documents.add_file_path(&path).unwrap();
+        reload_settings(&mut h.state, Some(&mut file));
+        h.resize(SIZE);
+        {
+            let tb = h.state.documents.active().unwrap().buffer.borrow();
+            assert!(!tb.is_word_wrap_enabled());
+            assert!(tb.margin_width() > 0);
+        }
+
+        // Valid settings apply to the open document, while invalid ones are reported.
+        fs::write(
+            &settings,
+            r#"{ "tab_size": 99, "file_types": { "markdown": { "word_wrap": true, "line_numbers": false } } }"#,
+        )
+        .unwrap();
+        reload_settings(&mut h.state, Some(&mut file));
+        h.send(None);
+        {
+            let tb = h.state.documents.active().unwrap().buffer.borrow();
+            assert!(tb.is_word_wrap_enabled());
+            assert_eq!(tb.margin_width(), 0);
+        }
+        assert!(
+            h.screen().contains("settings.json: tab_size: expected an integer between 1 and 8")
+        );
+
+        // Nothing changed, so nothing is reloaded.
+        h.key(vk::RETURN);
+        assert_eq!(h.state.error_log_count, 0);
+        reload_settings(&mut h.state, Some(&mut file));
+        assert_eq!(h.state.error_log_count, 0);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_keybindings() {
+        let dir = temp_dir("keybindings");
+        let path = dir.join("keybindings.json");
+        fs::write(
+            &path,
+            r#"[
+                { "key": "ctrl+k ctrl+x", "command": "edit.cut" },
+                { "key": "ctrl+k z", "command": "edit.undo" },
+                { "key": "ctrl+x", "command": "-edit.cut" }
+            ]"#,
+        )
+        .unwrap();
+        let mut file = ConfigFile::new(path);
+
+        let mut h = Harness::new();
+        h.state.documents.add_untitled().unwrap();
+        reload_keybindings(&mut h.tui, &mut h.state, Some(&mut file));
+        h.resize(SIZE);
+        h.text("hello");
+
+        // The removed default does nothing, while the chord cuts the selection.
+        h.key(kbmod::CTRL | vk::A);
+        h.key(kbmod::CTRL | vk::X);
+        assert!(h.active_text().starts_with("hello"));
+        h.key(kbmod::CTRL | vk::K);
+        assert!(h.screen().lines().last().unwrap().contains("Ctrl+K"));
+        h.key(kbmod::CTRL | vk::X);
+        assert_eq!(h.active_text(), "");
+
+        // Plain text completes a chord instead of being inserted,
+        // and a key that doesn't complete it is swallowed.
+        h.key(kbmod::CTRL | vk::K);
+        h.text("z");
+        assert!(h.active_text().starts_with("hello"));
+        h.key(kbmod::CTRL | vk::K);
+        h.text("q");
+        assert!(h.active_text().starts_with("hello"));
+        assert!(!h.screen().lines().last().unwrap().contains("Ctrl+K"));
+
+        // The menus show the rebound keys.
+        h.key(kbmod::ALT | vk::E);
+        assert!(h.screen().contains("Ctrl+K Ctrl+X"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_command_palette() {
+        let mut h = Harness::new();
+        h.state.documents.add_untitled().unwrap();
+        h.resize(SIZE);
+
+        // Every entry shows its shortcut.
+        h.key(kbmod::CTRL_SHIFT | vk::P);
+        assert!(h.state.wants_command_palette);
+        let screen = h.screen();
+        assert!(screen.contains("Edit: Undo   Ctrl+Z"));
+        assert!(screen.contains("File: Save As…   Ctrl+Shift+S"));
+
+        // Enter runs the best match.
+        h.text("wrap");
+        h.key(vk::RETURN);
+        assert!(!h.state.wants_command_palette);
+        assert!(h.state.documents.active().unwrap().buffer.borrow().is_word_wrap_enabled());
+
+        // Commands without a menu entry work as well.
+        h.key(vk::F1);
+        h.text("overtype");
+        h.key(vk::RETURN);
+        assert!(h.state.documents.active().unwrap().buffer.borrow().is_overtype());
+
+        h.key(vk::F1);
+        h.text("crlf");
+        h.key(vk::RETURN);
+        assert!(h.state.documents.active().unwrap().buffer.borrow().is_crlf());
+
+        h.key(vk::F1);
+        h.text("tab size");
+        h.key(vk::RETURN);
+        assert!(h.state.wants_indentation_picker);
+        assert!(h.screen().contains(loc(LocId::IndentationTabs)));
+
+        // Escape closes it without doing anything.
+        h.key(vk::ESCAPE);
+        h.key(vk::F1);
+        h.text("exit");
+        h.key(vk::ESCAPE);
+        assert!(!h.state.wants_command_palette);
+        assert!(!h.state.wants_exit);
+        assert!(h.state.command_palette_needle.is_empty());
+    }
+
+    #[test]
+    fn test_plugins() {
+        // `cargo test` builds the examples next to the `deps` directory the test runs from.
+        let exe = env::current_exe().unwrap();
+        let name = format!("{}sample_plugin{}", env::consts::DLL_PREFIX, env::consts::DLL_SUFFIX);
+        let plugin = exe.parent().unwrap().parent().unwrap().join("examples").join(name);
+
+        let dir = temp_dir("plugins");
+        let path = dir.join("c.txt");
+        fs::write(&path, "one two").unwrap();
+
+        let mut h = Harness::new();
+        plugins::load(&mut h.state, &plugin).unwrap();
+        // Its command IDs are taken now, so a second instance fails to initialize.
+        assert_eq!(plugins::load(&mut h.state, &plugin), Err("failed to initialize".to_string()));
+
+        let mut errors = Vec::new();
+        let keymap = keybindings::parse(
+            r#"[
+                { "key": "ctrl+k w", "command": "sample.count_words" },
+                { "key": "ctrl+k p", "command": "sample.panel" }
+            ]"#,
+            &mut errors,
+            &h.state.plugins.command_ids(),
+        );
+        assert!(errors.is_empty());
+        h.tui.set_keymap(keymap);
+        h.resize(SIZE);
+
+        // Commands can report errors.
+        h.key(kbmod::CTRL | vk::K);
+        h.text("w");
+        assert!(h.screen().contains("sample_plugin: there's no document"));
+        h.state.error_log_count = 0;
+
+        h.state.documents.add_file_path(&path).unwrap();
+        h.key(kbmod::CTRL | vk::K);
+        h.text("w");
+        h.key(kbmod::CTRL | vk::K);
+        h.text("p");
+        let screen = h.screen();
+        assert!(screen.contains("Words: 2"));
+        assert!(screen.contains("Changes: 0"));
+
+        // Commands show up in the palette and can modify the buffer.
+        h.key(kbmod::CTRL | vk::A);
+        h.key(vk::F1);
+        h.text("uppercase");
+        assert!(h.screen().contains("sample_plugin: Uppercase Selection"));
+        h.key(vk::RETURN);
+        assert_eq!(h.active_text(), "ONE TWO");
+        assert!(h.screen().contains("Changes: 1"));
+
+        h.key(kbmod::CTRL | vk::S);
+        assert!(h.screen().contains(&format!("Saved: {}", path.display())));
+
+        // The panel's widgets work like any other.
+        let screen = h.screen();
+        let (y, line) =
+            screen.lines().enumerate().find(|(_, l)| l.contains("Insert Greeting")).unwrap();
+        let x = line[..line.find("Insert Greeting").unwrap()].chars().count();
+        h.click(Point { x: x as CoordType, y: y as CoordType });
+        // The selection survived the uppercasing, so the greeting replaces it.
+        assert_eq!(h.active_text(), "Hello");
+        let doc = h.state.documents.active().unwrap();
+        assert_eq!(doc.buffer.borrow().cursor_logical_pos(), Point { x: 5, y: 0 });
+
+        // Escape closes the panel while it has the focus.
+        h.key(vk::ESCAPE);
+        assert!(!h.state.plugins.is_panel_visible());
+        assert!(!h.screen().contains("Insert Greeting"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_split_panes() {
+        let mut h = Harness::new();
+        h.state.documents.add_untitled().unwrap();
+        h.resize(SIZE);
+        h.send_vt("aaa\rbbb\rccc");
+        h.key(kbmod::CTRL | vk::HOME);
+
+        h.key(vk::F1);
+        h.text("split right");
+        h.key(vk::RETURN);
+        assert_eq!(h.state.panes.len(), 2);
+
+        // Both panes show the same buffer and the new one has the focus.
+        h.text("X");
+        assert!(h.active_text().starts_with("Xaaa\nbbb\nccc"));
+        assert_eq!(h.screen().lines().nth(1).unwrap().matches("Xaaa").count(), 2);
+
+        // Each pane keeps its own cursor.
+        h.key(vk::F6);
+        h.key(vk::DOWN);
+        h.text("1");
+        h.key(vk::F6);
+        h.text("2");
+        assert!(h.active_text().starts_with("X2aaa\n1bbb\nccc"));
+
+        // Clicking into a pane activates it.
+        h.click(Point { x: 10, y: 3 });
+        h.text("3");
+        assert!(h.active_text().starts_with("X2aaa\n1bbb\nccc3"));
+
+        // The divider can be dragged to resize the panes.
+        let mouse = |state, x| {
+            Input::Mouse(InputMouse {
+                state,
+                modifiers: kbmod::NONE,
+                position: Point { x, y: 5 },
+                scroll: Point::default(),
+            })
+        };
+        h.send([mouse(InputMouseState::Left, 40)]);
+        h.send([mouse(InputMouseState::Left, 50)]);
+        h.send([mouse(InputMouseState::None, 50)]);
+        let screen = h.screen();
+        let line = screen.lines().nth(1).unwrap();
+        // The right pane's text started at column 45 (after the line numbers).
+        assert_eq!(line[..line.rfind("X2aaa").unwrap()].chars().count(), 55);
+
+        h.text("4");
+        assert!(h.active_text().starts_with("X2aaa\n1bbb\nccc34"));
+
+        h.key(vk::F1);
+        h.text("close pane");
+        h.key(vk::RETURN);
+        assert_eq!(h.state.panes.len(), 1);
+        assert_eq!(h.screen().lines().nth(1).unwrap().matches("X2aaa").count(), 1);
+    }
+
+    #[test]
+    fn test_split_pane_views() {
+        let mut h = Harness::new();
+        h.state.documents.add_untitled().unwrap();
+        h.resize(SIZE);
+        let text: String = (1..=100).map(|i| format!("line {i}\n")).collect();
+        h.text(&text);
+        h.key(kbmod::SHIFT | vk::UP);
+        assert!(h.screen().contains("line 100"));
+
+        // Splitting keeps the scroll position of the existing pane.
+        h.key(vk::F1);
+        h.text("split right");
+        h.key(vk::RETURN);
+        assert_eq!(h.screen().matches("line 100").count(), 2);
+
+        // Each pane is drawn with its own selection, whether it's active or not.
+        let bg = |h: &mut Harness| {
+            let snapshot = h.tui.render_snapshot();
+            let y = (0..SIZE.height).find(|&y| snapshot.line(y).contains("line 100")).unwrap();
+            let line = snapshot.line(y);
+            let left = line.find("line 100").unwrap();
+            let right = left + 1 + line[left + 1..].find("line 100").unwrap();
+            let cell = |x: usize| {
+                let x = line[..x].chars().count() as CoordType;
+                snapshot.cell(Point { x, y }).unwrap().bg
+            };
+            (cell(left), cell(right))
+        };
+        h.key(kbmod::CTRL | vk::END);
+        let (left, plain) = bg(&mut h);
+        assert_ne!(left, plain);
+        h.key(vk::F6);
+        let (left, right) = bg(&mut h);
+        assert_ne!(left, plain);
+        assert_eq!(right, plain);
+    }
+
+    #[test]
+    fn test_split_pane_word_wrap() {
+        // Each pane wraps the text to its own width.
+        let mut h = Harness::new();
+        h.state.documents.add_untitled().unwrap();
+        h.resize(SIZE);
+        h.text(&"q".repeat(40));
+        h.state.documents.active().unwrap().buffer.borrow_mut().set_word_wrap(true);
+        h.key(vk::F1);
+        h.text("split right");
+        h.key(vk::RETURN);
+        if let Some(Layout::Split(split)) = &mut h.state.panes.layout {
+            split.ratio = 0.7;
+        }
+        h.send(None);
+
+        for _ in 0..2 {
+            // The left pane fits the line on a single row, and none of it is cut off on the right.
+            let screen = h.screen();
+            assert!(screen.contains(&"q".repeat(40)));
+            assert_eq!(screen.matches('q').count(), 80);
+            h.key(vk::F6);
+        }
+    }
+
+    #[test]
+    fn test_tab_bar() {
+        let mut h = Harness::new();
+        h.state.tab_bar = true;
+        for _ in 0..3 {
+            h.state.documents.add_untitled().unwrap();
+        }
+        h.resize(SIZE);
+
+        let tabs = |h: &mut Harness| h.screen().lines().nth(1).unwrap().to_string();
+        let column = |line: &str, needle: &str| {
+            let x = line[..line.find(needle).unwrap()].chars().count();
+            x as CoordType
+        };
+        let active = |h: &Harness| h.state.documents.active().unwrap().filename.clone();
+
+        // The tabs are in the order the documents were opened, not in the order of last use.
+        let line = tabs(&mut h);
+        assert!(line.starts_with(" Untitled-1.txt ×  Untitled-2.txt ×  Untitled-3.txt ×"));
+        assert_eq!(active(&h), "Untitled-3.txt");
+
+        h.key(kbmod::CTRL | vk::TAB);
+        assert_eq!(active(&h), "Untitled-1.txt");
+        h.key(kbmod::CTRL | vk::PRIOR);
+        assert_eq!(active(&h), "Untitled-3.txt");
+
+        h.text("x");
+        assert!(tabs(&mut h).contains(" Untitled-3.txt* "));
+
+        // Clicking a tab activates its document without taking the focus from the editor.
+        h.click(Point { x: column(&line, "Untitled-2"), y: 1 });
+        assert_eq!(active(&h), "Untitled-2.txt");
+        h.text("y");
+        assert!(h.active_text().starts_with('y'));
+
+        // Tabs can be dragged to another spot.
+        let mouse = |state, x| {
+            Input::Mouse(InputMouse {
+                state,
+                modifiers: kbmod::NONE,
+                position: Point { x, y: 1 },
+                scroll: Point::default(),
+            })
+        };
+        let x = column(&line, "Untitled-1");
+        h.send([mouse(InputMouseState::Left, x)]);
+        h.send([mouse(InputMouseState::Left, x + 18)]);
+        h.send([mouse(InputMouseState::None, x + 18)]);
+        let line = tabs(&mut h);
+        assert!(line.starts_with(" Untitled-2.txt* ×  Untitled-1.txt ×  Untitled-3.txt* ×"));
+
+        // A middle-click closes a tab and the close button does so too,
+        // but asks first if there are unsaved changes.
+        h.send([mouse(InputMouseState::Middle, column(&line, "Untitled-1"))]);
+        h.send([mouse(InputMouseState::None, column(&line, "Untitled-1"))]);
+        assert_eq!(h.state.documents.len(), 2);
+        let line = tabs(&mut h);
+        h.click(Point { x: column(&line, "Untitled-3.txt* ×") + 16, y: 1 });
+        assert!(h.state.wants_close);
+        assert!(h.screen().contains(loc(LocId::UnsavedChangesDialogTitle)));
+        h.key(vk::ESCAPE);
+        assert_eq!(h.state.documents.len(), 2);
+
+        // Tabs that don't fit can be scrolled to and the active one is always shown.
+        for _ in 0..5 {
+            h.state.documents.add_untitled().unwrap();
+        }
+        h.resize(Size { width: 40, height: 10 });
+        let line = tabs(&mut h);
+        assert!(line.starts_with("◀ "));
+        assert!(line.ends_with(" ▶"));
+        assert!(line.contains(" Untitled-8.txt "));
+        h.click(Point { x: 0, y: 1 });
+        h.click(Point { x: 0, y: 1 });
+        let line = tabs(&mut h);
+        assert!(line.contains(" Untitled-5.txt ") && !line.contains("Untitled-8"));
+
+        h.state.tab_bar = false;
+        h.resize(SIZE);
+        assert!(!h.screen().contains("Untitled-4.txt ×"));
+    }
+
+    #[test]
+    fn test_disk_changes() {
+        let dir = temp_dir("disk");
+        let path = dir.join("c.txt");
+        fs::write(&path, "one\ntwo\n").unwrap();
+
+        let mut h = Harness::new();
+        h.state.documents.add_file_path(&path).unwrap();
+        h.resize(SIZE);
+        h.key(vk::DOWN);
+        h.key(vk::RIGHT);
+
+        let cursor =
+            |h: &Harness| h.state.documents.active().unwrap().buffer.borrow().cursor_logical_pos();
+
+        // Regaining the focus checks the files. A modified one without unsaved changes
+        // gets reloaded right away, which keeps the cursor where it was.
+        fs::write(&path, "ONE\nTWO\nTHREE\n").unwrap();
+        h.send_vt("\x1b[I");
+        assert!(h.state.disk_changes.is_empty());
+        assert_eq!(h.active_text(), "ONE\nTWO\nTHREE\n");
+        assert_eq!(cursor(&h), Point { x: 1, y: 1 });
+
+        // With unsaved changes it asks first.
+        h.text("x");
+        fs::write(&path, "ONE\nTWO\nTHREE\nFOUR\n").unwrap();
+        h.send_vt("\x1b[I");
+        assert!(h.screen().contains(loc(LocId::DiskChangeDialogModified)));
+        h.key(vk::R);
+        assert!(h.state.disk_changes.is_empty());
+        assert_eq!(h.active_text(), "ONE\nTWO\nTHREE\nFOUR\n");
+        assert_eq!(cursor(&h), Point { x: 2, y: 1 });
+
+        // Saving asks before it overwrites changes made by another program.
+        fs::write(&path, "other\n").unwrap();
+        h.text("x");
+        h.key(kbmod::CTRL | vk::S);
+        assert!(h.screen().contains(loc(LocId::SaveConflictDialogDescription)));
+        h.key(vk::N);
+        assert!(!h.state.wants_save);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "other\n");
+
+        h.key(kbmod::CTRL | vk::S);
+        h.key(vk::Y);
+        assert!(!h.state.wants_save);
+        assert_eq!(fs::read_to_string(&path).unwrap().trim_end(), "ONE\nTWxO\nTHREE\nFOUR");
+
+        // A deleted file's contents are kept and count as unsaved.
+        fs::remove_file(&path).unwrap();
+        h.send_vt("\x1b[I");
+        assert!(h.screen().contains(loc(LocId::DiskChangeDialogDeleted)));
+        h.key(vk::K);
+        assert!(h.state.disk_changes.is_empty());
+        h.key(kbmod::CTRL | vk::W);
+        assert!(h.screen().contains(loc(LocId::UnsavedChangesDialogTitle)));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_persistent_undo() {
+        let dir = temp_dir("undo");
+        let path = dir.join("u.txt");
+        fs::write(&path, "one\ntwo\n").unwrap();
+
+        let mut h = Harness::new();
+        let settings = Settings::parse(r#"{ "persistent_undo": true }"#, &mut Vec::new());
+        h.state.documents.set_settings(settings);
+        h.state.documents.set_undo_dir(&dir.join("undo"));
+        h.state.documents.add_file_path(&path).unwrap();
+        h.resize(SIZE);
+        h.text("x");
+        h.key(kbmod::CTRL | vk::S);
+        h.key(kbmod::CTRL | vk::W);
+        assert_eq!(h.state.documents.len(), 0);
+
+        // Reopening the saved file brings back its history.
+        h.state.documents.add_file_path(&path).unwrap();
+        h.send(None);
+        h.key(kbmod::CTRL | vk::Z);
+        assert_eq!(h.active_text(), "one\ntwo\n");
+        assert!(h.state.documents.active().unwrap().buffer.borrow().is_dirty());
+        h.key(kbmod::CTRL | vk::Y);
+        assert!(!h.state.documents.active().unwrap().buffer.borrow().is_dirty());
+        h.key(kbmod::CTRL | vk::W);
+
+        // Unless it was changed in the meantime.
+        fs::write(&path, "three\n").unwrap();
+        h.state.documents.add_file_path(&path).unwrap();
+        h.send(None);
+        h.key(kbmod::CTRL | vk::Z);
+        assert_eq!(h.active_text(), "three\n");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_undo_history() {
+        let mut h = Harness::new();
+        h.state.documents.add_untitled().unwrap();
+        h.resize(SIZE);
+
+        // Typing after an undo starts a new branch, which the history lists alongside the old one.
+        h.text("one");
+        h.key(kbmod::CTRL | vk::Z);
+        h.text("two");
+        h.key(vk::F1);
+        h.text("undo history");
+        h.key(vk::RETURN);
+        assert!(h.state.wants_history);
+        let screen = h.screen();
+        assert!(screen.contains(loc(LocId::EditUndoHistory)));
+        assert!(screen.contains(loc(LocId::HistoryDialogInitial)));
+        // The final newline gets inserted along with the text.
+        let two = screen.lines().position(|l| l.contains("•   0s  1:1  +4 -0  two")).unwrap();
+        let one = screen.lines().position(|l| l.contains("    0s  1:1  +4 -0  one")).unwrap();
+        assert_eq!(one, two + 1);
+
+        // Selecting a state previews it, activating it goes there.
+        h.key(vk::DOWN);
+        assert_eq!(h.state.history_preview.as_ref().unwrap().1, [("one".to_string(), true)]);
+        assert_eq!(h.active_text(), "two\n");
+        h.key(vk::RETURN);
+        assert!(!h.state.wants_history);
+        assert_eq!(h.active_text(), "one\n");
+
+        // None of the edits are 10 minutes old yet, so that's the initial state.
+        h.key(vk::F1);
+        h.text("undo history");
+        h.key(vk::RETURN);
+        h.key(vk::M);
+        assert_eq!(h.state.history_selected, 0);
+        h.key(vk::G);
+        assert_eq!(h.active_text(), "");
+
+        // Escape closes it without going anywhere.
+        h.key(vk::F1);
+        h.text("undo history");
+        h.key(vk::RETURN);
+        h.key(vk::DOWN);
+        h.key(vk::ESCAPE);
+        assert!(!h.state.wants_history);
+        assert_eq!(h.active_text(), "");
+        h.key(kbmod::CTRL | vk::Y);
+        assert_eq!(h.active_text(), "one\n");
+    }
+
+    #[test]
+    fn test_swap_files() {
+        let dir = temp_dir("swap");
+        let swap_dir = dir.join("swap");
+        let path = dir.join("d.txt");
+        fs::write(&path, "one\ntwo\nthree\n").unwrap();
+        let swap_files = || -> Vec<std::path::PathBuf> {
+            let entries = fs::read_dir(&swap_dir).into_iter().flatten().flatten();
+            entries
+                .map(|e| e.path())
+                .filter(|p| p.extension().is_some_and(|e| e == "swp"))
+                .collect()
+        };
+        let wait_for = |cond: &dyn Fn() -> bool| {
+            for _ in 0..200 {
+                if cond() {
+                    return;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+            panic!("timed out");
+        };
+
+        let mut h = Harness::new();
+        h.state.documents.add_file_path(&path).unwrap();
+        h.resize(SIZE);
+        let mut swap = SwapFiles::new(swap_dir.clone());
+
+        // Unsaved changes end up in a swap file...
+        h.key(vk::DOWN);
+        h.text("x");
+        swap.update(&h.state.documents);
+        wait_for(&|| swap_files().len() == 1);
+        let own_swap = swap_files().remove(0);
+
+        // ...which is offered for recovery, once its editor isn't running anymore,
+        // even if another process got the same ID since.
+        assert!(swap::find_recoverable(&swap_dir).is_empty());
+        fs::copy(&own_swap, swap_dir.join(format!("{}-0-1.swp", process::id()))).unwrap();
+        // Unfinished writes of dead editors are cleaned up.
+        let tmp = swap_dir.join(format!("{}-0-2.tmp", process::id()));
+        fs::write(&tmp, "").unwrap();
+        let recovery = swap::find_recoverable(&swap_dir);
+        assert_eq!(recovery.len(), 1);
+        assert!(!tmp.exists());
+        assert_eq!((recovery[0].added, recovery[0].removed), (1, 1));
+        assert_eq!(recovery[0].diff[1], Some((DiffOp::Delete, "two".to_string())));
+        assert_eq!(recovery[0].diff[2], Some((DiffOp::Insert, "xtwo".to_string())));
+
+        // Saving removes the swap file.
+        h.key(kbmod::CTRL | vk::S);
+        swap.update(&h.state.documents);
+        wait_for(&|| !own_swap.exists());
+
+        // Recovering reopens the document with the unsaved changes.
+        fs::write(&path, "one\ntwo\nthree\n").unwrap();
+        h.key(kbmod::CTRL | vk::W);
+        assert_eq!(h.state.documents.len(), 0);
+        h.state.recovery = recovery;
+        h.send(None);
+        assert!(h.screen().contains(loc(LocId::RecoveryDialogTitle)));
+        assert!(h.screen().contains("+xtwo"));
+        h.key(vk::R);
+        assert!(h.state.recovery.is_empty());
+        assert_eq!(h.active_text(), "one\nxtwo\nthree\n");
+        assert!(h.state.documents.active().unwrap().buffer.borrow().is_dirty());
+        assert!(swap::find_recoverable(&swap_dir).is_empty());
+
+        // The recovered contents replaced the ones from disk as a single edit.
+        h.key(kbmod::CTRL | vk::Z);
+        assert_eq!(h.active_text(), "one\ntwo\nthree\n");
+
+        // Exiting releases the lock, along with its file.
+        drop(swap);
+        let mut entries = fs::read_dir(&swap_dir).unwrap().flatten();
+        assert!(entries.all(|e| e.path().extension().is_none_or(|e| e != "lock")));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_swap_file_failures() {
+        let dir = temp_dir("swap-fail");
+        // A file where the directory should be makes every write fail.
+        let swap_dir = dir.join("swap");
+        fs::write(&swap_dir, "").unwrap();
+
+        let mut h = Harness::new();
+        h.state.documents.add_untitled().unwrap();
+        h.resize(SIZE);
+        h.text("x");
+        let mut swap = SwapFiles::new(swap_dir);
+        swap.update(&h.state.documents);
+        assert!(!swap.pending(&h.state.documents));
+
+        // The failure is reported back, so that the write gets retried.
+        for _ in 0..200 {
+            swap.update(&h.state.documents);
+            if swap.pending(&h.state.documents) {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert!(swap.pending(&h.state.documents));
+
+        drop(swap);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_find_in_files() {
+        let dir = temp_dir("find");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(dir.join("a.txt"), "one foo\ntwo\n").unwrap();
+        fs::write(dir.join("sub/b.txt"), "foo foo\n").unwrap();
+        fs::write(dir.join("c.log"), "foo\n").unwrap();
+
+        let mut h = Harness::new();
+        h.state.documents.add_untitled().unwrap();
+        h.resize(SIZE);
+        h.state.find_in_files.dir = dir.display().to_string();
+
+        let search = |h: &mut Harness| {
+            while h.state.find_in_files.is_searching() {
+                h.state.find_in_files.update(&h.state.documents);
+            }
+            h.send(None);
+        };
+        let click = |h: &mut Harness, needle: &str| {
+            let screen = h.screen();
+            let (y, line) = screen.lines().enumerate().find(|(_, l)| l.contains(needle)).unwrap();
+            let x = line[..line.find(needle).unwrap()].chars().count();
+            h.click(Point { x: x as CoordType, y: y as CoordType });
+        };
+
+        // Ignored files aren't searched.
+        h.key(kbmod::CTRL_SHIFT | vk::F);
+        assert!(h.state.find_in_files.is_visible());
+        h.text("foo");
+        h.key(vk::RETURN);
+        search(&mut h);
+        let fif = &h.state.find_in_files;
+        let names: Vec<_> = fif.files.iter().map(|f| (f.name.as_str(), f.matches.len())).collect();
+        assert_eq!(names, [("a.txt", 1), ("sub/b.txt", 2)]);
+        let screen = h.screen();
+        assert!(screen.contains("3 matches in 2 files"));
+        assert!(screen.contains("    1: foo foo"));
+
+        // Activating a match opens the file with the match selected.
+        click(&mut h, "1: one foo");
+        h.key(vk::RETURN);
+        assert_eq!(h.state.documents.active().unwrap().filename, "a.txt");
+        {
+            let doc = h.state.documents.active().unwrap();
+            let (beg, end) = doc.buffer.borrow().selection_range().unwrap();
+            assert_eq!(
+                (beg.logical_pos, end.logical_pos),
+                (Point { x: 4, y: 0 }, Point { x: 7, y: 0 })
+            );
+        }
+
+        // Replacing changes open documents and saves the other files without opening them.
+        h.key(kbmod::CTRL_SHIFT | vk::R);
+        assert!(h.state.find_in_files.kind == StateSearchKind::Replace);
+        h.state.find_in_files.replacement = "bar".to_string();
+        click(&mut h, loc(LocId::SearchReplaceAll));
+        assert!(h.state.find_in_files.files.iter().all(|f| f.replaced.is_some()));
+        assert!(h.screen().contains(loc(LocId::FindInFilesReplaced)));
+        assert_eq!(h.state.documents.len(), 1);
+        assert_eq!(h.state.documents.active().unwrap().filename, "a.txt");
+        assert_eq!(h.active_text(), "one bar\ntwo\n");
+        assert_eq!(fs::read_to_string(dir.join("a.txt")).unwrap(), "one foo\ntwo\n");
+        assert_eq!(fs::read_to_string(dir.join("sub/b.txt")).unwrap(), "bar bar\n");
+
+        // Each file can be undone on its own.
+        click(&mut h, "sub/b.txt");
+        click(&mut h, loc(LocId::FindInFilesUndoFile));
+        assert!(h.state.find_in_files.files[1].replaced.is_none());
+        assert_eq!(fs::read_to_string(dir.join("sub/b.txt")).unwrap(), "foo foo\n");
+
+        // Files that changed since they were searched are outdated and left alone.
+        h.state.find_in_files.start().unwrap();
+        search(&mut h);
+        assert_eq!(h.state.find_in_files.files.len(), 1);
+        fs::write(dir.join("sub/b.txt"), "foo\n").unwrap();
+        click(&mut h, loc(LocId::SearchReplaceAll));
+        assert!(h.state.find_in_files.files[0].outdated);
+        assert!(h.state.find_in_files.files[0].replaced.is_none());
+        assert!(h.screen().contains(loc(LocId::FindInFilesOutdated)));
+        assert_eq!(fs::read_to_string(dir.join("sub/b.txt")).unwrap(), "foo\n");
+
+        h.state.find_in_files.needle = "bar".to_string();
+        h.state.find_in_files.start().unwrap();
+        search(&mut h);
+        assert!(!h.state.find_in_files.files[0].outdated);
+        h.state.documents.active().unwrap().buffer.borrow_mut().write(b"x", false);
+        h.state.find_in_files.update(&h.state.documents);
+        assert!(h.state.find_in_files.files[0].outdated);
+
+        h.key(vk::ESCAPE);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,236 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The user keybindings file.
+//!
+//! It's a JSON array of bindings, which are applied on top of the defaults in order.
+//! A binding replaces any existing one for the same keys. Prefixing the command ID with
+//! a `-` removes the binding instead. Binding two commands to the same (or overlapping)
+//! keys within the file is reported as a conflict and the latter one wins:
+//! ```json
+//! [
+//!     { "key": "ctrl+k ctrl+c", "command": "edit.copy" },
+//!     { "key": "ctrl+k ctrl+x", "command": "edit.cut" },
+//!     { "key": "ctrl+y", "command": "-edit.redo" }
+//! ]
+//! ```
+
+use edit::input::{InputKey, kbmod, vk};
+use edit::json::{self, Value};
+use edit::keymap::{self, Keymap};
+
+/// The IDs of the commands implemented by the application, as opposed to the textarea.
+pub mod cmd {
+    pub const FILE_NEW: &str = "file.new";
+    pub const FILE_OPEN: &str = "file.open";
+    pub const FILE_SAVE: &str = "file.save";
+    pub const FILE_SAVE_AS: &str = "file.save_as";
+    pub const FILE_CLOSE: &str = "file.close";
+    pub const FILE_REOPEN_WITH_ENCODING: &str = "file.reopen_with_encoding";
+    pub const FILE_EXIT: &str = "file.exit";
+    pub const EDIT_FIND: &str = "edit.find";
+    pub const EDIT_REPLACE: &str = "edit.replace";
+    pub const EDIT_FIND_IN_FILES: &str = "edit.find_in_files";
+    pub const EDIT_REPLACE_IN_FILES: &str = "edit.replace_in_files";
+    pub const EDIT_CONVERT_TO_LF: &str = "edit.convert_to_lf";
+    pub const EDIT_CONVERT_TO_CRLF: &str = "edit.convert_to_crlf";
+    pub const EDIT_CHANGE_TAB_SIZE: &str = "edit.change_tab_size";
+    pub const EDIT_UNDO_HISTORY: &str = "edit.undo_history";
+    pub const VIEW_FOCUS_MENUBAR: &str = "view.focus_menubar";
+    pub const VIEW_FOCUS_STATUSBAR: &str = "view.focus_statusbar";
+    pub const VIEW_DOCUMENT_PICKER: &str = "view.document_picker";
+    pub const VIEW_GOTO: &str = "view.goto";
+    pub const VIEW_COMMAND_PALETTE: &str = "view.command_palette";
+    pub const VIEW_SPLIT_RIGHT: &str = "view.split_right";
+    pub const VIEW_SPLIT_DOWN: &str = "view.split_down";
+    pub const VIEW_CLOSE_PANE: &str = "view.close_pane";
+    pub const VIEW_FOCUS_NEXT_PANE: &str = "view.focus_next_pane";
+    pub const VIEW_FOCUS_PREVIOUS_PANE: &str = "view.focus_previous_pane";
+    pub const VIEW_NEXT_TAB: &str = "view.next_tab";
+    pub const VIEW_PREVIOUS_TAB: &str = "view.previous_tab";
+    pub const HELP_ABOUT: &str = "help.about";
+}
+
+/// All commands in [`cmd`].
+pub const APP_COMMANDS: &[&str] = &[
+    cmd::FILE_NEW,
+    cmd::FILE_OPEN,
+    cmd::FILE_SAVE,
+    cmd::FILE_SAVE_AS,
+    cmd::FILE_CLOSE,
+    cmd::FILE_REOPEN_WITH_ENCODING,
+    cmd::FILE_EXIT,
+    cmd::EDIT_FIND,
+    cmd::EDIT_REPLACE,
+    cmd::EDIT_FIND_IN_FILES,
+    cmd::EDIT_REPLACE_IN_FILES,
+    cmd::EDIT_CONVERT_TO_LF,
+    cmd::EDIT_CONVERT_TO_CRLF,
+    cmd::EDIT_CHANGE_TAB_SIZE,
+    cmd::EDIT_UNDO_HISTORY,
+    cmd::VIEW_FOCUS_MENUBAR,
+    cmd::VIEW_FOCUS_STATUSBAR,
+    cmd::VIEW_DOCUMENT_PICKER,
+    cmd::VIEW_GOTO,
+    cmd::VIEW_COMMAND_PALETTE,
+    cmd::VIEW_SPLIT_RIGHT,
+    cmd::VIEW_SPLIT_DOWN,
+    cmd::VIEW_CLOSE_PANE,
+    cmd::VIEW_FOCUS_NEXT_PANE,
+    cmd::VIEW_FOCUS_PREVIOUS_PANE,
+    cmd::VIEW_NEXT_TAB,
+    cmd::VIEW_PREVIOUS_TAB,
+    cmd::HELP_ABOUT,
+];
+
+/// Returns the textarea's default keymap, extended with the application's commands.
+pub fn default_keymap() -> Keymap {
+    let mut keymap = Keymap::new();
+    keymap.register(APP_COMMANDS);
+
+    let defaults = [
+        (cmd::FILE_NEW, kbmod::CTRL | vk::N),
+        (cmd::FILE_OPEN, kbmod::CTRL | vk::O),
+        (cmd::FILE_SAVE, kbmod::CTRL | vk::S),
+        (cmd::FILE_SAVE_AS, kbmod::CTRL_SHIFT | vk::S),
+        (cmd::FILE_CLOSE, kbmod::CTRL | vk::W),
+        (cmd::FILE_EXIT, kbmod::CTRL | vk::Q),
+        (cmd::EDIT_FIND, kbmod::CTRL | vk::F),
+        (cmd::EDIT_REPLACE, kbmod::CTRL | vk::R),
+        (cmd::EDIT_FIND_IN_FILES, kbmod::CTRL_SHIFT | vk::F),
+        (cmd::EDIT_REPLACE_IN_FILES, kbmod::CTRL_SHIFT | vk::R),
+        (cmd::VIEW_FOCUS_MENUBAR, vk::F10),
+        (cmd::VIEW_DOCUMENT_PICKER, kbmod::CTRL | vk::P),
+        (cmd::VIEW_GOTO, kbmod::CTRL | vk::G),
+        // F1 is for terminals that can't tell Ctrl+Shift+P and Ctrl+P apart.
+        (cmd::VIEW_COMMAND_PALETTE, vk::F1),
+        (cmd::VIEW_COMMAND_PALETTE, kbmod::CTRL_SHIFT | vk::P),
+        (cmd::VIEW_FOCUS_NEXT_PANE, vk::F6),
+        (cmd::VIEW_FOCUS_PREVIOUS_PANE, kbmod::SHIFT | vk::F6),
+        (cmd::VIEW_NEXT_TAB, kbmod::CTRL | vk::TAB),
+        (cmd::VIEW_NEXT_TAB, kbmod::CTRL | vk::NEXT),
+        (cmd::VIEW_PREVIOUS_TAB, kbmod::CTRL_SHIFT | vk::TAB),
+        (cmd::VIEW_PREVIOUS_TAB, kbmod::CTRL | vk::PRIOR),
+    ];
+    for (command, key) in defaults {
+        let removed = keymap.bind(&[key], command);
+        debug_assert!(removed.is_empty(), "{command} conflicts with another default");
+    }
+
+    keymap
+}
+
+/// Parses the contents of a keybindings file. Problems are reported in `errors`,
+/// but don't prevent the remaining, valid bindings from being used.
+/// The `extra_commands` (e.g. from plugins) can be bound in addition to the built-in ones.
+pub fn parse(text: &str, errors: &mut Vec<String>, extra_commands: &[&'static str]) -> Keymap {
+    let mut keymap = default_keymap();
+    keymap.register(extra_commands);
+    if text.trim_ascii().is_empty() {
+        return keymap;
+    }
+
+    let Some(root) = json::parse(text) else {
+        errors.push("not a valid JSON file".to_string());
+        return keymap;
+    };
+    let Some(entries) = root.as_array() else {
+        errors.push("expected an array".to_string());
+        return keymap;
+    };
+
+    // The bindings from this file so far, to tell conflicts apart from overridden defaults.
+    let mut user: Vec<(Vec<InputKey>, &str)> = Vec::new();
+
+    for (i, entry) in entries.iter().enumerate() {
+        let (Some(key), Some(command)) = (
+            entry.get("key").and_then(Value::as_str),
+            entry.get("command").and_then(Value::as_str),
+        ) else {
+            errors.push(format!("entry {}: expected a \"key\" and a \"command\"", i + 1));
+            continue;
+        };
+        let Some(keys) = keymap::parse_keys(key) else {
+            errors.push(format!("{key}: invalid key"));
+            continue;
+        };
+        let (remove, id) = match command.strip_prefix('-') {
+            Some(id) => (true, id),
+            None => (false, command),
+        };
+        let Some(id) = keymap.command(id) else {
+            errors.push(format!("{key}: unknown command {id}"));
+            continue;
+        };
+
+        if remove {
+            keymap.unbind(Some(&keys), id);
+            continue;
+        }
+
+        for removed in keymap.bind(&keys, id) {
+            if let Some(pos) = user.iter().position(|(k, _)| *k == removed.keys) {
+                let (_, other) = user.remove(pos);
+                errors.push(format!("{key}: conflicts with {other}"));
+            }
+        }
+        user.push((keys, key));
+    }
+
+    keymap
+}
+
+#[cfg(test)]
+mod tests {
+    use edit::keymap::{Resolution, cmd as textarea};
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let mut errors = Vec::new();
+        let mut keymap = parse(
+            r#"[
+                { "key": "ctrl+k ctrl+c", "command": "edit.copy" },
+                { "key": "ctrl+k ctrl+x", "command": "edit.cut" },
+                { "key": "ctrl+s", "command": "file.save_as" },
+                { "key": "ctrl+y", "command": "-edit.redo" },
+                { "key": "ctrl+k", "command": "file.exit" },
+                { "key": "ctrl+e", "command": "edit.frobnicate" },
+                { "key": "ctrl+hyper", "command": "edit.undo" },
+                { "command": "edit.undo" }
+            ]"#,
+            &mut errors,
+            &[],
+        );
+
+        assert_eq!(
+            errors,
+            [
+                "ctrl+k: conflicts with ctrl+k ctrl+c",
+                "ctrl+k: conflicts with ctrl+k ctrl+x",
+                "ctrl+e: unknown command edit.frobnicate",
+                "ctrl+hyper: invalid key",
+                "entry 8: expected a \"key\" and a \"command\"",
+            ]
+        );
+
+        // User bindings silently override the defaults.
+        assert_eq!(keymap.resolve(kbmod::CTRL | vk::S), Resolution::Command(cmd::FILE_SAVE_AS));
+        assert_eq!(keymap.keys_for(cmd::FILE_SAVE), None);
+        assert_eq!(keymap.resolve(kbmod::CTRL | vk::Y), Resolution::Unbound);
+        assert_eq!(keymap.keys_for(textarea::EDIT_REDO), Some(&[kbmod::CTRL_SHIFT | vk::Z][..]));
+        assert_eq!(keymap.resolve(kbmod::CTRL | vk::K), Resolution::Command(cmd::FILE_EXIT));
+    }
+
+    #[test]
+    fn test_invalid_json() {
+        let mut errors = Vec::new();
+        parse("[ { \"key\": ", &mut errors, &[]);
+        assert_eq!(errors, ["not a valid JSON file"]);
+
+        errors.clear();
+        parse("{}", &mut errors, &[]);
+        assert_eq!(errors, ["expected an array"]);
+    }
+}
@@ -43,11 +43,25 @@ pub enum LocId {
     ViewFocusStatusbar,
     ViewWordWrap,
     ViewDocumentPicker,
+    ViewCommandPalette,
+    ViewSplitRight,
+    ViewSplitDown,
+    ViewClosePane,
 
     // Help menu
     Help,
     HelpAbout,
 
+    // Commands without a menu entry, listed in the command palette
+    CommandToggleOvertype,
+    CommandConvertToLf,
+    CommandConvertToCrlf,
+    CommandChangeTabSize,
+    CommandFocusNextPane,
+    CommandFocusPreviousPane,
+    CommandNextTab,
+    CommandPreviousTab,
+
     // Exit dialog
     UnsavedChangesDialogTitle,
     UnsavedChangesDialogDescription,
@@ -91,6 +105,38 @@ pub enum LocId {
     FileOverwriteWarning,
     FileOverwriteWarningDescription,
 
+    DiskChangeDialogTitle,
+    DiskChangeDialogModified,
+    DiskChangeDialogDeleted,
+    DiskChangeDialogReload,
+    DiskChangeDialogKeep,
+    SaveConflictDialogDescription,
+
+    RecoveryDialogTitle,
+    RecoveryDialogDescription,
+    RecoveryDialogRecover,
+    RecoveryDialogDiscard,
+    RecoveryDialogLater,
+
+    EditUndoHistory,
+    HistoryDialogInitial,
+    HistoryDialogSaved,
+    HistoryDialogGoto,
+    HistoryDialogLastSave,
+    HistoryDialogTenMinutesAgo,
+
+    EditFindInFiles,
+    EditReplaceInFiles,
+    FindInFilesFolderLabel,
+    FindInFilesStatus,
+    FindInFilesSearching,
+    FindInFilesUndoFile,
+    FindInFilesReplaced,
+    FindInFilesOutdated,
+
+    SearchMatchIndex,
+    SearchMatchCount,
+
     Count,
 }
 
@@ -116,7 +162,7 @@ enum LangId {
 }
 
 #[rustfmt::skip]
-const S_LANG_LUT: [[&str; LangId::Count as usize]; LocId::Count as usize] = [
+static S_LANG_LUT: [[&str; LangId::Count as usize]; LocId::Count as usize] = [
     // Ctrl (the keyboard key)
     [
         /* en      */ "Ctrl",
@@ -527,6 +573,62 @@ const S_LANG_LUT: [[&str; LangId::Count as usize]; LocId::Count as usize] = [
         /* zh_hans */ "文档选择器…",
         /* zh_hant */ "文件選擇器…",
     ],
+    // ViewCommandPalette
+    [
+        /* en      */ "Command Palette…",
+        /* de      */ "Befehlspalette…",
+        /* es      */ "Paleta de comandos…",
+        /* fr      */ "Palette de commandes…",
+        /* it      */ "Tavolozza comandi…",
+        /* ja      */ "コマンド パレット…",
+        /* ko      */ "명령 팔레트…",
+        /* pt_br   */ "Paleta de comandos…",
+        /* ru      */ "Палитра команд…",
+        /* zh_hans */ "命令面板…",
+        /* zh_hant */ "命令選擇區…",
+    ],
+    // ViewSplitRight
+    [
+        /* en      */ "Split Right",
+        /* de      */ "Rechts teilen",
+        /* es      */ "Dividir a la derecha",
+        /* fr      */ "Fractionner à droite",
+        /* it      */ "Dividi a destra",
+        /* ja      */ "右に分割",
+        /* ko      */ "오른쪽으로 분할",
+        /* pt_br   */ "Dividir à direita",
+        /* ru      */ "Разделить вправо",
+        /* zh_hans */ "向右拆分",
+        /* zh_hant */ "向右分割",
+    ],
+    // ViewSplitDown
+    [
+        /* en      */ "Split Down",
+        /* de      */ "Unten teilen",
+        /* es      */ "Dividir hacia abajo",
+        /* fr      */ "Fractionner en bas",
+        /* it      */ "Dividi in basso",
+        /* ja      */ "下に分割",
+        /* ko      */ "아래로 분할",
+        /* pt_br   */ "Dividir para baixo",
+        /* ru      */ "Разделить вниз",
+        /* zh_hans */ "向下拆分",
+        /* zh_hant */ "向下分割",
+    ],
+    // ViewClosePane
+    [
+        /* en      */ "Close Pane",
+        /* de      */ "Bereich schließen",
+        /* es      */ "Cerrar panel",
+        /* fr      */ "Fermer le volet",
+        /* it      */ "Chiudi riquadro",
+        /* ja      */ "ペインを閉じる",
+        /* ko      */ "창 닫기",
+        /* pt_br   */ "Fechar painel",
+        /* ru      */ "Закрыть панель",
+        /* zh_hans */ "关闭窗格",
+        /* zh_hant */ "關閉窗格",
+    ],
 
     // Help (a menu bar item)
     [
@@ -557,6 +659,121 @@ const S_LANG_LUT: [[&str; LangId::Count as usize]; LocId::Count as usize] = [
         /* zh_hant */ "關於",
     ],
 
+    // CommandToggleOvertype
+    [
+        /* en      */ "Toggle Overtype",
+        /* de      */ "Überschreibmodus umschalten",
+        /* es      */ "Alternar modo de sobrescritura",
+        /* fr      */ "Basculer le mode refrappe",
+        /* it      */ "Attiva/disattiva sovrascrittura",
+        /* ja      */ "上書きモードの切り替え",
+        /* ko      */ "겹쳐쓰기 모드 전환",
+        /* pt_br   */ "Alternar modo de sobrescrita",
+        /* ru      */ "Переключить режим замены",
+        /* zh_hans */ "切换改写模式",
+        /* zh_hant */ "切換覆寫模式",
+    ],
+    // CommandConvertToLf
+    [
+        /* en      */ "Convert Line Endings to LF",
+        /* de      */ "Zeilenenden in LF konvertieren",
+        /* es      */ "Convertir finales de línea a LF",
+        /* fr      */ "Convertir les fins de ligne en LF",
+        /* it      */ "Converti fine riga in LF",
+        /* ja      */ "改行コードを LF に変換",
+        /* ko      */ "줄 끝을 LF로 변환",
+        /* pt_br   */ "Converter finais de linha para LF",
+        /* ru      */ "Преобразовать концы строк в LF",
+        /* zh_hans */ "将行尾转换为 LF",
+        /* zh_hant */ "將行尾轉換為 LF",
+    ],
+    // CommandConvertToCrlf
+    [
+        /* en      */ "Convert Line Endings to CRLF",
+        /* de      */ "Zeilenenden in CRLF konvertieren",
+        /* es      */ "Convertir finales de línea a CRLF",
+        /* fr      */ "Convertir les fins de ligne en CRLF",
+        /* it      */ "Converti fine riga in CRLF",
+        /* ja      */ "改行コードを CRLF に変換",
+        /* ko      */ "줄 끝을 CRLF로 변환",
+        /* pt_br   */ "Converter finais de linha para CRLF",
+        /* ru      */ "Преобразовать концы строк в CRLF",
+        /* zh_hans */ "将行尾转换为 CRLF",
+        /* zh_hant */ "將行尾轉換為 CRLF",
+    ],
+    // CommandChangeTabSize
+    [
+        /* en      */ "Change Tab Size…",
+        /* de      */ "Tabulatorbreite ändern…",
+        /* es      */ "Cambiar tamaño de tabulación…",
+        /* fr      */ "Modifier la taille des tabulations…",
+        /* it      */ "Cambia dimensione tabulazione…",
+        /* ja      */ "タブのサイズを変更…",
+        /* ko      */ "탭 크기 변경…",
+        /* pt_br   */ "Alterar tamanho da tabulação…",
+        /* ru      */ "Изменить размер табуляции…",
+        /* zh_hans */ "更改制表符大小…",
+        /* zh_hant */ "變更定位字元大小…",
+    ],
+    // CommandFocusNextPane
+    [
+        /* en      */ "Focus Next Pane",
+        /* de      */ "Nächsten Bereich fokussieren",
+        /* es      */ "Enfocar el panel siguiente",
+        /* fr      */ "Activer le volet suivant",
+        /* it      */ "Attiva riquadro successivo",
+        /* ja      */ "次のペインにフォーカス",
+        /* ko      */ "다음 창에 포커스",
+        /* pt_br   */ "Focar no próximo painel",
+        /* ru      */ "Перейти к следующей панели",
+        /* zh_hans */ "聚焦到下一个窗格",
+        /* zh_hant */ "聚焦至下一個窗格",
+    ],
+    // CommandFocusPreviousPane
+    [
+        /* en      */ "Focus Previous Pane",
+        /* de      */ "Vorherigen Bereich fokussieren",
+        /* es      */ "Enfocar el panel anterior",
+        /* fr      */ "Activer le volet précédent",
+        /* it      */ "Attiva riquadro precedente",
+        /* ja      */ "前のペインにフォーカス",
+        /* ko      */ "이전 창에 포커스",
+        /* pt_br   */ "Focar no painel anterior",
+        /* ru      */ "Перейти к предыдущей панели",
+        /* zh_hans */ "聚焦到上一个窗格",
+        /* zh_hant */ "聚焦至上一個窗格",
+    ],
+
+    // CommandNextTab
+    [
+        /* en      */ "Next Tab",
+        /* de      */ "Nächster Tab",
+        /* es      */ "Pestaña siguiente",
+        /* fr      */ "Onglet suivant",
+        /* it      */ "Scheda successiva",
+        /* ja      */ "次のタブ",
+        /* ko      */ "다음 탭",
+        /* pt_br   */ "Próxima guia",
+        /* ru      */ "Следующая вкладка",
+        /* zh_hans */ "下一个选项卡",
+        /* zh_hant */ "下一個索引標籤",
+    ],
+
+    // CommandPreviousTab
+    [
+        /* en      */ "Previous Tab",
+        /* de      */ "Vorheriger Tab",
+        /* es      */ "Pestaña anterior",
+        /* fr      */ "Onglet précédent",
+        /* it      */ "Scheda precedente",
+        /* ja      */ "前のタブ",
+        /* ko      */ "이전 탭",
+        /* pt_br   */ "Guia anterior",
+        /* ru      */ "Предыдущая вкладка",
+        /* zh_hans */ "上一个选项卡",
+        /* zh_hant */ "上一個索引標籤",
+    ],
+
     // UnsavedChangesDialogTitle
     [
         /* en      */ "Unsaved Changes",
@@ -959,6 +1176,384 @@ const S_LANG_LUT: [[&str; LangId::Count as usize]; LocId::Count as usize] = [
         /* zh_hans */ "文件已存在。要覆盖它吗？",
         /* zh_hant */ "檔案已存在。要覆蓋它嗎？",
     ],
+    // DiskChangeDialogTitle
+    [
+        /* en      */ "File Changed on Disk",
+        /* de      */ "Datei auf dem Datenträger geändert",
+        /* es      */ "Archivo cambiado en el disco",
+        /* fr      */ "Fichier modifié sur le disque",
+        /* it      */ "File modificato sul disco",
+        /* ja      */ "ディスク上のファイルが変更されました",
+        /* ko      */ "디스크의 파일이 변경됨",
+        /* pt_br   */ "Arquivo alterado no disco",
+        /* ru      */ "Файл изменён на диске",
+        /* zh_hans */ "磁盘上的文件已更改",
+        /* zh_hant */ "磁碟上的檔案已變更",
+    ],
+    // DiskChangeDialogModified
+    [
+        /* en      */ "Another program changed the file. Reload it?",
+        /* de      */ "Ein anderes Programm hat die Datei geändert. Neu laden?",
+        /* es      */ "Otro programa cambió el archivo. ¿Desea volver a cargarlo?",
+        /* fr      */ "Un autre programme a modifié le fichier. Le recharger ?",
+        /* it      */ "Un altro programma ha modificato il file. Ricaricarlo?",
+        /* ja      */ "別のプログラムがファイルを変更しました。再読み込みしますか？",
+        /* ko      */ "다른 프로그램이 파일을 변경했습니다. 다시 로드하시겠습니까?",
+        /* pt_br   */ "Outro programa alterou o arquivo. Deseja recarregá-lo?",
+        /* ru      */ "Другая программа изменила файл. Загрузить его заново?",
+        /* zh_hans */ "另一个程序更改了该文件。要重新加载吗？",
+        /* zh_hant */ "另一個程式變更了該檔案。要重新載入嗎？",
+    ],
+    // DiskChangeDialogDeleted
+    [
+        /* en      */ "Another program deleted the file. Its contents are kept in the editor.",
+        /* de      */ "Ein anderes Programm hat die Datei gelöscht. Ihr Inhalt bleibt im Editor erhalten.",
+        /* es      */ "Otro programa eliminó el archivo. Su contenido se conserva en el editor.",
+        /* fr      */ "Un autre programme a supprimé le fichier. Son contenu est conservé dans l’éditeur.",
+        /* it      */ "Un altro programma ha eliminato il file. Il contenuto resta nell’editor.",
+        /* ja      */ "別のプログラムがファイルを削除しました。内容はエディターに保持されます。",
+        /* ko      */ "다른 프로그램이 파일을 삭제했습니다. 내용은 편집기에 유지됩니다.",
+        /* pt_br   */ "Outro programa excluiu o arquivo. O conteúdo é mantido no editor.",
+        /* ru      */ "Другая программа удалила файл. Его содержимое сохранено в редакторе.",
+        /* zh_hans */ "另一个程序删除了该文件。其内容保留在编辑器中。",
+        /* zh_hant */ "另一個程式刪除了該檔案。其內容保留在編輯器中。",
+    ],
+    // DiskChangeDialogReload
+    [
+        /* en      */ "Reload",
+        /* de      */ "Neu laden",
+        /* es      */ "Volver a cargar",
+        /* fr      */ "Recharger",
+        /* it      */ "Ricarica",
+        /* ja      */ "再読み込み",
+        /* ko      */ "다시 로드",
+        /* pt_br   */ "Recarregar",
+        /* ru      */ "Загрузить заново",
+        /* zh_hans */ "重新加载",
+        /* zh_hant */ "重新載入",
+    ],
+    // DiskChangeDialogKeep
+    [
+        /* en      */ "Keep",
+        /* de      */ "Behalten",
+        /* es      */ "Conservar",
+        /* fr      */ "Conserver",
+        /* it      */ "Mantieni",
+        /* ja      */ "保持",
+        /* ko      */ "유지",
+        /* pt_br   */ "Manter",
+        /* ru      */ "Оставить",
+        /* zh_hans */ "保留",
+        /* zh_hant */ "保留",
+    ],
+    // SaveConflictDialogDescription
+    [
+        /* en      */ "Another program changed the file since it was opened. Overwrite it?",
+        /* de      */ "Ein anderes Programm hat die Datei seit dem Öffnen geändert. Überschreiben?",
+        /* es      */ "Otro programa cambió el archivo desde que se abrió. ¿Desea sobrescribirlo?",
+        /* fr      */ "Un autre programme a modifié le fichier depuis son ouverture. L’écraser ?",
+        /* it      */ "Un altro programma ha modificato il file dopo l’apertura. Sovrascriverlo?",
+        /* ja      */ "開いた後に別のプログラムがファイルを変更しました。上書きしますか？",
+        /* ko      */ "파일을 연 후 다른 프로그램이 파일을 변경했습니다. 덮어쓰시겠습니까?",
+        /* pt_br   */ "Outro programa alterou o arquivo desde que foi aberto. Deseja sobrescrevê-lo?",
+        /* ru      */ "Другая программа изменила файл после открытия. Перезаписать?",
+        /* zh_hans */ "打开后另一个程序更改了该文件。要覆盖它吗？",
+        /* zh_hant */ "開啟後另一個程式變更了該檔案。要覆蓋它嗎？",
+    ],
+    // RecoveryDialogTitle
+    [
+        /* en      */ "Recover Unsaved Changes",
+        /* de      */ "Ungespeicherte Änderungen wiederherstellen",
+        /* es      */ "Recuperar cambios no guardados",
+        /* fr      */ "Récupérer les modifications non enregistrées",
+        /* it      */ "Recupera modifiche non salvate",
+        /* ja      */ "未保存の変更の回復",
+        /* ko      */ "저장되지 않은 변경 내용 복구",
+        /* pt_br   */ "Recuperar alterações não salvas",
+        /* ru      */ "Восстановление несохранённых изменений",
+        /* zh_hans */ "恢复未保存的更改",
+        /* zh_hant */ "復原未儲存的變更",
+    ],
+    // RecoveryDialogDescription
+    [
+        /* en      */ "The editor didn't exit normally. These documents had unsaved changes:",
+        /* de      */ "Der Editor wurde nicht normal beendet. Diese Dokumente hatten ungespeicherte Änderungen:",
+        /* es      */ "El editor no se cerró correctamente. Estos documentos tenían cambios sin guardar:",
+        /* fr      */ "L’éditeur ne s’est pas fermé normalement. Ces documents avaient des modifications non enregistrées :",
+        /* it      */ "L’editor non è stato chiuso normalmente. Questi documenti avevano modifiche non salvate:",
+        /* ja      */ "エディターが正常に終了しませんでした。次のドキュメントに未保存の変更がありました:",
+        /* ko      */ "편집기가 정상적으로 종료되지 않았습니다. 다음 문서에 저장되지 않은 변경 내용이 있었습니다.",
+        /* pt_br   */ "O editor não foi fechado normalmente. Estes documentos tinham alterações não salvas:",
+        /* ru      */ "Редактор был закрыт некорректно. В этих документах были несохранённые изменения:",
+        /* zh_hans */ "编辑器未正常退出。以下文档有未保存的更改:",
+        /* zh_hant */ "編輯器未正常結束。以下文件有未儲存的變更:",
+    ],
+    // RecoveryDialogRecover
+    [
+        /* en      */ "Recover",
+        /* de      */ "Wiederherstellen",
+        /* es      */ "Recuperar",
+        /* fr      */ "Récupérer",
+        /* it      */ "Recupera",
+        /* ja      */ "回復",
+        /* ko      */ "복구",
+        /* pt_br   */ "Recuperar",
+        /* ru      */ "Восстановить",
+        /* zh_hans */ "恢复",
+        /* zh_hant */ "復原",
+    ],
+    // RecoveryDialogDiscard
+    [
+        /* en      */ "Discard",
+        /* de      */ "Verwerfen",
+        /* es      */ "Descartar",
+        /* fr      */ "Abandonner",
+        /* it      */ "Scarta",
+        /* ja      */ "破棄",
+        /* ko      */ "삭제",
+        /* pt_br   */ "Descartar",
+        /* ru      */ "Удалить",
+        /* zh_hans */ "丢弃",
+        /* zh_hant */ "捨棄",
+    ],
+    // RecoveryDialogLater
+    [
+        /* en      */ "Later",
+        /* de      */ "Später",
+        /* es      */ "Más tarde",
+        /* fr      */ "Plus tard",
+        /* it      */ "Più tardi",
+        /* ja      */ "後で",
+        /* ko      */ "나중에",
+        /* pt_br   */ "Mais tarde",
+        /* ru      */ "Позже",
+        /* zh_hans */ "稍后",
+        /* zh_hant */ "稍後",
+    ],
+    // EditUndoHistory
+    [
+        /* en      */ "Undo History…",
+        /* de      */ "Rückgängig-Verlauf…",
+        /* es      */ "Historial de deshacer…",
+        /* fr      */ "Historique d'annulation…",
+        /* it      */ "Cronologia annullamenti…",
+        /* ja      */ "元に戻す履歴…",
+        /* ko      */ "실행 취소 기록…",
+        /* pt_br   */ "Histórico de desfazer…",
+        /* ru      */ "История отмены…",
+        /* zh_hans */ "撤销历史记录…",
+        /* zh_hant */ "復原歷程記錄…",
+    ],
+    // HistoryDialogInitial
+    [
+        /* en      */ "Original",
+        /* de      */ "Original",
+        /* es      */ "Original",
+        /* fr      */ "Original",
+        /* it      */ "Originale",
+        /* ja      */ "元の状態",
+        /* ko      */ "원본",
+        /* pt_br   */ "Original",
+        /* ru      */ "Исходное",
+        /* zh_hans */ "原始状态",
+        /* zh_hant */ "原始狀態",
+    ],
+    // HistoryDialogSaved
+    [
+        /* en      */ "saved",
+        /* de      */ "gespeichert",
+        /* es      */ "guardado",
+        /* fr      */ "enregistré",
+        /* it      */ "salvato",
+        /* ja      */ "保存済み",
+        /* ko      */ "저장됨",
+        /* pt_br   */ "salvo",
+        /* ru      */ "сохранено",
+        /* zh_hans */ "已保存",
+        /* zh_hant */ "已儲存",
+    ],
+    // HistoryDialogGoto
+    [
+        /* en      */ "Go To",
+        /* de      */ "Wechseln",
+        /* es      */ "Ir a",
+        /* fr      */ "Aller à",
+        /* it      */ "Vai a",
+        /* ja      */ "移動",
+        /* ko      */ "이동",
+        /* pt_br   */ "Ir para",
+        /* ru      */ "Перейти",
+        /* zh_hans */ "转到",
+        /* zh_hant */ "移至",
+    ],
+    // HistoryDialogLastSave
+    [
+        /* en      */ "Last Save",
+        /* de      */ "Letzte Speicherung",
+        /* es      */ "Último guardado",
+        /* fr      */ "Dernier enregistrement",
+        /* it      */ "Ultimo salvataggio",
+        /* ja      */ "最後の保存",
+        /* ko      */ "마지막 저장",
+        /* pt_br   */ "Último salvamento",
+        /* ru      */ "Последнее сохранение",
+        /* zh_hans */ "上次保存",
+        /* zh_hant */ "上次儲存",
+    ],
+    // HistoryDialogTenMinutesAgo
+    [
+        /* en      */ "10 Minutes Ago",
+        /* de      */ "Vor 10 Minuten",
+        /* es      */ "Hace 10 minutos",
+        /* fr      */ "Il y a 10 minutes",
+        /* it      */ "10 minuti fa",
+        /* ja      */ "10 分前",
+        /* ko      */ "10분 전",
+        /* pt_br   */ "10 minutos atrás",
+        /* ru      */ "10 минут назад",
+        /* zh_hans */ "10 分钟前",
+        /* zh_hant */ "10 分鐘前",
+    ],
+    // EditFindInFiles
+    [
+        /* en      */ "Find in Files",
+        /* de      */ "In Dateien suchen",
+        /* es      */ "Buscar en archivos",
+        /* fr      */ "Rechercher dans les fichiers",
+        /* it      */ "Cerca nei file",
+        /* ja      */ "複数ファイルを検索",
+        /* ko      */ "파일에서 찾기",
+        /* pt_br   */ "Localizar nos arquivos",
+        /* ru      */ "Найти в файлах",
+        /* zh_hans */ "在文件中查找",
+        /* zh_hant */ "在檔案中尋找",
+    ],
+    // EditReplaceInFiles
+    [
+        /* en      */ "Replace in Files",
+        /* de      */ "In Dateien ersetzen",
+        /* es      */ "Reemplazar en archivos",
+        /* fr      */ "Remplacer dans les fichiers",
+        /* it      */ "Sostituisci nei file",
+        /* ja      */ "複数ファイルで置換",
+        /* ko      */ "파일에서 바꾸기",
+        /* pt_br   */ "Substituir nos arquivos",
+        /* ru      */ "Заменить в файлах",
+        /* zh_hans */ "在文件中替换",
+        /* zh_hant */ "在檔案中取代",
+    ],
+    // FindInFilesFolderLabel (for input field)
+    [
+        /* en      */ "Folder:",
+        /* de      */ "Ordner:",
+        /* es      */ "Carpeta:",
+        /* fr      */ "Dossier :",
+        /* it      */ "Cartella:",
+        /* ja      */ "フォルダー:",
+        /* ko      */ "폴더:",
+        /* pt_br   */ "Pasta:",
+        /* ru      */ "Папка:",
+        /* zh_hans */ "文件夹:",
+        /* zh_hant */ "資料夾:",
+    ],
+    // FindInFilesStatus
+    [
+        /* en      */ "{matches} matches in {files} files",
+        /* de      */ "{matches} Treffer in {files} Dateien",
+        /* es      */ "{matches} coincidencias en {files} archivos",
+        /* fr      */ "{matches} résultats dans {files} fichiers",
+        /* it      */ "{matches} corrispondenze in {files} file",
+        /* ja      */ "{files} 個のファイルで {matches} 件",
+        /* ko      */ "{files}개 파일에서 {matches}개 일치",
+        /* pt_br   */ "{matches} correspondências em {files} arquivos",
+        /* ru      */ "Совпадений: {matches}, файлов: {files}",
+        /* zh_hans */ "{files} 个文件中有 {matches} 个匹配项",
+        /* zh_hant */ "{files} 個檔案中有 {matches} 個相符項目",
+    ],
+    // FindInFilesSearching
+    [
+        /* en      */ "Searching…",
+        /* de      */ "Suche läuft…",
+        /* es      */ "Buscando…",
+        /* fr      */ "Recherche…",
+        /* it      */ "Ricerca in corso…",
+        /* ja      */ "検索中…",
+        /* ko      */ "검색 중…",
+        /* pt_br   */ "Pesquisando…",
+        /* ru      */ "Поиск…",
+        /* zh_hans */ "正在搜索…",
+        /* zh_hant */ "正在搜尋…",
+    ],
+    // FindInFilesUndoFile (button)
+    [
+        /* en      */ "Undo File",
+        /* de      */ "Datei rückgängig",
+        /* es      */ "Deshacer archivo",
+        /* fr      */ "Annuler le fichier",
+        /* it      */ "Annulla file",
+        /* ja      */ "ファイルを元に戻す",
+        /* ko      */ "파일 실행 취소",
+        /* pt_br   */ "Desfazer arquivo",
+        /* ru      */ "Отменить в файле",
+        /* zh_hans */ "撤消文件",
+        /* zh_hant */ "復原檔案",
+    ],
+    // FindInFilesReplaced
+    [
+        /* en      */ "replaced",
+        /* de      */ "ersetzt",
+        /* es      */ "reemplazado",
+        /* fr      */ "remplacé",
+        /* it      */ "sostituito",
+        /* ja      */ "置換済み",
+        /* ko      */ "바뀜",
+        /* pt_br   */ "substituído",
+        /* ru      */ "заменено",
+        /* zh_hans */ "已替换",
+        /* zh_hant */ "已取代",
+    ],
+    // FindInFilesOutdated
+    [
+        /* en      */ "outdated",
+        /* de      */ "veraltet",
+        /* es      */ "desactualizado",
+        /* fr      */ "obsolète",
+        /* it      */ "obsoleto",
+        /* ja      */ "古い結果",
+        /* ko      */ "오래됨",
+        /* pt_br   */ "desatualizado",
+        /* ru      */ "устарело",
+        /* zh_hans */ "已过时",
+        /* zh_hant */ "已過時",
+    ],
+    // SearchMatchIndex
+    [
+        /* en      */ "{index} of {count}",
+        /* de      */ "{index} von {count}",
+        /* es      */ "{index} de {count}",
+        /* fr      */ "{index} sur {count}",
+        /* it      */ "{index} di {count}",
+        /* ja      */ "{count} 件中 {index} 件目",
+        /* ko      */ "{count}개 중 {index}번째",
+        /* pt_br   */ "{index} de {count}",
+        /* ru      */ "{index} из {count}",
+        /* zh_hans */ "第 {index} 个，共 {count} 个",
+        /* zh_hant */ "第 {index} 個，共 {count} 個",
+    ],
+    // SearchMatchCount
+    [
+        /* en      */ "{count} matches",
+        /* de      */ "{count} Treffer",
+        /* es      */ "{count} coincidencias",
+        /* fr      */ "{count} résultats",
+        /* it      */ "{count} corrispondenze",
+        /* ja      */ "{count} 件",
+        /* ko      */ "{count}개 일치",
+        /* pt_br   */ "{count} correspondências",
+        /* ru      */ "Совпадений: {count}",
+        /* zh_hans */ "{count} 个匹配项",
+        /* zh_hant */ "{count} 個相符項目",
+    ],
 ];
 
 static mut S_LANG: LangId = LangId::en;
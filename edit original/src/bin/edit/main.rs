@@ -6,31 +6,52 @@
 mod documents;
 mod draw_editor;
 mod draw_filepicker;
+mod draw_history;
 mod draw_menubar;
+mod draw_palette;
 mod draw_statusbar;
+mod draw_tabs;
+mod find_in_files;
+#[cfg(test)]
+mod harness;
+mod keybindings;
 mod localization;
+mod panes;
+mod plugins;
+mod screenshot;
+mod script;
+mod serve;
+mod settings;
 mod state;
+mod swap;
 
 use std::borrow::Cow;
 #[cfg(feature = "debug-latency")]
 use std::fmt::Write;
 use std::path::{Path, PathBuf};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::{env, process};
 
+use documents::DiskChange;
 use draw_editor::*;
 use draw_filepicker::*;
+use draw_history::*;
 use draw_menubar::*;
+use draw_palette::*;
 use draw_statusbar::*;
+use draw_tabs::*;
 use edit::arena::{self, Arena, ArenaString, scratch_arena};
 use edit::framebuffer::{self, IndexedColor};
 use edit::helpers::{CoordType, KIBI, MEBI, MetricFormatter, Rect, Size};
-use edit::input::{self, kbmod, vk};
+use edit::keymap::cmd as textarea;
 use edit::oklab::oklab_blend;
 use edit::tui::*;
 use edit::vt::{self, Token};
-use edit::{apperr, arena_format, base64, path, sys, unicode};
+use edit::{apperr, arena_format, base64, input, path, sys, unicode};
+use find_in_files::*;
+use keybindings::cmd;
 use localization::*;
+use settings::{ConfigFile, Settings};
 use state::*;
 
 #[cfg(target_pointer_width = "32")]
@@ -49,7 +70,7 @@ fn main() -> process::ExitCode {
     }
 
     match run() {
-        Ok(()) => process::ExitCode::SUCCESS,
+        Ok(code) => code,
         Err(err) => {
             sys::write_stdout(&format!("{}\r\n", FormatApperr::from(err)));
             process::ExitCode::FAILURE
@@ -57,7 +78,23 @@ fn main() -> process::ExitCode {
     }
 }
 
-fn run() -> apperr::Result<()> {
+fn run() -> apperr::Result<process::ExitCode> {
+    // The headless modes must not touch the terminal at all. This includes `sys::init()`,
+    // because it reopens stdin as the TTY if it's redirected, which fails if there's none.
+    {
+        let mut args = env::args_os().skip(1);
+        let mode = args.next().and_then(|m| m.into_string().ok()).unwrap_or_default();
+        if matches!(mode.as_str(), "--serve" | "--script" | "--screenshot") {
+            arena::init(SCRATCH_ARENA_CAPACITY)?;
+            localization::init();
+            return match mode.as_str() {
+                "--serve" => serve::run().map(|_| process::ExitCode::SUCCESS),
+                "--script" => Ok(script::run(args)),
+                _ => screenshot::run(args),
+            };
+        }
+    }
+
     // Init `sys` first, as everything else may depend on its functionality (IO, function pointers, etc.).
     let _sys_deinit = sys::init()?;
     // Next init `arena`, so that `scratch_arena` works. `loc` depends on it.
@@ -66,8 +103,21 @@ fn run() -> apperr::Result<()> {
     localization::init();
 
     let mut state = State::new()?;
+    let mut settings_file = ConfigFile::default_path("settings.json").map(ConfigFile::new);
+    let mut keybindings_file = ConfigFile::default_path("keybindings.json").map(ConfigFile::new);
+    reload_settings(&mut state, settings_file.as_mut());
+    if let Some(dir) = settings::default_state_path("undo") {
+        state.documents.set_undo_dir(&dir);
+    }
+    if let Some(dir) = ConfigFile::default_path("plugins") {
+        plugins::load_dir(&mut state, &dir);
+    }
     if handle_args(&mut state)? {
-        return Ok(());
+        return Ok(process::ExitCode::SUCCESS);
+    }
+    if let Some(dir) = swap::SwapFiles::default_dir() {
+        state.recovery = swap::find_recoverable(&dir);
+        state.swap = Some(swap::SwapFiles::new(dir));
     }
 
     // sys::init() will switch the terminal to raw mode which prevents the user from pressing Ctrl+C.
@@ -81,26 +131,10 @@ fn run() -> apperr::Result<()> {
     let mut tui = Tui::new()?;
 
     let _restore = setup_terminal(&mut tui, &mut state, &mut vt_parser);
-
-    state.menubar_color_bg = oklab_blend(
-        tui.indexed(IndexedColor::Background),
-        tui.indexed_alpha(IndexedColor::BrightBlue, 1, 2),
-    );
-    state.menubar_color_fg = tui.contrasted(state.menubar_color_bg);
-    let floater_bg = oklab_blend(
-        tui.indexed_alpha(IndexedColor::Background, 2, 3),
-        tui.indexed_alpha(IndexedColor::Foreground, 1, 3),
-    );
-    let floater_fg = tui.contrasted(floater_bg);
-    tui.setup_modifier_translations(ModifierTranslations {
-        ctrl: loc(LocId::Ctrl),
-        alt: loc(LocId::Alt),
-        shift: loc(LocId::Shift),
-    });
-    tui.set_floater_default_bg(floater_bg);
-    tui.set_floater_default_fg(floater_fg);
-    tui.set_modal_default_bg(floater_bg);
-    tui.set_modal_default_fg(floater_fg);
+    state.file_watcher = sys::FileWatcher::new();
+    setup_theme(&mut tui, &mut state);
+    tui.set_keymap(keybindings::default_keymap());
+    reload_keybindings(&mut tui, &mut state, keybindings_file.as_mut());
 
     sys::inject_window_size_into_stdin();
 
@@ -116,11 +150,35 @@ fn run() -> apperr::Result<()> {
         // Process a batch of input.
         {
             let scratch = scratch_arena(None);
-            let read_timeout = vt_parser.read_timeout().min(tui.read_timeout());
+            let mut read_timeout = vt_parser.read_timeout().min(tui.read_timeout());
+            if settings_file.is_some() || keybindings_file.is_some() || state.disk_poll {
+                read_timeout = read_timeout.min(settings::POLL_INTERVAL);
+            }
+            if state.swap.as_ref().is_some_and(|swap| swap.pending(&state.documents)) {
+                read_timeout = read_timeout.min(swap::SWAP_INTERVAL);
+            }
+            if state.find_in_files.is_searching() {
+                // Keep searching in between input events.
+                read_timeout = Duration::ZERO;
+            }
+            if let Some(doc) = state.documents.active() {
+                // Each frame finds more of the matches to highlight, and the count has to catch up.
+                let tb = doc.buffer.borrow();
+                if tb.search_matches_pending()
+                    || tb.search_match_count() != state.search_match_count
+                {
+                    read_timeout = Duration::ZERO;
+                }
+            }
             let Some(input) = sys::read_stdin(&scratch, read_timeout) else {
                 break;
             };
 
+            reload_settings(&mut state, settings_file.as_mut());
+            reload_keybindings(&mut tui, &mut state, keybindings_file.as_mut());
+            check_disk_changes(&mut state, false);
+            state.find_in_files.update(&state.documents);
+
             #[cfg(feature = "debug-latency")]
             {
                 time_beg = std::time::Instant::now();
@@ -128,34 +186,18 @@ fn run() -> apperr::Result<()> {
             }
 
             let vt_iter = vt_parser.parse(&input);
-            let mut input_iter = input_parser.parse(vt_iter);
-
-            while {
-                let input = input_iter.next();
-                let more = input.is_some();
-                let mut ctx = tui.create_context(input);
-
-                draw(&mut ctx, &mut state);
-
-                #[cfg(feature = "debug-latency")]
-                {
-                    passes += 1;
-                }
-
-                more
-            } {}
-        }
+            let input_iter = input_parser.parse(vt_iter);
 
-        // Continue rendering until the layout has settled.
-        // This can take >1 frame, if the input focus is tossed between different controls.
-        while tui.needs_settling() {
-            let mut ctx = tui.create_context(None);
+            #[cfg_attr(not(feature = "debug-latency"), allow(unused_variables))]
+            let batch_passes = process_input(&mut tui, &mut state, input_iter);
 
-            draw(&mut ctx, &mut state);
+            if let Some(swap) = &mut state.swap {
+                swap.update(&state.documents);
+            }
 
             #[cfg(feature = "debug-latency")]
             {
-                passes += 1;
+                passes += batch_passes;
             }
         }
 
@@ -223,7 +265,141 @@ fn run() -> apperr::Result<()> {
         }
     }
 
-    Ok(())
+    Ok(process::ExitCode::SUCCESS)
+}
+
+/// Runs one UI pass per input event, followed by one without input,
+/// and then continues until the layout has settled. Returns the number of passes.
+///
+/// This is the only place where input is fed into the [`Tui`]. The terminal
+/// provides it by parsing stdin, while tests can replay recorded events.
+fn process_input<'input>(
+    tui: &mut Tui,
+    state: &mut State,
+    inputs: impl IntoIterator<Item = input::Input<'input>>,
+) -> usize {
+    let mut inputs = inputs.into_iter();
+    let mut passes = 0;
+
+    while {
+        let input = inputs.next();
+        let more = input.is_some();
+        if let Some(input::Input::Focus(true)) = input {
+            // Whatever happened while the user was away should show up right away.
+            check_disk_changes(state, true);
+        }
+        let mut ctx = tui.create_context(input);
+
+        draw(&mut ctx, state);
+        passes += 1;
+
+        more
+    } {}
+
+    // Continue rendering until the layout has settled.
+    // This can take >1 frame, if the input focus is tossed between different controls.
+    while tui.needs_settling() {
+        let mut ctx = tui.create_context(None);
+
+        draw(&mut ctx, state);
+        passes += 1;
+    }
+
+    passes
+}
+
+/// Applies the settings file to all documents if it changed since the last call.
+/// Problems with it are shown in the error dialog.
+fn reload_settings(state: &mut State, file: Option<&mut ConfigFile>) {
+    if let Some(settings) = reload_config_file(state, file, Settings::parse) {
+        state.tab_bar = settings.tab_bar;
+        state.documents.set_settings(settings);
+    }
+}
+
+/// Reloads the documents whose file changed on disk since the last check, if they have no
+/// unsaved changes, and queues a prompt for the others and for those whose file is gone.
+/// With a [`sys::FileWatcher`] the files are only checked when it saw a change in their
+/// directories, otherwise every [`settings::POLL_INTERVAL`], and whenever it's `forced`.
+fn check_disk_changes(state: &mut State, forced: bool) {
+    let dirs: Vec<&Path> =
+        state.documents.iter().filter_map(|doc| doc.path.as_deref()?.parent()).collect();
+    let watched = state.file_watcher.as_mut().is_some_and(|w| w.set_dirs(&dirs));
+    state.disk_poll = !dirs.is_empty() && !watched;
+
+    let due = if watched {
+        state.file_watcher.as_mut().unwrap().poll()
+    } else {
+        state.disk_poll && state.disk_check_time.elapsed() >= settings::POLL_INTERVAL
+    };
+    if !due && !forced {
+        return;
+    }
+
+    state.disk_check_time = Instant::now();
+    for doc in state.documents.iter_mut() {
+        let Some(change) = doc.check_disk() else {
+            continue;
+        };
+        state.disk_changes.retain(|&(id, _)| id != doc.id);
+
+        // There's nothing to lose without unsaved changes. If it fails, the prompt offers a retry.
+        if change == DiskChange::Modified && !doc.buffer.borrow().is_dirty() {
+            let encoding = doc.buffer.borrow().encoding();
+            if doc.reread(Some(encoding)).is_ok() {
+                continue;
+            }
+        }
+        state.disk_changes.push((doc.id, change));
+    }
+}
+
+fn reload_keybindings(tui: &mut Tui, state: &mut State, file: Option<&mut ConfigFile>) {
+    let plugin_commands = state.plugins.command_ids();
+    let parse =
+        |text: &str, errors: &mut Vec<String>| keybindings::parse(text, errors, &plugin_commands);
+    if let Some(keymap) = reload_config_file(state, file, parse) {
+        tui.set_keymap(keymap);
+    }
+}
+
+/// Polls the config file for changes and logs any problems with it.
+fn reload_config_file<T>(
+    state: &mut State,
+    file: Option<&mut ConfigFile>,
+    parse: impl FnOnce(&str, &mut Vec<String>) -> T,
+) -> Option<T> {
+    let file = file?;
+    let (value, errors) = file.poll(parse)?;
+
+    let name = file.path().file_name().unwrap_or_default().to_string_lossy();
+    for err in errors {
+        error_log_add_message(state, format!("{name}: {err}"));
+    }
+    Some(value)
+}
+
+/// Derives the UI colors from the terminal's palette.
+fn setup_theme(tui: &mut Tui, state: &mut State) {
+    state.menubar_color_bg = oklab_blend(
+        tui.indexed(IndexedColor::Background),
+        tui.indexed_alpha(IndexedColor::BrightBlue, 1, 2),
+    );
+    state.menubar_color_fg = tui.contrasted(state.menubar_color_bg);
+    let floater_bg = oklab_blend(
+        tui.indexed_alpha(IndexedColor::Background, 2, 3),
+        tui.indexed_alpha(IndexedColor::Foreground, 1, 3),
+    );
+    let floater_fg = tui.contrasted(floater_bg);
+    tui.setup_modifier_translations(ModifierTranslations {
+        ctrl: loc(LocId::Ctrl),
+        alt: loc(LocId::Alt),
+        shift: loc(LocId::Shift),
+    });
+    tui.set_floater_default_bg(floater_bg);
+    tui.set_floater_default_fg(floater_fg);
+    tui.set_modal_default_bg(floater_bg);
+    tui.set_modal_default_fg(floater_fg);
 }
 
 // Returns true if the application should exit early.
@@ -279,6 +455,11 @@ fn print_help() {
         "Options:\r\n",
         "    -h, --help       Print this help message\r\n",
         "    -v, --version    Print the version number\r\n",
+        "    --serve          Speak line-delimited JSON-RPC over stdin/stdout instead of running the UI\r\n",
+        "    --script SCRIPT FILE...\r\n",
+        "                     Apply the editor commands in SCRIPT to each FILE and exit\r\n",
+        "    --screenshot WIDTHxHEIGHT [--ansi] [FILE...]\r\n",
+        "                     Print the UI as plain text (or with colors) and exit\r\n",
         "\r\n",
         "Arguments:\r\n",
         "    FILE[:LINE[:COLUMN]]    The file to open, optionally with line and column (e.g., foo.txt:123:45)\r\n",
@@ -290,8 +471,15 @@ fn print_version() {
 }
 
 fn draw(ctx: &mut Context, state: &mut State) {
+    plugins::dispatch_events(ctx, state);
+
     draw_menubar(ctx, state);
+    draw_tabs(ctx, state);
     draw_editor(ctx, state);
+    plugins::draw_plugin_panel(ctx, state);
+    if state.find_in_files.is_visible() {
+        draw_find_in_files(ctx, state);
+    }
     draw_statusbar(ctx, state);
 
     if state.wants_close {
@@ -309,12 +497,24 @@ fn draw(ctx: &mut Context, state: &mut State) {
     if state.wants_save {
         draw_handle_save(ctx, state);
     }
+    if !state.disk_changes.is_empty() {
+        draw_handle_disk_change(ctx, state);
+    }
+    if !state.recovery.is_empty() {
+        swap::draw_recovery_dialog(ctx, state);
+    }
     if state.wants_encoding_change != StateEncodingChange::None {
         draw_dialog_encoding_change(ctx, state);
     }
     if state.wants_document_picker {
         draw_document_picker(ctx, state);
     }
+    if state.wants_history {
+        draw_history(ctx, state);
+    }
+    if state.wants_command_palette {
+        draw_command_palette(ctx, state);
+    }
     if state.wants_about {
         draw_dialog_about(ctx, state);
     }
@@ -325,41 +525,113 @@ fn draw(ctx: &mut Context, state: &mut State) {
         draw_error_log(ctx, state);
     }
 
-    if let Some(key) = ctx.keyboard_input() {
-        // Shortcuts that are not handled as part of the textarea, etc.
+    if let Some(command) = ctx.command_input()
+        && (keybindings::APP_COMMANDS.contains(&command) || state.plugins.has_command(command))
+        && run_command(ctx, state, command)
+    {
+        // Commands that are not handled as part of the textarea, etc.
+        ctx.set_input_consumed();
+    }
+}
 
-        if key == kbmod::CTRL | vk::N {
-            draw_add_untitled_document(ctx, state);
-        } else if key == kbmod::CTRL | vk::O {
-            state.wants_file_picker = StateFilePicker::Open;
-        } else if key == kbmod::CTRL | vk::S {
-            state.wants_save = true;
-        } else if key == kbmod::CTRL_SHIFT | vk::S {
-            state.wants_file_picker = StateFilePicker::SaveAs;
-        } else if key == kbmod::CTRL | vk::W {
-            state.wants_close = true;
-        } else if key == kbmod::CTRL | vk::P {
-            state.wants_document_picker = true;
-        } else if key == kbmod::CTRL | vk::Q {
-            state.wants_exit = true;
-        } else if key == kbmod::CTRL | vk::G {
-            state.wants_goto = true;
-        } else if key == kbmod::CTRL | vk::F && state.wants_search.kind != StateSearchKind::Disabled
-        {
+/// Runs the given command, as if it was picked from the menus.
+/// Returns false if it's unknown or not applicable right now.
+pub fn run_command(ctx: &mut Context, state: &mut State, command: &str) -> bool {
+    let search = state.wants_search.kind != StateSearchKind::Disabled;
+
+    match command {
+        cmd::FILE_NEW => draw_add_untitled_document(ctx, state),
+        cmd::FILE_OPEN => state.wants_file_picker = StateFilePicker::Open,
+        cmd::FILE_SAVE => state.wants_save = true,
+        cmd::FILE_SAVE_AS => state.wants_file_picker = StateFilePicker::SaveAs,
+        cmd::FILE_CLOSE => state.wants_close = true,
+        cmd::FILE_EXIT => state.wants_exit = true,
+        cmd::VIEW_DOCUMENT_PICKER => state.wants_document_picker = true,
+        cmd::VIEW_GOTO => state.wants_goto = true,
+        cmd::VIEW_FOCUS_STATUSBAR => state.wants_statusbar_focus = true,
+        cmd::VIEW_COMMAND_PALETTE => state.wants_command_palette = true,
+        cmd::HELP_ABOUT => state.wants_about = true,
+        cmd::VIEW_SPLIT_RIGHT if state.panes.len() != 0 => {
+            state.panes.split(true, &mut state.documents)
+        }
+        cmd::VIEW_SPLIT_DOWN if state.panes.len() != 0 => {
+            state.panes.split(false, &mut state.documents)
+        }
+        cmd::VIEW_CLOSE_PANE => {
+            if !state.panes.close_active(&mut state.documents) {
+                return false;
+            }
+        }
+        cmd::VIEW_FOCUS_NEXT_PANE if state.panes.len() > 1 => {
+            state.panes.focus_next(1, &mut state.documents)
+        }
+        cmd::VIEW_FOCUS_PREVIOUS_PANE if state.panes.len() > 1 => {
+            state.panes.focus_next(-1, &mut state.documents)
+        }
+        cmd::VIEW_NEXT_TAB if state.documents.len() > 1 => state.documents.activate_next_tab(1),
+        cmd::VIEW_PREVIOUS_TAB if state.documents.len() > 1 => {
+            state.documents.activate_next_tab(-1)
+        }
+        cmd::EDIT_FIND if search => {
             state.wants_search.kind = StateSearchKind::Search;
             state.wants_search.focus = true;
-        } else if key == kbmod::CTRL | vk::R && state.wants_search.kind != StateSearchKind::Disabled
-        {
+        }
+        cmd::EDIT_REPLACE if search => {
             state.wants_search.kind = StateSearchKind::Replace;
             state.wants_search.focus = true;
-        } else {
-            return;
         }
+        cmd::EDIT_FIND_IN_FILES if state.find_in_files.kind != StateSearchKind::Disabled => {
+            state.find_in_files.kind = StateSearchKind::Search;
+            state.find_in_files.focus = true;
+        }
+        cmd::EDIT_REPLACE_IN_FILES if state.find_in_files.kind != StateSearchKind::Disabled => {
+            state.find_in_files.kind = StateSearchKind::Replace;
+            state.find_in_files.focus = true;
+        }
+        _ if state.plugins.has_command(command) => {
+            return plugins::run_command(ctx, state, command);
+        }
+        _ => {
+            // The remaining commands apply to the active document.
+            let Some(doc) = state.documents.active() else {
+                return false;
+            };
+            let has_path = doc.path.is_some();
+            let mut tb = doc.buffer.borrow_mut();
 
-        // All of the above shortcuts happen to require a rerender.
-        ctx.needs_rerender();
-        ctx.set_input_consumed();
+            match command {
+                cmd::FILE_REOPEN_WITH_ENCODING if has_path => {
+                    state.wants_encoding_change = StateEncodingChange::Reopen
+                }
+                cmd::EDIT_CONVERT_TO_LF => tb.normalize_newlines(false),
+                cmd::EDIT_CONVERT_TO_CRLF => tb.normalize_newlines(true),
+                cmd::EDIT_CHANGE_TAB_SIZE => state.wants_indentation_picker = true,
+                cmd::EDIT_UNDO_HISTORY => {
+                    state.wants_history = true;
+                    state.history_preview = None;
+                }
+                textarea::EDIT_UNDO => tb.undo(),
+                textarea::EDIT_REDO => tb.redo(),
+                textarea::EDIT_CUT => ctx.set_clipboard_from_selection(&mut tb, true),
+                textarea::EDIT_COPY => ctx.set_clipboard_from_selection(&mut tb, false),
+                textarea::EDIT_PASTE => ctx.paste_clipboard(&mut tb),
+                textarea::EDIT_TOGGLE_OVERTYPE => {
+                    let overtype = tb.is_overtype();
+                    tb.set_overtype(!overtype);
+                }
+                textarea::SELECT_ALL => tb.select_all(),
+                textarea::VIEW_WORD_WRAP => {
+                    let word_wrap = tb.is_word_wrap_enabled();
+                    tb.set_word_wrap(!word_wrap);
+                }
+                _ => return false,
+            }
+        }
     }
+
+    // All of the above commands happen to require a rerender.
+    ctx.needs_rerender();
+    true
 }
 
 fn draw_handle_wants_exit(_ctx: &mut Context, state: &mut State) {
@@ -499,7 +771,7 @@ impl Drop for RestoreModes {
         // Same as in the beginning but in the reverse order.
         // It also includes DECSCUSR 0 to reset the cursor style and DECTCEM to show the cursor.
         // We specifically don't reset mode 1036, because most applications expect it to be set nowadays.
-        sys::write_stdout("\x1b[0 q\x1b[?25h\x1b]0;\x07\x1b[?1002;1006;2004l\x1b[?1049l");
+        sys::write_stdout("\x1b[0 q\x1b[?25h\x1b]0;\x07\x1b[?1002;1004;1006;2004l\x1b[?1049l");
     }
 }
 
@@ -509,10 +781,11 @@ fn setup_terminal(tui: &mut Tui, state: &mut State, vt_parser: &mut vt::Parser)
         //   I put the ASB switch in the beginning, just in case the terminal performs
         //   some additional state tracking beyond the modes we enable/disable.
         // 1002: Cell Motion Mouse Tracking
+        // 1004: Focus In/Out Events (the files get checked for changes on focus-in)
         // 1006: SGR Mouse Mode
         // 2004: Bracketed Paste Mode
         // 1036: Xterm: "meta sends escape" (Alt keypresses should be encoded with ESC + char)
-        "\x1b[?1049h\x1b[?1002;1006;2004h\x1b[?1036h",
+        "\x1b[?1049h\x1b[?1002;1004;1006;2004h\x1b[?1036h",
         // OSC 4 color table requests for indices 0 through 15 (base colors).
         "\x1b]4;0;?;1;?;2;?;3;?;4;?;5;?;6;?;7;?\x07",
         "\x1b]4;8;?;9;?;10;?;11;?;12;?;13;?;14;?;15;?\x07",
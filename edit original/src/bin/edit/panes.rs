@@ -0,0 +1,291 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Split editor panes.
+//!
+//! The editor area is a tree of horizontal and vertical splits with a [`Pane`] at each leaf.
+//! Each pane shows one of the open documents and multiple panes may show the same one.
+//! The active pane always shows the active document of the [`DocumentManager`].
+//!
+//! A [`TextBuffer`](edit::buffer::TextBuffer) has just a single cursor, selection and width.
+//! Each pane thus shows its document through a [`TextBufferView`], which remembers its own.
+//! They're swapped into the buffer when the pane gets activated (and while it's drawn),
+//! and kept up to date with edits made through other panes.
+
+use std::rc::Rc;
+
+use edit::buffer::{RcTextBuffer, RcTextBufferView, TextBufferSubscription, TextBufferView};
+
+use crate::documents::DocumentManager;
+
+pub struct Pane {
+    /// Identifies the pane in the [`Layout`] and its UI nodes.
+    pub id: u64,
+    pub buffer: RcTextBuffer,
+    pub view: RcTextBufferView,
+    subscription: TextBufferSubscription,
+}
+
+impl Pane {
+    fn new(id: u64, buffer: RcTextBuffer) -> Self {
+        let view = TextBufferView::new_rc();
+        let subscription = Self::subscribe(&buffer, &view);
+        Self { id, buffer, view, subscription }
+    }
+
+    fn subscribe(buffer: &RcTextBuffer, view: &RcTextBufferView) -> TextBufferSubscription {
+        let view = view.clone();
+        buffer.borrow_mut().subscribe(move |change| {
+            let mut view = view.borrow_mut();
+            if !view.live {
+                view.adjust_for_change(change);
+            }
+        })
+    }
+
+    fn set_buffer(&mut self, buffer: RcTextBuffer) {
+        self.buffer.borrow_mut().unsubscribe(self.subscription);
+        self.view = TextBufferView::new_rc();
+        self.subscription = Self::subscribe(&buffer, &self.view);
+        self.buffer = buffer;
+    }
+}
+
+impl Drop for Pane {
+    fn drop(&mut self) {
+        self.buffer.borrow_mut().unsubscribe(self.subscription);
+    }
+}
+
+/// Makes the cursor and selection of the given pane those of the buffer.
+/// The pane that had them so far remembers where they were.
+pub fn make_cursor_live(panes: &[Pane], id: u64) {
+    let Some(pane) = panes.iter().find(|p| p.id == id) else {
+        return;
+    };
+    if pane.view.borrow().live {
+        return;
+    }
+
+    release_cursor(panes, &pane.buffer);
+    pane.buffer.borrow_mut().view_acquire(&mut pane.view.borrow_mut());
+}
+
+fn release_cursor(panes: &[Pane], buffer: &RcTextBuffer) {
+    for p in panes {
+        if Rc::ptr_eq(&p.buffer, buffer) && p.view.borrow().live {
+            buffer.borrow().view_release(&mut p.view.borrow_mut());
+        }
+    }
+}
+
+pub struct Split {
+    /// If true, the panes are side by side with a vertical divider in between.
+    pub vertical: bool,
+    /// The share of the space that goes to `first`.
+    pub ratio: f32,
+    /// The `ratio` when the divider drag started.
+    pub drag_start: Option<f32>,
+    pub first: Layout,
+    pub second: Layout,
+}
+
+pub enum Layout {
+    Pane(u64),
+    Split(Box<Split>),
+}
+
+impl Layout {
+    /// Returns the pane IDs in order: Left to right, top to bottom.
+    pub fn ids(&self) -> Vec<u64> {
+        let mut ids = Vec::new();
+        let mut stack = vec![self];
+        while let Some(layout) = stack.pop() {
+            match layout {
+                Layout::Pane(id) => ids.push(*id),
+                Layout::Split(split) => {
+                    stack.push(&split.second);
+                    stack.push(&split.first);
+                }
+            }
+        }
+        ids
+    }
+
+    pub fn contains(&self, id: u64) -> bool {
+        match self {
+            Layout::Pane(i) => *i == id,
+            Layout::Split(split) => split.first.contains(id) || split.second.contains(id),
+        }
+    }
+
+    /// Replaces the pane `id` with a split of it and `new`.
+    fn split(self, id: u64, new: u64, vertical: bool) -> Layout {
+        match self {
+            Layout::Pane(i) if i == id => Layout::Split(Box::new(Split {
+                vertical,
+                ratio: 0.5,
+                drag_start: None,
+                first: Layout::Pane(i),
+                second: Layout::Pane(new),
+            })),
+            Layout::Pane(_) => self,
+            Layout::Split(split) => {
+                let split = *split;
+                Layout::Split(Box::new(Split {
+                    first: split.first.split(id, new, vertical),
+                    second: split.second.split(id, new, vertical),
+                    ..split
+                }))
+            }
+        }
+    }
+
+    /// Removes the pane `id`. Its sibling takes the place of their split.
+    /// Returns `None` if nothing is left.
+    fn without(self, id: u64) -> Option<Layout> {
+        match self {
+            Layout::Pane(i) if i == id => None,
+            Layout::Pane(_) => Some(self),
+            Layout::Split(split) => {
+                let split = *split;
+                match (split.first.without(id), split.second.without(id)) {
+                    (Some(first), Some(second)) => {
+                        Some(Layout::Split(Box::new(Split { first, second, ..split })))
+                    }
+                    (Some(remaining), None) | (None, Some(remaining)) => Some(remaining),
+                    (None, None) => None,
+                }
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct PaneManager {
+    pub layout: Option<Layout>,
+    pub panes: Vec<Pane>,
+    pub active: u64,
+    next_id: u64,
+    /// Set when the active pane should take the focus during the next UI pass.
+    pub wants_focus: bool,
+}
+
+impl PaneManager {
+    pub fn len(&self) -> usize {
+        self.panes.len()
+    }
+
+    fn pane(&self, id: u64) -> &Pane {
+        self.panes.iter().find(|p| p.id == id).unwrap()
+    }
+
+    fn add_pane(&mut self, buffer: RcTextBuffer) -> u64 {
+        self.next_id += 1;
+        self.panes.push(Pane::new(self.next_id, buffer));
+        self.next_id
+    }
+
+    /// Brings the panes in line with the documents: Panes of closed documents are removed
+    /// and the active pane switches to the active document if it changed elsewhere
+    /// (e.g. by opening a file). Call this before drawing the panes.
+    pub fn sync(&mut self, documents: &mut DocumentManager) {
+        let Some(doc) = documents.active() else {
+            self.layout = None;
+            self.panes.clear();
+            return;
+        };
+
+        if self.layout.is_none() {
+            let id = self.add_pane(doc.buffer.clone());
+            self.pane(id).view.borrow_mut().live = true;
+            self.layout = Some(Layout::Pane(id));
+            self.active = id;
+            return;
+        }
+
+        // Panes of closed documents go away, but the last one stays.
+        let closed: Vec<u64> = self
+            .panes
+            .iter()
+            .filter(|p| !documents.iter().any(|d| Rc::ptr_eq(&d.buffer, &p.buffer)))
+            .map(|p| p.id)
+            .collect();
+        for id in closed {
+            if self.panes.len() > 1 {
+                self.remove(id);
+            }
+        }
+        if !self.panes.iter().any(|p| p.id == self.active) {
+            // The active pane was removed. The document of the next one becomes active.
+            let id = self.layout.as_ref().unwrap().ids()[0];
+            self.activate(id, documents);
+        }
+
+        let buffer = documents.active().unwrap().buffer.clone();
+        if !Rc::ptr_eq(&self.pane(self.active).buffer, &buffer) {
+            release_cursor(&self.panes, &buffer);
+            let active = self.active;
+            let pane = self.panes.iter_mut().find(|p| p.id == active).unwrap();
+            pane.set_buffer(buffer);
+            pane.view.borrow_mut().live = true;
+        }
+    }
+
+    /// Makes the pane the active one, along with its document.
+    pub fn activate(&mut self, id: u64, documents: &mut DocumentManager) {
+        self.active = id;
+        make_cursor_live(&self.panes, id);
+        let buffer = &self.pane(id).buffer;
+        documents.update_active(|doc| Rc::ptr_eq(&doc.buffer, buffer));
+    }
+
+    /// Activates the next pane, or the previous one if `delta` is negative.
+    pub fn focus_next(&mut self, delta: isize, documents: &mut DocumentManager) {
+        let Some(layout) = &self.layout else {
+            return;
+        };
+        let ids = layout.ids();
+        let idx = ids.iter().position(|&id| id == self.active).unwrap_or(0);
+        let idx = (idx as isize + delta).rem_euclid(ids.len() as isize) as usize;
+        self.activate(ids[idx], documents);
+        self.wants_focus = true;
+    }
+
+    /// Splits the active pane into two showing the same document. The new one becomes active.
+    pub fn split(&mut self, vertical: bool, documents: &mut DocumentManager) {
+        let Some(layout) = self.layout.take() else {
+            return;
+        };
+        let buffer = self.pane(self.active).buffer.clone();
+        let id = self.add_pane(buffer.clone());
+
+        // The new pane starts out where the current one is.
+        {
+            let mut view = self.pane(id).view.borrow_mut();
+            buffer.borrow().view_release(&mut view);
+            view.scroll_offset = self.pane(self.active).view.borrow().scroll_offset;
+        }
+        self.layout = Some(layout.split(self.active, id, vertical));
+        self.activate(id, documents);
+        self.wants_focus = true;
+    }
+
+    /// Closes the active pane, unless it's the last one.
+    pub fn close_active(&mut self, documents: &mut DocumentManager) -> bool {
+        if self.panes.len() <= 1 {
+            return false;
+        }
+        let ids = self.layout.as_ref().unwrap().ids();
+        let idx = ids.iter().position(|&id| id == self.active).unwrap_or(0);
+        self.remove(self.active);
+        self.activate(ids[if idx == 0 { 1 } else { idx - 1 }], documents);
+        self.wants_focus = true;
+        true
+    }
+
+    fn remove(&mut self, id: u64) {
+        self.layout = self.layout.take().and_then(|l| l.without(id));
+        self.panes.retain(|p| p.id != id);
+    }
+}
@@ -0,0 +1,661 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The plugin host.
+//!
+//! Plugins are shared libraries in the `plugins` directory next to the settings file.
+//! They're loaded once at startup and talk to the editor through the [`edit::plugin`] API.
+//! Their commands are run like any other (keybindings, command palette), their panels are
+//! drawn between the editor and the statusbar and their event handlers get called at the
+//! beginning of the next UI pass after the change happened.
+
+use std::cell::RefCell;
+use std::ffi::{OsStr, c_void};
+use std::path::Path;
+use std::rc::{Rc, Weak};
+use std::{env, fs, mem, ptr, slice};
+
+use edit::buffer::{TextBufferCell, TextBufferSubscription};
+use edit::hash::hash;
+use edit::helpers::*;
+use edit::input::vk;
+use edit::keymap::TEXTAREA_COMMANDS;
+use edit::plugin::{self, Api, CommandFn, DrawFn, EventFn, Host, Str};
+use edit::sys;
+use edit::tui::*;
+
+use crate::keybindings::APP_COMMANDS;
+use crate::state::*;
+
+/// The height of a plugin panel, including its title.
+pub const PANEL_HEIGHT: CoordType = 8;
+
+static API: Api = Api {
+    version: plugin::API_VERSION,
+    register_command,
+    register_panel,
+    subscribe,
+    log_error,
+    buffer_available,
+    buffer_length,
+    buffer_read,
+    buffer_selection,
+    buffer_replace,
+    ui_block_begin,
+    ui_block_end,
+    ui_label,
+    ui_button,
+    ui_list_begin,
+    ui_list_item,
+    ui_list_end,
+    ui_editline,
+};
+
+struct Plugin {
+    name: String,
+}
+
+#[derive(Clone, Copy)]
+enum Action {
+    Run(CommandFn, *mut c_void),
+    TogglePanel(usize),
+}
+
+struct Command {
+    plugin: usize,
+    id: &'static str,
+    label: String,
+    action: Action,
+}
+
+struct Panel {
+    plugin: usize,
+    title: String,
+    draw: DrawFn,
+    user: *mut c_void,
+}
+
+struct Handler {
+    plugin: usize,
+    handler: EventFn,
+    user: *mut c_void,
+}
+
+/// A document whose changes are reported to the event handlers.
+struct Tracked {
+    buffer: Weak<TextBufferCell>,
+    subscription: TextBufferSubscription,
+    save_generation: u32,
+}
+
+struct QueuedEvent {
+    kind: u32,
+    buffer: Weak<TextBufferCell>,
+    offset: usize,
+    deleted: Vec<u8>,
+    inserted: Vec<u8>,
+}
+
+#[derive(Default)]
+pub struct PluginHost {
+    plugins: Vec<Plugin>,
+    commands: Vec<Command>,
+    panels: Vec<Panel>,
+    handlers: Vec<Handler>,
+    visible_panel: Option<usize>,
+    tracked: Vec<Tracked>,
+    events: Rc<RefCell<Vec<QueuedEvent>>>,
+}
+
+impl PluginHost {
+    /// The IDs of all commands registered by plugins.
+    pub fn command_ids(&self) -> Vec<&'static str> {
+        self.commands.iter().map(|c| c.id).collect()
+    }
+
+    pub fn has_command(&self, id: &str) -> bool {
+        self.commands.iter().any(|c| c.id == id)
+    }
+
+    /// Iterates over the commands as `(plugin name, label, command ID)`.
+    pub fn palette_entries(&self) -> impl Iterator<Item = (&str, &str, &'static str)> {
+        self.commands.iter().map(|c| (self.plugins[c.plugin].name.as_str(), c.label.as_str(), c.id))
+    }
+
+    pub fn is_panel_visible(&self) -> bool {
+        self.visible_panel.is_some()
+    }
+}
+
+impl Drop for PluginHost {
+    fn drop(&mut self) {
+        for t in &self.tracked {
+            if let Some(buffer) = t.buffer.upgrade() {
+                buffer.borrow_mut().unsubscribe(t.subscription);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// During `edit_plugin_init`: Things may be registered.
+    Init,
+    /// Running a command or an event handler.
+    Run,
+    /// Drawing a panel: The `ui_*` functions may be used.
+    Draw,
+}
+
+/// What the [`Host`] pointer passed to plugins points to.
+struct Call<'c, 'a, 'i> {
+    state: &'c mut State,
+    ctx: Option<&'c mut Context<'a, 'i>>,
+    plugin: usize,
+    mode: Mode,
+    /// The containers opened by the plugin. `true` for lists.
+    open: Vec<bool>,
+}
+
+impl<'a, 'i> Call<'_, 'a, 'i> {
+    /// # Safety
+    ///
+    /// `host` must be a pointer passed to the plugin by [`call`], which hasn't returned yet.
+    unsafe fn from<'h>(host: *mut Host) -> &'h mut Self {
+        unsafe { &mut *host.cast::<Self>() }
+    }
+
+    fn ui(&mut self) -> Option<&mut Context<'a, 'i>> {
+        if self.mode == Mode::Draw { self.ctx.as_deref_mut() } else { None }
+    }
+}
+
+/// Calls into a plugin via `f`, passing it a [`Host`] pointer for the given `mode`.
+fn call(
+    state: &mut State,
+    ctx: Option<&mut Context>,
+    plugin: usize,
+    mode: Mode,
+    f: impl FnOnce(*mut Host),
+) {
+    let mut call = Call { state, ctx, plugin, mode, open: Vec::new() };
+    f((&raw mut call).cast());
+
+    // Close whatever the plugin forgot to close, so that the rest of the UI stays intact.
+    let open = mem::take(&mut call.open);
+    if let Some(ctx) = call.ui() {
+        for list in open.into_iter().rev() {
+            if list {
+                ctx.list_end();
+            } else {
+                ctx.block_end();
+            }
+        }
+    }
+}
+
+/// Loads all plugins in `dir`, in alphabetical order. It's fine if it doesn't exist.
+/// Problems are shown in the error dialog.
+pub fn load_dir(state: &mut State, dir: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut paths: Vec<_> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension() == Some(OsStr::new(env::consts::DLL_EXTENSION)))
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        if let Err(err) = load(state, &path) {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            error_log_add_message(state, format!("{name}: {err}"));
+        }
+    }
+}
+
+/// Loads the plugin at `path` and calls its entrypoint.
+pub fn load(state: &mut State, path: &Path) -> Result<(), String> {
+    let handle = sys::load_plugin(path).map_err(|err| FormatApperr::from(err).to_string())?;
+
+    // NOTE: The library is never unloaded, even if it fails to initialize.
+    // It may have registered callbacks elsewhere (e.g. atexit) that we can't know about.
+    let (version, init) = unsafe {
+        let version = sys::get_proc_address::<plugin::VersionFn>(handle, plugin::VERSION_SYMBOL);
+        let init = sys::get_proc_address::<plugin::InitFn>(handle, plugin::INIT_SYMBOL);
+        match (version, init) {
+            (Ok(version), Ok(init)) => (version(), init),
+            _ => return Err("not a plugin".to_string()),
+        }
+    };
+    if version == 0 || version > plugin::API_VERSION {
+        return Err(format!(
+            "requires API version {version}, but this editor provides {}",
+            plugin::API_VERSION
+        ));
+    }
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = stem.strip_prefix(env::consts::DLL_PREFIX).unwrap_or(&stem).to_string();
+    let idx = state.plugins.plugins.len();
+    state.plugins.plugins.push(Plugin { name });
+
+    let mut ok = false;
+    call(state, None, idx, Mode::Init, |host| ok = unsafe { init(&API, host) });
+
+    if !ok {
+        let host = &mut state.plugins;
+        host.commands.retain(|c| c.plugin != idx);
+        host.panels.retain(|p| p.plugin != idx);
+        host.handlers.retain(|h| h.plugin != idx);
+        host.plugins.pop();
+        return Err("failed to initialize".to_string());
+    }
+    Ok(())
+}
+
+/// Runs a command registered by a plugin. Returns false if there's no such command.
+pub fn run_command(ctx: &mut Context, state: &mut State, command: &str) -> bool {
+    let Some(c) = state.plugins.commands.iter().find(|c| c.id == command) else {
+        return false;
+    };
+    let (plugin, action) = (c.plugin, c.action);
+
+    match action {
+        Action::Run(run, user) => {
+            call(state, Some(ctx), plugin, Mode::Run, |host| unsafe { run(host, user) });
+        }
+        Action::TogglePanel(idx) => {
+            let host = &mut state.plugins;
+            host.visible_panel = if host.visible_panel == Some(idx) { None } else { Some(idx) };
+        }
+    }
+
+    ctx.needs_rerender();
+    true
+}
+
+/// Delivers the events that were queued since the last call.
+pub fn dispatch_events(ctx: &mut Context, state: &mut State) {
+    if state.plugins.handlers.is_empty() {
+        return;
+    }
+
+    track_documents(state);
+
+    let events = mem::take(&mut *state.plugins.events.borrow_mut());
+    for e in &events {
+        let path = state
+            .documents
+            .iter()
+            .find(|doc| ptr::eq(Rc::as_ptr(&doc.buffer), e.buffer.as_ptr()))
+            .and_then(|doc| doc.path.as_ref())
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let event = plugin::Event {
+            kind: e.kind,
+            path: Str::new(&path),
+            offset: e.offset,
+            deleted: Str::from_bytes(&e.deleted),
+            inserted: Str::from_bytes(&e.inserted),
+        };
+
+        for i in 0..state.plugins.handlers.len() {
+            let Handler { plugin, handler, user } = state.plugins.handlers[i];
+            call(state, Some(ctx), plugin, Mode::Run, |host| unsafe {
+                handler(host, &event, user)
+            });
+        }
+    }
+}
+
+/// Subscribes to new documents and queues save events.
+fn track_documents(state: &mut State) {
+    let host = &mut state.plugins;
+    host.tracked.retain(|t| t.buffer.strong_count() > 0);
+
+    for doc in state.documents.iter() {
+        let buffer = Rc::downgrade(&doc.buffer);
+
+        if let Some(t) = host.tracked.iter_mut().find(|t| t.buffer.ptr_eq(&buffer)) {
+            if t.save_generation != doc.save_generation {
+                t.save_generation = doc.save_generation;
+                host.events.borrow_mut().push(QueuedEvent {
+                    kind: plugin::EVENT_SAVED,
+                    buffer,
+                    offset: 0,
+                    deleted: Vec::new(),
+                    inserted: Vec::new(),
+                });
+            }
+            continue;
+        }
+
+        let subscription = doc.buffer.borrow_mut().subscribe({
+            let events = host.events.clone();
+            let buffer = buffer.clone();
+            move |change| {
+                events.borrow_mut().push(QueuedEvent {
+                    kind: plugin::EVENT_BUFFER_CHANGED,
+                    buffer: buffer.clone(),
+                    offset: change.offset,
+                    deleted: change.deleted.to_vec(),
+                    inserted: change.inserted.to_vec(),
+                });
+            }
+        });
+        host.tracked.push(Tracked { buffer, subscription, save_generation: doc.save_generation });
+    }
+}
+
+pub fn draw_plugin_panel(ctx: &mut Context, state: &mut State) {
+    let Some(idx) = state.plugins.visible_panel else {
+        return;
+    };
+    let panel = &state.plugins.panels[idx];
+    let (plugin, draw, user) = (panel.plugin, panel.draw, panel.user);
+
+    ctx.block_begin("plugin-panel");
+    ctx.attr_intrinsic_size(Size { width: COORD_TYPE_SAFE_MAX, height: PANEL_HEIGHT });
+    {
+        if ctx.contains_focus() && ctx.consume_shortcut(vk::ESCAPE) {
+            state.plugins.visible_panel = None;
+            ctx.needs_rerender();
+        }
+
+        ctx.label("title", &state.plugins.panels[idx].title);
+        ctx.attr_background_rgba(state.menubar_color_bg);
+        ctx.attr_foreground_rgba(state.menubar_color_fg);
+        ctx.attr_padding(Rect::two(0, 1));
+
+        ctx.scrollarea_begin("content", Size { width: 0, height: PANEL_HEIGHT - 1 });
+        ctx.attr_padding(Rect::two(0, 1));
+        call(state, Some(ctx), plugin, Mode::Draw, |host| unsafe { draw(host, user) });
+        ctx.scrollarea_end();
+    }
+    ctx.block_end();
+}
+
+/// Gives the next UI element an ID derived from the plugin-provided `name`.
+fn ui_name(ctx: &mut Context, name: Str) {
+    ctx.next_block_id_mixin(hash(0, unsafe { name.as_bytes() }));
+}
+
+fn lossy(s: Str) -> String {
+    String::from_utf8_lossy(unsafe { s.as_bytes() }).into_owned()
+}
+
+unsafe extern "C" fn register_command(
+    host: *mut Host,
+    id: Str,
+    label: Str,
+    run: CommandFn,
+    user: *mut c_void,
+) -> bool {
+    let call = unsafe { Call::from(host) };
+    register(call, id, label, Action::Run(run, user))
+}
+
+unsafe extern "C" fn register_panel(
+    host: *mut Host,
+    id: Str,
+    title: Str,
+    draw: DrawFn,
+    user: *mut c_void,
+) -> bool {
+    let call = unsafe { Call::from(host) };
+    let idx = call.state.plugins.panels.len();
+    if !register(call, id, title, Action::TogglePanel(idx)) {
+        return false;
+    }
+    call.state.plugins.panels.push(Panel { plugin: call.plugin, title: lossy(title), draw, user });
+    true
+}
+
+fn register(call: &mut Call, id: Str, label: Str, action: Action) -> bool {
+    let Some(id) = (unsafe { id.as_str() }) else {
+        return false;
+    };
+    let host = &mut call.state.plugins;
+    if call.mode != Mode::Init
+        || id.is_empty()
+        || APP_COMMANDS.contains(&id)
+        || TEXTAREA_COMMANDS.contains(&id)
+        || host.has_command(id)
+    {
+        return false;
+    }
+
+    // The keymap needs `'static` IDs. Plugins are never unloaded, so it's fine to leak them.
+    let id = String::leak(id.to_string());
+    host.commands.push(Command { plugin: call.plugin, id, label: lossy(label), action });
+    true
+}
+
+unsafe extern "C" fn subscribe(host: *mut Host, handler: EventFn, user: *mut c_void) {
+    let call = unsafe { Call::from(host) };
+    if call.mode == Mode::Init {
+        call.state.plugins.handlers.push(Handler { plugin: call.plugin, handler, user });
+    }
+}
+
+unsafe extern "C" fn log_error(host: *mut Host, message: Str) {
+    let call = unsafe { Call::from(host) };
+    let name = &call.state.plugins.plugins[call.plugin].name;
+    let message = format!("{name}: {}", lossy(message));
+    error_log_add_message(call.state, message);
+    if let Some(ctx) = &mut call.ctx {
+        ctx.needs_rerender();
+    }
+}
+
+unsafe extern "C" fn buffer_available(host: *mut Host) -> bool {
+    let call = unsafe { Call::from(host) };
+    call.state.documents.active().is_some()
+}
+
+unsafe extern "C" fn buffer_length(host: *mut Host) -> usize {
+    let call = unsafe { Call::from(host) };
+    call.state.documents.active().map_or(0, |doc| doc.buffer.borrow().text_length())
+}
+
+unsafe extern "C" fn buffer_read(
+    host: *mut Host,
+    offset: usize,
+    dst: *mut u8,
+    cap: usize,
+) -> usize {
+    let call = unsafe { Call::from(host) };
+    let Some(doc) = call.state.documents.active() else {
+        return 0;
+    };
+    let tb = doc.buffer.borrow();
+    let mut off = offset;
+    let end = offset.saturating_add(cap).min(tb.text_length());
+
+    while off < end {
+        let chunk = tb.read_forward(off);
+        if chunk.is_empty() {
+            break;
+        }
+        let len = chunk.len().min(end - off);
+        unsafe { ptr::copy_nonoverlapping(chunk.as_ptr(), dst.add(off - offset), len) };
+        off += len;
+    }
+
+    off.saturating_sub(offset)
+}
+
+unsafe extern "C" fn buffer_selection(host: *mut Host, beg: *mut usize, end: *mut usize) {
+    let call = unsafe { Call::from(host) };
+    let range = call.state.documents.active().map_or((0, 0), |doc| {
+        let tb = doc.buffer.borrow();
+        match tb.selection_range() {
+            Some((beg, end)) => (beg.offset, end.offset),
+            None => (tb.cursor_offset(), tb.cursor_offset()),
+        }
+    });
+    unsafe {
+        *beg = range.0;
+        *end = range.1;
+    }
+}
+
+unsafe extern "C" fn buffer_replace(host: *mut Host, beg: usize, end: usize, text: Str) -> bool {
+    let call = unsafe { Call::from(host) };
+    let Some(doc) = call.state.documents.active() else {
+        return false;
+    };
+    let text = unsafe { text.as_bytes() };
+
+    {
+        let mut tb = doc.buffer.borrow_mut();
+        if beg > end || end > tb.text_length() {
+            return false;
+        }
+        if beg == end && text.is_empty() {
+            return true;
+        }
+        // The user's cursor and selection are left alone.
+        tb.replace_range(beg..end, text);
+    }
+
+    if let Some(ctx) = &mut call.ctx {
+        ctx.needs_rerender();
+    }
+    true
+}
+
+unsafe extern "C" fn ui_block_begin(host: *mut Host, name: Str) {
+    let call = unsafe { Call::from(host) };
+    let Some(ctx) = call.ui() else {
+        return;
+    };
+    ui_name(ctx, name);
+    ctx.block_begin("plugin");
+    call.open.push(false);
+}
+
+unsafe extern "C" fn ui_block_end(host: *mut Host) {
+    let call = unsafe { Call::from(host) };
+    if call.open.last() == Some(&false)
+        && let Some(ctx) = call.ui()
+    {
+        ctx.block_end();
+        call.open.pop();
+    }
+}
+
+unsafe extern "C" fn ui_label(host: *mut Host, name: Str, text: Str) {
+    let call = unsafe { Call::from(host) };
+    if let Some(ctx) = call.ui() {
+        ui_name(ctx, name);
+        ctx.label("plugin", &lossy(text));
+    }
+}
+
+unsafe extern "C" fn ui_button(host: *mut Host, name: Str, text: Str) -> bool {
+    let call = unsafe { Call::from(host) };
+    let Some(ctx) = call.ui() else {
+        return false;
+    };
+    ui_name(ctx, name);
+    ctx.button("plugin", &lossy(text), ButtonStyle::default())
+}
+
+unsafe extern "C" fn ui_list_begin(host: *mut Host, name: Str) {
+    let call = unsafe { Call::from(host) };
+    let Some(ctx) = call.ui() else {
+        return;
+    };
+    ui_name(ctx, name);
+    ctx.list_begin("plugin");
+    call.open.push(true);
+}
+
+unsafe extern "C" fn ui_list_item(host: *mut Host, select: bool, text: Str) -> u32 {
+    let call = unsafe { Call::from(host) };
+    if call.open.last() != Some(&true) {
+        return plugin::LIST_UNCHANGED;
+    }
+    let Some(ctx) = call.ui() else {
+        return plugin::LIST_UNCHANGED;
+    };
+    match ctx.list_item(select, &lossy(text)) {
+        ListSelection::Unchanged => plugin::LIST_UNCHANGED,
+        ListSelection::Selected => plugin::LIST_SELECTED,
+        ListSelection::Activated => plugin::LIST_ACTIVATED,
+    }
+}
+
+unsafe extern "C" fn ui_list_end(host: *mut Host) {
+    let call = unsafe { Call::from(host) };
+    if call.open.last() == Some(&true)
+        && let Some(ctx) = call.ui()
+    {
+        ctx.list_end();
+        call.open.pop();
+    }
+}
+
+unsafe extern "C" fn ui_editline(
+    host: *mut Host,
+    name: Str,
+    buf: *mut u8,
+    len: *mut usize,
+    cap: usize,
+) -> bool {
+    let call = unsafe { Call::from(host) };
+    let Some(ctx) = call.ui() else {
+        return false;
+    };
+
+    let text = unsafe { slice::from_raw_parts(buf, (*len).min(cap)) };
+    let mut text = String::from_utf8_lossy(text).into_owned();
+    ui_name(ctx, name);
+    if !ctx.editline("plugin", &mut text) {
+        return false;
+    }
+
+    // Truncate the text to fit into the plugin's buffer.
+    let mut n = text.len().min(cap);
+    while !text.is_char_boundary(n) {
+        n -= 1;
+    }
+    unsafe {
+        ptr::copy_nonoverlapping(text.as_ptr(), buf, n);
+        *len = n;
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::lock;
+    use crate::serve::read_range;
+
+    #[test]
+    fn test_buffer_replace() {
+        let _lock = lock();
+        let mut state = State::new().unwrap();
+        state.documents.add_untitled().unwrap();
+        let mut call =
+            Call { state: &mut state, ctx: None, plugin: 0, mode: Mode::Run, open: Vec::new() };
+        let host: *mut Host = (&raw mut call).cast();
+        let replace = |beg, end, text| unsafe { buffer_replace(host, beg, end, Str::new(text)) };
+
+        assert!(replace(0, 0, "abc"));
+        assert!(replace(1, 1, ""));
+        assert!(!replace(2, 1, "x"));
+        assert!(!replace(1, 4, "x"));
+        assert!(replace(1, 2, ""));
+
+        let doc = call.state.documents.active().unwrap();
+        let tb = doc.buffer.borrow();
+        assert_eq!(read_range(&tb, 0..tb.text_length()), "ac");
+        // The cursor stayed in front of the inserted text.
+        assert_eq!(tb.cursor_logical_pos(), Point { x: 0, y: 0 });
+    }
+}
@@ -0,0 +1,79 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Offscreen rendering, enabled via `edit --screenshot WIDTHxHEIGHT [--ansi] [FILE...]`.
+//!
+//! Opens the given files, draws a single (settled) frame of the UI at the given size
+//! and prints it to stdout, either as plain text or with SGR color sequences.
+//! Like `--serve` it never touches the terminal.
+
+use std::ffi::OsString;
+use std::io::Write as _;
+use std::path::Path;
+use std::{env, io, process};
+
+use edit::helpers::{CoordType, Size};
+use edit::input::Input;
+use edit::tui::Tui;
+use edit::{apperr, path};
+
+use crate::state::{FormatApperr, State};
+use crate::{draw, keybindings, setup_theme};
+
+const USAGE: &str = "Usage: edit --screenshot WIDTHxHEIGHT [--ansi] [FILE...]";
+
+pub fn run(mut args: impl Iterator<Item = OsString>) -> apperr::Result<process::ExitCode> {
+    let mut stderr = io::stderr().lock();
+
+    let Some(size) = args.next().and_then(|s| parse_size(s.to_str()?)) else {
+        _ = writeln!(stderr, "{USAGE}");
+        return Ok(process::ExitCode::FAILURE);
+    };
+
+    let mut ansi = false;
+    let mut state = State::new()?;
+    let cwd = env::current_dir()?;
+
+    for arg in args {
+        if arg == "--ansi" {
+            ansi = true;
+            continue;
+        }
+        let path = path::normalize(&cwd.join(Path::new(&arg)));
+        if let Err(err) = state.documents.add_file_path(&path) {
+            _ = writeln!(stderr, "{}: {}", Path::new(&arg).display(), FormatApperr::from(err));
+            return Ok(process::ExitCode::FAILURE);
+        }
+    }
+    if state.documents.active().is_none() {
+        state.documents.add_untitled()?;
+    }
+
+    let mut tui = Tui::new()?;
+    setup_theme(&mut tui, &mut state);
+    tui.set_keymap(keybindings::default_keymap());
+
+    {
+        let mut ctx = tui.create_context(Some(Input::Resize(size)));
+        draw(&mut ctx, &mut state);
+    }
+    while tui.needs_settling() {
+        let mut ctx = tui.create_context(None);
+        draw(&mut ctx, &mut state);
+    }
+
+    let snapshot = tui.render_snapshot();
+    let output = if ansi { snapshot.to_ansi() } else { snapshot.to_text() };
+    io::stdout().lock().write_all(output.as_bytes())?;
+    Ok(process::ExitCode::SUCCESS)
+}
+
+fn parse_size(s: &str) -> Option<Size> {
+    let (width, height) = s.split_once(['x', 'X'])?;
+    let width: CoordType = width.parse().ok()?;
+    let height: CoordType = height.parse().ok()?;
+    if width <= 0 || height <= 0 {
+        return None;
+    }
+    Some(Size { width, height })
+}
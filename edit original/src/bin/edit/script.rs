@@ -0,0 +1,411 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Batch mode, enabled via `edit --script SCRIPT FILE...`.
+//!
+//! The script is a list of commands, one per line, that is applied to each
+//! of the given files in turn. Like `--serve` it never touches the terminal.
+//! Empty lines and lines starting with `#` are ignored. Arguments are separated by
+//! whitespace and may be put in double quotes, which support `\"`, `\\`, `\n` and `\t`.
+//!
+//! `patch [-F FUZZ] PATCHFILE` applies a unified diff. If it covers several files,
+//! the hunks for the file being edited are used. Unless all of them apply, none do.
+//!
+//! If a command fails, the remaining commands are skipped for that file
+//! (unsaved changes are discarded) and the exit code will be non-zero.
+
+use std::ffi::OsString;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::{env, fmt, fs, io, process};
+
+use edit::buffer::{PatchOptions, SearchOptions, TextBuffer};
+use edit::helpers::Point;
+use edit::patch::{self, Patch};
+use edit::{apperr, icu, path};
+
+use crate::documents::DocumentManager;
+use crate::draw_editor::validate_goto_point;
+use crate::state::FormatApperr;
+
+const USAGE: &str = "Usage: edit --script SCRIPT FILE...";
+
+enum Command {
+    Goto(Point),
+    Select(Point, Point),
+    SelectAll,
+    SelectLine,
+    SelectWord,
+    Find(String, SearchOptions),
+    Replace(String, SearchOptions, String),
+    ReplaceAll(String, SearchOptions, String),
+    Write(String),
+    Indent,
+    Unindent,
+    Newlines(bool),
+    Encoding(&'static str),
+    Patch(Patch, PatchOptions),
+    Save(Option<PathBuf>),
+}
+
+enum ScriptError {
+    Message(String),
+    App(apperr::Error),
+}
+
+impl From<apperr::Error> for ScriptError {
+    fn from(err: apperr::Error) -> Self {
+        Self::App(err)
+    }
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Message(msg) => f.write_str(msg),
+            Self::App(err) => FormatApperr::from(*err).fmt(f),
+        }
+    }
+}
+
+/// Runs the script given by the remaining command line arguments.
+pub fn run(mut args: impl Iterator<Item = OsString>) -> process::ExitCode {
+    let mut stderr = io::stderr().lock();
+
+    let Some(script_path) = args.next() else {
+        _ = writeln!(stderr, "{USAGE}");
+        return process::ExitCode::FAILURE;
+    };
+    let files: Vec<_> = args.collect();
+    if files.is_empty() {
+        _ = writeln!(stderr, "{USAGE}");
+        return process::ExitCode::FAILURE;
+    }
+
+    let script_name = Path::new(&script_path).display();
+    let script = match fs::read(&script_path) {
+        Ok(script) => String::from_utf8_lossy_owned(script),
+        Err(err) => {
+            _ = writeln!(stderr, "{script_name}: {}", FormatApperr::from(apperr::Error::from(err)));
+            return process::ExitCode::FAILURE;
+        }
+    };
+
+    // Parse the entire script upfront, so that typos don't leave files half-edited.
+    let mut commands = Vec::new();
+    let mut ok = true;
+    for (i, line) in script.lines().enumerate() {
+        match parse_line(line) {
+            Ok(Some(cmd)) => commands.push((i + 1, cmd)),
+            Ok(None) => {}
+            Err(err) => {
+                _ = writeln!(stderr, "{script_name}:{}: {err}", i + 1);
+                ok = false;
+            }
+        }
+    }
+    if !ok {
+        return process::ExitCode::FAILURE;
+    }
+
+    let cwd = env::current_dir().unwrap_or_default();
+    let mut documents = DocumentManager::default();
+
+    for file in &files {
+        let path = path::normalize(&cwd.join(Path::new(file)));
+        let file_name = Path::new(file).display();
+
+        if let Err(err) = documents.add_file_path(&path) {
+            _ = writeln!(stderr, "{file_name}: {}", FormatApperr::from(err));
+            ok = false;
+            continue;
+        }
+
+        for (line, cmd) in &commands {
+            if let Err(err) = execute(&mut documents, cmd) {
+                _ = writeln!(stderr, "{script_name}:{line}: {file_name}: {err}");
+                ok = false;
+                break;
+            }
+        }
+
+        documents.remove_active();
+    }
+
+    if ok { process::ExitCode::SUCCESS } else { process::ExitCode::FAILURE }
+}
+
+fn execute(documents: &mut DocumentManager, cmd: &Command) -> Result<(), ScriptError> {
+    let doc = documents.active_mut().unwrap();
+
+    if let Command::Save(path) = cmd {
+        if path.is_none() && doc.path.is_none() {
+            return Err(ScriptError::Message("save requires a path for untitled documents".into()));
+        }
+        doc.save(path.clone())?;
+        return Ok(());
+    }
+
+    // Multi-file patches are matched up with the file by path.
+    let file_patch = match cmd {
+        Command::Patch(patch, _) if patch.files.len() > 1 => {
+            let cwd = env::current_dir().map_err(apperr::Error::from)?;
+            let file = patch.files.iter().find(|file| {
+                file.path().is_some_and(|p| doc.path == Some(path::normalize(&cwd.join(p))))
+            });
+            file.ok_or_else(|| ScriptError::Message("The patch doesn't cover this file".into()))?
+        }
+        Command::Patch(patch, _) => &patch.files[0],
+        _ => &Default::default(),
+    };
+
+    let mut tb = doc.buffer.borrow_mut();
+
+    match cmd {
+        Command::Goto(pos) => tb.cursor_move_to_logical(*pos),
+        Command::Select(beg, end) => {
+            tb.clear_selection();
+            tb.cursor_move_to_logical(*beg);
+            tb.start_selection();
+            tb.selection_update_logical(*end);
+        }
+        Command::SelectAll => tb.select_all(),
+        Command::SelectLine => tb.select_line(),
+        Command::SelectWord => tb.select_word(),
+        Command::Find(needle, options) => {
+            icu::init()?;
+            tb.find_and_select(needle, *options)?;
+            require_match(&tb, needle)?;
+        }
+        Command::Replace(needle, options, replacement) => {
+            icu::init()?;
+            // `find_and_replace` replaces the current search hit (if any) and then selects the
+            // next one. Running the search first makes this replace the next occurrence instead.
+            tb.find_and_select(needle, *options)?;
+            require_match(&tb, needle)?;
+            tb.find_and_replace(needle, *options, replacement)?;
+        }
+        Command::ReplaceAll(needle, options, replacement) => {
+            icu::init()?;
+            tb.find_and_replace_all(needle, *options, replacement)?;
+        }
+        Command::Write(text) => tb.write(text.as_bytes(), true),
+        Command::Indent => tb.indent(),
+        Command::Unindent => tb.unindent(),
+        Command::Newlines(crlf) => tb.normalize_newlines(*crlf),
+        Command::Encoding(encoding) => tb.set_encoding(encoding),
+        Command::Patch(_, options) => {
+            let report = tb.apply_patch(&file_patch.hunks, *options);
+            if !report.rejected.is_empty() {
+                let rejected: Vec<_> = report
+                    .rejected
+                    .iter()
+                    .map(|h| format!("hunk #{} expected at line {}", h.index + 1, h.line + 1))
+                    .collect();
+                return Err(ScriptError::Message(format!(
+                    "Patch doesn't apply: {}",
+                    rejected.join(", ")
+                )));
+            }
+        }
+        Command::Save(_) => unreachable!(),
+    }
+
+    Ok(())
+}
+
+fn require_match(tb: &TextBuffer, needle: &str) -> Result<(), ScriptError> {
+    if tb.has_selection() {
+        Ok(())
+    } else {
+        Err(ScriptError::Message(format!("\"{needle}\" not found")))
+    }
+}
+
+fn parse_line(line: &str) -> Result<Option<Command>, ScriptError> {
+    let line = line.trim_ascii();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+
+    let args = split_args(line)?;
+    let (name, args) = args.split_first().unwrap();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let cmd = match (name.as_str(), args.as_slice()) {
+        ("goto", [pos]) => Command::Goto(parse_point(pos)?),
+        ("select", ["all"]) => Command::SelectAll,
+        ("select", ["line"]) => Command::SelectLine,
+        ("select", ["word"]) => Command::SelectWord,
+        ("select", [beg, end]) => Command::Select(parse_point(beg)?, parse_point(end)?),
+        ("find", args) => match parse_search(args)? {
+            (options, [needle]) => Command::Find(needle.to_string(), options),
+            _ => return Err(usage("find [-c] [-w] [-r] NEEDLE")),
+        },
+        ("replace" | "replace-all", args) => match parse_search(args)? {
+            (options, [needle, replacement]) => {
+                let (needle, replacement) = (needle.to_string(), replacement.to_string());
+                if name == "replace" {
+                    Command::Replace(needle, options, replacement)
+                } else {
+                    Command::ReplaceAll(needle, options, replacement)
+                }
+            }
+            _ => return Err(usage(&format!("{name} [-c] [-w] [-r] NEEDLE REPLACEMENT"))),
+        },
+        ("write", [text]) => Command::Write(text.to_string()),
+        ("indent", []) => Command::Indent,
+        ("unindent", []) => Command::Unindent,
+        ("newlines", [kind]) if kind.eq_ignore_ascii_case("lf") => Command::Newlines(false),
+        ("newlines", [kind]) if kind.eq_ignore_ascii_case("crlf") => Command::Newlines(true),
+        ("encoding", [label]) => Command::Encoding(parse_encoding(label)?),
+        ("patch", ["-F", fuzz, file]) => {
+            let fuzz = fuzz.parse().map_err(|_| usage("patch [-F FUZZ] PATCHFILE"))?;
+            Command::Patch(read_patch(file)?, PatchOptions { fuzz, atomic: true })
+        }
+        ("patch", [file]) => {
+            Command::Patch(read_patch(file)?, PatchOptions { atomic: true, ..Default::default() })
+        }
+        ("save", []) => Command::Save(None),
+        ("save", [path]) => {
+            let cwd = env::current_dir().map_err(apperr::Error::from)?;
+            Command::Save(Some(path::normalize(&cwd.join(path))))
+        }
+        ("goto", _) => return Err(usage("goto LINE[:COLUMN]")),
+        ("select", _) => return Err(usage("select all|line|word|FROM TO")),
+        ("write", _) => return Err(usage("write TEXT")),
+        ("newlines", _) => return Err(usage("newlines lf|crlf")),
+        ("encoding", _) => return Err(usage("encoding NAME")),
+        ("patch", _) => return Err(usage("patch [-F FUZZ] PATCHFILE")),
+        ("indent" | "unindent" | "save", _) => {
+            return Err(ScriptError::Message(format!("Too many arguments for {name}")));
+        }
+        _ => return Err(ScriptError::Message(format!("Unknown command: {name}"))),
+    };
+    Ok(Some(cmd))
+}
+
+fn usage(syntax: &str) -> ScriptError {
+    ScriptError::Message(format!("Usage: {syntax}"))
+}
+
+fn parse_point(s: &str) -> Result<Point, ScriptError> {
+    validate_goto_point(s).map_err(|_| ScriptError::Message(format!("Invalid position: {s}")))
+}
+
+fn parse_search<'a, 'b>(
+    mut args: &'b [&'a str],
+) -> Result<(SearchOptions, &'b [&'a str]), ScriptError> {
+    let mut options = SearchOptions::default();
+
+    while let Some((&arg, rest)) = args.split_first() {
+        match arg {
+            "-c" | "--match-case" => options.match_case = true,
+            "-w" | "--whole-word" => options.whole_word = true,
+            "-r" | "--regex" => options.use_regex = true,
+            "--" => {
+                args = rest;
+                break;
+            }
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                return Err(ScriptError::Message(format!("Unknown option: {arg}")));
+            }
+            _ => break,
+        }
+        args = rest;
+    }
+
+    Ok((options, args))
+}
+
+fn read_patch(file: &str) -> Result<Patch, ScriptError> {
+    let text = fs::read(file).map_err(apperr::Error::from)?;
+    let patch =
+        patch::parse(&text).map_err(|err| ScriptError::Message(format!("{file}: {err}")))?;
+    if patch.files.is_empty() {
+        return Err(ScriptError::Message(format!("{file}: No hunks found")));
+    }
+    Ok(patch)
+}
+
+fn parse_encoding(label: &str) -> Result<&'static str, ScriptError> {
+    icu::init()?;
+    icu::get_available_encodings()
+        .all
+        .iter()
+        .find(|enc| enc.label.eq_ignore_ascii_case(label) || enc.canonical == label)
+        .map(|enc| enc.canonical)
+        .ok_or_else(|| ScriptError::Message(format!("Unknown encoding: {label}")))
+}
+
+fn split_args(line: &str) -> Result<Vec<String>, ScriptError> {
+    let mut args = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+        let Some(&first) = chars.peek() else {
+            break;
+        };
+
+        let mut arg = String::new();
+
+        if first == '"' {
+            chars.next();
+            loop {
+                match chars.next() {
+                    None => return Err(ScriptError::Message("Unterminated string".into())),
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some('n') => arg.push('\n'),
+                        Some('t') => arg.push('\t'),
+                        Some(c @ ('"' | '\\')) => arg.push(c),
+                        _ => return Err(ScriptError::Message("Invalid escape sequence".into())),
+                    },
+                    Some(c) => arg.push(c),
+                }
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| !c.is_ascii_whitespace()) {
+                arg.push(c);
+            }
+        }
+
+        args.push(arg);
+    }
+
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_args() {
+        let split = |s| split_args(s).ok();
+        assert_eq!(split("goto 12:3"), Some(vec!["goto".into(), "12:3".into()]));
+        assert_eq!(
+            split(r#"replace-all  "a \"b\"" "\t\\n" "#),
+            Some(vec!["replace-all".into(), "a \"b\"".into(), "\t\\n".into()])
+        );
+        assert_eq!(split(r#"find "abc"#), None);
+        assert_eq!(split(r#"find "\x""#), None);
+    }
+
+    #[test]
+    fn test_parse_line() {
+        assert!(matches!(parse_line("  # comment"), Ok(None)));
+        assert!(matches!(parse_line("goto 3:4"), Ok(Some(Command::Goto(Point { x: 3, y: 2 })))));
+        assert!(matches!(
+            parse_line(r#"replace-all -w -c -- -x "y""#),
+            Ok(Some(Command::ReplaceAll(needle, SearchOptions { match_case: true, whole_word: true, use_regex: false }, replacement)))
+                if needle == "-x" && replacement == "y"
+        ));
+        assert!(matches!(parse_line("newlines CRLF"), Ok(Some(Command::Newlines(true)))));
+        assert!(parse_line("patch -F x some.patch").is_err());
+        assert!(parse_line("find -q abc").is_err());
+        assert!(parse_line("indent 2").is_err());
+        assert!(parse_line("frobnicate").is_err());
+    }
+}
@@ -0,0 +1,451 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Headless JSON-RPC mode, enabled via `edit --serve`.
+//!
+//! Each line on stdin is a JSON-RPC 2.0 request and each response is written as a single line
+//! to stdout. This mode never touches the terminal, so it works with plain pipes. It drives the
+//! same [`DocumentManager`] and [`TextBuffer`] code as the TUI, so encodings, newlines and
+//! indentation behave exactly the same.
+//!
+//! Positions are 1-based `{"line": ..., "column": ...}` objects, like in the status bar.
+//! Methods that operate on a document accept an optional `"document"` parameter, which is
+//! either a path or a filename of an open document. It defaults to the active document.
+
+use std::env;
+use std::io::{self, BufRead as _, Write as _};
+use std::ops::Range;
+use std::path::Path;
+
+use edit::buffer::{CursorMovement, PatchOptions, SearchOptions, TextBuffer};
+use edit::helpers::{CoordType, Point};
+use edit::json::{self, Value};
+use edit::{apperr, icu, patch, path};
+
+use crate::documents::{Document, DocumentManager};
+use crate::state::FormatApperr;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// Any [`apperr::Error`], like I/O or ICU errors.
+const APP_ERROR: i64 = -32000;
+/// The requested document isn't open, or there's none open at all.
+const NO_DOCUMENT: i64 = -32001;
+/// Closing the document would lose unsaved changes.
+const DOCUMENT_DIRTY: i64 = -32002;
+
+pub struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+
+    fn invalid_params(message: impl Into<String>) -> Self {
+        Self::new(INVALID_PARAMS, message)
+    }
+}
+
+impl From<apperr::Error> for RpcError {
+    fn from(err: apperr::Error) -> Self {
+        Self::new(APP_ERROR, FormatApperr::from(err).to_string())
+    }
+}
+
+pub type RpcResult = Result<Value, RpcError>;
+
+pub fn run() -> apperr::Result<()> {
+    let mut server = Server { documents: Default::default(), exit: false };
+    let mut stdin = io::stdin().lock();
+    let mut stdout = io::stdout().lock();
+    let mut line = Vec::new();
+
+    while !server.exit {
+        line.clear();
+        if stdin.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+
+        if let Some(response) = server.handle_message(&line) {
+            writeln!(stdout, "{response}")?;
+            stdout.flush()?;
+        }
+    }
+
+    Ok(())
+}
+
+pub struct Server {
+    pub documents: DocumentManager,
+    pub exit: bool,
+}
+
+impl Server {
+    /// Handles a single line of input and returns the response, if any.
+    /// Notifications (requests without an `"id"`) don't get a response.
+    pub fn handle_message(&mut self, line: &[u8]) -> Option<Value> {
+        let line = std::str::from_utf8(line).ok().map(str::trim_ascii).unwrap_or("\u{FFFD}");
+        if line.is_empty() {
+            return None;
+        }
+
+        let Some(request) = json::parse(line) else {
+            return Some(response(Value::Null, Err(RpcError::new(PARSE_ERROR, "Parse error"))));
+        };
+
+        let id = request.get("id").cloned();
+        let result = match (request.get("method").and_then(Value::as_str), request.get("params")) {
+            (Some(method), None) => self.dispatch(method, &Value::Object(Vec::new())),
+            (Some(method), Some(params @ Value::Object(_))) => self.dispatch(method, params),
+            _ => Err(RpcError::new(INVALID_REQUEST, "Invalid Request")),
+        };
+
+        id.map(|id| response(id, result))
+    }
+
+    pub fn dispatch(&mut self, method: &str, params: &Value) -> RpcResult {
+        match method {
+            "open" => {
+                let path = resolve_path(param_str(params, "path")?)?;
+                let doc = self.documents.add_file_path(&path)?;
+                Ok(document_info(doc))
+            }
+            "new" => {
+                let doc = self.documents.add_untitled()?;
+                Ok(document_info(doc))
+            }
+            "list" => {
+                Ok(Value::Array(self.documents.iter().map(document_info).collect::<Vec<_>>()))
+            }
+            "close" => {
+                let force = param_bool(params, "force")?.unwrap_or(false);
+                let doc = self.document(params)?;
+                if !force && doc.buffer.borrow().is_dirty() {
+                    return Err(RpcError::new(DOCUMENT_DIRTY, "Document has unsaved changes"));
+                }
+                self.documents.remove_active();
+                Ok(Value::Null)
+            }
+            "save" => {
+                let new_path = param_opt_str(params, "path")?.map(resolve_path).transpose()?;
+                let doc = self.document(params)?;
+                if new_path.is_none() && doc.path.is_none() {
+                    return Err(RpcError::invalid_params("Untitled documents require a path"));
+                }
+                doc.save(new_path)?;
+                Ok(document_info(doc))
+            }
+            "info" => Ok(document_info(self.document(params)?)),
+            "get_text" => {
+                let doc = self.document(params)?;
+                let tb = doc.buffer.borrow();
+                Ok(read_range(&tb, 0..tb.text_length()).into())
+            }
+            "apply_patch" => self.apply_patch(params),
+            "exit" => {
+                self.exit = true;
+                Ok(Value::Null)
+            }
+            _ => self.dispatch_buffer(method, params),
+        }
+    }
+
+    /// Methods that operate on the [`TextBuffer`] of a document.
+    fn dispatch_buffer(&mut self, method: &str, params: &Value) -> RpcResult {
+        let doc = self.document(params)?;
+        let mut tb = doc.buffer.borrow_mut();
+
+        match method {
+            "goto" => {
+                tb.cursor_move_to_logical(param_point(params)?);
+            }
+            "write" => {
+                let text = param_str(params, "text")?;
+                // Raw writes are what a paste does: No auto-indentation, no overtype.
+                let raw = param_bool(params, "raw")?.unwrap_or(true);
+                tb.write(text.as_bytes(), raw);
+            }
+            "delete" => {
+                let granularity = match param_opt_str(params, "granularity")? {
+                    None | Some("grapheme") => CursorMovement::Grapheme,
+                    Some("word") => CursorMovement::Word,
+                    Some(_) => {
+                        return Err(RpcError::invalid_params(
+                            "granularity must be \"grapheme\" or \"word\"",
+                        ));
+                    }
+                };
+                let delta = param_int(params, "delta")?.unwrap_or(1);
+                tb.delete(granularity, delta);
+            }
+            "undo" => tb.undo(),
+            "redo" => tb.redo(),
+            "select" => {
+                let beg = param_point(params.get("start").unwrap_or(&Value::Null))?;
+                let end = param_point(params.get("end").unwrap_or(&Value::Null))?;
+                tb.clear_selection();
+                tb.cursor_move_to_logical(beg);
+                tb.start_selection();
+                tb.selection_update_logical(end);
+            }
+            "select_word" => tb.select_word(),
+            "select_line" => tb.select_line(),
+            "select_all" => tb.select_all(),
+            "clear_selection" => _ = tb.clear_selection(),
+            "get_selection" => {
+                return Ok(tb
+                    .selection_range()
+                    .map(|(beg, end)| read_range(&tb, beg.offset..end.offset))
+                    .into());
+            }
+            "find" => {
+                let (needle, options) = param_search(params)?;
+                tb.find_and_select(needle, options)?;
+                return Ok(Value::from([("found", tb.has_selection().into())]));
+            }
+            "find_and_replace_all" => {
+                let (needle, options) = param_search(params)?;
+                let replacement = param_str(params, "replacement")?;
+                tb.find_and_replace_all(needle, options, replacement)?;
+            }
+            _ => return Err(RpcError::new(METHOD_NOT_FOUND, "Method not found")),
+        }
+
+        drop(tb);
+        Ok(document_info(doc))
+    }
+
+    /// Applies a unified diff. Files named in the patch are opened (or created) unless a `"document"`
+    /// is given, which then receives all hunks. Hunks without a file header go to the active document.
+    /// Returns one report per file. With `"atomic"`, a file is only changed if all of its hunks apply.
+    fn apply_patch(&mut self, params: &Value) -> RpcResult {
+        let patch = patch::parse(param_str(params, "patch")?.as_bytes())
+            .map_err(|err| RpcError::invalid_params(format!("Invalid patch: {err}")))?;
+        let mut options = PatchOptions::default();
+        if let Some(fuzz) = param_int(params, "fuzz")? {
+            options.fuzz = fuzz.max(0) as usize;
+        }
+        options.atomic = param_bool(params, "atomic")?.unwrap_or(false);
+
+        let mut results = Vec::new();
+        for file in &patch.files {
+            let doc = match file.path() {
+                Some(path) if params.get("document").is_none() => {
+                    self.documents.add_file_path(&resolve_path(path)?)?
+                }
+                _ => self.document(params)?,
+            };
+            let report = doc.buffer.borrow_mut().apply_patch(&file.hunks, options);
+            let applied = report.applied.iter().map(|h| {
+                Value::from([
+                    ("hunk", (h.index as i64 + 1).into()),
+                    ("line", (h.line as i64 + 1).into()),
+                    ("offset", (h.offset as i64).into()),
+                    ("fuzz", (h.fuzz as i64).into()),
+                ])
+            });
+            let rejected = report.rejected.iter().map(|h| {
+                Value::from([
+                    ("hunk", (h.index as i64 + 1).into()),
+                    ("line", (h.line as i64 + 1).into()),
+                ])
+            });
+            results.push(Value::from([
+                ("document", document_info(doc)),
+                ("applied", Value::Array(applied.collect())),
+                ("rejected", Value::Array(rejected.collect())),
+            ]));
+        }
+        Ok(Value::Array(results))
+    }
+
+    /// Returns the document addressed by the `"document"` parameter and makes it the active one.
+    fn document(&mut self, params: &Value) -> Result<&mut Document, RpcError> {
+        if let Some(name) = param_opt_str(params, "document")? {
+            let path = resolve_path(name)?;
+            if !self.documents.update_active(|doc| {
+                doc.path.as_deref() == Some(path.as_path()) || doc.filename == name
+            }) {
+                return Err(RpcError::new(NO_DOCUMENT, "No such document"));
+            }
+        }
+        self.documents.active_mut().ok_or_else(|| RpcError::new(NO_DOCUMENT, "No open document"))
+    }
+}
+
+fn response(id: Value, result: RpcResult) -> Value {
+    let (key, value) = match result {
+        Ok(value) => ("result", value),
+        Err(err) => {
+            ("error", Value::from([("code", err.code.into()), ("message", err.message.into())]))
+        }
+    };
+    Value::from([("jsonrpc", "2.0".into()), ("id", id), (key, value)])
+}
+
+fn document_info(doc: &Document) -> Value {
+    let tb = doc.buffer.borrow();
+    let selection = tb.selection_range().map(|(beg, end)| {
+        Value::from([
+            ("start", point_value(beg.logical_pos)),
+            ("end", point_value(end.logical_pos)),
+        ])
+    });
+
+    Value::from([
+        ("filename", doc.filename.as_str().into()),
+        ("path", doc.path.as_ref().map(|p| p.to_string_lossy().into_owned()).into()),
+        ("dirty", tb.is_dirty().into()),
+        ("encoding", tb.encoding().into()),
+        ("crlf", tb.is_crlf().into()),
+        ("indent_with_tabs", tb.indent_with_tabs().into()),
+        ("tab_size", (tb.tab_size() as i64).into()),
+        ("line_count", (tb.logical_line_count() as i64).into()),
+        ("cursor", point_value(tb.cursor_logical_pos())),
+        ("selection", selection.into()),
+    ])
+}
+
+fn point_value(pos: Point) -> Value {
+    Value::from([("line", (pos.y as i64 + 1).into()), ("column", (pos.x as i64 + 1).into())])
+}
+
+pub fn read_range(tb: &TextBuffer, range: Range<usize>) -> String {
+    String::from_utf8_lossy_owned(tb.text_in_range(range))
+}
+
+/// Paths are resolved relative to the current working directory, same as on the command line.
+fn resolve_path(arg: &str) -> Result<std::path::PathBuf, RpcError> {
+    let cwd = env::current_dir().map_err(apperr::Error::from)?;
+    Ok(path::normalize(&cwd.join(Path::new(arg))))
+}
+
+fn param_opt_str<'a>(params: &'a Value, key: &str) -> Result<Option<&'a str>, RpcError> {
+    match params.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s)),
+        Some(_) => Err(RpcError::invalid_params(format!("{key} must be a string"))),
+    }
+}
+
+fn param_str<'a>(params: &'a Value, key: &str) -> Result<&'a str, RpcError> {
+    param_opt_str(params, key)?
+        .ok_or_else(|| RpcError::invalid_params(format!("{key} is required")))
+}
+
+fn param_bool(params: &Value, key: &str) -> Result<Option<bool>, RpcError> {
+    match params.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::Bool(b)) => Ok(Some(*b)),
+        Some(_) => Err(RpcError::invalid_params(format!("{key} must be a boolean"))),
+    }
+}
+
+fn param_int(params: &Value, key: &str) -> Result<Option<CoordType>, RpcError> {
+    match params.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(v) => v
+            .as_i64()
+            .map(|i| Some(i as CoordType))
+            .ok_or_else(|| RpcError::invalid_params(format!("{key} must be an integer"))),
+    }
+}
+
+/// Parses a 1-based `{"line": ..., "column": ...}` object. The column defaults to 1.
+fn param_point(params: &Value) -> Result<Point, RpcError> {
+    let Some(line) = param_int(params, "line")? else {
+        return Err(RpcError::invalid_params("line is required"));
+    };
+    let column = param_int(params, "column")?.unwrap_or(1);
+    Ok(Point { x: column.saturating_sub(1).max(0), y: line.saturating_sub(1).max(0) })
+}
+
+fn param_search(params: &Value) -> Result<(&str, SearchOptions), RpcError> {
+    icu::init()?;
+
+    let needle = param_str(params, "needle")?;
+    let options = SearchOptions {
+        match_case: param_bool(params, "match_case")?.unwrap_or(false),
+        whole_word: param_bool(params, "whole_word")?.unwrap_or(false),
+        use_regex: param_bool(params, "use_regex")?.unwrap_or(false),
+    };
+    Ok((needle, options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(server: &mut Server, request: &str) -> Value {
+        let response = server.handle_message(request.as_bytes()).unwrap();
+        if let Some(error) = response.get("error") {
+            return error.clone();
+        }
+        response.get("result").unwrap().clone()
+    }
+
+    fn get_text(server: &mut Server) -> String {
+        let text = call(server, r#"{"id":0,"method":"get_text"}"#);
+        // New documents use CRLF on Windows.
+        text.as_str().unwrap().replace("\r\n", "\n")
+    }
+
+    #[test]
+    fn test_session() {
+        let _lock = crate::harness::lock();
+
+        let mut server = Server { documents: Default::default(), exit: false };
+
+        let info = call(&mut server, r#"{"jsonrpc":"2.0","id":1,"method":"new"}"#);
+        assert_eq!(info.get("filename").and_then(Value::as_str), Some("Untitled-1.txt"));
+
+        let info = call(
+            &mut server,
+            r#"{"jsonrpc":"2.0","id":2,"method":"write","params":{"text":"hello\nworld\n"}}"#,
+        );
+        assert_eq!(info.get("dirty"), Some(&Value::Bool(true)));
+        assert_eq!(info.get("line_count").and_then(Value::as_i64), Some(3));
+
+        call(
+            &mut server,
+            r#"{"id":3,"method":"select","params":{"start":{"line":2},"end":{"line":2,"column":3}}}"#,
+        );
+        let text = call(&mut server, r#"{"id":4,"method":"get_selection"}"#);
+        assert_eq!(text.as_str(), Some("wo"));
+
+        call(&mut server, r#"{"id":5,"method":"write","params":{"text":"W"}}"#);
+        assert_eq!(get_text(&mut server), "hello\nWrld\n");
+
+        // Notifications don't produce a response.
+        assert!(server.handle_message(br#"{"jsonrpc":"2.0","method":"undo"}"#).is_none());
+        assert_eq!(get_text(&mut server), "hello\nworld\n");
+
+        let error = call(&mut server, r#"{"id":8,"method":"close"}"#);
+        assert_eq!(error.get("code").and_then(Value::as_i64), Some(DOCUMENT_DIRTY));
+        let error = call(&mut server, r#"{"id":9,"method":"bogus"}"#);
+        assert_eq!(error.get("code").and_then(Value::as_i64), Some(METHOD_NOT_FOUND));
+        let error = call(&mut server, "{");
+        assert_eq!(error.get("code").and_then(Value::as_i64), Some(PARSE_ERROR));
+
+        // Hunks without a file header apply to the active document, wherever they match.
+        let patch = r#"@@ -1,2 +1,2 @@\n-hello\n+hi\n world\n@@ -9 +9 @@\n-nope\n+yes\n"#;
+        let reports = call(
+            &mut server,
+            &format!(r#"{{"id":10,"method":"apply_patch","params":{{"patch":"{patch}"}}}}"#),
+        );
+        assert_eq!(get_text(&mut server), "hi\nworld\n");
+        let report = &reports.as_array().unwrap()[0];
+        assert_eq!(report.get("applied").and_then(Value::as_array).map(<[_]>::len), Some(1));
+        let rejected = &report.get("rejected").and_then(Value::as_array).unwrap()[0];
+        assert_eq!(rejected.get("hunk").and_then(Value::as_i64), Some(2));
+
+        call(&mut server, r#"{"id":11,"method":"close","params":{"force":true}}"#);
+        let list = call(&mut server, r#"{"id":12,"method":"list"}"#);
+        assert_eq!(list.as_array().map(<[_]>::len), Some(0));
+    }
+}
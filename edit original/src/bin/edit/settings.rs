@@ -0,0 +1,467 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The user settings file.
+//!
+//! It's a JSON object with the settings below, all optional. The same settings, except for
+//! `tab_bar` and `persistent_undo`, can be nested under `"file_types"`, keyed by language
+//! name (`"rust"`), extension (`".txt"`) or filename (`"Makefile"`), which then take
+//! precedence for matching documents:
+//! ```json
+//! {
+//!     "tab_bar": true,
+//!     "persistent_undo": false,
+//!     "tab_size": 4,
+//!     "indent_style": "spaces",
+//!     "detect_indentation": true,
+//!     "word_wrap": false,
+//!     "line_numbers": true,
+//!     "rulers": [80, 100],
+//!     "line_highlight": true,
+//!     "insert_final_newline": true,
+//!     "encoding": "UTF-8",
+//!     "newline": "lf",
+//!     "file_types": { "markdown": { "word_wrap": true } }
+//! }
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use edit::buffer::TextBuffer;
+use edit::helpers::CoordType;
+use edit::json::{self, Value};
+use edit::syntax::Language;
+use edit::{icu, path};
+
+/// How often the config files are checked for changes while the editor is idle.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The settings for one document, after applying the overrides for its file type.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FileSettings {
+    pub tab_size: CoordType,
+    pub indent_with_tabs: bool,
+    /// If true, files keep the indentation guessed from their contents.
+    pub detect_indentation: bool,
+    pub word_wrap: bool,
+    pub line_numbers: bool,
+    pub rulers: Vec<CoordType>,
+    pub line_highlight: bool,
+    pub insert_final_newline: bool,
+    /// Used for new documents and files without a BOM.
+    pub encoding: &'static str,
+    /// Used for new documents and files without any newlines.
+    pub crlf: bool,
+}
+
+impl Default for FileSettings {
+    fn default() -> Self {
+        Self {
+            tab_size: 4,
+            indent_with_tabs: false,
+            detect_indentation: true,
+            word_wrap: false,
+            line_numbers: true,
+            rulers: Vec::new(),
+            line_highlight: true,
+            insert_final_newline: !cfg!(windows), // As mandated by POSIX.
+            encoding: "UTF-8",
+            crlf: cfg!(windows), // Windows users want CRLF
+        }
+    }
+}
+
+impl FileSettings {
+    /// Applies the settings to `tb`. `is_new` is true if it wasn't read from a file,
+    /// in which case there's nothing to detect and the defaults apply as well.
+    pub fn apply(&self, tb: &mut TextBuffer, is_new: bool) {
+        tb.set_word_wrap(self.word_wrap);
+        tb.set_margin_enabled(self.line_numbers);
+        tb.set_rulers(&self.rulers);
+        tb.set_line_highlight_enabled(self.line_highlight);
+        tb.set_default_encoding(self.encoding);
+
+        if is_new || !self.detect_indentation {
+            tb.set_tab_size(self.tab_size);
+            tb.set_indent_with_tabs(self.indent_with_tabs);
+        }
+        if is_new {
+            tb.set_insert_final_newline(self.insert_final_newline);
+        }
+        // Changing it is only safe while there are no newlines that would need converting.
+        if tb.logical_line_count() <= 1 {
+            tb.set_crlf(self.crlf);
+        }
+    }
+
+    /// Overwrites the fields present in `obj`. Invalid ones are skipped and reported
+    /// in `errors`, prefixed with `prefix`. Returns the `file_types` object, if any.
+    fn merge<'a>(
+        &mut self,
+        obj: &'a [(String, Value)],
+        prefix: &str,
+        errors: &mut Vec<String>,
+    ) -> Option<&'a Value> {
+        let mut file_types = None;
+
+        for (key, value) in obj {
+            let res = match key.as_str() {
+                "tab_size" => value
+                    .as_i64()
+                    .filter(|n| (1..=8).contains(n))
+                    .map(|n| self.tab_size = n as CoordType)
+                    .ok_or("expected an integer between 1 and 8"),
+                "indent_style" => match value.as_str() {
+                    Some(style @ ("tabs" | "spaces")) => {
+                        self.indent_with_tabs = style == "tabs";
+                        Ok(())
+                    }
+                    _ => Err(r#"expected "tabs" or "spaces""#),
+                },
+                "detect_indentation" => bool_setting(value, &mut self.detect_indentation),
+                "word_wrap" => bool_setting(value, &mut self.word_wrap),
+                "line_numbers" => bool_setting(value, &mut self.line_numbers),
+                "rulers" => value
+                    .as_array()
+                    .and_then(|a| {
+                        a.iter()
+                            .map(|v| v.as_i64().filter(|&n| n > 0 && n <= 1000))
+                            .map(|n| n.map(|n| n as CoordType))
+                            .collect::<Option<Vec<_>>>()
+                    })
+                    .map(|rulers| self.rulers = rulers)
+                    .ok_or("expected a list of column numbers"),
+                "line_highlight" => bool_setting(value, &mut self.line_highlight),
+                "insert_final_newline" => bool_setting(value, &mut self.insert_final_newline),
+                "encoding" => value
+                    .as_str()
+                    .and_then(find_encoding)
+                    .map(|enc| self.encoding = enc)
+                    .ok_or("expected the name of a supported encoding"),
+                "newline" => match value.as_str() {
+                    Some(newline @ ("lf" | "crlf")) => {
+                        self.crlf = newline == "crlf";
+                        Ok(())
+                    }
+                    _ => Err(r#"expected "lf" or "crlf""#),
+                },
+                "file_types" if prefix.is_empty() => {
+                    file_types = Some(value);
+                    Ok(())
+                }
+                // Handled by `Settings::parse`, since they apply to the whole application.
+                "tab_bar" | "persistent_undo" if prefix.is_empty() => Ok(()),
+                _ => Err("unknown setting"),
+            };
+
+            if let Err(msg) = res {
+                errors.push(format!("{prefix}{key}: {msg}"));
+            }
+        }
+
+        file_types
+    }
+}
+
+fn bool_setting(value: &Value, dst: &mut bool) -> Result<(), &'static str> {
+    value.as_bool().map(|b| *dst = b).ok_or("expected true or false")
+}
+
+fn find_encoding(name: &str) -> Option<&'static str> {
+    icu::get_available_encodings()
+        .all
+        .iter()
+        .find(|enc| {
+            enc.label.eq_ignore_ascii_case(name) || enc.canonical.eq_ignore_ascii_case(name)
+        })
+        .map(|enc| enc.canonical)
+}
+
+/// The parsed settings file.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Settings {
+    /// Whether the open documents are shown as tabs below the menubar.
+    pub tab_bar: bool,
+    /// Whether the undo history of files is kept across sessions.
+    pub persistent_undo: bool,
+    global: FileSettings,
+    /// The per-file-type overrides in the order they're applied. Already validated.
+    file_types: Vec<(String, Value)>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            tab_bar: false,
+            persistent_undo: false,
+            global: FileSettings::default(),
+            file_types: vec![(
+                "Git Commit".to_string(),
+                Value::from([("rulers", vec![72i64].into())]),
+            )],
+        }
+    }
+}
+
+impl Settings {
+    /// Parses the contents of a settings file. Problems are reported in `errors`,
+    /// but don't prevent the remaining, valid settings from being used.
+    pub fn parse(text: &str, errors: &mut Vec<String>) -> Self {
+        let mut settings = Self::default();
+        if text.trim_ascii().is_empty() {
+            return settings;
+        }
+
+        let Some(root) = json::parse(text) else {
+            errors.push("not a valid JSON file".to_string());
+            return settings;
+        };
+        let Some(obj) = root.as_object() else {
+            errors.push("expected an object".to_string());
+            return settings;
+        };
+
+        for (key, dst) in
+            [("tab_bar", &mut settings.tab_bar), ("persistent_undo", &mut settings.persistent_undo)]
+        {
+            if let Some(value) = root.get(key)
+                && let Err(msg) = bool_setting(value, dst)
+            {
+                errors.push(format!("{key}: {msg}"));
+            }
+        }
+
+        let Some(file_types) = settings.global.merge(obj, "", errors) else {
+            return settings;
+        };
+        let Some(file_types) = file_types.as_object() else {
+            errors.push("file_types: expected an object".to_string());
+            return settings;
+        };
+
+        for (key, value) in file_types {
+            let prefix = format!("file_types.{key}.");
+            if let Some(obj) = value.as_object() {
+                // Validate them now, so that resolving them later can't fail.
+                let mut valid = Vec::new();
+                let mut scratch = FileSettings::default();
+                for entry in obj {
+                    let len = errors.len();
+                    scratch.merge(std::slice::from_ref(entry), &prefix, errors);
+                    if errors.len() == len {
+                        valid.push(entry.clone());
+                    }
+                }
+                settings.file_types.push((key.clone(), Value::Object(valid)));
+            } else {
+                errors.push(format!("file_types.{key}: expected an object"));
+            }
+        }
+
+        settings
+    }
+
+    /// Returns the settings for a document with the given filename and language.
+    pub fn resolve(&self, filename: &str, language: Option<&Language>) -> FileSettings {
+        let mut settings = self.global.clone();
+
+        for (key, value) in &self.file_types {
+            let matches = if key.starts_with('.') {
+                filename.len() > key.len()
+                    && filename.as_bytes()[filename.len() - key.len()..]
+                        .eq_ignore_ascii_case(key.as_bytes())
+            } else {
+                key == filename || language.is_some_and(|l| l.name.eq_ignore_ascii_case(key))
+            };
+            if matches && let Some(obj) = value.as_object() {
+                settings.merge(obj, "", &mut Vec::new());
+            }
+        }
+
+        settings
+    }
+}
+
+/// `$XDG_STATE_HOME/edit/{name}` (or `~/.local/state/...`) on UNIX
+/// and `%LOCALAPPDATA%\\edit\\{name}` on Windows. It's for data that
+/// should persist across sessions, but isn't worth backing up.
+pub fn default_state_path(name: &str) -> Option<PathBuf> {
+    let dir = if cfg!(windows) {
+        PathBuf::from(std::env::var_os("LOCALAPPDATA")?)
+    } else if let Some(dir) = std::env::var_os("XDG_STATE_HOME").filter(|d| !d.is_empty()) {
+        PathBuf::from(dir)
+    } else {
+        Path::new(&std::env::var_os("HOME")?).join(".local").join("state")
+    };
+    Some(path::normalize(&dir.join("edit").join(name)))
+}
+
+/// Keeps track of a config file on disk, like the settings file,
+/// so that it can be reloaded when it changes.
+pub struct ConfigFile {
+    path: PathBuf,
+    /// The modification time and size of the file, the last time it was read.
+    stamp: Option<(SystemTime, u64)>,
+    loaded: bool,
+}
+
+impl ConfigFile {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, stamp: None, loaded: false }
+    }
+
+    /// `$XDG_CONFIG_HOME/edit/{name}` (or `~/.config/...`) on UNIX
+    /// and `%APPDATA%\edit\{name}` on Windows.
+    pub fn default_path(name: &str) -> Option<PathBuf> {
+        let dir = if cfg!(windows) {
+            PathBuf::from(std::env::var_os("APPDATA")?)
+        } else if let Some(dir) = std::env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+            PathBuf::from(dir)
+        } else {
+            Path::new(&std::env::var_os("HOME")?).join(".config")
+        };
+        Some(path::normalize(&dir.join("edit").join(name)))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads the file on the first call and whenever it has changed since, and parses it.
+    /// Returns `None` if it hasn't changed. A missing file is parsed as if it was empty.
+    pub fn poll<T>(
+        &mut self,
+        parse: impl FnOnce(&str, &mut Vec<String>) -> T,
+    ) -> Option<(T, Vec<String>)> {
+        let stamp = fs::metadata(&self.path)
+            .ok()
+            .map(|m| (m.modified().unwrap_or(SystemTime::UNIX_EPOCH), m.len()));
+        if self.loaded && stamp == self.stamp {
+            return None;
+        }
+
+        self.loaded = true;
+        self.stamp = stamp;
+
+        let mut errors = Vec::new();
+        let text = match stamp.map(|_| fs::read_to_string(&self.path)) {
+            None => String::new(),
+            Some(Ok(text)) => text,
+            Some(Err(err)) => {
+                errors.push(err.to_string());
+                String::new()
+            }
+        };
+        let value = parse(&text, &mut errors);
+        Some((value, errors))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+    use crate::harness::lock;
+
+    #[test]
+    fn test_parse() {
+        let _lock = lock();
+        let mut errors = Vec::new();
+        let settings = Settings::parse(
+            r#"{
+                "tab_bar": true,
+                "persistent_undo": true,
+                "tab_size": 2,
+                "indent_style": "tabs",
+                "rulers": [80, 100],
+                "newline": "crlf",
+                "encoding": "utf-8 bom",
+                "word_wrap": "yes",
+                "frobnicate": 1,
+                "file_types": {
+                    "rust": { "tab_size": 8, "line_numbers": false },
+                    ".MD": { "word_wrap": true, "tab_size": 0, "tab_bar": true, "persistent_undo": 1 },
+                    "Makefile": 123
+                }
+            }"#,
+            &mut errors,
+        );
+
+        assert_eq!(
+            errors,
+            [
+                "word_wrap: expected true or false",
+                "frobnicate: unknown setting",
+                "file_types..MD.tab_size: expected an integer between 1 and 8",
+                "file_types..MD.tab_bar: unknown setting",
+                "file_types..MD.persistent_undo: unknown setting",
+                "file_types.Makefile: expected an object",
+            ]
+        );
+
+        assert!(settings.tab_bar);
+        assert!(settings.persistent_undo);
+
+        let global = settings.resolve("foo.txt", None);
+        assert_eq!(global.tab_size, 2);
+        assert!(global.indent_with_tabs);
+        assert_eq!(global.rulers, [80, 100]);
+        assert!(global.crlf);
+        assert_eq!(global.encoding, "UTF-8 BOM");
+        assert!(!global.word_wrap);
+
+        let rust = edit::syntax::language_by_name("Rust");
+        let rust = settings.resolve("main.rs", rust);
+        assert_eq!(rust.tab_size, 8);
+        assert!(!rust.line_numbers);
+        assert!(rust.indent_with_tabs);
+
+        let md = settings.resolve("README.md", None);
+        assert!(md.word_wrap);
+        assert_eq!(md.tab_size, 2);
+
+        // The built-in ruler for commit messages applies unless overridden.
+        let commit = edit::syntax::language_by_name("Git Commit");
+        assert_eq!(settings.resolve("COMMIT_EDITMSG", commit).rulers, [72]);
+    }
+
+    #[test]
+    fn test_invalid_json() {
+        let mut errors = Vec::new();
+        assert_eq!(Settings::parse("{ \"tab_size\": ", &mut errors), Settings::default());
+        assert_eq!(errors, ["not a valid JSON file"]);
+
+        errors.clear();
+        assert_eq!(Settings::parse("  \n", &mut errors), Settings::default());
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn test_poll() {
+        let path = env::temp_dir().join(format!("edit-settings-{}.json", process::id()));
+        _ = fs::remove_file(&path);
+        let mut file = ConfigFile::new(path.clone());
+
+        // A missing file yields the defaults once.
+        assert_eq!(file.poll(Settings::parse), Some((Settings::default(), Vec::new())));
+        assert_eq!(file.poll(Settings::parse), None);
+
+        fs::write(&path, r#"{ "tab_size": 3 }"#).unwrap();
+        let (settings, errors) = file.poll(Settings::parse).unwrap();
+        assert!(errors.is_empty());
+        assert_eq!(settings.resolve("a.txt", None).tab_size, 3);
+        assert_eq!(file.poll(Settings::parse), None);
+
+        // The size differs, so the change is noticed even if the mtime is coarse.
+        fs::write(&path, r#"{ "tab_size": 13 }"#).unwrap();
+        let (settings, errors) = file.poll(Settings::parse).unwrap();
+        assert_eq!(errors, ["tab_size: expected an integer between 1 and 8"]);
+        assert_eq!(settings, Settings::default());
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(file.poll(Settings::parse), Some((Settings::default(), Vec::new())));
+    }
+}
@@ -5,14 +5,19 @@ use std::borrow::Cow;
 use std::ffi::{OsStr, OsString};
 use std::mem;
 use std::path::{Path, PathBuf};
+use std::time::Instant;
 
 use edit::framebuffer::IndexedColor;
 use edit::helpers::*;
 use edit::tui::*;
 use edit::{apperr, buffer, icu, sys};
 
-use crate::documents::DocumentManager;
+use crate::documents::{DiskChange, DocumentManager};
+use crate::find_in_files::FindInFiles;
 use crate::localization::*;
+use crate::panes::PaneManager;
+use crate::plugins::PluginHost;
+use crate::swap::{Recoverable, SwapFiles};
 
 #[repr(transparent)]
 pub struct FormatApperr(apperr::Error);
@@ -125,6 +130,8 @@ pub struct State {
     pub menubar_color_fg: u32,
 
     pub documents: DocumentManager,
+    pub panes: PaneManager,
+    pub plugins: PluginHost,
 
     // A ring buffer of the last 10 errors.
     pub error_log: [String; 10],
@@ -143,20 +150,39 @@ pub struct State {
     pub search_replacement: String,
     pub search_options: buffer::SearchOptions,
     pub search_success: bool,
+    /// The match count shown in the search bar, which is that of the active document.
+    pub search_match_count: Option<buffer::SearchMatchCount>,
+    pub find_in_files: FindInFiles,
 
     pub wants_encoding_picker: bool,
     pub encoding_picker_needle: String,
     pub encoding_picker_results: Option<Vec<icu::Encoding>>,
 
     pub wants_save: bool,
+    pub file_watcher: Option<sys::FileWatcher>,
+    pub disk_poll: bool, // Whether some files need to be polled for changes on disk.
+    pub disk_check_time: Instant, // When the files were last checked for changes on disk.
+    pub disk_changes: Vec<(u64, DiskChange)>, // Document IDs to prompt about, in order.
+    pub swap: Option<SwapFiles>,
+    pub recovery: Vec<Recoverable>,
+    pub recovery_selected: usize,
     pub wants_statusbar_focus: bool,
     pub wants_encoding_change: StateEncodingChange,
     pub wants_indentation_picker: bool,
     pub wants_document_picker: bool,
+    pub wants_command_palette: bool,
+    pub command_palette_needle: String,
+    pub tab_bar: bool,
+    pub tab_scroll: usize,      // The index of the first visible tab.
+    pub tab_scroll_active: u64, // The active document when `tab_scroll` was last adjusted for it.
+    pub tab_drag_start: Option<CoordType>, // The center of the dragged tab when the drag started.
     pub wants_about: bool,
     pub wants_close: bool,
     pub wants_exit: bool,
     pub wants_goto: bool,
+    pub wants_history: bool,
+    pub history_selected: usize, // The ID of the selected state.
+    pub history_preview: Option<(usize, Vec<(String, bool)>)>, // For the state with the given ID.
     pub goto_target: String,
     pub goto_invalid: bool,
 
@@ -174,6 +200,8 @@ impl State {
             menubar_color_fg: 0,
 
             documents: Default::default(),
+            panes: Default::default(),
+            plugins: Default::default(),
 
             error_log: [const { String::new() }; 10],
             error_log_index: 0,
@@ -191,20 +219,38 @@ impl State {
             search_replacement: Default::default(),
             search_options: Default::default(),
             search_success: true,
+            search_match_count: None,
+            find_in_files: Default::default(),
 
             wants_encoding_picker: false,
             encoding_picker_needle: Default::default(),
             encoding_picker_results: Default::default(),
 
             wants_save: false,
+            file_watcher: None,
+            disk_poll: false,
+            disk_check_time: Instant::now(),
+            disk_changes: Vec::new(),
+            swap: None,
+            recovery: Vec::new(),
+            recovery_selected: 0,
             wants_statusbar_focus: false,
             wants_encoding_change: StateEncodingChange::None,
             wants_indentation_picker: false,
             wants_document_picker: false,
+            wants_command_palette: false,
+            command_palette_needle: Default::default(),
+            tab_bar: false,
+            tab_scroll: 0,
+            tab_scroll_active: 0,
+            tab_drag_start: None,
             wants_about: false,
             wants_close: false,
             wants_exit: false,
             wants_goto: false,
+            wants_history: false,
+            history_selected: 0,
+            history_preview: None,
             goto_target: Default::default(),
             goto_invalid: false,
 
@@ -226,13 +272,19 @@ pub fn draw_add_untitled_document(ctx: &mut Context, state: &mut State) {
 pub fn error_log_add(ctx: &mut Context, state: &mut State, err: apperr::Error) {
     let msg = format!("{}", FormatApperr::from(err));
     if !msg.is_empty() {
-        state.error_log[state.error_log_index] = msg;
-        state.error_log_index = (state.error_log_index + 1) % state.error_log.len();
-        state.error_log_count = state.error_log.len().min(state.error_log_count + 1);
+        error_log_add_message(state, msg);
         ctx.needs_rerender();
     }
 }
 
+/// Like [`error_log_add`], but for errors that aren't [`apperr::Error`]s and
+/// outside of a UI pass. The error dialog will show up during the next one.
+pub fn error_log_add_message(state: &mut State, msg: String) {
+    state.error_log[state.error_log_index] = msg;
+    state.error_log_index = (state.error_log_index + 1) % state.error_log.len();
+    state.error_log_count = state.error_log.len().min(state.error_log_count + 1);
+}
+
 pub fn draw_error_log(ctx: &mut Context, state: &mut State) {
     ctx.modal_begin("error", loc(LocId::ErrorDialogTitle));
     ctx.attr_background_rgba(ctx.indexed(IndexedColor::Red));
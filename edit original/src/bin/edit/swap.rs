@@ -0,0 +1,576 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Swap files, so that unsaved changes survive a crash.
+//!
+//! While a document has unsaved changes, its contents are periodically written to
+//! a file in the swap directory. The writing happens on a background thread.
+//! Saving or closing the document removes the file again. Files left behind by an
+//! editor that isn't running anymore are offered for recovery on the next start.
+//!
+//! Each editor names its swap files after a session key made of its process ID and
+//! start time. While it has swap files, it holds an exclusive lock on the file
+//! `<session>.lock`. The lock is what tells whether it's still running: the
+//! process ID alone may have been reused by another process since.
+//!
+//! A swap file starts with a line of JSON describing the document (path, encoding,
+//! newlines and cursor), which is followed by the contents of its buffer.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write as _};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use edit::buffer::{BufferSnapshot, CursorMovement, TextBuffer};
+use edit::diff::{self, DiffLine, DiffOp};
+use edit::document::ReadableDocument;
+use edit::framebuffer::{Attributes, IndexedColor};
+use edit::helpers::*;
+use edit::input::vk;
+use edit::json::{self, Value};
+use edit::tui::*;
+use edit::{apperr, icu, sys};
+
+use crate::documents::DocumentManager;
+use crate::localization::*;
+use crate::settings;
+use crate::state::*;
+
+/// How often the swap files are updated while there are unsaved changes.
+pub const SWAP_INTERVAL: Duration = Duration::from_secs(2);
+
+const EXTENSION: &str = "swp";
+const LOCK_EXTENSION: &str = "lock";
+const TMP_EXTENSION: &str = "tmp";
+const VERSION: i64 = 1;
+/// The number of unchanged lines shown around each change in the recovery dialog.
+const DIFF_CONTEXT: usize = 2;
+
+enum Job {
+    /// `written` identifies the document and the buffer generation, for reporting failures.
+    Write {
+        written: (u64, u32),
+        path: PathBuf,
+        header: String,
+        contents: BufferSnapshot,
+    },
+    Remove(PathBuf),
+}
+
+pub struct SwapFiles {
+    dir: PathBuf,
+    /// Identifies the files of this editor. See the module documentation.
+    session: String,
+    /// The documents that have a swap file, with the buffer generation that's in it.
+    written: Vec<(u64, u32)>,
+    last_write: Option<Instant>,
+    sender: Option<mpsc::Sender<Job>>,
+    /// The writes that failed, as found in `written`, so that they're retried.
+    failures: mpsc::Receiver<(u64, u32)>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl SwapFiles {
+    pub fn new(dir: PathBuf) -> Self {
+        let started = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+        let session = format!("{}-{:x}", std::process::id(), started.as_nanos());
+        let lock_path = lock_path(&dir, &session);
+
+        let (sender, receiver) = mpsc::channel();
+        let (failure_sender, failures) = mpsc::channel();
+        let thread = thread::Builder::new()
+            .name("swap".into())
+            .spawn(move || {
+                // Taken before the first swap file is written and held until the editor exits.
+                let mut lock = None;
+                for job in receiver {
+                    let written = match job {
+                        Job::Write { written, .. } => Some(written),
+                        Job::Remove(_) => None,
+                    };
+                    if written.is_some() && lock.is_none() {
+                        lock = acquire_lock(&lock_path);
+                    }
+                    // Without the lock, another editor would take the file for a leftover.
+                    let ok = (written.is_none() || lock.is_some()) && run_job(job).is_ok();
+                    if !ok && let Some(written) = written {
+                        _ = failure_sender.send(written);
+                    }
+                }
+                if lock.take().is_some() {
+                    _ = fs::remove_file(&lock_path);
+                }
+            })
+            .ok();
+        let sender = thread.is_some().then_some(sender);
+        Self { dir, session, written: Vec::new(), last_write: None, sender, failures, thread }
+    }
+
+    /// See [`settings::default_state_path`].
+    pub fn default_dir() -> Option<PathBuf> {
+        settings::default_state_path("swap")
+    }
+
+    /// Returns true if there are unsaved changes that aren't in a swap file yet.
+    pub fn pending(&self, documents: &DocumentManager) -> bool {
+        documents.iter().any(|doc| {
+            let tb = doc.buffer.borrow();
+            tb.is_dirty() && !self.written.contains(&(doc.id, tb.generation()))
+        })
+    }
+
+    /// Removes the swap files of documents that were saved or closed in the meantime
+    /// and writes those with unsaved changes, if the last write was [`SWAP_INTERVAL`] ago.
+    pub fn update(&mut self, documents: &DocumentManager) {
+        let mut jobs = Vec::new();
+
+        for failure in self.failures.try_iter() {
+            self.written.retain(|&written| written != failure);
+        }
+
+        self.written.retain(|&(id, _)| {
+            let keep = documents.iter().any(|doc| doc.id == id && doc.buffer.borrow().is_dirty());
+            if !keep {
+                jobs.push(Job::Remove(swap_path(&self.dir, &self.session, id)));
+            }
+            keep
+        });
+
+        if self.last_write.is_none_or(|t| t.elapsed() >= SWAP_INTERVAL) && self.pending(documents) {
+            self.last_write = Some(Instant::now());
+            for doc in documents.iter() {
+                let tb = doc.buffer.borrow();
+                let generation = tb.generation();
+                if !tb.is_dirty() || self.written.contains(&(doc.id, generation)) {
+                    continue;
+                }
+
+                let pos = tb.cursor_logical_pos();
+                let header = Value::from([
+                    ("version", VERSION.into()),
+                    ("path", doc.path.as_ref().map(|p| p.to_string_lossy().into_owned()).into()),
+                    ("name", doc.filename.as_str().into()),
+                    ("encoding", tb.encoding().into()),
+                    ("crlf", tb.is_crlf().into()),
+                    ("line", (pos.y as i64).into()),
+                    ("column", (pos.x as i64).into()),
+                ]);
+                jobs.push(Job::Write {
+                    written: (doc.id, generation),
+                    path: swap_path(&self.dir, &self.session, doc.id),
+                    header: header.to_string(),
+                    contents: tb.snapshot(),
+                });
+
+                self.written.retain(|&(id, _)| id != doc.id);
+                self.written.push((doc.id, generation));
+            }
+        }
+
+        if let Some(sender) = &self.sender {
+            for job in jobs {
+                _ = sender.send(job);
+            }
+        }
+    }
+}
+
+impl Drop for SwapFiles {
+    fn drop(&mut self) {
+        // Closing the channel ends the thread once it's done with the queued jobs.
+        self.sender = None;
+        if let Some(thread) = self.thread.take() {
+            _ = thread.join();
+        }
+    }
+}
+
+fn swap_path(dir: &Path, session: &str, id: u64) -> PathBuf {
+    dir.join(format!("{session}-{id}.{EXTENSION}"))
+}
+
+fn lock_path(dir: &Path, session: &str) -> PathBuf {
+    dir.join(format!("{session}.{LOCK_EXTENSION}"))
+}
+
+fn create_dir(dir: &Path) -> io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        // The contents are nobody else's business.
+        use std::os::unix::fs::DirBuilderExt as _;
+        builder.mode(0o700);
+    }
+    builder.create(dir)
+}
+
+fn acquire_lock(path: &Path) -> Option<File> {
+    create_dir(path.parent().unwrap()).ok()?;
+    let file = File::create(path).ok()?;
+    sys::file_try_lock(&file).is_ok_and(|locked| locked).then_some(file)
+}
+
+/// Returns true if the editor of the given session still holds its lock.
+/// Its lock file being gone means that it exited or never wrote a swap file.
+fn session_alive(dir: &Path, session: &str) -> bool {
+    File::open(lock_path(dir, session))
+        .is_ok_and(|file| sys::file_try_lock(&file).is_ok_and(|locked| !locked))
+}
+
+fn run_job(job: Job) -> io::Result<()> {
+    match job {
+        Job::Write { path, header, contents, .. } => {
+            create_dir(path.parent().unwrap())?;
+
+            // A crash while writing must not destroy the previous swap file.
+            let tmp = path.with_extension(TMP_EXTENSION);
+            {
+                let mut file = BufWriter::new(File::create(&tmp)?);
+                file.write_all(header.as_bytes())?;
+                file.write_all(b"\n")?;
+                let mut off = 0;
+                loop {
+                    let chunk = contents.read_forward(off);
+                    if chunk.is_empty() {
+                        break;
+                    }
+                    file.write_all(chunk)?;
+                    off += chunk.len();
+                }
+                file.flush()?;
+            }
+            fs::rename(tmp, path)
+        }
+        Job::Remove(path) => fs::remove_file(path),
+    }
+}
+
+/// A swap file left behind by an editor that didn't exit normally.
+pub struct Recoverable {
+    swap_path: PathBuf,
+    pub path: Option<PathBuf>,
+    pub name: String,
+    pub modified: SystemTime,
+    encoding: &'static str,
+    crlf: bool,
+    cursor: Point,
+    contents: Vec<u8>,
+    /// The changes compared to the file on disk, with a few lines of context around each.
+    /// `None` separates the parts that are far apart.
+    pub diff: Vec<Option<(DiffOp, String)>>,
+    pub added: usize,
+    pub removed: usize,
+}
+
+impl Recoverable {
+    /// Opens the document with the recovered contents. They replace what's on disk
+    /// as a single edit, so that it can be undone. The swap file is removed.
+    pub fn recover(&self, documents: &mut DocumentManager) -> apperr::Result<()> {
+        let doc = match &self.path {
+            Some(path) => documents.add_file_path(path)?,
+            None => documents.add_untitled()?,
+        };
+
+        {
+            let mut tb = doc.buffer.borrow_mut();
+            tb.select_all();
+            if self.contents.is_empty() {
+                tb.delete(CursorMovement::Grapheme, 1);
+            } else {
+                tb.write(&self.contents, true);
+            }
+            tb.set_encoding(self.encoding);
+            tb.set_crlf(self.crlf);
+            tb.cursor_move_to_logical(self.cursor);
+            tb.make_cursor_visible();
+        }
+
+        self.discard();
+        Ok(())
+    }
+
+    pub fn discard(&self) {
+        _ = fs::remove_file(&self.swap_path);
+    }
+}
+
+/// Returns the swap files in `dir` of editors that aren't running anymore, newest first.
+/// The lock files they left behind without any swap files are removed,
+/// as are the temporary files of writes they didn't get to finish.
+pub fn find_recoverable(dir: &Path) -> Vec<Recoverable> {
+    let mut result = Vec::new();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return result;
+    };
+
+    let mut sessions = Vec::new();
+    let mut locks = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        if path.extension().is_some_and(|ext| ext == LOCK_EXTENSION) {
+            locks.push(stem.to_string());
+            continue;
+        }
+        if path.extension().is_some_and(|ext| ext == TMP_EXTENSION) {
+            if let Some((session, _)) = stem.rsplit_once('-')
+                && !session_alive(dir, session)
+            {
+                _ = fs::remove_file(&path);
+            }
+            continue;
+        }
+        if path.extension().is_none_or(|ext| ext != EXTENSION) {
+            continue;
+        }
+        let Some((session, _)) = stem.rsplit_once('-') else {
+            continue;
+        };
+        if !sessions.iter().any(|s| s == session) {
+            sessions.push(session.to_string());
+        }
+        if session_alive(dir, session) {
+            continue;
+        }
+        if let Some(recoverable) = read_swap_file(path) {
+            result.push(recoverable);
+        }
+    }
+
+    for session in locks {
+        if !sessions.contains(&session) && !session_alive(dir, &session) {
+            _ = fs::remove_file(lock_path(dir, &session));
+        }
+    }
+
+    result.sort_by_key(|r| std::cmp::Reverse(r.modified));
+    result
+}
+
+fn read_swap_file(swap_path: PathBuf) -> Option<Recoverable> {
+    let modified = fs::metadata(&swap_path).and_then(|m| m.modified()).ok()?;
+    let mut contents = fs::read(&swap_path).ok()?;
+    let header_len = contents.iter().position(|&b| b == b'\n')?;
+    let header = json::parse(str::from_utf8(&contents[..header_len]).ok()?)?;
+    if header.get("version")?.as_i64()? != VERSION {
+        return None;
+    }
+    contents.drain(..=header_len);
+
+    let path = match header.get("path")? {
+        Value::Null => None,
+        path => Some(PathBuf::from(path.as_str()?)),
+    };
+    let encoding = header.get("encoding")?.as_str()?;
+    let encoding = icu::get_available_encodings()
+        .all
+        .iter()
+        .find(|e| e.canonical == encoding)
+        .map_or("UTF-8", |e| e.canonical);
+    let cursor = Point {
+        x: header.get("column")?.as_i64()? as CoordType,
+        y: header.get("line")?.as_i64()? as CoordType,
+    };
+
+    let disk = path.as_deref().map(|p| read_disk(p, encoding)).unwrap_or_default();
+    let lines = diff::diff_lines(&disk, &contents);
+
+    Some(Recoverable {
+        swap_path,
+        path,
+        name: header.get("name")?.as_str()?.to_string(),
+        modified,
+        encoding,
+        crlf: header.get("crlf")?.as_bool()?,
+        cursor,
+        diff: diff_preview(&lines),
+        added: lines.iter().filter(|l| l.op == DiffOp::Insert).count(),
+        removed: lines.iter().filter(|l| l.op == DiffOp::Delete).count(),
+        contents,
+    })
+}
+
+/// Reads the file the same way as when it's opened, so that it compares equal
+/// to the buffer contents regardless of its encoding.
+fn read_disk(path: &Path, encoding: &'static str) -> Vec<u8> {
+    let mut text = String::new();
+    if let Ok(mut file) = File::open(path)
+        && let Ok(mut tb) = TextBuffer::new(false)
+        && tb.read_file(&mut file, Some(encoding)).is_ok()
+    {
+        tb.save_as_string(&mut text);
+    }
+    text.into_bytes()
+}
+
+fn diff_preview(lines: &[DiffLine]) -> Vec<Option<(DiffOp, String)>> {
+    let mut shown = vec![false; lines.len()];
+    for (i, line) in lines.iter().enumerate() {
+        if line.op != DiffOp::Equal {
+            let beg = i.saturating_sub(DIFF_CONTEXT);
+            let end = (i + DIFF_CONTEXT + 1).min(lines.len());
+            shown[beg..end].fill(true);
+        }
+    }
+
+    let mut preview = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        if !shown[i] {
+            continue;
+        }
+        if i > 0 && !shown[i - 1] && !preview.is_empty() {
+            preview.push(None);
+        }
+        let text = String::from_utf8_lossy(line.text);
+        preview.push(Some((line.op, text.trim_end_matches(['\r', '\n']).to_string())));
+    }
+    preview
+}
+
+pub fn draw_recovery_dialog(ctx: &mut Context, state: &mut State) {
+    enum Action {
+        None,
+        Recover,
+        Discard,
+        Later,
+    }
+    let mut action = Action::None;
+
+    state.recovery_selected = state.recovery_selected.min(state.recovery.len() - 1);
+    let width = (ctx.size().width - 20).max(20);
+
+    ctx.modal_begin("recovery", loc(LocId::RecoveryDialogTitle));
+    {
+        let contains_focus = ctx.contains_focus();
+
+        ctx.label("description", loc(LocId::RecoveryDialogDescription));
+        ctx.attr_overflow(Overflow::TruncateTail);
+        ctx.attr_padding(Rect::three(1, 2, 1));
+
+        ctx.scrollarea_begin(
+            "entries",
+            Size { width, height: (state.recovery.len() as CoordType).clamp(1, 5) },
+        );
+        ctx.attr_background_rgba(ctx.indexed_alpha(IndexedColor::Black, 1, 4));
+        ctx.inherit_focus();
+        {
+            ctx.list_begin("entries");
+            ctx.inherit_focus();
+            for (idx, entry) in state.recovery.iter().enumerate() {
+                ctx.styled_list_item_begin();
+                ctx.attr_overflow(Overflow::TruncateTail);
+                ctx.styled_label_add_text(&entry.name);
+                ctx.styled_label_add_text(&format!("  +{} -{}", entry.added, entry.removed));
+                if let Some(dir) = entry.path.as_deref().and_then(Path::parent) {
+                    ctx.styled_label_add_text("   ");
+                    ctx.styled_label_set_attributes(Attributes::Italic);
+                    ctx.styled_label_add_text(&dir.to_string_lossy());
+                }
+                match ctx.styled_list_item_end(idx == state.recovery_selected) {
+                    ListSelection::Unchanged => {}
+                    ListSelection::Selected => state.recovery_selected = idx,
+                    ListSelection::Activated => {
+                        state.recovery_selected = idx;
+                        action = Action::Recover;
+                    }
+                }
+            }
+            ctx.list_end();
+        }
+        ctx.scrollarea_end();
+        ctx.attr_padding(Rect::three(0, 2, 0));
+
+        // The changes of the selected one.
+        ctx.scrollarea_begin("diff", Size { width, height: 10 });
+        ctx.attr_padding(Rect::three(1, 2, 1));
+        {
+            let entry = &state.recovery[state.recovery_selected];
+            for (idx, line) in entry.diff.iter().enumerate() {
+                ctx.next_block_id_mixin(idx as u64);
+                match line {
+                    None => {
+                        ctx.label("line", "…");
+                        ctx.attr_foreground_rgba(ctx.indexed(IndexedColor::BrightBlack));
+                    }
+                    Some((op, text)) => {
+                        let (prefix, color) = match op {
+                            DiffOp::Equal => (' ', None),
+                            DiffOp::Delete => ('-', Some(IndexedColor::BrightRed)),
+                            DiffOp::Insert => ('+', Some(IndexedColor::BrightGreen)),
+                        };
+                        ctx.label("line", &format!("{prefix}{text}"));
+                        ctx.attr_overflow(Overflow::TruncateTail);
+                        if let Some(color) = color {
+                            ctx.attr_foreground_rgba(ctx.indexed(color));
+                        }
+                    }
+                }
+            }
+        }
+        ctx.scrollarea_end();
+
+        ctx.table_begin("choices");
+        ctx.inherit_focus();
+        ctx.attr_padding(Rect::three(0, 2, 1));
+        ctx.attr_position(Position::Center);
+        ctx.table_set_cell_gap(Size { width: 2, height: 0 });
+        {
+            ctx.table_next_row();
+
+            if ctx.button(
+                "recover",
+                loc(LocId::RecoveryDialogRecover),
+                ButtonStyle::default().accelerator('R'),
+            ) {
+                action = Action::Recover;
+            }
+            if ctx.button(
+                "discard",
+                loc(LocId::RecoveryDialogDiscard),
+                ButtonStyle::default().accelerator('D'),
+            ) {
+                action = Action::Discard;
+            }
+            if ctx.button(
+                "later",
+                loc(LocId::RecoveryDialogLater),
+                ButtonStyle::default().accelerator('L'),
+            ) {
+                action = Action::Later;
+            }
+        }
+        ctx.table_end();
+
+        if contains_focus {
+            if ctx.consume_shortcut(vk::R) {
+                action = Action::Recover;
+            } else if ctx.consume_shortcut(vk::D) {
+                action = Action::Discard;
+            } else if ctx.consume_shortcut(vk::L) {
+                action = Action::Later;
+            }
+        }
+    }
+    if ctx.modal_end() {
+        action = Action::Later;
+    }
+
+    match action {
+        Action::None => return,
+        Action::Recover => {
+            let entry = state.recovery.remove(state.recovery_selected);
+            if let Err(err) = entry.recover(&mut state.documents) {
+                error_log_add(ctx, state, err);
+            }
+        }
+        Action::Discard => state.recovery.remove(state.recovery_selected).discard(),
+        // They'll be offered again next time.
+        Action::Later => state.recovery.clear(),
+    }
+    ctx.needs_rerender();
+}
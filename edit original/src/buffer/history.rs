@@ -0,0 +1,448 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The undo history, which is a tree of edits: Undoing and then making a new edit
+//! starts a new branch, but the undone edits stay reachable via [`TextBuffer::history_goto`].
+//!
+//! Each node is a state of the document, reached by applying the node's [`HistoryEntry`]
+//! to the state of its parent. The entries are stored the way they get applied next:
+//! Those on the path from the initial to the current state undo their edit,
+//! all others redo it. Applying an entry flips it. See [`TextBuffer::undo_redo`].
+
+use std::time::SystemTime;
+
+use super::{HistoryEntry, TextBuffer};
+use crate::cell::SemiRefCell;
+use crate::helpers::*;
+use crate::simd;
+use crate::unicode::{self, Cursor, MeasurementConfig};
+
+/// Beyond this many edits, the oldest ones are dropped.
+const MAX_HISTORY: usize = 1000;
+/// How many edits are left after dropping the oldest ones. Dropping them in
+/// batches keeps the cost of rebuilding the history off of every single edit.
+const PRUNED_HISTORY: usize = MAX_HISTORY * 3 / 4;
+
+pub(super) struct HistoryNode {
+    pub entry: SemiRefCell<HistoryEntry>,
+    /// The state this one was reached from. 0 is the initial state.
+    pub parent: usize,
+    /// The child that redo leads to: The one that was created or visited last.
+    pub redo: Option<usize>,
+    /// When the edit was made. For edits that were grouped together, the last one.
+    pub time: SystemTime,
+}
+
+impl Clone for HistoryNode {
+    fn clone(&self) -> Self {
+        Self {
+            entry: SemiRefCell::new(self.entry.borrow().clone()),
+            parent: self.parent,
+            redo: self.redo,
+            time: self.time,
+        }
+    }
+}
+
+/// The states are identified by their index in `nodes` plus 1.
+/// 0 is the initial state, from when the contents were last replaced as a whole.
+/// Parents always come before their children.
+#[derive(Clone, Default)]
+pub(super) struct History {
+    pub nodes: Vec<HistoryNode>,
+    pub current: usize,
+    /// The child of the initial state that redo leads to.
+    pub root_redo: Option<usize>,
+    /// The state that was last saved, unless it was dropped from the history.
+    pub saved: Option<usize>,
+}
+
+impl History {
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn node(&self, id: usize) -> &HistoryNode {
+        &self.nodes[id - 1]
+    }
+
+    pub fn parent(&self, id: usize) -> usize {
+        self.node(id).parent
+    }
+
+    /// The child of `id` that redo leads to.
+    pub fn redo_of(&self, id: usize) -> Option<usize> {
+        if id == 0 { self.root_redo } else { self.node(id).redo }
+    }
+
+    fn set_redo(&mut self, id: usize, redo: Option<usize>) {
+        if id == 0 {
+            self.root_redo = redo;
+        } else {
+            self.nodes[id - 1].redo = redo;
+        }
+    }
+
+    /// The entry that led to the current state.
+    pub fn current_entry(&self) -> Option<&SemiRefCell<HistoryEntry>> {
+        self.nodes.get(self.current.wrapping_sub(1)).map(|n| &n.entry)
+    }
+
+    /// Notes that the entry of the current state was extended by another edit.
+    pub fn touch_current(&mut self) {
+        if let Some(node) = self.nodes.get_mut(self.current.wrapping_sub(1)) {
+            node.time = SystemTime::now();
+        }
+    }
+
+    /// Adds a new state as a child of the current one and makes it the current one.
+    /// Call [`History::prune`] afterwards to stay within the size limit.
+    pub fn push(&mut self, entry: HistoryEntry) {
+        self.nodes.push(HistoryNode {
+            entry: SemiRefCell::new(entry),
+            parent: self.current,
+            redo: None,
+            time: SystemTime::now(),
+        });
+        let id = self.nodes.len();
+        self.set_redo(self.current, Some(id));
+        self.current = id;
+    }
+
+    /// Drops the oldest edits once there are more than the size limit.
+    pub fn prune(&mut self) {
+        if self.nodes.len() > MAX_HISTORY {
+            self.prune_to(PRUNED_HISTORY);
+        }
+    }
+
+    /// Drops all states from `len + 1` on, which must not be the current one.
+    /// Redo from `state` leads to `redo` afterwards.
+    pub fn truncate(&mut self, len: usize, state: usize, redo: Option<usize>) {
+        debug_assert!(self.current <= len);
+        self.nodes.truncate(len);
+        for node in &mut self.nodes {
+            node.redo = node.redo.filter(|&id| id <= len);
+        }
+        self.root_redo = self.root_redo.filter(|&id| id <= len);
+        self.saved = self.saved.filter(|&id| id <= len);
+        self.set_redo(state, redo);
+    }
+
+    /// Makes `id` the current state. `redo` is true if it's a child of the current one.
+    pub fn step(&mut self, id: usize, redo: bool) {
+        if redo {
+            self.set_redo(self.parent(id), Some(id));
+            self.current = id;
+        } else {
+            self.current = self.parent(id);
+        }
+    }
+
+    fn depth(&self, mut id: usize) -> usize {
+        let mut depth = 0;
+        while id != 0 {
+            id = self.parent(id);
+            depth += 1;
+        }
+        depth
+    }
+
+    /// Returns true if `ancestor` is `id` or one of its ancestors.
+    pub fn is_ancestor(&self, ancestor: usize, mut id: usize) -> bool {
+        // Parents come before their children.
+        while id > ancestor {
+            id = self.parent(id);
+        }
+        id == ancestor
+    }
+
+    /// Returns the states whose entries need to be undone to get from `from` to
+    /// the common ancestor with `to`, followed by those to redo to get to `to`.
+    pub fn path(&self, mut from: usize, mut to: usize) -> (Vec<usize>, Vec<usize>) {
+        let mut up = Vec::new();
+        let mut down = Vec::new();
+        let mut from_depth = self.depth(from);
+        let mut to_depth = self.depth(to);
+
+        while from_depth > to_depth {
+            up.push(from);
+            from = self.parent(from);
+            from_depth -= 1;
+        }
+        while to_depth > from_depth {
+            down.push(to);
+            to = self.parent(to);
+            to_depth -= 1;
+        }
+        while from != to {
+            up.push(from);
+            from = self.parent(from);
+            down.push(to);
+            to = self.parent(to);
+        }
+
+        down.reverse();
+        (up, down)
+    }
+
+    /// Drops the oldest edit, one at a time, until at most `len` states are left.
+    /// Returns the new ID of each state, if it was kept.
+    ///
+    /// If the current state is based on the oldest edit, the state after it becomes the
+    /// initial one. All other branches off the initial state have to go along with it.
+    /// Otherwise the oldest edit is on a different branch, which is dropped entirely.
+    pub fn prune_to(&mut self, len: usize) -> Vec<Option<usize>> {
+        #[derive(Clone, Copy, PartialEq, Eq)]
+        enum Fate {
+            Keep,
+            Drop,
+            /// Became the initial state. Its children are kept.
+            Root,
+        }
+
+        // The number of states in the subtree of each one, itself included.
+        // Parents come before their children, so a backwards pass adds them all up.
+        let mut sizes = vec![1; self.nodes.len() + 1];
+        for id in (1..=self.nodes.len()).rev() {
+            sizes[self.parent(id)] += sizes[id];
+        }
+        let mut on_path = vec![false; self.nodes.len() + 1];
+        let mut id = self.current;
+        while id != 0 {
+            on_path[id] = true;
+            id = self.parent(id);
+        }
+
+        // Decide what happens to each state in order, which is the order they're dropped in:
+        // The first one that's kept so far is the oldest edit at that point.
+        let mut fates = vec![Fate::Keep; self.nodes.len() + 1];
+        fates[0] = Fate::Root;
+        let mut root = 0;
+        let mut root_redo = self.root_redo;
+        let mut left = self.nodes.len();
+        for id in 1..=self.nodes.len() {
+            let parent = self.parent(id);
+            // Descendants of dropped states and the other branches of a former initial state.
+            if fates[parent] == Fate::Drop || (fates[parent] == Fate::Root && parent != root) {
+                fates[id] = Fate::Drop;
+                continue;
+            }
+            if left <= len {
+                continue;
+            }
+            if on_path[id] {
+                // Everything but its own subtree is gone now.
+                fates[id] = Fate::Root;
+                root = id;
+                root_redo = self.node(id).redo;
+                left = sizes[id] - 1;
+            } else {
+                fates[id] = Fate::Drop;
+                left -= sizes[id];
+            }
+        }
+
+        let mut remap = vec![None; self.nodes.len() + 1];
+        remap[0] = Some(0);
+        remap[root] = Some(0);
+
+        let mut nodes = Vec::with_capacity(left);
+        for (idx, node) in self.nodes.drain(..).enumerate() {
+            if fates[idx + 1] == Fate::Keep {
+                let parent = remap[node.parent].unwrap();
+                nodes.push(HistoryNode { parent, ..node });
+                remap[idx + 1] = Some(nodes.len());
+            }
+        }
+        self.nodes = nodes;
+
+        for node in &mut self.nodes {
+            node.redo = node.redo.and_then(|id| remap[id]);
+        }
+        self.current = remap[self.current].unwrap_or(0);
+        self.saved = self.saved.and_then(|id| remap[id]);
+        // Redo from the initial state should still lead somewhere, if possible.
+        self.root_redo = root_redo
+            .and_then(|id| remap[id])
+            .or_else(|| self.nodes.iter().rposition(|n| n.parent == 0).map(|idx| idx + 1));
+
+        remap
+    }
+}
+
+/// How a state relates to the current one. See [`HistoryState`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HistoryStateKind {
+    Current,
+    /// The current state is based on it. It can be reached with undo.
+    Undo,
+    /// It's based on the current state, so it can be reached by redoing edits.
+    Redo,
+    /// It's on another branch.
+    Branch,
+}
+
+/// A state in the undo history. See [`TextBuffer::history`].
+#[derive(Clone, Debug)]
+pub struct HistoryState {
+    /// Identifies the state for [`TextBuffer::history_goto`] and [`TextBuffer::history_text`],
+    /// until the next edit. 0 is the initial state.
+    pub id: usize,
+    pub kind: HistoryStateKind,
+    /// Whether it's the state that was last saved.
+    pub saved: bool,
+    /// When the edit that led to this state was made. `None` for the initial state.
+    pub time: Option<SystemTime>,
+    /// Where the edit took place.
+    pub position: Point,
+    /// The number of bytes the edit removed and added.
+    pub deleted_len: usize,
+    pub inserted_len: usize,
+    /// The start of the first line of the text the edit added, or else removed.
+    pub excerpt: String,
+}
+
+impl TextBuffer {
+    /// Returns the states in the undo history, from the oldest to the newest.
+    /// States in the middle of an edit that gets undone as a whole are skipped.
+    pub fn history(&self) -> Vec<HistoryState> {
+        let h = &self.history;
+        let kind = |id| {
+            if id == h.current {
+                HistoryStateKind::Current
+            } else if h.is_ancestor(id, h.current) {
+                HistoryStateKind::Undo
+            } else if h.is_ancestor(h.current, id) {
+                HistoryStateKind::Redo
+            } else {
+                HistoryStateKind::Branch
+            }
+        };
+
+        let mut states = vec![HistoryState {
+            id: 0,
+            kind: kind(0),
+            saved: h.saved == Some(0),
+            time: None,
+            position: Point::default(),
+            deleted_len: 0,
+            inserted_len: 0,
+            excerpt: String::new(),
+        }];
+        let mut grouped = vec![false; h.nodes.len() + 1];
+
+        for (idx, node) in h.nodes.iter().enumerate() {
+            let id = idx + 1;
+            let entry = node.entry.borrow();
+            if entry.linked {
+                grouped[node.parent] = true;
+            }
+
+            let kind = kind(id);
+            // Entries are stored the way they get applied next. See the module docs.
+            let (deleted, inserted) =
+                if matches!(kind, HistoryStateKind::Current | HistoryStateKind::Undo) {
+                    (&entry.deleted, &entry.added)
+                } else {
+                    (&entry.added, &entry.deleted)
+                };
+            let text = if inserted.is_empty() { deleted } else { inserted };
+            let line = text.split(|&c| c == b'\n' || c == b'\r').find(|l| !l.is_empty());
+            let line = line.unwrap_or_default();
+            let excerpt = String::from_utf8_lossy(&line[..line.len().min(64)]).into_owned();
+
+            states.push(HistoryState {
+                id,
+                kind,
+                saved: h.saved == Some(id),
+                time: Some(node.time),
+                position: entry.cursor,
+                deleted_len: deleted.len(),
+                inserted_len: inserted.len(),
+                excerpt,
+            });
+        }
+
+        let mut idx = 0;
+        states.retain(|_| {
+            idx += 1;
+            !grouped[idx - 1]
+        });
+        states
+    }
+
+    /// Returns the ID of the state as of the given `time`, i.e. after the last edit made before it.
+    pub fn history_at_time(&self, time: SystemTime) -> usize {
+        self.history().iter().rev().find(|s| s.time.is_none_or(|t| t <= time)).map_or(0, |s| s.id)
+    }
+
+    /// Returns the ID of the current state.
+    pub fn history_current(&self) -> usize {
+        self.history.current
+    }
+
+    /// Returns the ID of the state that was last saved, unless it's no longer in the history.
+    pub fn history_saved(&self) -> Option<usize> {
+        self.history.saved
+    }
+
+    /// Returns the contents as they are in the given state, without going there.
+    pub fn history_text(&self, id: usize) -> Vec<u8> {
+        let mut text = Vec::new();
+        self.buffer.extract_raw(0..self.buffer.len(), &mut text, 0);
+        if id > self.history.nodes.len() {
+            return text;
+        }
+
+        let newline: &[u8] = if self.newlines_are_crlf { b"\r\n" } else { b"\n" };
+        let (up, down) = self.history.path(self.history.current, id);
+        for id in up.into_iter().chain(down) {
+            // Same as what `undo_redo` does, including the newline translation.
+            let entry = self.history.node(id).entry.borrow();
+            let beg = offset_of(&text, entry.cursor);
+            let end = (beg + entry.added.len()).min(text.len());
+            let mut inserted = Vec::with_capacity(entry.deleted.len());
+            for line in entry.deleted.split_inclusive(|&c| c == b'\n') {
+                inserted.extend_from_slice(unicode::strip_newline(line));
+                if line.ends_with(b"\n") {
+                    inserted.extend_from_slice(newline);
+                }
+            }
+            text.splice(beg..end, inserted);
+        }
+        text
+    }
+
+    /// Undoes and redoes edits, across branches, until the given state is reached.
+    pub fn history_goto(&mut self, id: usize) {
+        if id > self.history.nodes.len() {
+            return;
+        }
+        self.cursors.clear();
+        self.block_selection = None;
+
+        let (up, down) = self.history.path(self.history.current, id);
+        for id in up {
+            self.undo_redo(id, false);
+        }
+        for id in down {
+            self.undo_redo(id, true);
+        }
+    }
+}
+
+/// Converts a logical position in `text` into an offset.
+fn offset_of(text: &[u8], pos: Point) -> usize {
+    let (offset, y) = simd::lines_fwd(text, 0, 0, pos.y);
+    if y < pos.y {
+        return text.len();
+    }
+    let cursor = Cursor {
+        offset,
+        logical_pos: Point { x: 0, y },
+        visual_pos: Point { x: 0, y },
+        ..Default::default()
+    };
+    MeasurementConfig::new(&text).with_cursor(cursor).goto_logical(pos).offset
+}
@@ -1,116 +1,241 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! A sparse index of line starts, which allows seeking to far away lines
+//! without having to count every newline between here and there.
+
 use std::ops::Range;
 
-use crate::{document::ReadableDocument, simd::memchr2};
+use crate::document::ReadableDocument;
+use crate::helpers::CoordType;
+use crate::simd;
 
-/// Cache a line/offset pair every CACHE_EVERY lines to speed up line/offset calculations
-const CACHE_EVERY: usize = 1024 * 64;
+/// The cache stores the start of every this many lines. Points may drift apart
+/// as lines are inserted or deleted, but never by more than twice this amount.
+/// Seeking to any line then takes a binary search plus a scan over a few thousand lines.
+const CACHE_EVERY: CoordType = 4096;
 
-#[derive(Clone)]
+/// The offset of the start of a logical line.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
 pub struct CachePoint {
-    pub index: usize,
-    pub line: usize,
-    // pub snapshot: ParserSnapshot
+    pub offset: usize,
+    pub line: CoordType,
 }
 
+/// A list of line starts, sorted by offset and roughly [`CACHE_EVERY`] lines apart.
+///
+/// The start of the document (offset 0, line 0) is implied and not stored.
+#[derive(Default)]
 pub struct LineCache {
-    cache: Vec<CachePoint>,
+    points: Vec<CachePoint>,
+    /// The number of newlines in the document.
+    newlines: CoordType,
 }
 
 impl LineCache {
     pub fn new() -> Self {
-        Self { cache: vec![] }
+        Self::default()
     }
 
-    pub fn from_document<T: ReadableDocument>(&mut self, document: &T) {
-        self.cache.clear();
+    /// Rebuilds the cache from scratch for the given document.
+    pub fn rebuild(&mut self, doc: &dyn ReadableDocument) {
+        self.points.clear();
+        let end = Self::scan(doc, CachePoint::default(), usize::MAX, CACHE_EVERY, &mut self.points);
+        self.newlines = end.line;
+    }
 
-        let mut offset = 0;
-        let mut line = 0;
-        loop {
-            let text = document.read_forward(offset);
-            if text.is_empty() { return; }
-            
-            let mut off = 0;
-            loop {
-                off = memchr2(b'\n', b'\n', text, off);
-                if off == text.len() { break; }
+    /// Updates the cache after `range` was inserted into `doc`.
+    /// `line` is the logical line at `range.start`.
+    pub fn insert(&mut self, doc: &dyn ReadableDocument, range: Range<usize>, line: CoordType) {
+        let beg = CachePoint { offset: range.start, line };
+        let newlines = Self::scan(doc, beg, range.end, CoordType::MAX, &mut Vec::new()).line - line;
+        let len = range.end - range.start;
+
+        // A point at `range.start` stays a line start, because the text preceding it is unchanged.
+        let idx = self.points.partition_point(|p| p.offset <= range.start);
+        for p in &mut self.points[idx..] {
+            p.offset += len;
+            p.line += newlines;
+        }
+        self.newlines += newlines;
 
-                if line % CACHE_EVERY == 0 {
-                    self.cache.push(CachePoint { index: offset+off, line });
-                }
-                line += 1;
-                off += 1;
-            }
+        if newlines != 0 {
+            self.fill_gap(doc, idx);
+        }
+    }
 
-            offset += text.len();
+    /// Updates the cache after `range` was deleted from `doc`, which contained `newlines` many newlines.
+    pub fn delete(&mut self, doc: &dyn ReadableDocument, range: Range<usize>, newlines: CoordType) {
+        // Points within the range are gone. The one at `range.end` is now at `range.start`,
+        // which may not be a line start anymore, so it's removed as well.
+        let beg = self.points.partition_point(|p| p.offset <= range.start);
+        let end = self.points.partition_point(|p| p.offset <= range.end);
+        let len = range.end - range.start;
+
+        for p in &mut self.points[end..] {
+            p.offset -= len;
+            p.line -= newlines;
+        }
+        self.newlines -= newlines;
+
+        if beg < end {
+            self.points.drain(beg..end);
+            self.fill_gap(doc, beg);
         }
     }
 
-    /// Updates the cache after a deletion.
-    /// `range` is the deleted byte range, and `text` is the content that was deleted.
-    pub fn delete(&mut self, range: Range<usize>, text: &Vec<u8>) {
-        let mut newlines = 0;
-        for c in text {
-            if *c == b'\n' {
-                newlines += 1;
-            }
+    /// Fills the gap in front of the point at `idx` (or the end) with new points, if it grew too large.
+    fn fill_gap(&mut self, doc: &dyn ReadableDocument, idx: usize) {
+        let prev = idx.checked_sub(1).map_or_else(CachePoint::default, |i| self.points[i]);
+        let next = self.points.get(idx).copied();
+        let next_line = next.map_or(self.newlines, |p| p.line);
+        if next_line - prev.line <= 2 * CACHE_EVERY {
+            return;
+        }
+
+        let mut added = Vec::new();
+        let end = next.map_or(usize::MAX, |p| p.offset);
+        Self::scan(doc, prev, end, CACHE_EVERY, &mut added);
+        // The last point may be `next` itself or too close to it.
+        while added.last().is_some_and(|p| next_line - p.line < CACHE_EVERY / 2) {
+            added.pop();
         }
 
-        let mut beg_del = None;
-        let mut end_del = None;
-        for (i, point) in self.cache.iter_mut().enumerate() {
-            if point.index >= range.start {
-                if point.index < range.end {
-                    // cache point is within the deleted range
-                    if beg_del.is_none() { beg_del = Some(i); }
-                    end_del = Some(i + 1);
+        self.points.splice(idx..idx, added);
+    }
+
+    /// Returns the closest line start at or before the given logical `line`.
+    pub fn before_line(&self, line: CoordType) -> CachePoint {
+        let idx = self.points.partition_point(|p| p.line <= line);
+        idx.checked_sub(1).map_or_else(CachePoint::default, |i| self.points[i])
+    }
+
+    /// Returns the closest line start at or before the given `offset`.
+    pub fn before_offset(&self, offset: usize) -> CachePoint {
+        let idx = self.points.partition_point(|p| p.offset <= offset);
+        idx.checked_sub(1).map_or_else(CachePoint::default, |i| self.points[i])
+    }
+
+    /// Seeks from `pos` up to `end` (or the end of the document) and adds a point to `out`
+    /// every `every` many lines. Returns the position at the end.
+    fn scan(
+        doc: &dyn ReadableDocument,
+        mut pos: CachePoint,
+        end: usize,
+        every: CoordType,
+        out: &mut Vec<CachePoint>,
+    ) -> CachePoint {
+        loop {
+            let stop = pos.line.saturating_add(every);
+
+            loop {
+                let chunk = doc.read_forward(pos.offset);
+                let chunk = &chunk[..chunk.len().min(end - pos.offset)];
+                if chunk.is_empty() {
+                    return pos;
                 }
-                else {
-                    point.index -= text.len();
-                    point.line -= newlines;
+
+                let (delta, line) = simd::lines_fwd(chunk, 0, pos.line, stop);
+                pos.offset += delta;
+                pos.line = line;
+                if line == stop {
+                    break;
                 }
             }
+
+            out.push(pos);
         }
+    }
+}
 
-        if let (Some(beg), Some(end)) = (beg_del, end_del) {
-            self.cache.drain(beg..end);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(count: usize) -> Vec<u8> {
+        let mut text = Vec::new();
+        for i in 0..count {
+            text.extend_from_slice(format!("line {i}\n").as_bytes());
         }
+        text
     }
 
-    /// Updates the cache after an insertion.
-    /// `offset` is where the insertion occurs, and `text` is the inserted content.
-    pub fn insert(&mut self, offset: usize, text: &[u8]) {
-        // Count how many newlines were inserted
-        let mut newlines = 0;
-        for c in text {
-            if *c == b'\n' {
-                newlines += 1;
-            }
+    fn line_start(text: &[u8], line: CoordType) -> usize {
+        if line == 0 {
+            return 0;
         }
+        text.iter().enumerate().filter(|&(_, &c)| c == b'\n').nth(line as usize - 1).unwrap().0 + 1
+    }
 
-        let len = text.len();
-        for point in &mut self.cache {
-            if point.index > offset {
-                point.index += len;
-                point.line += newlines;
-            }
+    fn verify(cache: &LineCache, text: &[u8]) {
+        let newlines = text.iter().filter(|&&c| c == b'\n').count() as CoordType;
+        assert_eq!(cache.newlines, newlines);
+
+        let mut prev = 0;
+        for p in &cache.points {
+            assert_eq!(p.offset, line_start(text, p.line));
+            assert!(p.line > prev && p.line - prev <= 2 * CACHE_EVERY);
+            prev = p.line;
         }
+        assert!(newlines - prev <= 2 * CACHE_EVERY);
+    }
 
-        // TODO: This also needs to insert new cache points
+    #[test]
+    fn test_rebuild() {
+        let text = lines(10000);
+        let mut cache = LineCache::new();
+        cache.rebuild(&&text[..]);
+
+        assert_eq!(cache.points.len(), 2);
+        assert_eq!(cache.before_line(4095), CachePoint::default());
+        assert_eq!(cache.before_line(5000).line, 4096);
+        assert_eq!(cache.before_line(100000).line, 8192);
+        assert_eq!(cache.before_offset(text.len()).line, 8192);
+        verify(&cache, &text);
     }
 
-    /// Finds the nearest cached line-offset pair relative to a target line.
-    /// If `reverse` is false, it returns the closest *before* the target.
-    /// If `reverse` is true, it returns the closest *after or at* the target.
-    pub fn nearest_offset(&self, target_count: usize, reverse: bool) -> Option<CachePoint> {
-        match self.cache.binary_search_by_key(&target_count, |p| p.line) {
-            Ok(i) => Some(self.cache[i].clone()),
-            Err(i) => {
-                if i == 0 || i == self.cache.len() { None }  // target < lowest cache point || target > highest cache point
-                else {
-                    Some(self.cache[ if reverse {i} else {i-1} ].clone())
-                }
-            }
+    #[test]
+    fn test_insert_delete() {
+        let mut text = lines(10000);
+        let mut cache = LineCache::new();
+        cache.rebuild(&&text[..]);
+
+        // Insert a lot of lines before the first point.
+        let off = line_start(&text, 100) + 2;
+        let added = lines(5000);
+        text.splice(off..off, added.iter().copied());
+        cache.insert(&&text[..], off..off + added.len(), 100);
+        verify(&cache, &text);
+        assert_eq!(cache.before_line(15000).line, 8192 + 5000);
+
+        // Paste the same small block over and over. The cache shouldn't grow much.
+        let before = cache.points.len();
+        for _ in 0..1000 {
+            let off = line_start(&text, 4000);
+            let added = lines(10);
+            text.splice(off..off, added.iter().copied());
+            cache.insert(&&text[..], off..off + added.len(), 4000);
+        }
+        verify(&cache, &text);
+        assert!(cache.points.len() <= before + 4);
+
+        // Delete from the middle of one line into the middle of another.
+        let beg = line_start(&text, 3000) + 3;
+        let end = line_start(&text, 9000) + 3;
+        let newlines = text[beg..end].iter().filter(|&&c| c == b'\n').count() as CoordType;
+        text.drain(beg..end);
+        cache.delete(&&text[..], beg..end, newlines);
+        verify(&cache, &text);
+
+        // Join the lines at consecutive points. The merged gap gets filled again.
+        let mut text = lines(10000);
+        cache.rebuild(&&text[..]);
+        for line in [4096, 8191] {
+            let off = line_start(&text, line) - 1;
+            text.remove(off);
+            cache.delete(&&text[..], off..off + 1, 1);
+            verify(&cache, &text);
         }
+        assert_eq!(cache.points.len(), 1);
     }
 }
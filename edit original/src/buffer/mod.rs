@@ -19,13 +19,26 @@
 //! The downside is that text navigation & search takes a performance hit due to small chunks.
 //! The solution to the former is to keep line caches, which further complicates the architecture.
 //! There's no solution for the latter. However, there's a chance that the performance will still be sufficient.
+//!
+//! Such a piece table exists in [`PieceTable`] and can be selected per buffer via [`TextStorage`].
+//! Consecutive writes are coalesced into a single piece, and a sparse line cache (see `line_cache`)
+//! avoids counting newlines from the cursor when seeking far away, regardless of the storage.
 
 mod gap_buffer;
+mod history;
+mod line_cache;
 mod navigation;
+mod patch;
+mod piece_table;
+mod positional;
+mod search_matches;
+mod storage;
+mod transaction;
+mod undo_file;
+mod view;
 
 use std::borrow::Cow;
 use std::cell::UnsafeCell;
-use std::collections::LinkedList;
 use std::fmt::Write as _;
 use std::fs::File;
 use std::io::{Read as _, Write as _};
@@ -35,14 +48,27 @@ use std::rc::Rc;
 use std::str;
 
 pub use gap_buffer::GapBuffer;
+use history::History;
+pub use history::{HistoryState, HistoryStateKind};
+use line_cache::{CachePoint, LineCache};
+pub use patch::{AppliedHunk, PatchOptions, PatchReport, RejectedHunk};
+pub use piece_table::{BufferSnapshot, PieceTable};
+pub use positional::TextPos;
+pub use search_matches::SearchMatchCount;
+use search_matches::SearchMatches;
+pub use storage::TextStorage;
+use transaction::Savepoint;
+pub use undo_file::UndoFile;
+pub use view::{RcTextBufferView, TextBufferView, TextBufferViewCell};
 
 use crate::arena::{ArenaString, scratch_arena};
 use crate::cell::SemiRefCell;
 use crate::document::{ReadableDocument, WriteableDocument};
-use crate::framebuffer::{Framebuffer, IndexedColor};
+use crate::framebuffer::{Attributes, Framebuffer, IndexedColor};
 use crate::helpers::*;
 use crate::oklab::oklab_blend;
 use crate::simd::memchr2;
+use crate::syntax::{Highlighter, Language, Theme, Token};
 use crate::unicode::{self, Cursor, MeasurementConfig};
 use crate::{apperr, icu, simd};
 
@@ -53,6 +79,8 @@ const MARGIN_TEMPLATE: &str = "                    │ ";
 /// Just a bunch of whitespace you can use for turning tabs into spaces.
 /// Happens to reuse MARGIN_TEMPLATE, because it has sufficient whitespace.
 const TAB_WHITESPACE: &str = MARGIN_TEMPLATE;
+/// Lines are only highlighted up to this many bytes.
+const HIGHLIGHT_MAX_LINE_LEN: usize = 64 * KIBI;
 
 /// Stores statistics about the whole document.
 #[derive(Copy, Clone)]
@@ -82,6 +110,7 @@ enum HistoryType {
 }
 
 /// An undo/redo entry.
+#[derive(Clone)]
 struct HistoryEntry {
     /// [`TextBuffer::cursor`] position before the change was made.
     cursor_before: Point,
@@ -98,6 +127,9 @@ struct HistoryEntry {
     deleted: Vec<u8>,
     /// Text that was added to the buffer.
     added: Vec<u8>,
+    /// If true, this entry is undone/redone together with the one before it.
+    /// This is used for edits made with multiple cursors.
+    linked: bool,
 }
 
 /// Caches an ICU search operation.
@@ -135,6 +167,43 @@ pub struct SearchOptions {
     pub use_regex: bool,
 }
 
+/// A committed change to a [`TextBuffer`], as reported
+/// to the listeners registered via [`TextBuffer::subscribe`].
+///
+/// The change replaced `deleted` at `offset` with `inserted`.
+pub struct TextBufferChange<'a> {
+    /// Byte offset at which the change took place.
+    pub offset: usize,
+    /// Text that was removed from the buffer.
+    pub deleted: &'a [u8],
+    /// Text that was added to the buffer.
+    pub inserted: &'a [u8],
+    /// Logical position of `offset`.
+    pub start: Point,
+    /// Logical position of the end of `deleted`, before the change.
+    pub deleted_end: Point,
+    /// Logical position of the end of `inserted`, after the change.
+    pub inserted_end: Point,
+    /// [`TextBuffer::generation`] after the change.
+    /// Undo/redo restore previous generations, so it's not monotonic.
+    pub generation: u32,
+}
+
+/// Identifies a listener registered via [`TextBuffer::subscribe`].
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct TextBufferSubscription(u32);
+
+type TextBufferListener = Box<dyn FnMut(&TextBufferChange)>;
+
+/// Accumulates the change made by the current edit operation,
+/// as long as there are listeners to report it to.
+struct ActiveChange {
+    offset: usize,
+    start: Point,
+    deleted: Vec<u8>,
+    inserted: Vec<u8>,
+}
+
 /// Caches the start and length of the active edit line for a single edit.
 /// This helps us avoid having to remeasure the buffer after an edit.
 struct ActiveEditLineInfo {
@@ -149,6 +218,7 @@ struct ActiveEditLineInfo {
 }
 
 /// Char- or word-wise navigation? Your choice.
+#[derive(Clone, Copy)]
 pub enum CursorMovement {
     Grapheme,
     Word,
@@ -171,16 +241,24 @@ pub type RcTextBuffer = Rc<TextBufferCell>;
 
 /// A text buffer for a text editor.
 pub struct TextBuffer {
-    buffer: GapBuffer,
+    buffer: TextStorage,
+    line_cache: LineCache,
 
-    undo_stack: LinkedList<SemiRefCell<HistoryEntry>>,
-    redo_stack: LinkedList<SemiRefCell<HistoryEntry>>,
+    history: History,
     last_history_type: HistoryType,
     last_save_generation: u32,
+    saved_snapshot: Option<BufferSnapshot>,
+    undo_file: Option<UndoFile>,
 
     active_edit_line_info: Option<ActiveEditLineInfo>,
     active_edit_depth: i32,
     active_edit_off: usize,
+    // The open transactions, innermost last. See `transaction_begin`.
+    transactions: Vec<Savepoint>,
+
+    listeners: Vec<(TextBufferSubscription, TextBufferListener)>,
+    listeners_next_id: u32,
+    active_change: Option<ActiveChange>,
 
     stats: TextBufferStatistics,
     cursor: Cursor,
@@ -191,7 +269,21 @@ pub struct TextBuffer {
     cursor_for_rendering: Option<Cursor>,
     selection: Option<TextBufferSelection>,
     selection_generation: u32,
+    // Additional cursors besides `cursor` and `selection`. `end` is the cursor position
+    // and `beg` the selection anchor. If they're equal, there's no selection.
+    // Any operation that isn't aware of them simply drops them.
+    cursors: Vec<TextBufferSelection>,
+    // The rectangular selection, if any. Unlike `selection`, its points are visual positions
+    // and the columns may lie past the end of a line. It's realized as one selection per line,
+    // via `cursors`, and any edit turns it into a plain set of cursors.
+    block_selection: Option<TextBufferSelection>,
+    // Collects the edits as (offset, deleted length, inserted length) while
+    // `for_each_cursor` is running, in order to adjust the other cursors.
+    cursor_edits: Option<Vec<(usize, usize, usize)>>,
+    // If true, the next undo entry will be linked to the previous one.
+    history_link_next: bool,
     search: Option<UnsafeCell<ActiveSearch>>,
+    search_matches: Option<SearchMatches>,
 
     width: CoordType,
     margin_width: CoordType,
@@ -201,8 +293,11 @@ pub struct TextBuffer {
     tab_size: CoordType,
     indent_with_tabs: bool,
     line_highlight_enabled: bool,
-    ruler: CoordType,
+    rulers: Vec<CoordType>,
+    highlighter: Option<Highlighter>,
+    syntax_theme: Theme,
     encoding: &'static str,
+    default_encoding: &'static str,
     newlines_are_crlf: bool,
     insert_final_newline: bool,
     overtype: bool,
@@ -221,24 +316,42 @@ impl TextBuffer {
     /// Creates a new text buffer. With `small` you can control
     /// if the buffer is optimized for <1MiB contents.
     pub fn new(small: bool) -> apperr::Result<Self> {
-        Ok(Self {
-            buffer: GapBuffer::new(small)?,
+        Ok(Self::with_storage(TextStorage::GapBuffer(GapBuffer::new(small)?)))
+    }
 
-            undo_stack: LinkedList::new(),
-            redo_stack: LinkedList::new(),
+    /// Creates a new text buffer on top of the given storage backend.
+    /// See [`TextStorage`] for the tradeoffs.
+    pub fn with_storage(storage: TextStorage) -> Self {
+        Self {
+            buffer: storage,
+            line_cache: LineCache::new(),
+
+            history: History::default(),
             last_history_type: HistoryType::Other,
             last_save_generation: 0,
+            saved_snapshot: None,
+            undo_file: None,
 
             active_edit_line_info: None,
             active_edit_depth: 0,
             active_edit_off: 0,
+            transactions: Vec::new(),
+
+            listeners: Vec::new(),
+            listeners_next_id: 0,
+            active_change: None,
 
             stats: TextBufferStatistics { logical_lines: 1, visual_lines: 1 },
             cursor: Default::default(),
             cursor_for_rendering: None,
             selection: None,
             selection_generation: 0,
+            cursors: Vec::new(),
+            block_selection: None,
+            cursor_edits: None,
+            history_link_next: false,
             search: None,
+            search_matches: None,
 
             width: 0,
             margin_width: 0,
@@ -248,14 +361,17 @@ impl TextBuffer {
             tab_size: 4,
             indent_with_tabs: false,
             line_highlight_enabled: false,
-            ruler: 0,
+            rulers: Vec::new(),
+            highlighter: None,
+            syntax_theme: Theme::default(),
             encoding: "UTF-8",
+            default_encoding: "UTF-8",
             newlines_are_crlf: cfg!(windows), // Windows users want CRLF
             insert_final_newline: false,
             overtype: false,
 
             wants_cursor_visibility: false,
-        })
+        }
     }
 
     /// Length of the document in bytes.
@@ -287,13 +403,51 @@ impl TextBuffer {
         self.buffer.generation()
     }
 
+    /// Registers a `listener` that gets called after every change to the contents,
+    /// including undo/redo and reloads via [`TextBuffer::read_file`].
+    /// The listener must not access this buffer.
+    pub fn subscribe(
+        &mut self,
+        listener: impl FnMut(&TextBufferChange) + 'static,
+    ) -> TextBufferSubscription {
+        let id = TextBufferSubscription(self.listeners_next_id);
+        self.listeners_next_id += 1;
+        self.listeners.push((id, Box::new(listener)));
+        id
+    }
+
+    /// Removes a listener previously registered via [`TextBuffer::subscribe`].
+    pub fn unsubscribe(&mut self, subscription: TextBufferSubscription) {
+        self.listeners.retain(|(id, _)| *id != subscription);
+    }
+
     /// Force the buffer to be dirty.
     pub fn mark_as_dirty(&mut self) {
         self.last_save_generation = self.buffer.generation().wrapping_sub(1);
+        self.history.saved = None;
     }
 
     fn mark_as_clean(&mut self) {
         self.last_save_generation = self.buffer.generation();
+        self.history.saved = Some(self.history.current);
+        // Only keep the saved contents around if it's free to do so.
+        self.saved_snapshot = match &self.buffer {
+            TextStorage::PieceTable(b) => Some(b.snapshot()),
+            TextStorage::GapBuffer(_) => None,
+        };
+    }
+
+    /// Returns an immutable copy of the contents, which can be searched, saved or
+    /// compared against on another thread while editing continues. This is O(1) if
+    /// the buffer uses a [`PieceTable`] and copies the contents otherwise.
+    pub fn snapshot(&self) -> BufferSnapshot {
+        self.buffer.snapshot()
+    }
+
+    /// Returns the contents as of the last time the buffer was loaded or saved,
+    /// if the buffer uses a [`PieceTable`], for instance to diff against them.
+    pub fn saved_snapshot(&self) -> Option<&BufferSnapshot> {
+        self.saved_snapshot.as_ref()
     }
 
     /// The encoding used during reading/writing. "UTF-8" is the default.
@@ -309,6 +463,15 @@ impl TextBuffer {
         }
     }
 
+    /// Sets the encoding that [`TextBuffer::read_file()`] assumes for files without a BOM.
+    /// An empty, unmodified buffer is switched to it as well, since it's a new document.
+    pub fn set_default_encoding(&mut self, encoding: &'static str) {
+        self.default_encoding = encoding;
+        if self.buffer.len() == 0 && !self.is_dirty() {
+            self.encoding = encoding;
+        }
+    }
+
     /// The newline type used in the document. LF or CRLF.
     pub fn is_crlf(&self) -> bool {
         self.newlines_are_crlf
@@ -323,6 +486,8 @@ impl TextBuffer {
     ///
     /// NOTE: Cannot be undone.
     pub fn normalize_newlines(&mut self, crlf: bool) {
+        self.change_begin_replace_all();
+
         let newline: &[u8] = if crlf { b"\r\n" } else { b"\n" };
         let mut off = 0;
 
@@ -380,12 +545,15 @@ impl TextBuffer {
         #[cfg(debug_assertions)]
         debug_assert_eq!(adjusted_newlines, self.cursor.logical_pos.y);
 
+        self.rebuild_line_caches();
+
         self.cursor.offset = cursor_offset;
         if let Some(cursor) = &mut self.cursor_for_rendering {
             cursor.offset = cursor_for_rendering_offset;
         }
 
         self.newlines_are_crlf = crlf;
+        self.change_end_replace_all();
     }
 
     /// If enabled, automatically insert a final newline
@@ -404,6 +572,11 @@ impl TextBuffer {
         self.overtype = overtype;
     }
 
+    /// Gets the cursor position as a byte offset into the contents.
+    pub fn cursor_offset(&self) -> usize {
+        self.cursor.offset
+    }
+
     /// Gets the logical cursor position, that is,
     /// the position in lines and graphemes per line.
     pub fn cursor_logical_pos(&self) -> Point {
@@ -519,9 +692,27 @@ impl TextBuffer {
         self.line_highlight_enabled = enabled;
     }
 
-    /// Sets a ruler column, e.g. 80.
-    pub fn set_ruler(&mut self, column: CoordType) {
-        self.ruler = column;
+    /// Sets the ruler columns, e.g. 80. Empty to disable them.
+    pub fn set_rulers(&mut self, columns: &[CoordType]) {
+        self.rulers.clear();
+        self.rulers.extend_from_slice(columns);
+    }
+
+    /// The language used for syntax highlighting, if any.
+    pub fn language(&self) -> Option<&'static Language> {
+        self.highlighter.as_ref().map(|h| h.language())
+    }
+
+    /// Sets the language used for syntax highlighting. `None` disables it.
+    pub fn set_language(&mut self, language: Option<&'static Language>) {
+        if self.language().map(|l| l as *const _) != language.map(|l| l as *const _) {
+            self.highlighter = language.map(Highlighter::new);
+        }
+    }
+
+    /// Sets the colors used for syntax highlighting.
+    pub fn set_syntax_theme(&mut self, theme: Theme) {
+        self.syntax_theme = theme;
     }
 
     pub fn reflow(&mut self) {
@@ -557,31 +748,53 @@ impl TextBuffer {
             self.stats.visual_lines = self.stats.logical_lines;
         }
 
+        // The block selection is made of visual positions which may have just changed meaning.
+        self.block_selection = None;
         self.cursor_for_rendering = None;
     }
 
     /// Replaces the entire buffer contents with the given `text`.
     /// Assumes that the line count doesn't change.
     pub fn copy_from_str(&mut self, text: &dyn ReadableDocument) {
+        self.change_begin_replace_all();
+
         if self.buffer.copy_from(text) {
+            self.rebuild_line_caches();
             self.recalc_after_content_swap();
             self.cursor_move_to_logical(Point { x: CoordType::MAX, y: 0 });
 
             let delete = self.buffer.len() - self.cursor.offset;
             if delete != 0 {
                 self.buffer.allocate_gap(self.cursor.offset, 0, delete);
+                self.line_cache.delete(
+                    &self.buffer,
+                    self.cursor.offset..self.cursor.offset + delete,
+                    0,
+                );
             }
         }
+
+        self.change_end_replace_all();
+    }
+
+    /// Rebuilds the per-line caches after the contents were replaced wholesale.
+    fn rebuild_line_caches(&mut self) {
+        self.line_cache.rebuild(&self.buffer);
+        if let Some(h) = &mut self.highlighter {
+            h.reset();
+        }
     }
 
     fn recalc_after_content_swap(&mut self) {
         // If the buffer was changed, nothing we previously saved can be relied upon.
-        self.undo_stack.clear();
-        self.redo_stack.clear();
+        self.history.clear();
         self.last_history_type = HistoryType::Other;
+        self.transactions.clear();
         self.cursor = Default::default();
         self.cursor_for_rendering = None;
         self.set_selection(None);
+        self.cursors.clear();
+        self.block_selection = None;
         self.search = None;
         self.mark_as_clean();
         self.reflow();
@@ -594,6 +807,7 @@ impl TextBuffer {
     }
 
     /// Reads a file from disk into the text buffer, detecting encoding and BOM.
+    /// The undo history gets restored from the [`UndoFile`], if it matches the contents.
     pub fn read_file(
         &mut self,
         file: &mut File,
@@ -617,18 +831,29 @@ impl TextBuffer {
             self.encoding = encoding;
         } else {
             let bom = detect_bom(unsafe { buf[..first_chunk_len].assume_init_ref() });
-            self.encoding = bom.unwrap_or("UTF-8");
+            // Without a BOM the file can't be "UTF-8 BOM", but it may still be UTF-8.
+            self.encoding = bom.unwrap_or(match self.default_encoding {
+                "UTF-8 BOM" => "UTF-8",
+                encoding => encoding,
+            });
         }
 
         // TODO: Since reading the file can fail, we should ensure that we also reset the cursor here.
         // I don't do it, so that `recalc_after_content_swap()` works.
+        self.change_begin_replace_all();
         self.buffer.clear();
 
         let done = read == 0;
-        if self.encoding == "UTF-8" {
-            self.read_file_as_utf8(file, &mut buf, first_chunk_len, done)?;
+        let res = if self.encoding == "UTF-8" {
+            self.read_file_as_utf8(file, &mut buf, first_chunk_len, done)
         } else {
-            self.read_file_with_icu(file, &mut buf, first_chunk_len, done)?;
+            self.read_file_with_icu(file, &mut buf, first_chunk_len, done)
+        };
+        self.rebuild_line_caches();
+        if res.is_err() {
+            // Whatever we managed to read is now the buffer contents.
+            self.change_end_replace_all();
+            return res;
         }
 
         // Figure out
@@ -738,6 +963,8 @@ impl TextBuffer {
         }
 
         self.recalc_after_content_swap();
+        self.read_undo_file();
+        self.change_end_replace_all();
         Ok(())
     }
 
@@ -860,6 +1087,7 @@ impl TextBuffer {
     }
 
     /// Writes the text buffer contents to a file, handling BOM and encoding.
+    /// The undo history gets persisted in the [`UndoFile`], if there's one.
     pub fn write_file(&mut self, file: &mut File) -> apperr::Result<()> {
         let mut offset = 0;
 
@@ -880,6 +1108,8 @@ impl TextBuffer {
         }
 
         self.mark_as_clean();
+        // Failing to persist the history is no reason to fail the save.
+        _ = self.write_undo_file();
         Ok(())
     }
 
@@ -1000,13 +1230,470 @@ impl TextBuffer {
         }
     }
 
-    /// Destroy the current selection.
+    /// Destroy the current selection, as well as any additional cursors.
     pub fn clear_selection(&mut self) -> bool {
-        let had_selection = self.selection.is_some();
+        let had_selection = self.selection.is_some() || !self.cursors.is_empty();
         self.set_selection(None);
+        self.cursors.clear();
+        self.block_selection = None;
         had_selection
     }
 
+    /// Number of cursors, including the primary one.
+    pub fn cursor_count(&self) -> usize {
+        self.cursors.len() + 1
+    }
+
+    /// Adds a cursor at the given visual `column` in the line above (`delta < 0`)
+    /// or below (`delta > 0`) the topmost or bottommost cursor respectively.
+    /// The new cursor becomes the primary one.
+    pub fn add_cursor_vertical(&mut self, delta: CoordType, column: CoordType) {
+        let mut edge = self.cursor;
+        for c in &self.cursors {
+            if (delta < 0 && c.end < edge.logical_pos) || (delta > 0 && c.end > edge.logical_pos) {
+                edge = self.cursor_move_to_logical_internal(edge, c.end);
+            }
+        }
+
+        let y = edge.visual_pos.y + delta.signum();
+        if delta == 0 || y < 0 || y >= self.stats.visual_lines {
+            return;
+        }
+
+        let target = self.cursor_move_to_visual_internal(edge, Point { x: column, y });
+        self.push_cursor(target, target);
+    }
+
+    /// If there's no selection, the word at the cursor gets selected.
+    /// Otherwise, this adds a cursor which selects the next occurrence of the
+    /// primary selection's text. The new cursor becomes the primary one.
+    ///
+    /// Returns `false` if there was no further occurrence.
+    pub fn add_cursor_at_next_occurrence(&mut self) -> bool {
+        let Some((beg, end)) = self.selection_range_internal(false) else {
+            if self.cursors.is_empty() {
+                self.select_word();
+            }
+            return self.selection.is_some();
+        };
+
+        let mut needle = Vec::new();
+        self.buffer.extract_raw(beg.offset..end.offset, &mut needle, 0);
+
+        // Ranges that are already selected, so that we can skip them.
+        let mut taken: Vec<Range<usize>> = self
+            .cursors
+            .iter()
+            .map(|c| {
+                let [b, e] = minmax(c.beg, c.end);
+                let b = self.cursor_move_to_logical_internal(self.cursor, b);
+                let e = self.cursor_move_to_logical_internal(b, e);
+                b.offset..e.offset
+            })
+            .collect();
+        taken.push(beg.offset..end.offset);
+
+        // Search forward from the primary selection and wrap around at the end.
+        let hit = self
+            .find_bytes(&needle, end.offset, self.text_length(), &taken)
+            .or_else(|| self.find_bytes(&needle, 0, end.offset, &taken));
+        let Some(hit) = hit else {
+            return false;
+        };
+
+        let hit_beg = self.cursor_move_to_offset_internal(self.cursor, hit.start);
+        let hit_end = self.cursor_move_to_offset_internal(hit_beg, hit.end);
+        self.push_cursor(hit_beg, hit_end);
+        true
+    }
+
+    /// Finds the first literal occurrence of `needle` that starts within `from..until`
+    /// and isn't one of the `taken` ranges. Unlike the regular search, this works
+    /// on the raw bytes, so that it doesn't care whether the text is valid UTF-8.
+    fn find_bytes(
+        &self,
+        needle: &[u8],
+        from: usize,
+        until: usize,
+        taken: &[Range<usize>],
+    ) -> Option<Range<usize>> {
+        let &first = needle.first()?;
+        let mut off = from;
+
+        while off < until {
+            let chunk = self.buffer.read_forward(off);
+            if chunk.is_empty() {
+                break;
+            }
+
+            let idx = memchr2(first, first, chunk, 0);
+            if idx >= chunk.len() {
+                off += chunk.len();
+                continue;
+            }
+
+            let start = off + idx;
+            if start >= until {
+                break;
+            }
+
+            let hit = start..start + needle.len();
+            if self.bytes_equal_at(start, needle) && !taken.contains(&hit) {
+                return Some(hit);
+            }
+            off = start + 1;
+        }
+
+        None
+    }
+
+    /// Returns whether the text at `off` starts with `needle`, even if it spans several chunks.
+    fn bytes_equal_at(&self, mut off: usize, mut needle: &[u8]) -> bool {
+        while !needle.is_empty() {
+            let chunk = self.buffer.read_forward(off);
+            if chunk.is_empty() {
+                return false;
+            }
+            let len = chunk.len().min(needle.len());
+            if chunk[..len] != needle[..len] {
+                return false;
+            }
+            off += len;
+            needle = &needle[len..];
+        }
+        true
+    }
+
+    /// Turns the primary cursor into an additional one, and makes
+    /// the given selection from `anchor` to `cursor` the primary one.
+    fn push_cursor(&mut self, anchor: Cursor, cursor: Cursor) {
+        let previous = TextBufferSelection {
+            beg: self.selection.map_or(self.cursor.logical_pos, |s| s.beg),
+            end: self.cursor.logical_pos,
+        };
+        let mut cursors = mem::take(&mut self.cursors);
+
+        unsafe { self.set_cursor(cursor) };
+        self.set_selection(Some(TextBufferSelection {
+            beg: anchor.logical_pos,
+            end: cursor.logical_pos,
+        }));
+
+        if previous.end != cursor.logical_pos
+            && !cursors.iter().any(|c| c.end == cursor.logical_pos)
+        {
+            cursors.push(previous);
+            self.cursors = cursors;
+        } else {
+            // The cursor already existed. Drop it, but keep the other cursors.
+            cursors.retain(|c| c.end != cursor.logical_pos);
+            self.cursors = cursors;
+            if previous.end != cursor.logical_pos {
+                self.cursors.push(previous);
+            }
+        }
+    }
+
+    /// Calls `f` once for every cursor, with the respective cursor and its selection
+    /// temporarily being the primary one. The cursors are visited from the end of the
+    /// document to its start. All edits are grouped into a single undo step and
+    /// cursors that end up overlapping are merged.
+    pub fn for_each_cursor(&mut self, mut f: impl FnMut(&mut Self)) {
+        if self.cursors.is_empty() {
+            f(self);
+            return;
+        }
+
+        // Convert all cursors into [anchor, cursor] offsets, since those
+        // are trivial to adjust for the edits made by the other cursors.
+        let primary = TextBufferSelection {
+            beg: self.selection.map_or(self.cursor.logical_pos, |s| s.beg),
+            end: self.cursor.logical_pos,
+        };
+        let mut pending: Vec<(bool, [usize; 2])> = mem::take(&mut self.cursors)
+            .into_iter()
+            .map(|c| (false, c))
+            .chain([(true, primary)])
+            .map(|(is_primary, c)| {
+                let anchor = self.cursor_move_to_logical_internal(self.cursor, c.beg);
+                let cursor = self.cursor_move_to_logical_internal(anchor, c.end);
+                (is_primary, [anchor.offset, cursor.offset])
+            })
+            .collect();
+        pending.sort_by_key(|(_, [a, c])| *a.min(c));
+        let mut done = Vec::with_capacity(pending.len());
+
+        self.cursor_edits = Some(Vec::new());
+        self.last_history_type = HistoryType::Other;
+
+        while let Some((is_primary, [anchor, cursor])) = pending.pop() {
+            let anchor = self.cursor_move_to_offset_internal(self.cursor, anchor);
+            let cursor = self.cursor_move_to_offset_internal(anchor, cursor);
+            self.set_cursor_internal(cursor);
+            self.set_selection(Some(TextBufferSelection {
+                beg: anchor.logical_pos,
+                end: cursor.logical_pos,
+            }));
+            // Ensure that each cursor gets its own undo entry.
+            self.last_history_type = HistoryType::Other;
+
+            f(self);
+
+            let edits = self.cursor_edits.as_mut().unwrap();
+            for (off, del, ins) in edits.drain(..) {
+                for (_, offsets) in pending.iter_mut().chain(done.iter_mut()) {
+                    for p in offsets {
+                        if *p >= off + del {
+                            *p = *p - del + ins;
+                        } else if *p > off {
+                            *p = off;
+                        }
+                    }
+                }
+            }
+
+            let anchor = match self.selection {
+                Some(s) => self.cursor_move_to_logical_internal(self.cursor, s.beg).offset,
+                None => self.cursor.offset,
+            };
+            done.push((is_primary, [anchor, self.cursor.offset]));
+        }
+
+        self.cursor_edits = None;
+        self.history_link_next = false;
+        self.last_history_type = HistoryType::Other;
+        debug_assert!(self.cursors.is_empty());
+
+        // Merge overlapping cursors. Selections that merely touch are kept apart.
+        done.sort_by_key(|(_, [a, c])| *a.min(c));
+        let mut merged: Vec<(bool, [usize; 2])> = Vec::with_capacity(done.len());
+        for (is_primary, [anchor, cursor]) in done {
+            if let Some((last_primary, last)) = merged.last_mut() {
+                let last_end = last[0].max(last[1]);
+                let beg = anchor.min(cursor);
+                if beg < last_end || (beg == last_end && anchor == cursor) {
+                    let end = anchor.max(cursor).max(last_end);
+                    *last = [last[0].min(last[1]), end];
+                    *last_primary |= is_primary;
+                    continue;
+                }
+            }
+            merged.push((is_primary, [anchor, cursor]));
+        }
+
+        let mut primary = None;
+        let mut pos = self.cursor;
+        for (is_primary, [anchor, cursor]) in merged {
+            let anchor = self.cursor_move_to_offset_internal(pos, anchor);
+            let cursor = self.cursor_move_to_offset_internal(anchor, cursor);
+            pos = cursor;
+            if is_primary {
+                primary = Some((anchor, cursor));
+            } else {
+                self.cursors
+                    .push(TextBufferSelection { beg: anchor.logical_pos, end: cursor.logical_pos });
+            }
+        }
+
+        if let Some((anchor, cursor)) = primary {
+            self.set_cursor_internal(cursor);
+            self.set_selection(Some(TextBufferSelection {
+                beg: anchor.logical_pos,
+                end: cursor.logical_pos,
+            }));
+        }
+    }
+
+    /// Returns true if there's a rectangular selection that's at least one column wide.
+    pub fn has_block_selection(&self) -> bool {
+        self.block_selection.is_some_and(|b| b.beg.x != b.end.x)
+    }
+
+    /// Extends the rectangular selection to `visual_pos`, starting a new one
+    /// at the cursor (or the anchor of the current selection) if there's none.
+    /// This is meant to be used for Alt+Drag.
+    pub fn block_selection_update_visual(&mut self, visual_pos: Point) {
+        let anchor = match self.block_selection {
+            Some(block) => block.beg,
+            None => match self.selection {
+                Some(s) => self.cursor_move_to_logical_internal(self.cursor, s.beg).visual_pos,
+                None => self.cursor.visual_pos,
+            },
+        };
+        let end =
+            Point { x: visual_pos.x.max(0), y: visual_pos.y.clamp(0, self.stats.visual_lines - 1) };
+        self.set_block_selection(TextBufferSelection { beg: anchor, end });
+    }
+
+    /// Moves the corner of the rectangular selection by the given amount of
+    /// columns and rows. This is meant to be used for Alt+Shift+Arrow keys.
+    pub fn block_selection_update_delta(&mut self, delta: Point) {
+        let end = match self.block_selection {
+            Some(block) => block.end,
+            None => self.cursor.visual_pos,
+        };
+        self.block_selection_update_visual(Point { x: end.x + delta.x, y: end.y + delta.y });
+    }
+
+    /// Returns the range covered by the rectangular selection on each of its rows, from top
+    /// to bottom. Graphemes that straddle the edges of the rectangle are considered part of it.
+    /// Rows that are too short to reach into the rectangle get an empty range at their end.
+    fn block_selection_ranges(&self, block: TextBufferSelection) -> Vec<(Cursor, Cursor)> {
+        let [left, right] = minmax(block.beg.x, block.end.x);
+        let [top, bottom] = minmax(block.beg.y, block.end.y);
+        let mut ranges = Vec::with_capacity((bottom - top + 1) as usize);
+        let mut pos = self.cursor;
+
+        for y in top..=bottom {
+            let beg = self.cursor_move_to_visual_internal(pos, Point { x: left, y });
+            let mut end = self.cursor_move_to_visual_internal(beg, Point { x: right, y });
+
+            // `goto_visual` stops in front of a wide glyph or tab that crosses the target column.
+            if end.visual_pos.x < right {
+                let next = self.cursor_move_delta_internal(end, CursorMovement::Grapheme, 1);
+                if next.visual_pos.y == y && next.offset > end.offset {
+                    end = next;
+                }
+            }
+
+            ranges.push((beg, end));
+            pos = end;
+        }
+
+        ranges
+    }
+
+    /// Replaces the selection and cursors with one selection per row of the given rectangle.
+    /// Rows that don't reach into it are skipped, except for the one with the cursor.
+    fn set_block_selection(&mut self, block: TextBufferSelection) {
+        let [left, _] = minmax(block.beg.x, block.end.x);
+        let backward = block.end.x < block.beg.x;
+        let ranges = self.block_selection_ranges(block);
+
+        let [top, _] = minmax(block.beg.y, block.end.y);
+        let primary_index = (block.end.y - top) as usize;
+        let mut cursors = Vec::with_capacity(ranges.len());
+        let mut primary = ranges[primary_index];
+
+        for (i, &(beg, end)) in ranges.iter().enumerate() {
+            let (anchor, cursor) = if backward { (end, beg) } else { (beg, end) };
+            if i == primary_index {
+                primary = (anchor, cursor);
+            } else if beg.visual_pos.x >= left || beg.offset != end.offset {
+                cursors
+                    .push(TextBufferSelection { beg: anchor.logical_pos, end: cursor.logical_pos });
+            }
+        }
+
+        let (anchor, cursor) = primary;
+        unsafe { self.set_cursor(cursor) };
+        self.set_selection(Some(TextBufferSelection {
+            beg: anchor.logical_pos,
+            end: cursor.logical_pos,
+        }));
+        self.cursors = cursors;
+        self.block_selection = Some(block);
+    }
+
+    /// Deletes the contents of all selections, but unlike [`TextBuffer::delete()`]
+    /// it leaves the text at cursors without a selection alone.
+    fn delete_selections(&mut self) {
+        if self.selection.is_some() || self.cursors.iter().any(|c| c.beg != c.end) {
+            self.for_each_cursor(|tb| {
+                if tb.has_selection() {
+                    tb.delete(CursorMovement::Grapheme, 1);
+                }
+            });
+        }
+    }
+
+    /// Extracts the contents of the rectangular selection, one line per row.
+    fn extract_block_selection(&self, block: TextBufferSelection) -> Vec<u8> {
+        let newline: &[u8] = if self.newlines_are_crlf { b"\r\n" } else { b"\n" };
+        let mut out = Vec::new();
+
+        for (i, (beg, end)) in self.block_selection_ranges(block).into_iter().enumerate() {
+            if i != 0 {
+                out.extend_from_slice(newline);
+            }
+            self.buffer.extract_raw(beg.offset..end.offset, &mut out, usize::MAX);
+        }
+
+        out
+    }
+
+    /// Inserts `text` as a column: Each of its lines is inserted on a successive row,
+    /// at the visual column of the topmost cursor. Rows that are too short are padded
+    /// with spaces and rows are appended to the document as needed.
+    /// The selection or block selection is replaced. All of it is a single undo step.
+    pub fn paste_block(&mut self, text: &[u8]) {
+        if text.is_empty() {
+            return;
+        }
+
+        let generation = self.buffer.generation();
+        self.delete_selections();
+
+        let top = self.cursors.iter().fold(self.cursor.logical_pos, |top, c| top.min(c.end));
+        let origin = self.cursor_move_to_logical_internal(self.cursor, top);
+        let column = origin.visual_pos.x;
+        let newline: &[u8] = if self.newlines_are_crlf { b"\r\n" } else { b"\n" };
+
+        self.cursors.clear();
+        self.block_selection = None;
+        self.set_selection(None);
+
+        let text = text.strip_suffix(b"\n").unwrap_or(text);
+        let text = text.strip_suffix(b"\r").unwrap_or(text);
+        let mut link = self.buffer.generation() != generation;
+        let mut pos = origin;
+
+        for (i, line) in text.split(|&c| c == b'\n').enumerate() {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            let y = origin.visual_pos.y + i as CoordType;
+            let mut padding = 0;
+            let mut append_row = false;
+
+            if y >= self.stats.visual_lines {
+                pos = self.cursor_move_to_logical_internal(pos, Point::MAX);
+                padding = column;
+                append_row = true;
+            } else {
+                pos = self.cursor_move_to_visual_internal(pos, Point { x: column, y });
+                if pos.visual_pos.x < column {
+                    let line_end =
+                        self.cursor_move_to_visual_internal(pos, Point { x: CoordType::MAX, y });
+                    if line_end.offset == pos.offset {
+                        padding = column - pos.visual_pos.x;
+                    }
+                }
+            }
+
+            if line.is_empty() && padding == 0 && !append_row {
+                continue;
+            }
+
+            self.last_history_type = HistoryType::Other;
+            self.history_link_next = link;
+            self.edit_begin(HistoryType::Write, pos);
+            if append_row {
+                self.edit_write(newline);
+            }
+            if padding > 0 {
+                let spaces = vec![b' '; padding as usize];
+                self.edit_write(&spaces);
+            }
+            self.edit_write(line);
+            self.edit_end();
+
+            pos = self.cursor;
+            link = true;
+        }
+
+        self.history_link_next = false;
+        self.last_history_type = HistoryType::Other;
+    }
+
     /// Find the next occurrence of the given `pattern` and select it.
     pub fn find_and_select(&mut self, pattern: &str, options: SearchOptions) -> apperr::Result<()> {
         if let Some(search) = &mut self.search {
@@ -1101,6 +1788,17 @@ impl TextBuffer {
         Ok(())
     }
 
+    /// Returns the ranges of all occurrences of the given `pattern`,
+    /// without moving the cursor or the selection.
+    pub fn find_all(
+        &self,
+        pattern: &str,
+        options: SearchOptions,
+    ) -> apperr::Result<Vec<Range<usize>>> {
+        let search = self.find_construct_search(pattern, options)?;
+        Ok(search.regex.collect())
+    }
+
     fn find_construct_search(
         &self,
         pattern: &str,
@@ -1212,6 +1910,15 @@ impl TextBuffer {
         let mut result = cursor;
         let mut seek_to_line_start = true;
 
+        // Without word wrap, line starts from the line cache are complete cursors.
+        // Start from there if it's closer than the given cursor.
+        if self.word_wrap_column <= 0 {
+            let point = self.line_cache.before_line(y);
+            if y - point.line < (y - cursor.logical_pos.y).abs() {
+                result = Self::cursor_from_cache_point(point);
+            }
+        }
+
         if y > result.logical_pos.y {
             while y > result.logical_pos.y {
                 let chunk = self.read_forward(result.offset);
@@ -1291,11 +1998,74 @@ impl TextBuffer {
         result
     }
 
+    /// Tokenizes the logical line starting at `line_start` into `tokens`,
+    /// after bringing the highlighter's line states up to date.
+    fn highlight_line(&mut self, line_start: Cursor, text: &mut Vec<u8>, tokens: &mut Vec<Token>) {
+        let y = line_start.logical_pos.y as usize;
+        // The line that was read last and the offset of the one after it.
+        let mut next: Option<(usize, usize)> = None;
+
+        while let Some(l) = self.highlighter.as_ref().and_then(|h| h.next_line_to_update(y)) {
+            let offset = match next {
+                Some((line, offset)) if line == l - 1 => offset,
+                _ => {
+                    let pos = Point { x: 0, y: l as CoordType - 1 };
+                    self.cursor_move_to_logical_internal(line_start, pos).offset
+                }
+            };
+            next = Some((l, self.read_line(offset, text)));
+            if let Some(h) = &mut self.highlighter {
+                h.update(l, text, tokens);
+            }
+        }
+
+        self.read_line(line_start.offset, text);
+        if let Some(h) = &self.highlighter {
+            h.tokenize(y, text, tokens);
+        }
+    }
+
+    /// Copies the line starting at `offset` into `out` and returns the offset of the next line.
+    /// Only the first [`HIGHLIGHT_MAX_LINE_LEN`] bytes are copied, which is plenty for
+    /// highlighting, but keeps minified files from slowing down rendering.
+    fn read_line(&self, mut offset: usize, out: &mut Vec<u8>) -> usize {
+        out.clear();
+        loop {
+            let chunk = self.buffer.read_forward(offset);
+            if chunk.is_empty() {
+                return offset;
+            }
+
+            let newline = memchr2(b'\n', b'\n', chunk, 0);
+            let len = (newline + 1).min(chunk.len());
+            let room = HIGHLIGHT_MAX_LINE_LEN.saturating_sub(out.len());
+            out.extend_from_slice(&chunk[..len.min(room)]);
+            offset += len;
+
+            if newline < chunk.len() {
+                return offset;
+            }
+        }
+    }
+
+    fn cursor_from_cache_point(point: CachePoint) -> Cursor {
+        let pos = Point { x: 0, y: point.line };
+        Cursor { offset: point.offset, logical_pos: pos, visual_pos: pos, ..Default::default() }
+    }
+
     fn cursor_move_to_offset_internal(&self, mut cursor: Cursor, offset: usize) -> Cursor {
         if offset == cursor.offset {
             return cursor;
         }
 
+        // Same as in `goto_line_start()`.
+        if self.word_wrap_column <= 0 {
+            let point = self.line_cache.before_offset(offset);
+            if offset - point.offset < offset.abs_diff(cursor.offset) {
+                cursor = Self::cursor_from_cache_point(point);
+            }
+        }
+
         // goto_line_start() is fast for seeking across lines _if_ line wrapping is disabled.
         // For backward seeking we have to use it either way, so we're covered there.
         // This implements the forward seeking portion, if it's approx. worth doing so.
@@ -1466,9 +2236,14 @@ impl TextBuffer {
         self.set_cursor_internal(cursor);
         self.last_history_type = HistoryType::Other;
         self.set_selection(None);
+        self.cursors.clear();
+        self.block_selection = None;
     }
 
     fn set_cursor_for_selection(&mut self, cursor: Cursor) {
+        self.cursors.clear();
+        self.block_selection = None;
+
         let beg = match self.selection {
             Some(TextBufferSelection { beg, .. }) => beg,
             None => self.cursor.logical_pos,
@@ -1517,6 +2292,10 @@ impl TextBuffer {
         let mut visualizer_buf = [0xE2, 0x90, 0x80]; // U+2400 in UTF8
         let mut line = ArenaString::new_in(&scratch);
         let mut visual_pos_x_max = 0;
+        let mut highlight_text = Vec::new();
+        let mut highlight_tokens = Vec::new();
+        let mut highlight_line = None;
+        let mut highlight_offset = 0;
 
         // Pick the cursor closer to the `origin.y`.
         let mut cursor = {
@@ -1532,6 +2311,12 @@ impl TextBuffer {
             Some(TextBufferSelection { beg, end }) => minmax(beg, end),
         };
 
+        // The primary selection, followed by those of any additional cursors.
+        let mut selections = Vec::new_in(&*scratch);
+        selections.push([selection_beg, selection_end]);
+        selections
+            .extend(self.cursors.iter().filter(|c| c.beg != c.end).map(|c| minmax(c.beg, c.end)));
+
         line.reserve(width as usize * 2);
 
         for y in 0..height {
@@ -1695,13 +2480,105 @@ impl TextBuffer {
 
             fb.replace_text(destination.top + y, destination.left, destination.right, &line);
 
-            // Draw the selection on this line, if any.
-            // FYI: `cursor_beg.visual_pos.y == visual_line` is necessary as the `visual_line`
-            // may be past the end of the document, and so it may not receive a highlight.
+            // Color the syntax on this line. Tokens span entire logical lines,
+            // so they're reused for all the rows that the line wraps into.
+            if self.highlighter.is_some() && cursor_beg.offset != cursor_end.offset {
+                if highlight_line != Some(cursor_beg.logical_pos.y) {
+                    let line_start = self.goto_line_start(cursor_beg, cursor_beg.logical_pos.y);
+                    self.highlight_line(line_start, &mut highlight_text, &mut highlight_tokens);
+                    highlight_line = Some(cursor_beg.logical_pos.y);
+                    highlight_offset = line_start.offset;
+                }
+
+                let left = destination.left + self.margin_width - origin.x;
+                let top = destination.top + y;
+                let mut pos = cursor_beg;
+
+                for t in &highlight_tokens {
+                    let beg = (highlight_offset + t.beg).max(cursor_beg.offset);
+                    let end = (highlight_offset + t.end).min(cursor_end.offset);
+                    if beg >= cursor_end.offset {
+                        break;
+                    }
+                    if beg >= end {
+                        continue;
+                    }
+
+                    pos = self.cursor_move_to_offset_internal(pos, beg);
+                    let x_beg = pos.visual_pos.x.max(origin.x);
+                    let x_end = if end == cursor_end.offset {
+                        cursor_end.visual_pos.x
+                    } else {
+                        pos = self.cursor_move_to_offset_internal(pos, end);
+                        pos.visual_pos.x
+                    };
+                    let x_end = x_end.min(origin.x + text_width);
+                    if x_beg >= x_end {
+                        continue;
+                    }
+
+                    let rect =
+                        Rect { left: left + x_beg, top, right: left + x_end, bottom: top + 1 };
+                    let style = self.syntax_theme.style(t.kind);
+                    if let Some(color) = style.fg {
+                        fb.blend_fg(rect, fb.indexed(color));
+                    }
+                    if style.attr != Attributes::None {
+                        fb.replace_attr(rect, style.attr, style.attr);
+                    }
+                }
+            }
+
+            // Highlight the search matches on this line, if any.
+            // The selections are drawn on top, which makes the current match stand out.
             if cursor_beg.visual_pos.y == visual_line
-                && selection_beg <= cursor_end.logical_pos
-                && selection_end >= cursor_beg.logical_pos
+                && let Some(matches) = self.search_matches_current()
             {
+                let left = destination.left + self.margin_width - origin.x;
+                let top = destination.top + y;
+                let first = matches.ranges.partition_point(|r| r.end <= cursor_beg.offset);
+                let mut pos = cursor_beg;
+
+                for r in &matches.ranges[first..] {
+                    let beg = r.start.max(cursor_beg.offset);
+                    let end = r.end.min(cursor_end.offset);
+                    if beg >= cursor_end.offset {
+                        break;
+                    }
+                    if beg >= end {
+                        continue;
+                    }
+
+                    pos = self.cursor_move_to_offset_internal(pos, beg);
+                    let x_beg = pos.visual_pos.x.max(origin.x);
+                    let x_end = if end == cursor_end.offset {
+                        cursor_end.visual_pos.x
+                    } else {
+                        pos = self.cursor_move_to_offset_internal(pos, end);
+                        pos.visual_pos.x
+                    };
+                    let x_end = x_end.min(origin.x + text_width);
+                    if x_beg >= x_end {
+                        continue;
+                    }
+
+                    let rect =
+                        Rect { left: left + x_beg, top, right: left + x_end, bottom: top + 1 };
+                    fb.blend_bg(rect, fb.indexed_alpha(IndexedColor::BrightYellow, 1, 2));
+                }
+            }
+
+            // Draw the selections on this line, if any.
+            // FYI: `cursor_beg.visual_pos.y == visual_line` is necessary as the `visual_line`
+            // may be past the end of the document, and so it may not receive a highlight.
+            for &[selection_beg, selection_end] in &selections {
+                if cursor_beg.visual_pos.y != visual_line
+                    || selection_beg > cursor_end.logical_pos
+                    || selection_end < cursor_beg.logical_pos
+                {
+                    continue;
+                }
+
                 // By default, we assume the entire line is selected.
                 let mut beg = 0;
                 let mut end = COORD_TYPE_SAFE_MAX;
@@ -1756,8 +2633,9 @@ impl TextBuffer {
             fb.blend_fg(margin, 0x7f3f3f3f);
         }
 
-        if self.ruler > 0 {
-            let left = destination.left + self.margin_width + (self.ruler - origin.x).max(0);
+        // Each ruler shades everything past it, so that later rulers stack on top of earlier ones.
+        for &ruler in self.rulers.iter().filter(|&&r| r > 0) {
+            let left = destination.left + self.margin_width + (ruler - origin.x).max(0);
             let right = destination.right;
             if left < right {
                 fb.blend_bg(
@@ -1768,6 +2646,23 @@ impl TextBuffer {
         }
 
         if focused {
+            // The additional cursors are drawn as reversed cells, since the terminal only has one.
+            let text = Rect {
+                left: destination.left + self.margin_width,
+                top: destination.top,
+                right: destination.right,
+                bottom: destination.bottom,
+            };
+            let mut pos = self.cursor;
+            for c in &self.cursors {
+                pos = self.cursor_move_to_logical_internal(pos, c.end);
+                let x = pos.visual_pos.x + destination.left - origin.x + self.margin_width;
+                let y = pos.visual_pos.y + destination.top - origin.y;
+                if text.contains(Point { x, y }) {
+                    fb.reverse(Rect { left: x, top: y, right: x + 1, bottom: y + 1 });
+                }
+            }
+
             let mut x = self.cursor.visual_pos.x;
             let mut y = self.cursor.visual_pos.y;
 
@@ -1783,12 +2678,6 @@ impl TextBuffer {
             y += destination.top - origin.y;
 
             let cursor = Point { x, y };
-            let text = Rect {
-                left: destination.left + self.margin_width,
-                top: destination.top,
-                right: destination.right,
-                bottom: destination.bottom,
-            };
 
             if text.contains(cursor) {
                 fb.set_cursor(cursor, self.overtype);
@@ -1818,6 +2707,10 @@ impl TextBuffer {
         if text.is_empty() {
             return;
         }
+        if !self.cursors.is_empty() {
+            self.for_each_cursor(|tb| tb.write(text, raw));
+            return;
+        }
 
         if let Some((beg, end)) = self.selection_range_internal(false) {
             self.edit_begin(HistoryType::Write, beg);
@@ -1968,6 +2861,10 @@ impl TextBuffer {
         if delta == 0 {
             return;
         }
+        if !self.cursors.is_empty() {
+            self.for_each_cursor(|tb| tb.delete(granularity, delta));
+            return;
+        }
 
         let mut beg;
         let mut end;
@@ -2033,6 +2930,11 @@ impl TextBuffer {
     /// * The cursor movement at the end is rather costly, but at least without word wrap
     ///   it should be possible to calculate it directly from the removed amount.
     pub fn unindent(&mut self) {
+        if !self.cursors.is_empty() {
+            self.for_each_cursor_line_range(Self::unindent);
+            return;
+        }
+
         let mut selection_beg = self.cursor.logical_pos;
         let mut selection_end = selection_beg;
 
@@ -2102,9 +3004,127 @@ impl TextBuffer {
         self.set_cursor_internal(self.cursor_move_to_logical_internal(self.cursor, selection_end));
     }
 
+    /// Indents the selected lines, or the current line if there's no selection, by one level.
+    /// This is the counterpart to [`TextBuffer::unindent()`]. Empty lines are left as they are.
+    pub fn indent(&mut self) {
+        if !self.cursors.is_empty() {
+            self.for_each_cursor_line_range(Self::indent);
+            return;
+        }
+
+        let mut selection_beg = self.cursor.logical_pos;
+        let mut selection_end = selection_beg;
+
+        if let Some(TextBufferSelection { beg, end }) = self.selection {
+            selection_beg = beg;
+            selection_end = end;
+        }
+
+        let [beg, end] = minmax(selection_beg, selection_end);
+        let beg = self.cursor_move_to_logical_internal(self.cursor, Point { x: 0, y: beg.y });
+        let end = self.cursor_move_to_logical_internal(beg, Point { x: CoordType::MAX, y: end.y });
+
+        let indentation =
+            if self.indent_with_tabs { "\t" } else { &TAB_WHITESPACE[..self.tab_size as usize] };
+        // Tabs and spaces are 1 grapheme cluster per byte.
+        let indentation_width = indentation.len() as CoordType;
+
+        let mut original = Vec::new();
+        self.buffer.extract_raw(beg.offset..end.offset, &mut original, 0);
+
+        let mut replacement = Vec::with_capacity(original.len() + 64);
+        let mut offset = 0;
+        let mut y = beg.logical_pos.y;
+
+        loop {
+            let (next_offset, next_y) = simd::lines_fwd(&original, offset, y, y + 1);
+            let line = &original[offset..next_offset];
+
+            if !matches!(line, [] | [b'\n'] | [b'\r', b'\n']) {
+                replacement.extend_from_slice(indentation.as_bytes());
+
+                if y == selection_beg.y {
+                    selection_beg.x += indentation_width;
+                }
+                if y == selection_end.y {
+                    selection_end.x += indentation_width;
+                }
+            }
+
+            replacement.extend_from_slice(line);
+
+            if next_offset >= original.len() {
+                break;
+            }
+            (offset, y) = (next_offset, next_y);
+        }
+
+        if replacement.len() == original.len() {
+            // Nothing to do.
+            return;
+        }
+
+        self.edit_begin(HistoryType::Other, beg);
+        self.edit_delete(end);
+        self.edit_write(&replacement);
+        self.edit_end();
+
+        if let Some(TextBufferSelection { beg, end }) = &mut self.selection {
+            *beg = selection_beg;
+            *end = selection_end;
+        }
+
+        self.set_cursor_internal(self.cursor_move_to_logical_internal(self.cursor, selection_end));
+    }
+
+    /// Calls the line-based operation `f` for each cursor,
+    /// but only once for each line, even if multiple cursors share it.
+    fn for_each_cursor_line_range(&mut self, mut f: impl FnMut(&mut Self)) {
+        // The cursors are visited from the bottom up.
+        let mut next_y = CoordType::MAX;
+        self.for_each_cursor(|tb| {
+            let [beg, end] = match tb.selection {
+                Some(TextBufferSelection { beg, end }) => minmax(beg, end),
+                None => [tb.cursor.logical_pos; 2],
+            };
+            if end.y < next_y {
+                next_y = beg.y;
+                f(tb);
+            }
+        });
+    }
+
     /// Extracts the contents of the current selection.
     /// May optionally delete it, if requested. This is meant to be used for Ctrl+X.
+    ///
+    /// With multiple cursors, their selections are joined with newlines.
+    /// A rectangular selection results in one line per row, including empty ones.
     pub fn extract_selection(&mut self, delete: bool) -> Vec<u8> {
+        if let Some(block) = self.block_selection
+            && block.beg.x != block.end.x
+        {
+            let out = self.extract_block_selection(block);
+            if delete {
+                self.delete_selections();
+            }
+            return out;
+        }
+        if !self.cursors.is_empty() {
+            let newline: &[u8] = if self.newlines_are_crlf { b"\r\n" } else { b"\n" };
+            let mut parts = Vec::new();
+            self.for_each_cursor(|tb| parts.push(tb.extract_selection(delete)));
+
+            // The cursors were visited from the bottom up.
+            let mut out = Vec::new();
+            for part in parts.iter().rev() {
+                if !out.is_empty() && !out.ends_with(b"\n") {
+                    out.extend_from_slice(newline);
+                }
+                out.extend_from_slice(part);
+            }
+            return out;
+        }
+
         let Some((beg, end)) = self.selection_range_internal(true) else {
             return Vec::new();
         };
@@ -2175,6 +3195,10 @@ impl TextBuffer {
             return;
         }
 
+        // Edits that aren't made via `for_each_cursor` invalidate the other cursors.
+        self.cursors.clear();
+        self.block_selection = None;
+
         let cursor_before = self.cursor;
         self.set_cursor_internal(cursor);
 
@@ -2182,13 +3206,11 @@ impl TextBuffer {
         if history_type != self.last_history_type
             || !matches!(history_type, HistoryType::Write | HistoryType::Delete)
         {
-            self.redo_stack.clear();
-            while self.undo_stack.len() > 1000 {
-                self.undo_stack.pop_front();
-            }
-
+            // Within a transaction, all edits after the first one get undone along with it.
+            let linked = self.history_link_next
+                || self.transactions.first().is_some_and(|t| t.state != self.history.current);
             self.last_history_type = history_type;
-            self.undo_stack.push_back(SemiRefCell::new(HistoryEntry {
+            self.history.push(HistoryEntry {
                 cursor_before: cursor_before.logical_pos,
                 selection_before: self.selection,
                 stats_before: self.stats,
@@ -2196,10 +3218,19 @@ impl TextBuffer {
                 cursor: cursor.logical_pos,
                 deleted: Vec::new(),
                 added: Vec::new(),
-            }));
+                linked,
+            });
+            self.history_link_next = self.cursor_edits.is_some();
+            // Aborting a transaction relies on its states keeping their IDs.
+            if self.transactions.is_empty() {
+                self.history.prune();
+            }
+        } else {
+            self.history.touch_current();
         }
 
         self.active_edit_off = cursor.offset;
+        self.change_begin(cursor);
 
         // If word-wrap is enabled, the visual layout of all logical lines affected by the write
         // may have changed. This includes even text before the insertion point up to the line
@@ -2226,18 +3257,30 @@ impl TextBuffer {
 
         // Copy the written portion into the undo entry.
         {
-            let mut undo = self.undo_stack.back_mut().unwrap().borrow_mut();
+            let mut undo = self.history.current_entry().unwrap().borrow_mut();
             undo.added.extend_from_slice(text);
         }
+        if let Some(change) = &mut self.active_change {
+            change.inserted.extend_from_slice(text);
+        }
 
         // Write!
         self.buffer.replace(self.active_edit_off..self.active_edit_off, text);
+        self.line_cache.insert(
+            &self.buffer,
+            self.active_edit_off..self.active_edit_off + text.len(),
+            logical_y_before,
+        );
 
         // Move self.cursor to the end of the newly written text. Can't use `self.set_cursor_internal`,
         // because we're still in the progress of recalculating the line stats.
         self.active_edit_off += text.len();
         self.cursor = self.cursor_move_to_offset_internal(self.cursor, self.active_edit_off);
         self.stats.logical_lines += self.cursor.logical_pos.y - logical_y_before;
+        if let Some(h) = &mut self.highlighter {
+            let added = self.cursor.logical_pos.y - logical_y_before;
+            h.invalidate(logical_y_before as usize, 0, added as usize);
+        }
     }
 
     /// Deletes the text between the current cursor position and `to`.
@@ -2249,7 +3292,7 @@ impl TextBuffer {
         let off = self.active_edit_off;
         let mut out_off = usize::MAX;
 
-        let mut undo = self.undo_stack.back_mut().unwrap().borrow_mut();
+        let mut undo = self.history.current_entry().unwrap().borrow_mut();
         if self.cursor.logical_pos < undo.cursor {
             out_off = 0; // Prepend the deleted portion.
             undo.cursor = self.cursor.logical_pos; // Note the start of the deleted portion.
@@ -2258,12 +3301,21 @@ impl TextBuffer {
         // Copy the deleted portion into the undo entry.
         let deleted = &mut undo.deleted;
         self.buffer.extract_raw(off..to.offset, deleted, out_off);
+        if let Some(change) = &mut self.active_change {
+            // Within a single edit operation, deletions always move forward.
+            self.buffer.extract_raw(off..to.offset, &mut change.deleted, usize::MAX);
+        }
 
         // Delete the portion from the buffer by enlarging the gap.
         let count = to.offset - off;
         self.buffer.allocate_gap(off, 0, count);
+        self.line_cache.delete(&self.buffer, off..to.offset, to.logical_pos.y - logical_y_before);
 
         self.stats.logical_lines += logical_y_before - to.logical_pos.y;
+        if let Some(h) = &mut self.highlighter {
+            let removed = to.logical_pos.y - logical_y_before;
+            h.invalidate(logical_y_before as usize, removed as usize, 0);
+        }
     }
 
     /// Finalizes the current edit operation
@@ -2277,12 +3329,12 @@ impl TextBuffer {
 
         #[cfg(debug_assertions)]
         {
-            let entry = self.undo_stack.back_mut().unwrap().borrow_mut();
+            let entry = self.history.current_entry().unwrap().borrow_mut();
             debug_assert!(!entry.deleted.is_empty() || !entry.added.is_empty());
         }
 
         if let Some(info) = self.active_edit_line_info.take() {
-            let deleted_count = self.undo_stack.back_mut().unwrap().borrow_mut().deleted.len();
+            let deleted_count = self.history.current_entry().unwrap().borrow_mut().deleted.len();
             let target = self.cursor.logical_pos;
 
             // From our safe position we can measure the actual visual position of the cursor.
@@ -2313,38 +3365,44 @@ impl TextBuffer {
 
         self.search = None;
         self.cursor_for_rendering = None;
+        self.change_end();
     }
 
     /// Undo the last edit operation.
     pub fn undo(&mut self) {
-        self.undo_redo(true);
+        self.cursors.clear();
+        self.block_selection = None;
+        loop {
+            let id = self.history.current;
+            let Some(entry) = self.history.current_entry() else {
+                break;
+            };
+            let linked = entry.borrow().linked;
+            self.undo_redo(id, false);
+            if !linked {
+                break;
+            }
+        }
     }
 
     /// Redo the last undo operation.
     pub fn redo(&mut self) {
-        self.undo_redo(false);
-    }
-
-    fn undo_redo(&mut self, undo: bool) {
-        // Transfer the last entry from the undo stack to the redo stack or vice versa.
+        self.cursors.clear();
+        self.block_selection = None;
+        let mut first = true;
+        while let Some(id) = self.history.redo_of(self.history.current)
+            && (first || self.history.node(id).entry.borrow().linked)
         {
-            let (from, to) = if undo {
-                (&mut self.undo_stack, &mut self.redo_stack)
-            } else {
-                (&mut self.redo_stack, &mut self.undo_stack)
-            };
-
-            let Some(list) = from.cursor_back_mut().remove_current_as_list() else {
-                return;
-            };
-
-            to.cursor_back_mut().splice_after(list);
+            self.undo_redo(id, true);
+            first = false;
         }
+    }
 
-        let change = {
-            let to = if undo { &self.redo_stack } else { &self.undo_stack };
-            to.back().unwrap()
-        };
+    /// Applies the entry of the state `id`, which must either be the current state (undo)
+    /// or a child of it (redo). Afterwards the entry is flipped to do the opposite.
+    fn undo_redo(&mut self, id: usize, redo: bool) {
+        self.history.step(id, redo);
+        let change = &self.history.node(id).entry;
 
         // Move to the point where the modification took place.
         let cursor = self.cursor_move_to_logical_internal(self.cursor, change.borrow().cursor);
@@ -2365,8 +3423,27 @@ impl TextBuffer {
             // Undo: Whatever was deleted is now added and vice versa.
             mem::swap(&mut change.deleted, &mut change.added);
 
+            // Same as `change_begin`, but `self` is still borrowed.
+            if let Some(m) = &mut self.search_matches {
+                m.edited_from = m.edited_from.min(cursor.offset);
+            }
+            if !self.listeners.is_empty() {
+                self.active_change = Some(ActiveChange {
+                    offset: cursor.offset,
+                    start: cursor.logical_pos,
+                    deleted: change.deleted.clone(),
+                    inserted: Vec::new(),
+                });
+            }
+
             // Delete the inserted portion.
+            let deleted_newlines = change.deleted.iter().filter(|&&c| c == b'\n').count();
             self.buffer.allocate_gap(cursor.offset, 0, change.deleted.len());
+            self.line_cache.delete(
+                &self.buffer,
+                cursor.offset..cursor.offset + change.deleted.len(),
+                deleted_newlines as CoordType,
+            );
 
             // Reinsert the deleted portion.
             {
@@ -2402,6 +3479,17 @@ impl TextBuffer {
                     beg = end;
                     offset += written;
                 }
+
+                self.line_cache.insert(&self.buffer, cursor.offset..offset, cursor.logical_pos.y);
+                if let Some(h) = &mut self.highlighter {
+                    let added_newlines = added.iter().filter(|&&c| c == b'\n').count();
+                    h.invalidate(cursor.logical_pos.y as usize, deleted_newlines, added_newlines);
+                }
+
+                // The newlines may have been translated, so we can't just copy `change.added`.
+                if let Some(active) = &mut self.active_change {
+                    self.buffer.extract_raw(cursor.offset..offset, &mut active.inserted, 0);
+                }
             }
 
             // Restore the previous line statistics.
@@ -2420,13 +3508,80 @@ impl TextBuffer {
             change.cursor_before = self.cursor.logical_pos;
             // Can't use `set_cursor_internal` here, because we haven't updated the line stats yet.
             self.cursor = cursor_before;
-
-            if self.undo_stack.is_empty() {
-                self.last_history_type = HistoryType::Other;
-            }
         }
 
+        // New edits must not be merged into an entry that isn't the current one anymore.
+        self.last_history_type = HistoryType::Other;
+
         self.cursor_for_rendering = None;
+        self.change_end();
+    }
+
+    /// Starts recording the change of an edit operation at `cursor`,
+    /// unless there's no one to report it to.
+    fn change_begin(&mut self, cursor: Cursor) {
+        self.search_matches_edit(cursor.offset);
+        if !self.listeners.is_empty() || self.cursor_edits.is_some() {
+            self.active_change = Some(ActiveChange {
+                offset: cursor.offset,
+                start: cursor.logical_pos,
+                deleted: Vec::new(),
+                inserted: Vec::new(),
+            });
+        }
+    }
+
+    /// Starts recording a change that (potentially) replaces the entire contents.
+    fn change_begin_replace_all(&mut self) {
+        self.change_begin(Default::default());
+        if let Some(change) = &mut self.active_change {
+            self.buffer.extract_raw(0..self.buffer.len(), &mut change.deleted, 0);
+        }
+    }
+
+    fn change_end_replace_all(&mut self) {
+        if let Some(change) = &mut self.active_change {
+            self.buffer.extract_raw(0..self.buffer.len(), &mut change.inserted, 0);
+        }
+        self.change_end();
+    }
+
+    /// Reports the recorded change to all listeners.
+    fn change_end(&mut self) {
+        let Some(change) = self.active_change.take() else {
+            return;
+        };
+        if change.deleted == change.inserted {
+            return;
+        }
+        if let Some(edits) = &mut self.cursor_edits {
+            edits.push((change.offset, change.deleted.len(), change.inserted.len()));
+        }
+        if self.listeners.is_empty() {
+            return;
+        }
+
+        let end_of = |text: &[u8]| {
+            let end = MeasurementConfig::new(&text).goto_offset(text.len()).logical_pos;
+            if end.y == 0 {
+                Point { x: change.start.x + end.x, y: change.start.y }
+            } else {
+                Point { x: end.x, y: change.start.y + end.y }
+            }
+        };
+        let change = TextBufferChange {
+            offset: change.offset,
+            deleted: &change.deleted,
+            inserted: &change.inserted,
+            start: change.start,
+            deleted_end: end_of(&change.deleted),
+            inserted_end: end_of(&change.inserted),
+            generation: self.buffer.generation(),
+        };
+
+        for (_, listener) in &mut self.listeners {
+            listener(&change);
+        }
     }
 
     /// For interfacing with ICU.
@@ -2477,3 +3632,859 @@ fn detect_bom(bytes: &[u8]) -> Option<&'static str> {
     }
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use std::fmt::Write as _;
+    use std::sync::{Mutex, MutexGuard, Once, PoisonError};
+    use std::time::SystemTime;
+    use std::{env, fs, process};
+
+    use super::*;
+
+    /// The scratch arenas are global, which is why tests that use them must not run concurrently.
+    fn lock() -> MutexGuard<'static, ()> {
+        static LOCK: Mutex<()> = Mutex::new(());
+        static INIT: Once = Once::new();
+
+        let guard = LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        INIT.call_once(|| crate::arena::init(16 * 1024 * 1024).unwrap());
+        guard
+    }
+
+    fn contents(tb: &TextBuffer) -> String {
+        let mut text = Vec::new();
+        tb.buffer.extract_raw(0..tb.buffer.len(), &mut text, 0);
+        String::from_utf8(text).unwrap()
+    }
+
+    type Log = Rc<RefCell<Vec<(usize, Vec<u8>, Vec<u8>, Point, Point, Point, u32)>>>;
+
+    #[track_caller]
+    fn expect(log: &Log, offset: usize, deleted: &str, inserted: &str, points: [Point; 3]) {
+        let (off, del, ins, start, deleted_end, inserted_end, _) = log.borrow_mut().remove(0);
+        assert_eq!(off, offset);
+        assert_eq!(del, deleted.as_bytes());
+        assert_eq!(ins, inserted.as_bytes());
+        assert_eq!([start, deleted_end, inserted_end], points);
+    }
+
+    #[test]
+    fn test_change_notifications() {
+        let _lock = lock();
+
+        let log = Log::default();
+        let mut tb = TextBuffer::new(true).unwrap();
+        tb.set_crlf(false);
+        let subscription = tb.subscribe({
+            let log = log.clone();
+            move |c| {
+                log.borrow_mut().push((
+                    c.offset,
+                    c.deleted.to_vec(),
+                    c.inserted.to_vec(),
+                    c.start,
+                    c.deleted_end,
+                    c.inserted_end,
+                    c.generation,
+                ))
+            }
+        });
+
+        let p = |x, y| Point { x, y };
+
+        tb.write(b"hello\nworld", true);
+        assert_eq!(log.borrow()[0].6, tb.generation());
+        expect(&log, 0, "", "hello\nworld", [p(0, 0), p(0, 0), p(5, 1)]);
+
+        // Replacing a selection is a single change.
+        tb.cursor_move_to_logical(p(2, 1));
+        tb.set_selection(Some(TextBufferSelection { beg: p(2, 1), end: p(5, 1) }));
+        tb.write(b"\xC3\xA4!", true);
+        expect(&log, 8, "rld", "ä!", [p(2, 1), p(5, 1), p(4, 1)]);
+
+        tb.cursor_move_to_logical(p(0, 1));
+        tb.delete(CursorMovement::Grapheme, -1);
+        expect(&log, 5, "\n", "", [p(5, 0), p(0, 1), p(5, 0)]);
+
+        tb.undo();
+        assert_eq!(log.borrow()[0].6, tb.generation());
+        expect(&log, 5, "", "\n", [p(5, 0), p(5, 0), p(0, 1)]);
+        tb.redo();
+        expect(&log, 5, "\n", "", [p(5, 0), p(0, 1), p(5, 0)]);
+
+        // Reloading reports the replacement of the entire contents.
+        let path = env::temp_dir().join(format!("edit-test-changes-{}.txt", process::id()));
+        fs::write(&path, "new").unwrap();
+        tb.read_file(&mut File::open(&path).unwrap(), None).unwrap();
+        fs::remove_file(&path).unwrap();
+        expect(&log, 0, "hellowo\u{e4}!", "new", [p(0, 0), p(9, 0), p(3, 0)]);
+
+        tb.unsubscribe(subscription);
+        tb.write(b"x", true);
+        assert!(log.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_newline_notifications() {
+        let _lock = lock();
+
+        let log = Log::default();
+        let mut tb = TextBuffer::new(true).unwrap();
+        tb.set_crlf(false);
+        tb.write(b"a\nb\n", true);
+        tb.subscribe({
+            let log = log.clone();
+            move |c| {
+                log.borrow_mut().push((
+                    c.offset,
+                    c.deleted.to_vec(),
+                    c.inserted.to_vec(),
+                    c.start,
+                    c.deleted_end,
+                    c.inserted_end,
+                    c.generation,
+                ))
+            }
+        });
+
+        let p = |x, y| Point { x, y };
+
+        // Each conversion is reported once, as a replacement of the entire contents.
+        tb.normalize_newlines(true);
+        expect(&log, 0, "a\nb\n", "a\r\nb\r\n", [p(0, 0), p(0, 2), p(0, 2)]);
+        assert!(log.borrow().is_empty());
+
+        // Merely switching the newline type changes nothing.
+        tb.set_crlf(false);
+        assert!(log.borrow().is_empty());
+
+        tb.normalize_newlines(false);
+        expect(&log, 0, "a\r\nb\r\n", "a\nb\n", [p(0, 0), p(0, 2), p(0, 2)]);
+
+        // Nothing is left over to be reported along with a later edit.
+        tb.write(b"x", true);
+        assert_eq!(log.borrow().len(), 1);
+        assert_eq!(log.borrow()[0].2, b"x");
+    }
+
+    #[test]
+    fn test_multiple_cursors() {
+        let _lock = lock();
+
+        let p = |x, y| Point { x, y };
+        let mut tb = TextBuffer::new(true).unwrap();
+        tb.set_crlf(false);
+        tb.write(b"ab\nab\nab", true);
+
+        tb.cursor_move_to_logical(p(1, 0));
+        tb.add_cursor_vertical(1, 1);
+        tb.add_cursor_vertical(1, 1);
+        assert_eq!(tb.cursor_count(), 3);
+        assert_eq!(tb.cursor_logical_pos(), p(1, 2));
+
+        tb.write(b"--", true);
+        assert_eq!(contents(&tb), "a--b\na--b\na--b");
+        tb.delete(CursorMovement::Grapheme, -1);
+        assert_eq!(contents(&tb), "a-b\na-b\na-b");
+        assert_eq!(tb.cursor_count(), 3);
+
+        // Each edit across all cursors is a single undo step.
+        tb.undo();
+        assert_eq!(contents(&tb), "a--b\na--b\na--b");
+        tb.undo();
+        assert_eq!(contents(&tb), "ab\nab\nab");
+        tb.redo();
+        assert_eq!(contents(&tb), "a--b\na--b\na--b");
+        assert_eq!(tb.cursor_count(), 1);
+
+        // Indentation applies to each line only once, even with two cursors on it.
+        tb.cursor_move_to_logical(p(2, 0));
+        tb.set_selection(Some(TextBufferSelection { beg: p(1, 0), end: p(2, 0) }));
+        assert!(tb.add_cursor_at_next_occurrence());
+        assert_eq!(tb.selection_range().unwrap().0.logical_pos, p(2, 0));
+        tb.add_cursor_vertical(1, 0);
+        assert_eq!(tb.cursor_count(), 3);
+        tb.indent();
+        assert_eq!(contents(&tb), "    a--b\n    a--b\na--b");
+        tb.undo();
+
+        let mut tb = TextBuffer::new(true).unwrap();
+        tb.set_crlf(false);
+        tb.write(b"foo bar foo foo", true);
+        tb.cursor_move_to_logical(p(1, 0));
+
+        assert!(tb.add_cursor_at_next_occurrence());
+        assert_eq!(tb.cursor_count(), 1);
+        assert!(tb.add_cursor_at_next_occurrence());
+        assert!(tb.add_cursor_at_next_occurrence());
+        assert!(!tb.add_cursor_at_next_occurrence());
+        assert_eq!(tb.cursor_count(), 3);
+
+        assert_eq!(tb.extract_selection(false), b"foo\nfoo\nfoo");
+        tb.write(b"x", true);
+        assert_eq!(contents(&tb), "x bar x x");
+        assert_eq!(tb.cursor_logical_pos(), p(9, 0));
+
+        // The search is literal and case-sensitive, and works on invalid UTF-8.
+        let mut tb = TextBuffer::new(true).unwrap();
+        tb.set_crlf(false);
+        tb.write(b"\xff\xfe Foo \xff\xfe \xff\xff \xff\xfe", true);
+        tb.cursor_move_to_logical(p(2, 0));
+        tb.set_selection(Some(TextBufferSelection { beg: p(0, 0), end: p(2, 0) }));
+        assert!(tb.add_cursor_at_next_occurrence());
+        assert!(tb.add_cursor_at_next_occurrence());
+        assert!(!tb.add_cursor_at_next_occurrence());
+        assert_eq!(tb.cursor_count(), 3);
+        tb.write(b"x", true);
+        let mut text = Vec::new();
+        tb.buffer.extract_raw(0..tb.text_length(), &mut text, 0);
+        assert_eq!(text, b"x Foo x \xff\xff x");
+
+        tb.cursor_move_to_logical(p(5, 0));
+        tb.set_selection(Some(TextBufferSelection { beg: p(2, 0), end: p(5, 0) }));
+        assert!(!tb.add_cursor_at_next_occurrence());
+    }
+
+    #[test]
+    fn test_block_selection() {
+        let _lock = lock();
+
+        let p = |x, y| Point { x, y };
+        let original = "a = 1\n\nccc = 3\n\u{4e00}\u{4e00}\u{4e00}";
+        let mut tb = TextBuffer::new(true).unwrap();
+        tb.set_crlf(false);
+        tb.write(original.as_bytes(), true);
+
+        // Columns 1 to 4 of all rows. The empty row doesn't reach into it
+        // and the wide glyph straddling the left edge is part of it.
+        tb.cursor_move_to_logical(p(1, 0));
+        tb.block_selection_update_visual(p(4, 3));
+        assert!(tb.has_block_selection());
+        assert_eq!(tb.cursor_count(), 3);
+        assert_eq!(tb.extract_selection(false), "\x20= \n\ncc \n\u{4e00}\u{4e00}".as_bytes());
+
+        // Cutting and typing apply to each row, as a single undo step each.
+        let block = tb.extract_selection(true);
+        assert_eq!(contents(&tb), "a1\n\nc= 3\n\u{4e00}");
+        tb.write(b"|", true);
+        assert_eq!(contents(&tb), "a|1\n\nc|= 3\n|\u{4e00}");
+        tb.undo();
+        tb.undo();
+        assert_eq!(contents(&tb), original);
+
+        // Pasting a block inserts it as a column, padding short rows and adding new ones.
+        tb.cursor_move_to_logical(p(3, 0));
+        tb.paste_block(b"x\ny\nz\nw\nv\n");
+        assert_eq!(contents(&tb), "a =x 1\n   y\ncccz = 3\n\u{4e00}w\u{4e00}\u{4e00}\n   v");
+        tb.undo();
+        assert_eq!(contents(&tb), original);
+
+        // Alt+Shift+Arrows grow a block from the cursor. Pasting replaces it.
+        tb.cursor_move_to_logical(p(0, 0));
+        tb.block_selection_update_delta(p(1, 0));
+        tb.block_selection_update_delta(p(0, 1));
+        tb.block_selection_update_delta(p(0, 1));
+        assert_eq!(tb.extract_selection(false), b"a\n\nc");
+        tb.paste_block(&block);
+        assert_eq!(contents(&tb), " =  = 1\n\ncc cc = 3\n\u{4e00}\u{4e00}\u{4e00}\u{4e00}\u{4e00}");
+    }
+
+    #[test]
+    fn test_piece_table_storage() {
+        let _lock = lock();
+
+        let mut tb = TextBuffer::with_storage(TextStorage::PieceTable(PieceTable::new()));
+        tb.set_crlf(false);
+        tb.write(b"fn main() {\n    println!();\n}\n", true);
+        let before = tb.snapshot();
+
+        tb.cursor_move_to_logical(Point { x: 13, y: 1 });
+        tb.write(b"\"hi\"", true);
+        tb.cursor_move_to_logical(Point { x: 3, y: 0 });
+        tb.delete(CursorMovement::Word, 1);
+        tb.write(b"run", true);
+        assert_eq!(contents(&tb), "fn run() {\n    println!(\"hi\");\n}\n");
+        assert_eq!(tb.logical_line_count(), 4);
+
+        let mut text = Vec::new();
+        before.extract_raw(0..before.len(), &mut text, 0);
+        assert_eq!(text, b"fn main() {\n    println!();\n}\n");
+        assert_ne!(before.generation(), tb.generation());
+
+        tb.undo();
+        tb.undo();
+        tb.undo();
+        assert_eq!(contents(&tb), "fn main() {\n    println!();\n}\n");
+        assert!(tb.saved_snapshot().is_none());
+    }
+
+    #[test]
+    fn test_line_cache() {
+        let _lock = lock();
+
+        fn line_at(tb: &mut TextBuffer, y: CoordType) -> String {
+            tb.cursor_move_to_logical(Point { x: 0, y });
+            let beg = tb.cursor.offset;
+            tb.cursor_move_to_logical(Point { x: CoordType::MAX, y });
+            let mut text = Vec::new();
+            tb.buffer.extract_raw(beg..tb.cursor.offset, &mut text, 0);
+            String::from_utf8(text).unwrap()
+        }
+
+        let mut text = String::new();
+        for i in 0..20000 {
+            _ = writeln!(text, "line {i}");
+        }
+
+        let mut tb = TextBuffer::new(false).unwrap();
+        tb.set_crlf(false);
+        tb.write(text.as_bytes(), true);
+        assert_eq!(line_at(&mut tb, 15000), "line 15000");
+        assert_eq!(line_at(&mut tb, 3), "line 3");
+        assert_eq!(line_at(&mut tb, 19999), "line 19999");
+
+        // Delete lines 10 to 5009.
+        tb.cursor_move_to_logical(Point { x: 0, y: 10 });
+        tb.selection_update_logical(Point { x: 0, y: 5010 });
+        tb.delete(CursorMovement::Grapheme, 1);
+        assert_eq!(line_at(&mut tb, 9), "line 9");
+        assert_eq!(line_at(&mut tb, 10), "line 5010");
+        assert_eq!(line_at(&mut tb, 14999), "line 19999");
+
+        // Insert 3000 lines in front of line 5.
+        let mut added = String::new();
+        for i in 0..3000 {
+            _ = writeln!(added, "new {i}");
+        }
+        tb.cursor_move_to_logical(Point { x: 0, y: 5 });
+        tb.write(added.as_bytes(), true);
+        assert_eq!(line_at(&mut tb, 3004), "new 2999");
+        assert_eq!(line_at(&mut tb, 3005), "line 5");
+        assert_eq!(line_at(&mut tb, 17999), "line 19999");
+
+        tb.undo();
+        assert_eq!(line_at(&mut tb, 14999), "line 19999");
+        tb.undo();
+        assert_eq!(line_at(&mut tb, 15000), "line 15000");
+        tb.redo();
+        assert_eq!(line_at(&mut tb, 10), "line 5010");
+        assert_eq!(line_at(&mut tb, 14999), "line 19999");
+    }
+
+    #[test]
+    fn test_history_tree() {
+        let _lock = lock();
+        let mut tb = TextBuffer::new(true).unwrap();
+        let kinds = |tb: &TextBuffer| -> Vec<_> { tb.history().iter().map(|s| s.kind).collect() };
+
+        tb.write(b"a", true);
+        tb.write(b"b", true);
+        tb.undo();
+        assert_eq!(contents(&tb), "");
+
+        // A new edit after undoing starts a new branch instead of discarding the old one.
+        tb.write(b"c", true);
+        tb.redo();
+        assert_eq!(contents(&tb), "c");
+        assert_eq!(
+            kinds(&tb),
+            [HistoryStateKind::Undo, HistoryStateKind::Branch, HistoryStateKind::Current]
+        );
+        assert_eq!(tb.history()[1].excerpt, "ab");
+        assert_eq!(tb.history_text(1), b"ab");
+        assert_eq!(tb.history_text(0), b"");
+
+        tb.history_goto(1);
+        assert_eq!(contents(&tb), "ab");
+        assert_eq!(
+            kinds(&tb),
+            [HistoryStateKind::Undo, HistoryStateKind::Current, HistoryStateKind::Branch]
+        );
+        tb.undo();
+        assert_eq!(contents(&tb), "");
+        assert!(!tb.is_dirty());
+        // Redo follows the branch that was visited last.
+        tb.redo();
+        assert_eq!(contents(&tb), "ab");
+        assert_eq!(tb.history_at_time(SystemTime::UNIX_EPOCH), 0);
+        assert_eq!(tb.history_at_time(SystemTime::now()), 2);
+
+        // The oldest edits are dropped in batches, along with branches that can't be reached anymore.
+        tb.history_goto(2);
+        for i in 0..1000 {
+            tb.cursor_move_to_logical(Point { x: i, y: 0 });
+            tb.write(b"x", true);
+        }
+        let kept = tb.history.nodes.len();
+        assert!((750..1000).contains(&kept));
+        assert!(tb.history().iter().all(|s| s.kind != HistoryStateKind::Branch));
+        let initial = format!("{}c", "x".repeat(1000 - kept));
+        assert_eq!(tb.history_text(0), initial.as_bytes());
+        tb.history_goto(0);
+        assert_eq!(contents(&tb), initial);
+        tb.redo();
+        assert_eq!(contents(&tb), format!("x{initial}"));
+    }
+
+    #[test]
+    fn test_history_prune() {
+        let _lock = lock();
+        let mut tb = TextBuffer::new(true).unwrap();
+        // A few branches, some of which are on the way to the current state.
+        for i in 0..40 {
+            tb.write(b"a", true);
+            if i % 3 == 0 {
+                tb.undo();
+            }
+            if i % 7 == 0 {
+                tb.history_goto(i / 2);
+            }
+        }
+        assert!(tb.history().iter().any(|s| s.kind == HistoryStateKind::Branch));
+        tb.history.saved = Some(tb.history.current);
+
+        let summary = |h: &History| {
+            let nodes: Vec<_> = h.nodes.iter().map(|n| (n.parent, n.redo)).collect();
+            (nodes, h.current, h.root_redo, h.saved)
+        };
+        // Dropping many at once is the same as dropping them one by one.
+        for len in 0..tb.history.nodes.len() {
+            let mut batch = tb.history.clone();
+            batch.prune_to(len);
+            let mut single = tb.history.clone();
+            while single.nodes.len() > len {
+                single.prune_to(single.nodes.len() - 1);
+            }
+            assert_eq!(summary(&batch), summary(&single));
+        }
+    }
+
+    #[test]
+    fn test_history_text_newlines() {
+        let _lock = lock();
+        let mut tb = TextBuffer::new(true).unwrap();
+        tb.set_crlf(false);
+        tb.write(b"a\nb", true);
+        tb.select_all();
+        tb.write(b"c", true);
+
+        // Undoing writes the newlines of the buffer, so the preview must do so too.
+        tb.normalize_newlines(true);
+        assert_eq!(tb.history_text(1), b"a\r\nb");
+        tb.history_goto(1);
+        assert_eq!(contents(&tb), "a\r\nb");
+    }
+
+    #[test]
+    fn test_transactions() {
+        let _lock = lock();
+        let mut tb = TextBuffer::new(true).unwrap();
+        tb.write(b"one\ntwo\nthree", true);
+        tb.undo();
+        tb.write(b"one\ntwo\n", true);
+
+        // The edits of a transaction are undone as one, no matter where they were made.
+        tb.transaction_begin();
+        tb.cursor_move_to_logical(Point { x: 0, y: 0 });
+        tb.write(b"1", true);
+        tb.cursor_move_to_logical(Point { x: 3, y: 1 });
+        tb.delete(CursorMovement::Grapheme, -1);
+        tb.write(b"2", true);
+        tb.transaction_commit();
+        assert_eq!(contents(&tb), "1one\ntw2\n");
+        tb.undo();
+        assert_eq!(contents(&tb), "one\ntwo\n");
+        tb.redo();
+        assert_eq!(contents(&tb), "1one\ntw2\n");
+        tb.undo();
+
+        // Aborting one leaves everything as it was, including the redo branch.
+        tb.undo();
+        assert_eq!(contents(&tb), "");
+        let history_len = tb.history().len();
+        tb.cursor_move_to_logical(Point { x: 0, y: 0 });
+        tb.transaction_begin();
+        tb.write(b"x", true);
+        tb.cursor_move_to_logical(Point { x: 0, y: 0 });
+        tb.write(b"y", true);
+        tb.transaction_abort();
+        assert_eq!(contents(&tb), "");
+        assert!(!tb.is_dirty());
+        assert_eq!(tb.history().len(), history_len);
+        tb.redo();
+        assert_eq!(contents(&tb), "one\ntwo\n");
+
+        // Nested transactions only abort their own edits.
+        tb.cursor_move_to_logical(Point { x: 3, y: 0 });
+        tb.transaction_begin();
+        tb.write(b"!", true);
+        tb.transaction_begin();
+        tb.cursor_move_to_logical(Point { x: 0, y: 1 });
+        tb.write(b"?", true);
+        tb.transaction_abort();
+        assert_eq!(contents(&tb), "one!\ntwo\n");
+        assert_eq!(tb.cursor_logical_pos(), Point { x: 4, y: 0 });
+        tb.cursor_move_to_logical(Point { x: 3, y: 1 });
+        tb.write(b"!", true);
+        tb.transaction_commit();
+        assert_eq!(contents(&tb), "one!\ntwo!\n");
+        tb.undo();
+        assert_eq!(contents(&tb), "one\ntwo\n");
+
+        // Typing afterwards isn't merged into the transaction.
+        let result = tb.transaction(|tb| {
+            tb.write(b"a", true);
+            Ok::<_, ()>(())
+        });
+        assert!(result.is_ok());
+        tb.write(b"b", true);
+        tb.undo();
+        assert_eq!(contents(&tb), "onea\ntwo\n");
+        let result = tb.transaction(|tb| {
+            tb.write(b"c", true);
+            Err::<(), _>(())
+        });
+        assert!(result.is_err());
+        assert_eq!(contents(&tb), "onea\ntwo\n");
+        assert!(!tb.in_transaction());
+    }
+
+    #[test]
+    fn test_positional_edits() {
+        let _lock = lock();
+        let mut tb = TextBuffer::new(true).unwrap();
+        tb.write(b"one\ntwo\nthree\n", true);
+        assert_eq!(tb.line(1), b"two");
+        assert_eq!(tb.line(3), b"");
+        assert_eq!(tb.lines(1..10), [b"two".to_vec(), b"three".to_vec(), b"".to_vec()]);
+        assert_eq!(tb.lines(0..2), [b"one".to_vec(), b"two".to_vec()]);
+        assert!(tb.lines(5..10).is_empty());
+
+        let mut crlf = TextBuffer::new(true).unwrap();
+        crlf.write(b"a\r\nb\r\nc", true);
+        assert_eq!(crlf.lines(0..3), [b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]);
+        assert_eq!(tb.text_in_range(Point { x: 1, y: 0 }..Point { x: 2, y: 1 }), b"ne\ntw");
+        assert_eq!(tb.text_in_range(2usize..5usize), b"e\nt");
+
+        // Edits before the cursor and selection move them along.
+        tb.cursor_move_to_logical(Point { x: 1, y: 2 });
+        tb.selection_update_logical(Point { x: 4, y: 2 });
+        let range = tb.replace_range(Point { x: 0, y: 1 }..Point { x: 3, y: 1 }, b"TWO\r\nand");
+        assert_eq!(range, 4..11);
+        assert_eq!(contents(&tb), "one\nTWO\nand\nthree\n");
+        let (beg, end) = tb.selection_range().unwrap();
+        assert_eq!(
+            (beg.logical_pos, end.logical_pos),
+            (Point { x: 1, y: 3 }, Point { x: 4, y: 3 })
+        );
+        assert_eq!(tb.cursor_logical_pos(), Point { x: 4, y: 3 });
+
+        // Edits after them don't.
+        tb.insert_at(Point::MAX, b"four");
+        assert_eq!(contents(&tb), "one\nTWO\nand\nthree\nfour");
+        assert_eq!(tb.cursor_logical_pos(), Point { x: 4, y: 3 });
+
+        // Deleting the text around the cursor moves it to the start.
+        tb.clear_selection();
+        tb.delete_range(Point { x: 0, y: 3 }..Point { x: 0, y: 4 });
+        assert_eq!(contents(&tb), "one\nTWO\nand\nfour");
+        assert_eq!(tb.cursor_logical_pos(), Point { x: 0, y: 3 });
+
+        // Each edit is an undo step of its own, which doesn't get merged with typing.
+        tb.write(b"!", true);
+        tb.undo();
+        tb.undo();
+        assert_eq!(contents(&tb), "one\nTWO\nand\nthree\nfour");
+        tb.undo();
+        tb.undo();
+        assert_eq!(contents(&tb), "one\ntwo\nthree\n");
+
+        // Newlines are translated to those of the buffer.
+        tb.set_crlf(true);
+        let range = tb.insert_at(0usize, b"a\nb\rc");
+        assert_eq!(range, 0..7);
+        assert_eq!(tb.text_in_range(range), b"a\r\nb\r\nc");
+        assert_eq!(tb.logical_pos_at(7usize), Point { x: 1, y: 2 });
+        assert_eq!(tb.offset_at(Point { x: 0, y: 2 }), 6);
+    }
+
+    #[test]
+    fn test_apply_patch() {
+        let _lock = lock();
+        let hunks = |text: &[u8]| crate::patch::parse(text).unwrap().files.remove(0).hunks;
+        let mut tb = TextBuffer::new(true).unwrap();
+        tb.write(b"0\n1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n", true);
+
+        // The first hunk is found a line further down, which moves the second one along.
+        let patch = hunks(
+            b"@@ -2,3 +2,3 @@\n 2\n-3\n+THREE\n 4\n\
+              @@ -8,3 +8,3 @@\n 8\n-9\n+NINE\n 10\n",
+        );
+        let report = tb.apply_patch(&patch, PatchOptions::default());
+        assert!(report.rejected.is_empty());
+        assert_eq!(
+            report.applied,
+            [
+                AppliedHunk { index: 0, line: 2, offset: 1, fuzz: 0 },
+                AppliedHunk { index: 1, line: 8, offset: 0, fuzz: 0 },
+            ]
+        );
+        assert_eq!(contents(&tb), "0\n1\n2\nTHREE\n4\n5\n6\n7\n8\nNINE\n10\n");
+
+        // All of it is a single undo step.
+        tb.undo();
+        assert_eq!(contents(&tb), "0\n1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n");
+
+        // Context that doesn't match needs fuzz. Hunks that don't match at all are rejected.
+        let patch = hunks(b"@@ -5,3 +5,3 @@\n x\n-5\n+FIVE\n 6\n@@ -8 +8 @@\n-nope\n+yes\n");
+        let options = PatchOptions { fuzz: 0, atomic: false };
+        let report = tb.apply_patch(&patch, options);
+        assert!(report.applied.is_empty());
+        assert_eq!(report.rejected.len(), 2);
+
+        let options = PatchOptions { fuzz: 1, atomic: true };
+        let report = tb.apply_patch(&patch, options);
+        assert!(report.applied.is_empty());
+        assert_eq!(report.rejected, [RejectedHunk { index: 1, line: 7 }]);
+        assert_eq!(contents(&tb), "0\n1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n");
+
+        let options = PatchOptions { fuzz: 1, atomic: false };
+        let report = tb.apply_patch(&patch, options);
+        assert_eq!(report.applied, [AppliedHunk { index: 0, line: 4, offset: 0, fuzz: 1 }]);
+        assert_eq!(contents(&tb), "0\n1\n2\n3\n4\nFIVE\n6\n7\n8\n9\n10\n");
+
+        // The final newline is added or removed as the patch says.
+        let mut tb = TextBuffer::new(true).unwrap();
+        tb.write(b"a\nb", true);
+        tb.apply_patch(
+            &hunks(b"@@ -1,2 +1,3 @@\n a\n-b\n\\ No newline at end of file\n+b\n+c\n"),
+            PatchOptions::default(),
+        );
+        assert_eq!(contents(&tb), "a\nb\nc\n");
+        tb.apply_patch(
+            &hunks(b"@@ -3 +3 @@\n-c\n+C\n\\ No newline at end of file\n"),
+            PatchOptions::default(),
+        );
+        assert_eq!(contents(&tb), "a\nb\nC");
+
+        // The newlines of the buffer are kept.
+        let mut tb = TextBuffer::new(true).unwrap();
+        tb.set_crlf(true);
+        tb.write(b"a\nb\n", true);
+        tb.apply_patch(&hunks(b"@@ -1,2 +1,2 @@\n-a\n+A\n b\n"), PatchOptions::default());
+        assert_eq!(contents(&tb), "A\r\nb\r\n");
+    }
+
+    #[test]
+    fn test_undo_file() {
+        let _lock = lock();
+        let p = |x, y| Point { x, y };
+        let dir = env::temp_dir().join(format!("edit-test-undo-{}", process::id()));
+        let path = dir.join("doc.txt");
+        let undo_file = UndoFile::new(&dir.join("undo"), &path);
+        fs::create_dir_all(&dir).unwrap();
+
+        let save = |tb: &mut TextBuffer| tb.write_file(&mut File::create(&path).unwrap()).unwrap();
+        let load = || {
+            let mut tb = TextBuffer::new(false).unwrap();
+            tb.set_undo_file(Some(undo_file.clone()));
+            tb.read_file(&mut File::open(&path).unwrap(), None).unwrap();
+            tb
+        };
+
+        let mut tb = TextBuffer::new(true).unwrap();
+        tb.set_crlf(false);
+        tb.set_undo_file(Some(undo_file.clone()));
+        tb.write(b"hello\nworld\n", true);
+        tb.cursor_move_to_logical(p(0, 0));
+        tb.write(b"one ", true);
+        tb.undo();
+        save(&mut tb);
+
+        // Both the undo and the redo steps are restored, relative to the saved state.
+        let mut tb = load();
+        assert_eq!(contents(&tb), "hello\nworld\n");
+        tb.redo();
+        assert_eq!(contents(&tb), "one hello\nworld\n");
+        assert!(tb.is_dirty());
+        tb.undo();
+        assert!(!tb.is_dirty());
+        tb.undo();
+        assert_eq!(contents(&tb), "");
+        assert!(tb.is_dirty());
+        tb.redo();
+
+        // New edits aren't merged into the restored ones.
+        tb.cursor_move_to_logical(p(5, 0));
+        tb.write(b"!", true);
+        assert_eq!(contents(&tb), "hello!\nworld\n");
+        save(&mut tb);
+        let mut tb = load();
+        tb.undo();
+        assert_eq!(contents(&tb), "hello\nworld\n");
+        tb.undo();
+        assert_eq!(contents(&tb), "");
+
+        // The history doesn't apply to different contents.
+        fs::write(&path, "other\n").unwrap();
+        let mut tb = load();
+        tb.undo();
+        assert_eq!(contents(&tb), "other\n");
+
+        // Without any history, there's no need for the file.
+        save(&mut tb);
+        assert!(!undo_file.path().exists());
+
+        // The oldest steps are dropped to stay below the size limit.
+        let mut tb = TextBuffer::new(false).unwrap();
+        tb.set_undo_file(Some(undo_file.clone()));
+        for _ in 0..3 {
+            tb.cursor_move_to_logical(Point::MAX);
+            tb.write(&vec![b'a'; 3 * MEBI / 2], true);
+        }
+        save(&mut tb);
+        let mut tb = load();
+        tb.undo();
+        tb.undo();
+        assert_eq!(tb.text_length(), 3 * MEBI / 2);
+        tb.undo();
+        assert_eq!(tb.text_length(), 3 * MEBI / 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_search_matches() {
+        let _lock = lock();
+        icu::init().unwrap();
+        let budget = std::time::Duration::from_secs(1);
+        let options = SearchOptions::default();
+        let mut tb = TextBuffer::new(true).unwrap();
+        tb.write(b"foo bar\nbar\n\nFoo foo\n", true);
+
+        tb.set_search_matches("foo", options);
+        assert!(tb.search_matches_pending());
+        tb.search_matches_update(budget);
+        assert!(!tb.search_matches_pending());
+        assert_eq!(
+            tb.search_match_count(),
+            Some(SearchMatchCount { index: None, total: 3, complete: true })
+        );
+        assert_eq!(tb.search_match_lines(), [0, 3]);
+
+        // Selecting a match makes it the current one.
+        tb.cursor_move_to_logical(Point { x: 1, y: 0 });
+        tb.find_and_select("foo", options).unwrap();
+        assert_eq!(tb.search_match_count().unwrap().index, Some(1));
+
+        // Edits make the search continue from the edited line.
+        tb.clear_selection();
+        tb.cursor_move_to_logical(Point { x: 0, y: 1 });
+        tb.write(b"foo", true);
+        assert!(tb.search_matches_pending());
+        assert_eq!(tb.search_match_lines(), []);
+        tb.search_matches_update(budget);
+        assert_eq!(tb.search_match_lines(), [0, 1, 3]);
+        assert_eq!(tb.search_match_count().unwrap().total, 4);
+
+        // Setting the same pattern again keeps the results.
+        tb.set_search_matches("foo", options);
+        assert!(!tb.search_matches_pending());
+
+        tb.set_search_matches("", options);
+        assert_eq!(tb.search_match_count(), None);
+        tb.set_search_matches("(", SearchOptions { use_regex: true, ..options });
+        assert_eq!(tb.search_match_count(), None);
+    }
+
+    #[test]
+    fn test_search_matches_after_edit() {
+        let _lock = lock();
+        icu::init().unwrap();
+        let options = SearchOptions::default();
+        let mut tb = TextBuffer::new(true).unwrap();
+        tb.write(&b"foo\n".repeat(200), true);
+        tb.set_search_matches("foo", options);
+        tb.search_matches_update(std::time::Duration::from_secs(1));
+
+        // The 150 matches before the edited line are kept. Without a budget, the search
+        // stops at the next multiple of 64, instead of after the first 64 matches.
+        tb.cursor_move_to_logical(Point { x: 0, y: 150 });
+        tb.write(b"x", true);
+        tb.search_matches_update(std::time::Duration::ZERO);
+        assert_eq!(
+            tb.search_match_count(),
+            Some(SearchMatchCount { index: None, total: 192, complete: false })
+        );
+        assert_eq!(tb.search_match_lines().len(), 192);
+
+        tb.search_matches_update(std::time::Duration::from_secs(1));
+        assert_eq!(tb.search_match_count().unwrap().total, 200);
+        assert_eq!(tb.search_match_lines(), (0..200).collect::<Vec<_>>());
+
+        // Undo works the same way.
+        tb.undo();
+        tb.search_matches_update(std::time::Duration::ZERO);
+        assert_eq!(tb.search_match_count().unwrap().total, 192);
+    }
+
+    #[test]
+    fn test_adjust_for_change() {
+        let _lock = lock();
+        let buffer = TextBuffer::new_rc(false).unwrap();
+        let pos = Rc::new(Cell::new(Vec::new()));
+        let mut tb = buffer.borrow_mut();
+        tb.write(b"aaa\nbbb\nccc", true);
+
+        let mut positions = [Point { x: 1, y: 0 }, Point { x: 2, y: 1 }, Point { x: 1, y: 2 }];
+        tb.subscribe({
+            let pos = pos.clone();
+            move |change| {
+                for p in &mut positions {
+                    *p = view::adjust_for_change(*p, change);
+                }
+                pos.set(positions.to_vec());
+            }
+        });
+
+        // Insert a line in the middle of "bbb".
+        tb.cursor_move_to_logical(Point { x: 1, y: 1 });
+        tb.write(b"x\ny", true);
+        assert_eq!(pos.take(), [Point { x: 1, y: 0 }, Point { x: 2, y: 2 }, Point { x: 1, y: 3 }]);
+
+        // Delete from the middle of "aaa" to the middle of "ybb".
+        tb.cursor_move_to_logical(Point { x: 2, y: 0 });
+        tb.selection_update_logical(Point { x: 2, y: 2 });
+        tb.delete(CursorMovement::Grapheme, 1);
+        assert_eq!(pos.take(), [Point { x: 1, y: 0 }, Point { x: 2, y: 0 }, Point { x: 1, y: 1 }]);
+    }
+
+    #[test]
+    fn test_view_swap() {
+        let _lock = lock();
+        let mut tb = TextBuffer::new(false).unwrap();
+        tb.write(b"aaaa bbbb cccc dddd\neeee", true);
+        tb.set_word_wrap(true);
+        tb.set_width(10);
+
+        // A second view at the start of the buffer, twice as wide.
+        let mut view = TextBufferView::default();
+        tb.cursor_move_to_logical(Point { x: 0, y: 0 });
+        tb.view_release(&mut view);
+        view.set_width(20);
+        tb.cursor_move_to_logical(Point { x: 2, y: 1 });
+        tb.selection_update_logical(Point { x: 4, y: 1 });
+
+        tb.view_swap(&mut view);
+        assert_eq!(tb.cursor_logical_pos(), Point { x: 0, y: 0 });
+        assert!(!tb.has_selection());
+        assert_eq!(tb.visual_line_count(), 2);
+
+        tb.view_swap(&mut view);
+        assert_eq!(tb.cursor_logical_pos(), Point { x: 4, y: 1 });
+        assert!(tb.has_selection());
+        assert_eq!(tb.visual_line_count(), 3);
+    }
+}
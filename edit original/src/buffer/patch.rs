@@ -0,0 +1,202 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Applies the hunks of a unified diff. See [`crate::patch`] for parsing them.
+//!
+//! Like GNU patch, each hunk is searched for by its context, starting where it's
+//! expected and moving outwards. With fuzz, the outermost context lines may
+//! mismatch. The changes are made as a single undo step via [`TextBuffer::replace_range`],
+//! which means that the newlines of the buffer are kept as they are.
+
+use super::TextBuffer;
+use crate::diff::DiffOp;
+use crate::helpers::*;
+use crate::patch::Hunk;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PatchOptions {
+    /// How many context lines at the start and end of a hunk may mismatch.
+    pub fuzz: usize,
+    /// If true, nothing is changed unless all hunks apply.
+    pub atomic: bool,
+}
+
+impl Default for PatchOptions {
+    fn default() -> Self {
+        Self { fuzz: 2, atomic: false }
+    }
+}
+
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct PatchReport {
+    pub applied: Vec<AppliedHunk>,
+    pub rejected: Vec<RejectedHunk>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct AppliedHunk {
+    /// The index of the hunk within the given ones.
+    pub index: usize,
+    /// The 0-based line where the hunk starts now.
+    pub line: CoordType,
+    /// How many lines away from the expected position it was found.
+    pub offset: CoordType,
+    /// How many context lines had to be ignored at the start or end.
+    pub fuzz: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RejectedHunk {
+    /// The index of the hunk within the given ones.
+    pub index: usize,
+    /// The 0-based line where the hunk was expected, taking the previous hunks into account.
+    pub line: CoordType,
+}
+
+/// A change to the lines of the buffer.
+struct Edit<'a> {
+    line: usize,
+    len: usize,
+    lines: Vec<&'a [u8]>,
+    /// Whether the last of the new `lines` lacks a newline, if it's the last one in the buffer.
+    no_newline: bool,
+}
+
+impl TextBuffer {
+    /// Applies the given hunks, which must be in order, as a single undo step.
+    /// Hunks that don't match the contents are skipped and reported as rejected.
+    pub fn apply_patch(&mut self, hunks: &[Hunk], options: PatchOptions) -> PatchReport {
+        // The lines as they are after applying the hunks so far.
+        let mut contents = self.lines(0..self.logical_line_count());
+        if contents.last().is_some_and(Vec::is_empty) {
+            // The empty line after a final newline isn't a line as far as diffs are concerned.
+            contents.pop();
+        }
+        let mut lines: Vec<&[u8]> = contents.iter().map(Vec::as_slice).collect();
+
+        let mut report = PatchReport::default();
+        let mut edits = Vec::new();
+        // How far the lines have moved compared to the line numbers in the hunks.
+        let mut delta = 0isize;
+        // Hunks must not overlap the ones before them.
+        let mut min_line = 0;
+
+        for (index, hunk) in hunks.iter().enumerate() {
+            let old: Vec<_> =
+                hunk.lines.iter().filter(|l| l.op != DiffOp::Insert).map(|l| &l.text[..]).collect();
+            let new: Vec<_> =
+                hunk.lines.iter().filter(|l| l.op != DiffOp::Delete).map(|l| &l.text[..]).collect();
+            let leading = hunk.lines.iter().take_while(|l| l.op == DiffOp::Equal).count();
+            let trailing = hunk.lines.iter().rev().take_while(|l| l.op == DiffOp::Equal).count();
+            let start = if hunk.old_len == 0 { hunk.old_start } else { hunk.old_start.max(1) - 1 };
+            let expected = (start as isize + delta).clamp(min_line as isize, lines.len() as isize);
+            let expected = expected as usize;
+
+            let mut found = None;
+            for fuzz in 0..=options.fuzz {
+                let top = fuzz.min(leading);
+                let bottom = fuzz.min(trailing);
+                let pattern = &old[top..old.len().saturating_sub(bottom).max(top)];
+                if let Some(line) = find_lines(&lines, pattern, expected + top, min_line) {
+                    found = Some((line, top, bottom, fuzz));
+                    break;
+                }
+                // Without context there's nothing more to ignore.
+                if top == leading && bottom == trailing {
+                    break;
+                }
+            }
+
+            let Some((line, top, bottom, fuzz)) = found else {
+                report.rejected.push(RejectedHunk { index, line: expected as CoordType });
+                continue;
+            };
+
+            let len = old.len() - top - bottom;
+            let new_lines = new[top..new.len() - bottom].to_vec();
+            let no_newline = bottom == 0
+                && hunk.lines.last().is_some_and(|l| l.no_newline && l.op != DiffOp::Delete);
+            lines.splice(line..line + len, new_lines.iter().copied());
+
+            report.applied.push(AppliedHunk {
+                index,
+                line: (line - top) as CoordType,
+                offset: (line - top) as CoordType - expected as CoordType,
+                fuzz,
+            });
+            delta =
+                (line - top) as isize - start as isize + new.len() as isize - old.len() as isize;
+            min_line = line + new_lines.len();
+            edits.push(Edit { line, len, lines: new_lines, no_newline });
+        }
+
+        if options.atomic && !report.rejected.is_empty() {
+            report.applied.clear();
+            return report;
+        }
+        if edits.is_empty() {
+            return report;
+        }
+
+        self.transaction_begin();
+        let newline_at_end = |tb: &Self| {
+            let len = tb.text_length();
+            len == 0 || tb.text_in_range(len - 1..len) == b"\n"
+        };
+        let mut count = self.logical_line_count() as usize - newline_at_end(self) as usize;
+
+        for edit in edits {
+            let mut text = Vec::new();
+            let at_end = edit.line + edit.len >= count;
+            // Appending to a last line without a newline needs one in between.
+            if edit.line >= count && count > 0 && !newline_at_end(self) && !edit.lines.is_empty() {
+                text.push(b'\n');
+            }
+            for line in &edit.lines {
+                text.extend_from_slice(line);
+                text.push(b'\n');
+            }
+            if at_end && edit.no_newline {
+                text.pop();
+            }
+
+            let beg = Point { x: 0, y: edit.line as CoordType };
+            let end = if at_end {
+                Point::MAX
+            } else {
+                Point { x: 0, y: (edit.line + edit.len) as CoordType }
+            };
+            let beg = if edit.line >= count { Point::MAX } else { beg };
+            self.replace_range(beg..end, &text);
+            count = count - edit.len + edit.lines.len();
+        }
+
+        self.transaction_commit();
+        report
+    }
+}
+
+/// Returns the line where `pattern` occurs in `lines`, the closest one to `expected` that's at least `min`.
+fn find_lines(lines: &[&[u8]], pattern: &[&[u8]], expected: usize, min: usize) -> Option<usize> {
+    let max = lines.len().checked_sub(pattern.len())?;
+    if min > max {
+        return None;
+    }
+    let expected = expected.clamp(min, max);
+    let matches = |line: usize| lines[line..line + pattern.len()] == *pattern;
+
+    for distance in 0..=(max - min) {
+        if let Some(line) = expected.checked_add(distance).filter(|&l| l <= max)
+            && matches(line)
+        {
+            return Some(line);
+        }
+        if distance > 0
+            && let Some(line) = expected.checked_sub(distance).filter(|&l| l >= min)
+            && matches(line)
+        {
+            return Some(line);
+        }
+    }
+    None
+}
@@ -0,0 +1,508 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! A piece table, built on an immutable (persistent) tree.
+//!
+//! The text is stored in append-only chunks that are never modified once written.
+//! The document is then a sequence of pieces that refer to ranges within those chunks.
+//! The pieces are kept in a treap (a randomized binary search tree) whose nodes are
+//! reference counted and copied on write. Taking a snapshot of the document is thus
+//! just a matter of cloning the root pointer, and snapshots can be read from any thread.
+//!
+//! Compared to the [`super::GapBuffer`] the downside is that edits leave behind
+//! ever smaller pieces, which makes reading the text in chunks slower.
+
+use std::ops::Range;
+use std::ptr::NonNull;
+use std::slice;
+use std::sync::Arc;
+
+use crate::document::{ReadableDocument, WriteableDocument};
+use crate::helpers::*;
+
+/// The minimum size of a chunk of text. Larger writes get a chunk of their own.
+const CHUNK_SIZE: usize = 64 * KIBI;
+
+/// An append-only block of memory. Bytes may only be written past the
+/// length of the owning [`PieceTable`] and aren't modified after that.
+struct Chunk {
+    ptr: NonNull<u8>,
+    cap: usize,
+}
+
+// SAFETY: The referenced memory is owned by the chunk and any part of it that's
+// referenced by a piece is immutable. Only the owning `PieceTable` writes into it.
+unsafe impl Send for Chunk {}
+unsafe impl Sync for Chunk {}
+
+impl Chunk {
+    fn new(cap: usize) -> Arc<Self> {
+        let data = Box::into_raw(vec![0u8; cap].into_boxed_slice());
+        let ptr = unsafe { NonNull::new_unchecked(data as *mut u8) };
+        Arc::new(Self { ptr, cap })
+    }
+}
+
+impl Drop for Chunk {
+    fn drop(&mut self) {
+        unsafe {
+            let data = std::ptr::slice_from_raw_parts_mut(self.ptr.as_ptr(), self.cap);
+            drop(Box::from_raw(data));
+        }
+    }
+}
+
+/// A range of text within a [`Chunk`].
+#[derive(Clone)]
+struct Piece {
+    chunk: Arc<Chunk>,
+    off: usize,
+    len: usize,
+}
+
+impl Piece {
+    fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.chunk.ptr.as_ptr().add(self.off), self.len) }
+    }
+
+    /// Pieces never share their start, so its address hashes into a good treap priority.
+    fn priority(&self) -> u32 {
+        let addr = self.chunk.ptr.as_ptr() as u64 + self.off as u64;
+        (addr.wrapping_mul(0x9E3779B97F4A7C15) >> 32) as u32
+    }
+
+    fn into_node(self) -> Tree {
+        let priority = self.priority();
+        let len = self.len;
+        Some(Arc::new(Node { piece: self, priority, len, left: None, right: None }))
+    }
+}
+
+#[derive(Clone)]
+struct Node {
+    piece: Piece,
+    /// Treap priority. Parents always have a priority >= their children.
+    priority: u32,
+    /// Length of the text in this subtree, including this node.
+    len: usize,
+    left: Tree,
+    right: Tree,
+}
+
+type Tree = Option<Arc<Node>>;
+
+impl Node {
+    fn update(&mut self) {
+        self.len = tree_len(&self.left) + self.piece.len + tree_len(&self.right);
+    }
+}
+
+fn tree_len(tree: &Tree) -> usize {
+    tree.as_ref().map_or(0, |n| n.len)
+}
+
+/// Splits the tree into the text before and after `off`.
+/// Nodes that are shared with snapshots are copied, everything else is modified in place.
+fn split(tree: Tree, off: usize) -> (Tree, Tree) {
+    let Some(mut node) = tree else {
+        return (None, None);
+    };
+
+    let n = Arc::make_mut(&mut node);
+    let left_len = tree_len(&n.left);
+
+    if off <= left_len {
+        let (l, r) = split(n.left.take(), off);
+        n.left = r;
+        n.update();
+        (l, Some(node))
+    } else if off >= left_len + n.piece.len {
+        let (l, r) = split(n.right.take(), off - left_len - n.piece.len);
+        n.right = l;
+        n.update();
+        (Some(node), r)
+    } else {
+        // The offset is inside this piece, so the second half becomes a new node.
+        let cut = off - left_len;
+        let chunk = n.piece.chunk.clone();
+        let tail = Piece { chunk, off: n.piece.off + cut, len: n.piece.len - cut };
+        let right = merge(tail.into_node(), n.right.take());
+        n.piece.len = cut;
+        n.update();
+        (Some(node), right)
+    }
+}
+
+/// Concatenates two trees.
+fn merge(a: Tree, b: Tree) -> Tree {
+    match (a, b) {
+        (None, t) | (t, None) => t,
+        (Some(mut a), Some(mut b)) => {
+            if a.priority >= b.priority {
+                let n = Arc::make_mut(&mut a);
+                n.right = merge(n.right.take(), Some(b));
+                n.update();
+                Some(a)
+            } else {
+                let n = Arc::make_mut(&mut b);
+                n.left = merge(Some(a), n.left.take());
+                n.update();
+                Some(b)
+            }
+        }
+    }
+}
+
+/// Calls `f` with the piece containing `off`, which must change its length by `delta`.
+/// This is a lot cheaper than a split and merge, but the piece must not become empty.
+fn modify_at(node: &mut Arc<Node>, off: usize, delta: isize, f: impl FnOnce(&mut Piece)) {
+    let n = Arc::make_mut(node);
+    let left_len = tree_len(&n.left);
+    n.len = n.len.wrapping_add_signed(delta);
+
+    if off < left_len {
+        modify_at(n.left.as_mut().unwrap(), off, delta, f);
+    } else if off < left_len + n.piece.len {
+        f(&mut n.piece);
+        debug_assert!(n.piece.len > 0);
+    } else {
+        modify_at(n.right.as_mut().unwrap(), off - left_len - n.piece.len, delta, f);
+    }
+}
+
+/// Returns the piece containing `off` and the offset within it.
+fn find(tree: &Tree, mut off: usize) -> Option<(&Piece, usize)> {
+    let mut node = tree.as_deref()?;
+    loop {
+        let left_len = tree_len(&node.left);
+        if off < left_len {
+            node = node.left.as_deref()?;
+        } else if off < left_len + node.piece.len {
+            return Some((&node.piece, off - left_len));
+        } else {
+            off -= left_len + node.piece.len;
+            node = node.right.as_deref()?;
+        }
+    }
+}
+
+fn read_forward(tree: &Tree, off: usize) -> &[u8] {
+    match find(tree, off) {
+        Some((piece, off)) => &piece.as_slice()[off..],
+        None => &[],
+    }
+}
+
+fn read_backward(tree: &Tree, off: usize) -> &[u8] {
+    if off == 0 {
+        return &[];
+    }
+    match find(tree, off.min(tree_len(tree)) - 1) {
+        Some((piece, off)) => &piece.as_slice()[..off + 1],
+        None => &[],
+    }
+}
+
+fn extract_raw(doc: &dyn ReadableDocument, range: Range<usize>, out: &mut Vec<u8>, out_off: usize) {
+    let mut beg = range.start;
+    let mut out_off = out_off.min(out.len());
+
+    while beg < range.end {
+        let chunk = doc.read_forward(beg);
+        if chunk.is_empty() {
+            break;
+        }
+        let chunk = &chunk[..chunk.len().min(range.end - beg)];
+        out.replace_range(out_off..out_off, chunk);
+        beg += chunk.len();
+        out_off += chunk.len();
+    }
+}
+
+/// A text storage with O(1) snapshots. Its interface mirrors that of [`super::GapBuffer`].
+pub struct PieceTable {
+    root: Tree,
+    /// The chunk that new text is appended to.
+    chunk: Arc<Chunk>,
+    /// The number of bytes in `chunk` that are in use.
+    chunk_len: usize,
+    /// The offset at which [`PieceTable::commit_gap`] inserts.
+    gap_off: usize,
+    /// Increments every time the buffer is modified.
+    generation: u32,
+}
+
+impl Default for PieceTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PieceTable {
+    pub fn new() -> Self {
+        Self { root: None, chunk: Chunk::new(0), chunk_len: 0, gap_off: 0, generation: 0 }
+    }
+
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        tree_len(&self.root)
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    pub fn set_generation(&mut self, generation: u32) {
+        self.generation = generation;
+    }
+
+    /// Returns the number of pieces the text is split into.
+    pub fn piece_count(&self) -> usize {
+        fn count(tree: &Tree) -> usize {
+            tree.as_ref().map_or(0, |n| 1 + count(&n.left) + count(&n.right))
+        }
+        count(&self.root)
+    }
+
+    /// Returns an immutable copy of the current contents. This is O(1).
+    pub fn snapshot(&self) -> BufferSnapshot {
+        BufferSnapshot { root: self.root.clone(), generation: self.generation }
+    }
+
+    /// Deletes `delete` bytes at `off` and returns a buffer for up to `len` bytes
+    /// of text, which gets inserted at `off` by calling [`PieceTable::commit_gap`].
+    pub fn allocate_gap(&mut self, off: usize, len: usize, delete: usize) -> &mut [u8] {
+        let off = off.min(self.len());
+        let delete = delete.min(self.len() - off);
+
+        if delete > 0 {
+            self.delete(off, delete);
+        }
+
+        if len > self.chunk.cap - self.chunk_len {
+            self.chunk = Chunk::new(len.max(CHUNK_SIZE));
+            self.chunk_len = 0;
+        }
+
+        self.gap_off = off;
+        self.generation = self.generation.wrapping_add(1);
+
+        // SAFETY: The memory past `chunk_len` isn't referenced by any piece yet.
+        unsafe { slice::from_raw_parts_mut(self.chunk.ptr.as_ptr().add(self.chunk_len), len) }
+    }
+
+    pub fn commit_gap(&mut self, len: usize) {
+        assert!(len <= self.chunk.cap - self.chunk_len);
+        if len == 0 {
+            return;
+        }
+
+        let off = self.chunk_len;
+        self.chunk_len += len;
+
+        // Consecutive writes (= typing) are appended to the previous piece.
+        let contiguous = self.gap_off > 0
+            && find(&self.root, self.gap_off - 1).is_some_and(|(piece, within)| {
+                within + 1 == piece.len
+                    && piece.off + piece.len == off
+                    && Arc::ptr_eq(&piece.chunk, &self.chunk)
+            });
+
+        if contiguous {
+            let root = self.root.as_mut().unwrap();
+            modify_at(root, self.gap_off - 1, len as isize, |piece| piece.len += len);
+        } else {
+            let piece = Piece { chunk: self.chunk.clone(), off, len };
+            let (left, right) = split(self.root.take(), self.gap_off);
+            self.root = merge(merge(left, piece.into_node()), right);
+        }
+
+        self.gap_off += len;
+    }
+
+    fn delete(&mut self, off: usize, len: usize) {
+        // Deleting from either end of a piece (= backspace) doesn't change the tree's shape.
+        if let Some((piece, within)) = find(&self.root, off)
+            && len < piece.len
+            && (within == 0 || within + len == piece.len)
+        {
+            let root = self.root.as_mut().unwrap();
+            modify_at(root, off, -(len as isize), |piece| {
+                if within == 0 {
+                    piece.off += len;
+                }
+                piece.len -= len;
+            });
+            return;
+        }
+
+        let (left, rest) = split(self.root.take(), off);
+        let (_, right) = split(rest, len);
+        self.root = merge(left, right);
+    }
+
+    pub fn replace(&mut self, range: Range<usize>, src: &[u8]) {
+        let gap = self.allocate_gap(range.start, src.len(), range.end.saturating_sub(range.start));
+        let len = slice_copy_safe(gap, src);
+        self.commit_gap(len);
+    }
+
+    pub fn clear(&mut self) {
+        self.root = None;
+        self.gap_off = 0;
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    pub fn extract_raw(&self, range: Range<usize>, out: &mut Vec<u8>, out_off: usize) {
+        extract_raw(self, range, out, out_off);
+    }
+
+    /// Replaces the entire buffer contents with the given `text`.
+    /// Returns `true` if the buffer contents were changed.
+    pub fn copy_from(&mut self, src: &dyn ReadableDocument) -> bool {
+        // Skip the common prefix, just like `GapBuffer::copy_from`.
+        let mut off = 0;
+        loop {
+            let dst_chunk = self.read_forward(off);
+            let src_chunk = src.read_forward(off);
+            let len = dst_chunk.len().min(src_chunk.len());
+
+            if dst_chunk[..len] != src_chunk[..len] {
+                break;
+            }
+            if len == 0 {
+                if dst_chunk.len() == src_chunk.len() {
+                    return false;
+                }
+                break;
+            }
+
+            off += len;
+        }
+
+        self.replace(off..usize::MAX, &[]);
+        loop {
+            let chunk = src.read_forward(off);
+            if chunk.is_empty() {
+                return true;
+            }
+            self.replace(off..off, chunk);
+            off += chunk.len();
+        }
+    }
+
+    /// Copies the contents of the buffer into a string.
+    pub fn copy_into(&self, dst: &mut dyn WriteableDocument) {
+        dst.replace(0..usize::MAX, &[]);
+
+        let mut off = 0;
+        loop {
+            let chunk = self.read_forward(off);
+            if chunk.is_empty() {
+                break;
+            }
+            dst.replace(usize::MAX..usize::MAX, chunk);
+            off += chunk.len();
+        }
+    }
+}
+
+impl ReadableDocument for PieceTable {
+    fn read_forward(&self, off: usize) -> &[u8] {
+        read_forward(&self.root, off)
+    }
+
+    fn read_backward(&self, off: usize) -> &[u8] {
+        read_backward(&self.root, off)
+    }
+}
+
+/// An immutable version of a document's contents.
+/// It's cheap to clone and can be sent to other threads.
+#[derive(Clone, Default)]
+pub struct BufferSnapshot {
+    root: Tree,
+    generation: u32,
+}
+
+impl BufferSnapshot {
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        tree_len(&self.root)
+    }
+
+    /// The generation of the buffer at the time the snapshot was taken.
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    pub fn extract_raw(&self, range: Range<usize>, out: &mut Vec<u8>, out_off: usize) {
+        extract_raw(self, range, out, out_off);
+    }
+}
+
+impl ReadableDocument for BufferSnapshot {
+    fn read_forward(&self, off: usize) -> &[u8] {
+        read_forward(&self.root, off)
+    }
+
+    fn read_backward(&self, off: usize) -> &[u8] {
+        read_backward(&self.root, off)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contents(doc: &dyn ReadableDocument) -> String {
+        let mut out = Vec::new();
+        extract_raw(doc, 0..usize::MAX, &mut out, 0);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_edits_and_snapshots() {
+        let mut pt = PieceTable::new();
+        pt.replace(0..0, b"hello world");
+        pt.replace(5..5, b",");
+        pt.replace(6..6, b" big");
+        assert_eq!(contents(&pt), "hello, big world");
+        // The second insert got appended to the piece of the first one.
+        assert_eq!(pt.piece_count(), 3);
+
+        let snapshot = pt.snapshot();
+        pt.replace(0..5, b"goodbye");
+        pt.replace(pt.len()..pt.len(), b"!");
+        assert_eq!(contents(&pt), "goodbye, big world!");
+        assert_eq!(contents(&snapshot), "hello, big world");
+        assert_eq!(snapshot.read_backward(7), b", ");
+        assert_eq!(pt.read_backward(7), b"goodbye");
+
+        assert!(!pt.copy_from(&"goodbye, big world!".to_string()));
+        assert!(pt.copy_from(&"goodbye".to_string()));
+        assert_eq!(contents(&pt), "goodbye");
+        assert_eq!(contents(&snapshot), "hello, big world");
+    }
+
+    #[test]
+    fn test_random_edits() {
+        let mut pt = PieceTable::new();
+        let mut expected = Vec::new();
+        let mut rng = 1u32;
+        let mut next = |max: usize| {
+            rng = rng.wrapping_mul(1103515245).wrapping_add(12345);
+            (rng >> 8) as usize % (max + 1)
+        };
+
+        for i in 0..2000 {
+            let beg = next(expected.len());
+            let end = beg + next((expected.len() - beg).min(8));
+            let text = format!("{i}");
+            pt.replace(beg..end, text.as_bytes());
+            expected.splice(beg..end, text.bytes());
+        }
+
+        assert_eq!(contents(&pt).as_bytes(), expected);
+    }
+}
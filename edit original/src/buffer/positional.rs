@@ -0,0 +1,192 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Reading and editing the contents at given positions, instead of at the cursor.
+//!
+//! This is meant for tools, like formatters or patches. The user's cursors and selection
+//! stay where they are, relative to the surrounding text, and the edits can be undone.
+
+use std::ops::Range;
+
+use super::{HistoryType, TextBuffer, TextBufferSelection};
+use crate::helpers::*;
+use crate::simd::memchr2;
+use crate::unicode::{self, Cursor};
+
+/// A position in a [`TextBuffer`], for the methods in this module.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TextPos {
+    /// A logical position: The 0-based line (.y) and grapheme cluster within it (.x).
+    Logical(Point),
+    /// A byte offset. It's moved back to the start of the grapheme cluster it's in.
+    Offset(usize),
+}
+
+impl From<Point> for TextPos {
+    fn from(pos: Point) -> Self {
+        Self::Logical(pos)
+    }
+}
+
+impl From<usize> for TextPos {
+    fn from(offset: usize) -> Self {
+        Self::Offset(offset)
+    }
+}
+
+impl TextBuffer {
+    /// Returns the cursor at the given position, clamped to the contents.
+    fn cursor_at(&self, pos: TextPos) -> Cursor {
+        match pos {
+            TextPos::Logical(pos) => self.cursor_move_to_logical_internal(self.cursor, pos),
+            TextPos::Offset(offset) => self.cursor_move_to_offset_internal(self.cursor, offset),
+        }
+    }
+
+    /// Returns the cursors at the start and end of `range`, in that order.
+    fn cursor_range<P: Into<TextPos>>(&self, range: Range<P>) -> (Cursor, Cursor) {
+        let beg = self.cursor_at(range.start.into());
+        let end = self.cursor_at(range.end.into());
+        if beg.offset <= end.offset { (beg, end) } else { (end, beg) }
+    }
+
+    /// Returns the byte offset of the given position, clamped to the contents.
+    pub fn offset_at(&self, pos: impl Into<TextPos>) -> usize {
+        self.cursor_at(pos.into()).offset
+    }
+
+    /// Returns the logical position at the given position, clamped to the contents.
+    pub fn logical_pos_at(&self, pos: impl Into<TextPos>) -> Point {
+        self.cursor_at(pos.into()).logical_pos
+    }
+
+    /// Returns the contents within `range`, as they are stored (i.e. with CRLF if [`TextBuffer::is_crlf`]).
+    pub fn text_in_range<P: Into<TextPos>>(&self, range: Range<P>) -> Vec<u8> {
+        let (beg, end) = self.cursor_range(range);
+        let mut out = Vec::new();
+        self.buffer.extract_raw(beg.offset..end.offset, &mut out, 0);
+        out
+    }
+
+    /// Returns the contents of the 0-based line `y`, without its line ending.
+    /// Lines past the end are empty.
+    pub fn line(&self, y: CoordType) -> Vec<u8> {
+        let mut out = self.text_in_range(Point { x: 0, y }..Point { x: 0, y: y + 1 });
+        out.truncate(unicode::strip_newline(&out).len());
+        if y >= self.stats.logical_lines {
+            out.clear();
+        }
+        out
+    }
+
+    /// Returns the contents of the 0-based lines in `range`, without their line endings.
+    /// Only those that exist are returned.
+    pub fn lines(&self, range: Range<CoordType>) -> Vec<Vec<u8>> {
+        let range = range.start.max(0)..range.end.min(self.stats.logical_lines);
+        if range.is_empty() {
+            return Vec::new();
+        }
+        // Extracted in one go, because seeking to each line on its own is quadratic.
+        let text = self.text_in_range(Point { x: 0, y: range.start }..Point { x: 0, y: range.end });
+        text.split(|&b| b == b'\n')
+            .take(range.len())
+            .map(|line| unicode::strip_newline(line).to_vec())
+            .collect()
+    }
+
+    /// Inserts `text` at `pos`. See [`TextBuffer::replace_range`].
+    pub fn insert_at(&mut self, pos: impl Into<TextPos>, text: &[u8]) -> Range<usize> {
+        let pos = pos.into();
+        self.replace_range(pos..pos, text)
+    }
+
+    /// Deletes the contents within `range`. See [`TextBuffer::replace_range`].
+    pub fn delete_range<P: Into<TextPos>>(&mut self, range: Range<P>) {
+        self.replace_range(range, b"");
+    }
+
+    /// Replaces the contents within `range` with `text` as a single undo step and
+    /// returns the range of offsets the `text` ended up at. Its newlines are
+    /// translated to those of the buffer, but it's otherwise written as-is.
+    ///
+    /// The cursors and the selection stay where they are relative to the surrounding
+    /// text. Those within the replaced range are moved to the end of the new text.
+    /// Those right at an insertion point stay in front of the new text.
+    pub fn replace_range<P: Into<TextPos>>(
+        &mut self,
+        range: Range<P>,
+        text: &[u8],
+    ) -> Range<usize> {
+        debug_assert_eq!(self.active_edit_depth, 0);
+        let (beg, end) = self.cursor_range(range);
+        if beg.offset == end.offset && text.is_empty() {
+            return beg.offset..beg.offset;
+        }
+
+        // Remember where the user's cursors are, to restore them afterwards.
+        let offset_of =
+            |tb: &Self, pos: Point| tb.cursor_move_to_logical_internal(tb.cursor, pos).offset;
+        let cursor = self.cursor.offset;
+        let selection = self.selection.map(|s| [offset_of(self, s.beg), offset_of(self, s.end)]);
+        let cursors: Vec<_> =
+            self.cursors.iter().map(|s| [offset_of(self, s.beg), offset_of(self, s.end)]).collect();
+
+        self.edit_begin(HistoryType::Other, beg);
+        if end.offset > beg.offset {
+            self.edit_delete(end);
+        }
+
+        // Same newline handling as in `write`.
+        let newline: &[u8] = if self.newlines_are_crlf { b"\r\n" } else { b"\n" };
+        let mut off = 0;
+        while off < text.len() {
+            let next = memchr2(b'\r', b'\n', text, off);
+            if next > off {
+                self.edit_write(&text[off..next]);
+            }
+            if next >= text.len() {
+                break;
+            }
+            self.edit_write(newline);
+            off = next + 1;
+            if text[next] == b'\r' && text.get(off) == Some(&b'\n') {
+                off += 1;
+            }
+        }
+
+        let inserted = self.active_edit_off - beg.offset;
+        self.edit_end();
+
+        let adjust = |off: usize| {
+            if off <= beg.offset {
+                off
+            } else if off >= end.offset {
+                off - (end.offset - beg.offset) + inserted
+            } else {
+                beg.offset + inserted
+            }
+        };
+        let logical_pos = |tb: &Self, off: usize| {
+            tb.cursor_move_to_offset_internal(tb.cursor, adjust(off)).logical_pos
+        };
+
+        self.set_cursor_internal(self.cursor_move_to_offset_internal(self.cursor, adjust(cursor)));
+        // Assigned directly, because it's the same selection as before as far as the user is concerned.
+        self.selection = selection
+            .map(|[b, e]| TextBufferSelection {
+                beg: logical_pos(self, b),
+                end: logical_pos(self, e),
+            })
+            .filter(|s| s.beg != s.end);
+        self.cursors = cursors
+            .into_iter()
+            .map(|[b, e]| TextBufferSelection {
+                beg: logical_pos(self, b),
+                end: logical_pos(self, e),
+            })
+            .collect();
+        self.last_history_type = HistoryType::Other;
+
+        beg.offset..beg.offset + inserted
+    }
+}
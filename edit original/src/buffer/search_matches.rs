@@ -0,0 +1,192 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Finds all matches of a search pattern, for highlighting them.
+//!
+//! Unlike [`TextBuffer::find_and_select`], which only ever looks for the next hit, this goes
+//! through the entire document. Large documents would take a while, so it's done in slices:
+//! Each [`TextBuffer::search_matches_update`] continues where the last one stopped, and
+//! [`TextBuffer::search_matches_pending`] tells the caller to call it again.
+//! After an edit, the matches before the edited line are kept and only the rest is searched again.
+
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+use super::{SearchOptions, TextBuffer, TextBufferSelection};
+use crate::helpers::*;
+use crate::icu;
+use crate::unicode::Cursor;
+
+pub(super) struct SearchMatches {
+    pattern: String,
+    options: SearchOptions,
+    text: icu::Text,
+    regex: icu::Regex,
+    /// [`super::GapBuffer::generation`] and word wrap column the results are for.
+    /// If the word wrap column changes, the search starts over.
+    buffer_generation: u32,
+    word_wrap_column: CoordType,
+    /// The lowest offset edited since the results were last updated, or `usize::MAX`.
+    pub(super) edited_from: usize,
+    /// The matches found so far, in order.
+    pub(super) ranges: Vec<Range<usize>>,
+    /// The distinct visual lines the `ranges` start on, in order.
+    lines: Vec<CoordType>,
+    /// The start of the last match, to compute the next line from.
+    cursor: Cursor,
+    /// Whether the entire document has been searched.
+    complete: bool,
+}
+
+/// The result of [`TextBuffer::search_match_count`].
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct SearchMatchCount {
+    /// The 0-based index of the match that's selected, if any.
+    pub index: Option<usize>,
+    /// The number of matches found so far.
+    pub total: usize,
+    /// Whether the entire document has been searched, meaning that `total` is final.
+    pub complete: bool,
+}
+
+impl TextBuffer {
+    /// Highlights all matches of `pattern` from now on, or none if it's empty or invalid.
+    /// Calling this again with the same arguments keeps the results found so far.
+    pub fn set_search_matches(&mut self, pattern: &str, options: SearchOptions) {
+        if let Some(m) = &self.search_matches
+            && m.pattern == pattern
+            && m.options == options
+        {
+            return;
+        }
+
+        self.search_matches = None;
+        if pattern.is_empty() {
+            return;
+        }
+
+        // Invalid patterns are left for `find_and_select` to report.
+        if let Ok(search) = self.find_construct_search(pattern, options) {
+            self.search_matches = Some(SearchMatches {
+                pattern: search.pattern,
+                options,
+                text: search.text,
+                regex: search.regex,
+                buffer_generation: search.buffer_generation,
+                word_wrap_column: self.word_wrap_column,
+                edited_from: usize::MAX,
+                ranges: Vec::new(),
+                lines: Vec::new(),
+                cursor: Cursor::default(),
+                complete: false,
+            });
+        }
+    }
+
+    /// Returns true if the search for the matches isn't done yet,
+    /// or needs to start over because the contents changed.
+    pub fn search_matches_pending(&self) -> bool {
+        self.search_matches.as_ref().is_some_and(|m| !m.complete || self.search_matches_stale(m))
+    }
+
+    /// Continues searching for matches for up to `budget`.
+    pub fn search_matches_update(&mut self, budget: Duration) {
+        let Some(mut m) = self.search_matches.take() else {
+            return;
+        };
+
+        if self.search_matches_stale(&m) {
+            // A match may depend on the text after it, up to the end of its line (e.g. `\b`).
+            // Those that end before the edited line are still valid.
+            let keep = if m.word_wrap_column != self.word_wrap_column {
+                0
+            } else {
+                let edited = self.cursor_move_to_offset_internal(self.cursor, m.edited_from);
+                let line_start = self.goto_line_start(edited, edited.logical_pos.y).offset;
+                m.ranges.partition_point(|r| r.end < line_start)
+            };
+            m.ranges.truncate(keep);
+
+            // Continue where the search would've been after the last kept match.
+            let resume = match m.ranges.last() {
+                Some(r) => {
+                    m.cursor = self.cursor_move_to_offset_internal(self.cursor, r.start);
+                    let y = m.cursor.visual_pos.y;
+                    m.lines.truncate(m.lines.partition_point(|&l| l <= y));
+                    r.end
+                }
+                None => {
+                    m.cursor = Cursor::default();
+                    m.lines.clear();
+                    0
+                }
+            };
+
+            unsafe { m.regex.set_text(&mut m.text, resume) };
+            m.buffer_generation = self.buffer.generation();
+            m.word_wrap_column = self.word_wrap_column;
+            m.complete = false;
+        }
+        m.edited_from = usize::MAX;
+
+        let deadline = Instant::now() + budget;
+        while !m.complete {
+            let Some(range) = m.regex.next() else {
+                m.complete = true;
+                break;
+            };
+
+            m.cursor = self.cursor_move_to_offset_internal(m.cursor, range.start);
+            if m.lines.last() != Some(&m.cursor.visual_pos.y) {
+                m.lines.push(m.cursor.visual_pos.y);
+            }
+            m.ranges.push(range);
+
+            // Reading the clock for every single match would add up.
+            if m.ranges.len() % 64 == 0 && Instant::now() >= deadline {
+                break;
+            }
+        }
+
+        self.search_matches = Some(m);
+    }
+
+    /// Records that the contents are about to change at `offset`.
+    pub(super) fn search_matches_edit(&mut self, offset: usize) {
+        if let Some(m) = &mut self.search_matches {
+            m.edited_from = m.edited_from.min(offset);
+        }
+    }
+
+    /// Returns how many matches were found so far, and which of them is selected.
+    /// `None` if there's no pattern set via [`TextBuffer::set_search_matches`].
+    pub fn search_match_count(&self) -> Option<SearchMatchCount> {
+        let m = self.search_matches.as_ref()?;
+        let index = self.selection.and_then(|TextBufferSelection { beg, end }| {
+            let [beg, end] = minmax(beg, end);
+            let beg = self.cursor_move_to_logical_internal(self.cursor, beg).offset;
+            let end = self.cursor_move_to_logical_internal(self.cursor, end).offset;
+            let idx = m.ranges.binary_search_by_key(&beg, |r| r.start).ok()?;
+            (m.ranges[idx].end == end).then_some(idx)
+        });
+        Some(SearchMatchCount { index, total: m.ranges.len(), complete: m.complete })
+    }
+
+    /// Returns the visual lines with matches on them, in order, for marking them on a scrollbar.
+    pub fn search_match_lines(&self) -> &[CoordType] {
+        match &self.search_matches {
+            Some(m) if !self.search_matches_stale(m) => &m.lines,
+            _ => &[],
+        }
+    }
+
+    /// Returns the matches found so far, unless they're outdated.
+    pub(super) fn search_matches_current(&self) -> Option<&SearchMatches> {
+        self.search_matches.as_ref().filter(|m| !self.search_matches_stale(m))
+    }
+
+    fn search_matches_stale(&self, m: &SearchMatches) -> bool {
+        m.buffer_generation != self.buffer.generation()
+            || m.word_wrap_column != self.word_wrap_column
+    }
+}
@@ -0,0 +1,100 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::ops::Range;
+
+use super::{BufferSnapshot, GapBuffer, PieceTable};
+use crate::document::{ReadableDocument, WriteableDocument};
+
+/// The storage backend of a [`super::TextBuffer`].
+///
+/// The [`GapBuffer`] is the fastest to edit and navigate, while the [`PieceTable`]
+/// offers O(1) snapshots of the contents, at the cost of some performance.
+pub enum TextStorage {
+    GapBuffer(GapBuffer),
+    PieceTable(PieceTable),
+}
+
+macro_rules! dispatch {
+    ($self:expr, $b:ident => $e:expr) => {
+        match $self {
+            TextStorage::GapBuffer($b) => $e,
+            TextStorage::PieceTable($b) => $e,
+        }
+    };
+}
+
+impl TextStorage {
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        dispatch!(self, b => b.len())
+    }
+
+    pub fn generation(&self) -> u32 {
+        dispatch!(self, b => b.generation())
+    }
+
+    pub fn set_generation(&mut self, generation: u32) {
+        dispatch!(self, b => b.set_generation(generation))
+    }
+
+    /// See [`GapBuffer::allocate_gap`].
+    pub fn allocate_gap(&mut self, off: usize, len: usize, delete: usize) -> &mut [u8] {
+        dispatch!(self, b => b.allocate_gap(off, len, delete))
+    }
+
+    pub fn commit_gap(&mut self, len: usize) {
+        dispatch!(self, b => b.commit_gap(len))
+    }
+
+    pub fn replace(&mut self, range: Range<usize>, src: &[u8]) {
+        dispatch!(self, b => b.replace(range, src))
+    }
+
+    pub fn clear(&mut self) {
+        dispatch!(self, b => b.clear())
+    }
+
+    pub fn extract_raw(&self, range: Range<usize>, out: &mut Vec<u8>, out_off: usize) {
+        dispatch!(self, b => b.extract_raw(range, out, out_off))
+    }
+
+    pub fn copy_from(&mut self, src: &dyn ReadableDocument) -> bool {
+        dispatch!(self, b => b.copy_from(src))
+    }
+
+    pub fn copy_into(&self, dst: &mut dyn WriteableDocument) {
+        dispatch!(self, b => b.copy_into(dst))
+    }
+
+    /// Returns an immutable copy of the current contents.
+    /// This is O(1) for a [`PieceTable`], but a full copy for a [`GapBuffer`].
+    pub fn snapshot(&self) -> BufferSnapshot {
+        match self {
+            Self::GapBuffer(b) => {
+                let mut copy = PieceTable::new();
+                let gap = copy.allocate_gap(0, b.len(), 0);
+                let mut off = 0;
+                while off < gap.len() {
+                    let chunk = b.read_forward(off);
+                    gap[off..off + chunk.len()].copy_from_slice(chunk);
+                    off += chunk.len();
+                }
+                copy.commit_gap(off);
+                copy.set_generation(b.generation());
+                copy.snapshot()
+            }
+            Self::PieceTable(b) => b.snapshot(),
+        }
+    }
+}
+
+impl ReadableDocument for TextStorage {
+    fn read_forward(&self, off: usize) -> &[u8] {
+        dispatch!(self, b => b.read_forward(off))
+    }
+
+    fn read_backward(&self, off: usize) -> &[u8] {
+        dispatch!(self, b => b.read_backward(off))
+    }
+}
@@ -0,0 +1,86 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Transactions group any number of edits, wherever they are made,
+//! into a single undo step. Aborting one rolls its edits back instead.
+
+use super::{HistoryType, TextBuffer, TextBufferSelection};
+use crate::helpers::*;
+
+/// What's needed to roll back a transaction. See [`TextBuffer::transaction_begin`].
+pub(super) struct Savepoint {
+    /// The state of the undo history when the transaction began.
+    pub state: usize,
+    /// The child of `state` that redo led to.
+    redo: Option<usize>,
+    /// The number of states in the undo history.
+    len: usize,
+    last_history_type: HistoryType,
+    cursor: Point,
+    selection: Option<TextBufferSelection>,
+}
+
+impl TextBuffer {
+    /// Starts a transaction: All edits until the matching [`TextBuffer::transaction_commit`]
+    /// are undone and redone as one. [`TextBuffer::transaction_abort`] reverts them instead,
+    /// leaving the contents and the undo history exactly as they were before.
+    ///
+    /// Transactions can be nested. Aborting an inner one only reverts the edits made since it began.
+    /// Don't undo or redo while one is open.
+    pub fn transaction_begin(&mut self) {
+        self.transactions.push(Savepoint {
+            state: self.history.current,
+            redo: self.history.redo_of(self.history.current),
+            len: self.history.nodes.len(),
+            last_history_type: self.last_history_type,
+            cursor: self.cursor.logical_pos,
+            selection: self.selection,
+        });
+        // The first edit must not be merged into one from before the transaction.
+        self.last_history_type = HistoryType::Other;
+    }
+
+    /// Ends the innermost transaction and keeps its edits.
+    pub fn transaction_commit(&mut self) {
+        if self.transactions.pop().is_none() || !self.transactions.is_empty() {
+            return;
+        }
+        self.history.prune();
+        // The next edit starts a new undo step.
+        self.last_history_type = HistoryType::Other;
+    }
+
+    /// Ends the innermost transaction and reverts its edits.
+    pub fn transaction_abort(&mut self) {
+        let Some(savepoint) = self.transactions.pop() else {
+            return;
+        };
+
+        if self.history.current != savepoint.state || self.history.nodes.len() != savepoint.len {
+            self.history_goto(savepoint.state);
+            self.history.truncate(savepoint.len, savepoint.state, savepoint.redo);
+        }
+
+        let cursor = self.cursor_move_to_logical_internal(self.cursor, savepoint.cursor);
+        self.set_cursor_internal(cursor);
+        self.set_selection(savepoint.selection);
+        self.last_history_type = savepoint.last_history_type;
+    }
+
+    /// Returns true while a transaction is open.
+    pub fn in_transaction(&self) -> bool {
+        !self.transactions.is_empty()
+    }
+
+    /// Runs `f` within a transaction, which gets aborted if it returns an error.
+    pub fn transaction<T, E>(&mut self, f: impl FnOnce(&mut Self) -> Result<T, E>) -> Result<T, E> {
+        self.transaction_begin();
+        let result = f(self);
+        if result.is_ok() {
+            self.transaction_commit();
+        } else {
+            self.transaction_abort();
+        }
+        result
+    }
+}
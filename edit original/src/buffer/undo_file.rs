@@ -0,0 +1,325 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Persists the undo history of a [`TextBuffer`] across sessions.
+//!
+//! The history gets written whenever the buffer is saved via [`TextBuffer::write_file`]
+//! and restored by [`TextBuffer::read_file`], but only if the file still has the same
+//! contents as when it was saved. The format is a compact, versioned binary one:
+//! ```text
+//! magic "EDITUNDO", version u8
+//! document path (len, bytes), content length, content hash u64
+//! node count, current state, redo child of the initial state
+//! nodes: parent, redo child, time in seconds, entry
+//! ```
+//! See [`History`] for how the nodes form a tree. Non-existent states are stored as 0.
+//! All integers except for the hash are LEB128 varints.
+
+use std::borrow::Cow;
+use std::fs::{self, File};
+use std::io::{self, Read as _, Write as _};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use super::history::{History, HistoryNode};
+use super::{HistoryEntry, HistoryType, TextBuffer, TextBufferSelection, TextBufferStatistics};
+use crate::cell::SemiRefCell;
+use crate::hash::hash;
+use crate::helpers::*;
+
+const MAGIC: &[u8; 8] = b"EDITUNDO";
+const VERSION: u8 = 2;
+/// The oldest undo steps get dropped to stay below this size.
+const MAX_UNDO_FILE_SIZE: usize = 4 * MEBI;
+
+/// Where a [`TextBuffer`] persists its undo history. See [`TextBuffer::set_undo_file`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct UndoFile {
+    path: PathBuf,
+    /// The history only gets restored for the same document path.
+    document: PathBuf,
+}
+
+impl UndoFile {
+    /// The history of the `document` gets stored in `dir`, in a file named after its path.
+    pub fn new(dir: &Path, document: &Path) -> Self {
+        let key = hash(0, document.as_os_str().as_encoded_bytes());
+        Self { path: dir.join(format!("{key:016x}.undo")), document: document.to_path_buf() }
+    }
+
+    /// The path of the file the history is stored in.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The path of the document the history belongs to.
+    pub fn document(&self) -> &Path {
+        &self.document
+    }
+}
+
+impl TextBuffer {
+    /// Enables persisting the undo history in the given file. `None` disables it.
+    pub fn set_undo_file(&mut self, undo_file: Option<UndoFile>) {
+        self.undo_file = undo_file;
+    }
+
+    /// The file the undo history is persisted in, if any.
+    pub fn undo_file(&self) -> Option<&UndoFile> {
+        self.undo_file.as_ref()
+    }
+
+    /// Hashes the contents independent of how they're laid out in memory,
+    /// which differs between a freshly read file and one that was edited.
+    fn content_hash(&self) -> u64 {
+        let mut block = Vec::with_capacity(64 * KIBI);
+        let mut h = 0;
+        let mut off = 0;
+        loop {
+            let chunk = self.read_forward(off);
+            if chunk.is_empty() {
+                break;
+            }
+            off += chunk.len();
+
+            let mut chunk = chunk;
+            while !chunk.is_empty() {
+                let n = chunk.len().min(block.capacity() - block.len());
+                block.extend_from_slice(&chunk[..n]);
+                chunk = &chunk[n..];
+                if block.len() == block.capacity() {
+                    h = hash(h, &block);
+                    block.clear();
+                }
+            }
+        }
+        hash(h, &block)
+    }
+
+    /// Writes the undo history to the undo file, if there's one.
+    /// Without any history the file gets removed instead.
+    pub(super) fn write_undo_file(&self) -> io::Result<()> {
+        let Some(undo_file) = &self.undo_file else {
+            return Ok(());
+        };
+        if self.history.nodes.is_empty() {
+            return match fs::remove_file(&undo_file.path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+                _ => Ok(()),
+            };
+        }
+
+        let mut data = Vec::new();
+        data.extend_from_slice(MAGIC);
+        data.push(VERSION);
+        write_bytes(&mut data, undo_file.document.as_os_str().as_encoded_bytes());
+        write_varint(&mut data, self.buffer.len() as u64);
+        data.extend_from_slice(&self.content_hash().to_le_bytes());
+
+        // The generations are stored relative to the current one, which is what
+        // makes undoing back to the saved state mark the buffer as clean again.
+        let generation = self.buffer.generation();
+        let mut entries: Vec<_> = self
+            .history
+            .nodes
+            .iter()
+            .map(|node| {
+                let mut buf = Vec::new();
+                write_entry(&mut buf, &node.entry.borrow(), generation);
+                buf
+            })
+            .collect();
+
+        // Drop the oldest edits until it fits. The IDs take up at most 20 bytes per node.
+        let mut history = Cow::Borrowed(&self.history);
+        let size = |entries: &[Vec<u8>]| entries.iter().map(|e| e.len() + 20).sum::<usize>();
+        while data.len() + size(&entries) > MAX_UNDO_FILE_SIZE && !history.nodes.is_empty() {
+            let len = history.nodes.len() - 1;
+            let remap = history.to_mut().prune_to(len);
+            let mut id = 0;
+            entries.retain(|_| {
+                id += 1;
+                remap[id].is_some_and(|id| id != 0)
+            });
+        }
+
+        write_varint(&mut data, history.nodes.len() as u64);
+        write_varint(&mut data, history.current as u64);
+        write_varint(&mut data, history.root_redo.unwrap_or(0) as u64);
+        for (node, entry) in history.nodes.iter().zip(&entries) {
+            let time = node.time.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+            write_varint(&mut data, node.parent as u64);
+            write_varint(&mut data, node.redo.unwrap_or(0) as u64);
+            write_varint(&mut data, time.as_secs());
+            data.extend_from_slice(entry);
+        }
+
+        let mut builder = fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        {
+            // The history contains the file contents, which are nobody else's business.
+            use std::os::unix::fs::DirBuilderExt as _;
+            builder.mode(0o700);
+        }
+        builder.create(undo_file.path.parent().unwrap_or(Path::new(".")))?;
+
+        // A crash while writing must not leave a corrupt file behind.
+        let tmp = undo_file.path.with_extension("tmp");
+        File::create(&tmp)?.write_all(&data)?;
+        fs::rename(tmp, &undo_file.path)
+    }
+
+    /// Restores the undo history from the undo file, if there's one
+    /// and it belongs to the current contents. Returns true if it did.
+    pub(super) fn read_undo_file(&mut self) -> bool {
+        let Some(undo_file) = &self.undo_file else {
+            return false;
+        };
+        let mut data = Vec::new();
+        let Ok(mut file) = File::open(&undo_file.path) else {
+            return false;
+        };
+        if file.read_to_end(&mut data).is_err() || data.len() > MAX_UNDO_FILE_SIZE {
+            return false;
+        }
+
+        let generation = self.buffer.generation();
+        let mut r = Reader { data: &data };
+        let Some(history) = (|| {
+            if r.take(MAGIC.len())? != MAGIC || r.take(1)?[0] != VERSION {
+                return None;
+            }
+            if r.bytes()? != undo_file.document.as_os_str().as_encoded_bytes()
+                || r.varint()? != self.buffer.len() as u64
+                || r.take(8)? != self.content_hash().to_le_bytes()
+            {
+                return None;
+            }
+
+            let count = r.id(usize::MAX)?;
+            let current = r.id(count)?;
+            let root_redo = r.id(count)?;
+            let mut nodes = Vec::new();
+            for id in 1..=count {
+                // Parents come before their children.
+                let parent = r.id(id - 1)?;
+                let redo = r.id(count)?;
+                let time = SystemTime::UNIX_EPOCH + Duration::from_secs(r.varint()?);
+                let entry = SemiRefCell::new(r.entry(generation)?);
+                let redo = (redo != 0).then_some(redo);
+                nodes.push(HistoryNode { entry, parent, redo, time });
+            }
+            let root_redo = (root_redo != 0).then_some(root_redo);
+            // The file was written right after saving.
+            let saved = Some(current);
+            r.data.is_empty().then_some(History { nodes, current, root_redo, saved })
+        })() else {
+            return false;
+        };
+
+        self.history = history;
+        // New edits must not get merged into the restored ones.
+        self.last_history_type = HistoryType::Other;
+        true
+    }
+}
+
+fn write_entry(buf: &mut Vec<u8>, entry: &HistoryEntry, generation: u32) {
+    let flags = entry.linked as u8 | (entry.selection_before.is_some() as u8) << 1;
+    buf.push(flags);
+    write_point(buf, entry.cursor_before);
+    if let Some(selection) = entry.selection_before {
+        write_point(buf, selection.beg);
+        write_point(buf, selection.end);
+    }
+    // With word wrap the visual line count depends on the window width, which may
+    // differ next time. Like after a resize, undo only restores an approximation then.
+    write_varint(buf, entry.stats_before.logical_lines as u64);
+    write_varint(buf, generation.wrapping_sub(entry.generation_before) as u64);
+    write_point(buf, entry.cursor);
+    write_bytes(buf, &entry.deleted);
+    write_bytes(buf, &entry.added);
+}
+
+fn write_point(buf: &mut Vec<u8>, p: Point) {
+    write_varint(buf, p.x as u64);
+    write_varint(buf, p.y as u64);
+}
+
+fn write_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    write_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let (head, tail) = self.data.split_at_checked(len)?;
+        self.data = tail;
+        Some(head)
+    }
+
+    fn varint(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    /// Reads a state ID, which must be at most `max`.
+    fn id(&mut self, max: usize) -> Option<usize> {
+        self.varint()?.try_into().ok().filter(|&id| id <= max)
+    }
+
+    fn coord(&mut self) -> Option<CoordType> {
+        self.varint()?.try_into().ok()
+    }
+
+    fn point(&mut self) -> Option<Point> {
+        Some(Point { x: self.coord()?, y: self.coord()? })
+    }
+
+    fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.varint()?.try_into().ok()?;
+        self.take(len)
+    }
+
+    fn entry(&mut self, generation: u32) -> Option<HistoryEntry> {
+        let flags = self.take(1)?[0];
+        let cursor_before = self.point()?;
+        let selection_before = if flags & 2 != 0 {
+            Some(TextBufferSelection { beg: self.point()?, end: self.point()? })
+        } else {
+            None
+        };
+        let logical_lines = self.coord()?;
+        let generation_before = generation.wrapping_sub(self.varint()?.try_into().ok()?);
+        Some(HistoryEntry {
+            cursor_before,
+            selection_before,
+            stats_before: TextBufferStatistics { logical_lines, visual_lines: logical_lines },
+            generation_before,
+            cursor: self.point()?,
+            deleted: self.bytes()?.to_vec(),
+            added: self.bytes()?.to_vec(),
+            linked: flags & 1 != 0,
+        })
+    }
+}
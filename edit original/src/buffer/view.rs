@@ -0,0 +1,119 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Views for showing a buffer more than once, e.g. in split panes.
+//!
+//! A [`TextBuffer`] has just a single cursor, selection and layout width. They belong to
+//! whichever view is "live". The other views remember their own, keep them on the same text
+//! via [`TextBufferView::adjust_for_change`] and swap them in to be laid out and drawn.
+
+use std::mem;
+use std::rc::Rc;
+
+use super::{TextBuffer, TextBufferChange, TextBufferSelection};
+use crate::cell::SemiRefCell;
+use crate::helpers::*;
+
+pub type TextBufferViewCell = SemiRefCell<TextBufferView>;
+pub type RcTextBufferView = Rc<TextBufferViewCell>;
+
+#[derive(Default)]
+pub struct TextBufferView {
+    /// Whether the buffer's cursor, selection and width belong to this view.
+    pub live: bool,
+    /// Where the view is scrolled to. Kept up to date by the text area showing it,
+    /// so that a new one can continue where it was.
+    pub scroll_offset: Point,
+    /// The cursor and selections while the view isn't live.
+    cursor: Point,
+    selection: Option<TextBufferSelection>,
+    cursors: Vec<TextBufferSelection>,
+    /// The width the view is shown with. 0 if it's unknown.
+    width: CoordType,
+    /// The block selection of the live view while another one is swapped in,
+    /// because the reflow for the other width would discard it.
+    block_selection: Option<TextBufferSelection>,
+}
+
+impl TextBufferView {
+    pub fn new_rc() -> RcTextBufferView {
+        Rc::new(SemiRefCell::new(Self::default()))
+    }
+
+    /// The logical position of the cursor while the view isn't live.
+    pub fn cursor_logical_pos(&self) -> Point {
+        self.cursor
+    }
+
+    /// Sets the width the view is shown with. See [`TextBuffer::set_width`].
+    pub fn set_width(&mut self, width: CoordType) {
+        if width > 0 {
+            self.width = width;
+        }
+    }
+
+    /// Moves the cursor and selections so that they stay on the same text after the `change`.
+    /// Only needed while the view isn't live, because the buffer takes care of its own.
+    pub fn adjust_for_change(&mut self, change: &TextBufferChange) {
+        self.cursor = adjust_for_change(self.cursor, change);
+        for s in self.selection.iter_mut().chain(&mut self.cursors) {
+            s.beg = adjust_for_change(s.beg, change);
+            s.end = adjust_for_change(s.end, change);
+        }
+        self.selection = self.selection.filter(|s| s.beg != s.end);
+    }
+}
+
+/// Moves `pos` so that it stays on the same text after the `change`.
+pub(super) fn adjust_for_change(pos: Point, change: &TextBufferChange) -> Point {
+    if pos <= change.start {
+        pos
+    } else if pos < change.deleted_end {
+        // The text it was on got deleted.
+        change.inserted_end
+    } else if pos.y == change.deleted_end.y {
+        Point { x: change.inserted_end.x + pos.x - change.deleted_end.x, y: change.inserted_end.y }
+    } else {
+        Point { x: pos.x, y: pos.y - change.deleted_end.y + change.inserted_end.y }
+    }
+}
+
+impl TextBuffer {
+    /// Remembers the cursor and selections in `view`, which stops being the live one.
+    pub fn view_release(&self, view: &mut TextBufferView) {
+        view.live = false;
+        view.cursor = self.cursor.logical_pos;
+        view.selection = self.selection;
+        view.cursors = self.cursors.clone();
+    }
+
+    /// Makes `view` the live one: The buffer takes over its cursor and selections.
+    /// The view that was live so far must've been released with [`TextBuffer::view_release`].
+    pub fn view_acquire(&mut self, view: &mut TextBufferView) {
+        self.clear_selection();
+        self.cursor_move_to_logical(view.cursor);
+        // Assigned directly, because it's the same selection as before as far as the user is concerned.
+        self.selection = view.selection.take();
+        self.cursors = mem::take(&mut view.cursors);
+        view.live = true;
+    }
+
+    /// Swaps the cursor, selections and width of the buffer with those of `view`,
+    /// to lay it out and draw it for a view that isn't live. Calling it again swaps them back.
+    ///
+    /// If the widths differ and word wrap is enabled, this reflows the entire buffer.
+    pub fn view_swap(&mut self, view: &mut TextBufferView) {
+        let block_selection = mem::replace(&mut view.block_selection, self.block_selection.take());
+        let width = mem::replace(&mut view.width, self.width);
+        if width > 0 {
+            self.set_width(width);
+        }
+        self.block_selection = block_selection;
+
+        let cursor = self.cursor_move_to_logical_internal(self.cursor, view.cursor);
+        view.cursor = self.cursor.logical_pos;
+        self.set_cursor_internal(cursor);
+        mem::swap(&mut self.selection, &mut view.selection);
+        mem::swap(&mut self.cursors, &mut view.cursors);
+    }
+}
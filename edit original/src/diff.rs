@@ -0,0 +1,178 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Line-based diffs, using the algorithm from Myers' "An O(ND) Difference Algorithm".
+
+use crate::simd;
+
+/// Beyond this many differing lines the rest of the files is considered
+/// to have been replaced entirely. It bounds the time and memory spent.
+const MAX_EDITS: usize = 1024;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DiffOp {
+    /// The line is in both the old and the new text.
+    Equal,
+    /// The line is only in the old text.
+    Delete,
+    /// The line is only in the new text.
+    Insert,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DiffLine<'a> {
+    pub op: DiffOp,
+    /// The line including its line ending, if any.
+    pub text: &'a [u8],
+}
+
+/// Splits the text into lines, each including its line ending.
+pub fn split_lines(text: &[u8]) -> Vec<&[u8]> {
+    let mut lines = Vec::new();
+    let mut beg = 0;
+    while beg < text.len() {
+        let end = (simd::memchr2(b'\n', b'\n', text, beg) + 1).min(text.len());
+        lines.push(&text[beg..end]);
+        beg = end;
+    }
+    lines
+}
+
+/// Returns the lines of both texts in order, marked by whether they were deleted or
+/// inserted. Where both happen at the same spot, the deletions come first.
+pub fn diff_lines<'a>(old: &'a [u8], new: &'a [u8]) -> Vec<DiffLine<'a>> {
+    let old = split_lines(old);
+    let new = split_lines(new);
+
+    // The common start and end don't need to go through the expensive part.
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut result = Vec::with_capacity(old.len().max(new.len()));
+    let equal = |text| DiffLine { op: DiffOp::Equal, text };
+    result.extend(old[..prefix].iter().copied().map(equal));
+    diff_middle(&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix], &mut result);
+    result.extend(old[old.len() - suffix..].iter().copied().map(equal));
+    result
+}
+
+fn diff_middle<'a>(old: &[&'a [u8]], new: &[&'a [u8]], result: &mut Vec<DiffLine<'a>>) {
+    let n = old.len() as isize;
+    let m = new.len() as isize;
+    let max = (old.len() + new.len()).min(MAX_EDITS) as isize;
+    let offset = max + 1;
+
+    // `v[k + offset]` is the furthest `x` reached on diagonal `k = x - y`.
+    // Before every step `d` the diagonals `-d..=d` of it are kept, to trace back the path at the end.
+    let mut v = vec![0isize; 2 * offset as usize + 1];
+    let mut trace = Vec::new();
+    let mut reached = false;
+
+    'outer: for d in 0..=max {
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let idx = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1] // Down: An insertion.
+            } else {
+                v[idx - 1] + 1 // Right: A deletion.
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+            if x >= n && y >= m {
+                reached = true;
+                break 'outer;
+            }
+        }
+    }
+
+    if !reached {
+        // Too different. Treat it as a replacement of everything.
+        result.extend(old.iter().map(|&text| DiffLine { op: DiffOp::Delete, text }));
+        result.extend(new.iter().map(|&text| DiffLine { op: DiffOp::Insert, text }));
+        return;
+    }
+
+    // Walk the path backwards from the end, collecting the lines in reverse.
+    let beg = result.len();
+    let mut x = n;
+    let mut y = m;
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let idx = (k + d) as usize;
+        let prev_k = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) { k + 1 } else { k - 1 };
+        let prev_x = if d == 0 { 0 } else { v[(prev_k + d) as usize] };
+        let prev_y = prev_x - prev_k;
+
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            result.push(DiffLine { op: DiffOp::Equal, text: old[x as usize] });
+        }
+        if d > 0 {
+            if x == prev_x {
+                y -= 1;
+                result.push(DiffLine { op: DiffOp::Insert, text: new[y as usize] });
+            } else {
+                x -= 1;
+                result.push(DiffLine { op: DiffOp::Delete, text: old[x as usize] });
+            }
+        }
+    }
+    result[beg..].reverse();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(lines: &[DiffLine]) -> String {
+        let mut s = String::new();
+        for line in lines {
+            s.push(match line.op {
+                DiffOp::Equal => ' ',
+                DiffOp::Delete => '-',
+                DiffOp::Insert => '+',
+            });
+            s.push_str(std::str::from_utf8(line.text).unwrap());
+        }
+        s
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        assert_eq!(render(&diff_lines(b"", b"")), "");
+        assert_eq!(render(&diff_lines(b"a\nb", b"a\nb")), " a\n b");
+        assert_eq!(render(&diff_lines(b"", b"a\n")), "+a\n");
+        assert_eq!(render(&diff_lines(b"a\n", b"")), "-a\n");
+
+        // The last line differs only by its line ending.
+        assert_eq!(render(&diff_lines(b"a\nb", b"a\nb\n")), " a\n-b+b\n");
+
+        assert_eq!(
+            render(&diff_lines(b"a\nb\nc\nd\ne\n", b"a\nx\nc\nd\ne\ny\n")),
+            " a\n-b\n+x\n c\n d\n e\n+y\n"
+        );
+        assert_eq!(
+            render(&diff_lines(b"a\nb\nc\na\nb\nb\na\n", b"c\nb\na\nb\na\nc\n")),
+            "-a\n-b\n c\n+b\n a\n b\n-b\n a\n+c\n"
+        );
+
+        // Past the limit everything in between the common start and end counts as replaced.
+        let old: String = (0..MAX_EDITS).map(|i| format!("{i}\n")).collect();
+        let new: String = (0..MAX_EDITS).map(|i| format!("{i}x\n")).collect();
+        let diff = diff_lines(old.as_bytes(), new.as_bytes());
+        assert_eq!(diff.len(), 2 * MAX_EDITS);
+        assert!(diff[..MAX_EDITS].iter().all(|line| line.op == DiffOp::Delete));
+    }
+}
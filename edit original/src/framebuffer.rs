@@ -5,7 +5,7 @@
 
 use std::cell::Cell;
 use std::fmt::Write;
-use std::ops::{BitOr, BitXor};
+use std::ops::{BitOr, BitXor, Range};
 use std::ptr;
 use std::slice::ChunksExact;
 
@@ -196,12 +196,15 @@ impl Framebuffer {
     ///   In absolute viewport coordinates.
     /// * `content_offset`: The current offset of the scrollarea.
     /// * `content_height`: The height of the scrollarea content.
+    /// * `markers`: Positions within the content to highlight on the track,
+    ///   like those of search matches. Must be sorted.
     pub fn draw_scrollbar(
         &mut self,
         clip_rect: Rect,
         track: Rect,
         content_offset: CoordType,
         content_height: CoordType,
+        markers: &[CoordType],
     ) -> CoordType {
         let track_clipped = track.intersect(clip_rect);
         if track_clipped.is_empty() {
@@ -304,6 +307,31 @@ impl Framebuffer {
             self.blend_fg(rect, self.indexed(IndexedColor::BrightBlack));
         }
 
+        // Draw the markers, except on the thumb. It represents the viewport,
+        // where the things they mark are visible anyway.
+        let thumb_top = thumb_top - (top_fract != 0) as CoordType;
+        let thumb_bottom = thumb_bottom + (bottom_fract != 0) as CoordType;
+        let mut last_y = CoordType::MIN;
+        for &marker in markers {
+            let y = track.top + (marker as i64 * viewport_height / content_height) as CoordType;
+            if y >= track_clipped.bottom {
+                break;
+            }
+            if y == last_y || y < track_clipped.top || (thumb_top..thumb_bottom).contains(&y) {
+                continue;
+            }
+            last_y = y;
+
+            self.replace_text(y, track_clipped.left, track_clipped.right, "━");
+            let rect = Rect {
+                left: track_clipped.left,
+                top: y,
+                right: track_clipped.right,
+                bottom: y + 1,
+            };
+            self.blend_fg(rect, self.indexed(IndexedColor::BrightYellow));
+        }
+
         ((thumb_height + 4) / 8) as CoordType
     }
 
@@ -537,9 +565,13 @@ impl Framebuffer {
         result
     }
 
-    fn format_color(&self, dst: &mut ArenaString, fg: bool, mut color: u32) {
-        let typ = if fg { '3' } else { '4' };
+    fn format_color(&self, dst: &mut ArenaString, fg: bool, color: u32) {
+        write_sgr_color(dst, fg, self.resolve_color(fg, color));
+    }
 
+    /// Resolves semi-transparent colors against the default foreground/background.
+    /// Returns 0 if the terminal's default color should be used.
+    fn resolve_color(&self, fg: bool, color: u32) -> u32 {
         // Some terminals support transparent backgrounds which are used
         // if the default background color is active (CSI 49 m).
         //
@@ -553,21 +585,206 @@ impl Framebuffer {
         // the output slightly and ensures that we keep "default foreground"
         // and "color that happens to be default foreground" separate.
         // (This also applies to the background color by the way.)
-        if color == 0 {
-            _ = write!(dst, "\x1b[{typ}9m");
-            return;
+        if color == 0 || (color & 0xff000000) == 0xff000000 {
+            return color;
+        }
+
+        let idx = if fg { IndexedColor::Foreground } else { IndexedColor::Background };
+        oklab_blend(self.indexed(idx), color)
+    }
+
+    /// Returns a copy of the current frame (= everything drawn since the last `flip()`).
+    ///
+    /// This is the offscreen counterpart to [`Framebuffer::render()`] and is meant
+    /// for tests and for tooling that needs to see what the user would see.
+    pub fn snapshot(&self) -> Snapshot {
+        let back = &self.buffers[self.frame_counter & 1];
+        let size = back.text.size;
+        let width = size.width as usize;
+        let mut cells = Vec::with_capacity(width * size.height as usize);
+
+        for (y, line) in back.text.lines.iter().enumerate() {
+            let line_bytes = line.as_bytes();
+            let mut cfg = MeasurementConfig::new(&line_bytes);
+            let mut beg = cfg.cursor();
+            let row = cells.len();
+
+            loop {
+                let end = cfg.goto_logical(Point { x: beg.logical_pos.x + 1, y: 0 });
+                if end.offset == beg.offset || end.visual_pos.x > size.width {
+                    break;
+                }
+
+                // The trailing columns of wide glyphs get an empty cell.
+                for x in beg.visual_pos.x..end.visual_pos.x {
+                    let text = if x == beg.visual_pos.x { beg.offset..end.offset } else { 0..0 };
+                    let i = y * width + x as usize;
+                    cells.push(SnapshotCellData {
+                        text,
+                        fg: self.resolve_color(true, back.fg_bitmap.data[i]),
+                        bg: self.resolve_color(false, back.bg_bitmap.data[i]),
+                        attributes: back.attributes.data[i],
+                    });
+                }
+
+                beg = end;
+            }
+
+            // The line buffer is always filled with whitespace up to the width, but let's be safe.
+            cells.resize(row + width, SnapshotCellData::default());
+        }
+
+        let cursor = back.cursor.pos;
+        Snapshot {
+            size,
+            lines: back.text.lines.clone(),
+            cells,
+            cursor: if cursor.x >= 0 && cursor.y >= 0 { Some(cursor) } else { None },
+            overtype: back.cursor.overtype,
+        }
+    }
+}
+
+fn write_sgr_color(dst: &mut impl Write, fg: bool, color: u32) {
+    let typ = if fg { '3' } else { '4' };
+
+    if color == 0 {
+        _ = write!(dst, "\x1b[{typ}9m");
+        return;
+    }
+
+    let r = color & 0xff;
+    let g = (color >> 8) & 0xff;
+    let b = (color >> 16) & 0xff;
+    _ = write!(dst, "\x1b[{typ}8;2;{r};{g};{b}m");
+}
+
+/// A single cell of a [`Snapshot`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnapshotCell<'a> {
+    /// The grapheme cluster in this cell.
+    /// Empty for the trailing columns of wide glyphs.
+    pub text: &'a str,
+    /// The foreground color as `0xAABBGGRR`, or 0 for the default color.
+    pub fg: u32,
+    /// The background color as `0xAABBGGRR`, or 0 for the default color.
+    pub bg: u32,
+    pub attributes: Attributes,
+}
+
+#[derive(Default, Clone)]
+struct SnapshotCellData {
+    text: Range<usize>,
+    fg: u32,
+    bg: u32,
+    attributes: Attributes,
+}
+
+/// An inspectable copy of a rendered frame. See [`Framebuffer::snapshot()`].
+pub struct Snapshot {
+    size: Size,
+    lines: Vec<String>,
+    cells: Vec<SnapshotCellData>,
+    cursor: Option<Point>,
+    overtype: bool,
+}
+
+impl Snapshot {
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    /// The position of the visible cursor, if any.
+    pub fn cursor(&self) -> Option<Point> {
+        self.cursor
+    }
+
+    /// Whether the cursor is shown in overtype (= block) style.
+    pub fn is_overtype(&self) -> bool {
+        self.overtype
+    }
+
+    /// Returns the text of the given row.
+    pub fn line(&self, y: CoordType) -> &str {
+        self.lines.get(y as usize).map_or("", |l| l.as_str())
+    }
+
+    /// Returns the cell at the given position, if it's inside the frame.
+    pub fn cell(&self, pos: Point) -> Option<SnapshotCell<'_>> {
+        if !self.size.as_rect().contains(pos) {
+            return None;
+        }
+
+        let data = &self.cells[(pos.y * self.size.width + pos.x) as usize];
+        Some(SnapshotCell {
+            text: &self.lines[pos.y as usize][data.text.clone()],
+            fg: data.fg,
+            bg: data.bg,
+            attributes: data.attributes,
+        })
+    }
+
+    /// Returns the frame as plain text, one line per row.
+    /// Trailing whitespace is trimmed from each line.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for line in &self.lines {
+            text.push_str(line.trim_end_matches(' '));
+            text.push('\n');
         }
+        text
+    }
+
+    /// Returns the frame as text with SGR sequences for colors and attributes, one line per row.
+    /// Unlike [`Framebuffer::render()`], this contains no cursor movements and
+    /// is meant to be printed as-is, for instance with `cat`.
+    pub fn to_ansi(&self) -> String {
+        let mut text = String::new();
+        let width = self.size.width as usize;
 
-        if (color & 0xff000000) != 0xff000000 {
-            let idx = if fg { IndexedColor::Foreground } else { IndexedColor::Background };
-            let dst = self.indexed(idx);
-            color = oklab_blend(dst, color);
+        for (y, line) in self.lines.iter().enumerate() {
+            // Each line ends with a reset, so every line starts out with the defaults.
+            let mut last_bg = 0;
+            let mut last_fg = 0;
+            let mut last_attr = Attributes::None;
+
+            for cell in &self.cells[y * width..(y + 1) * width] {
+                if last_bg != cell.bg {
+                    last_bg = cell.bg;
+                    write_sgr_color(&mut text, false, cell.bg);
+                }
+
+                if last_fg != cell.fg {
+                    last_fg = cell.fg;
+                    write_sgr_color(&mut text, true, cell.fg);
+                }
+
+                if last_attr != cell.attributes {
+                    let diff = last_attr ^ cell.attributes;
+                    if diff.is(Attributes::Italic) {
+                        if cell.attributes.is(Attributes::Italic) {
+                            text.push_str("\x1b[3m");
+                        } else {
+                            text.push_str("\x1b[23m");
+                        }
+                    }
+                    if diff.is(Attributes::Underlined) {
+                        if cell.attributes.is(Attributes::Underlined) {
+                            text.push_str("\x1b[4m");
+                        } else {
+                            text.push_str("\x1b[24m");
+                        }
+                    }
+                    last_attr = cell.attributes;
+                }
+
+                text.push_str(&line[cell.text.clone()]);
+            }
+
+            text.push_str("\x1b[m\n");
         }
 
-        let r = color & 0xff;
-        let g = (color >> 8) & 0xff;
-        let b = (color >> 16) & 0xff;
-        _ = write!(dst, "\x1b[{typ}8;2;{r};{g};{b}m");
+        text
     }
 }
 
@@ -804,7 +1021,7 @@ impl Bitmap {
 ///
 /// It being a bitfield allows for simple diffing.
 #[repr(transparent)]
-#[derive(Default, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct Attributes(u8);
 
 #[allow(non_upper_case_globals)]
@@ -902,3 +1119,47 @@ impl Cursor {
         Self { pos: Point { x: -1, y: -1 }, overtype: false }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot() {
+        let mut fb = Framebuffer::new();
+        fb.set_indexed_colors(DEFAULT_THEME);
+        fb.flip(Size { width: 6, height: 2 });
+        fb.replace_text(0, 0, 6, "a😀b");
+        fb.replace_text(1, 1, 6, "cd");
+        fb.blend_bg(Rect { left: 0, top: 1, right: 2, bottom: 2 }, 0xff0000ff);
+        fb.replace_attr(
+            Rect { left: 2, top: 1, right: 3, bottom: 2 },
+            Attributes::Underlined,
+            Attributes::Underlined,
+        );
+        fb.set_cursor(Point { x: 2, y: 1 }, false);
+
+        let snapshot = fb.snapshot();
+        assert_eq!(snapshot.size(), Size { width: 6, height: 2 });
+        assert_eq!(snapshot.cursor(), Some(Point { x: 2, y: 1 }));
+        assert_eq!(snapshot.to_text(), "a😀b\n cd\n");
+
+        // Wide glyphs occupy 2 cells, the second one being empty.
+        assert_eq!(snapshot.cell(Point { x: 1, y: 0 }).unwrap().text, "😀");
+        assert_eq!(snapshot.cell(Point { x: 2, y: 0 }).unwrap().text, "");
+        assert_eq!(snapshot.cell(Point { x: 3, y: 0 }).unwrap().text, "b");
+        assert_eq!(snapshot.cell(Point { x: 6, y: 0 }), None);
+
+        let cell = snapshot.cell(Point { x: 1, y: 1 }).unwrap();
+        assert_eq!(cell.bg, 0xff0000ff);
+        assert_eq!(cell.attributes, Attributes::None);
+        let cell = snapshot.cell(Point { x: 2, y: 1 }).unwrap();
+        assert_eq!(cell.bg, 0);
+        assert_eq!(cell.attributes, Attributes::Underlined);
+
+        assert_eq!(
+            snapshot.to_ansi(),
+            concat!("a😀b  \x1b[m\n", "\x1b[48;2;255;0;0m c\x1b[49m\x1b[4md\x1b[24m   \x1b[m\n",)
+        );
+    }
+}
@@ -16,7 +16,7 @@ use crate::vt;
 /// Of course you could just translate on the ABI boundary, but my hope is that this
 /// design lets me realize some restrictions early on that I can't foresee yet.
 #[repr(transparent)]
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct InputKey(u32);
 
 impl InputKey {
@@ -44,10 +44,6 @@ impl InputKey {
         Self(self.0 & 0x00FFFFFF)
     }
 
-    pub(crate) const fn modifiers(&self) -> InputKeyMod {
-        InputKeyMod(self.0 & 0xFF000000)
-    }
-
     pub(crate) const fn modifiers_contains(&self, modifier: InputKeyMod) -> bool {
         (self.0 & modifier.0) != 0
     }
@@ -257,6 +253,7 @@ pub struct InputMouse {
 }
 
 /// Primary result type of the parser.
+#[derive(Clone, Copy)]
 pub enum Input<'input> {
     /// Window resize event.
     Resize(Size),
@@ -268,6 +265,8 @@ pub enum Input<'input> {
     Keyboard(InputKey),
     /// Mouse input.
     Mouse(InputMouse),
+    /// The terminal gained (`true`) or lost (`false`) focus.
+    Focus(bool),
 }
 
 /// Parses VT sequences into input events.
@@ -383,6 +382,8 @@ impl<'input> Iterator for Stream<'_, '_, 'input> {
                                 ));
                             }
                         }
+                        'I' => return Some(Input::Focus(true)),
+                        'O' => return Some(Input::Focus(false)),
                         'Z' => return Some(Input::Keyboard(kbmod::SHIFT | vk::TAB)),
                         '~' => {
                             const LUT: [u8; 35] = [
@@ -0,0 +1,439 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! A tiny JSON reader and writer.
+//!
+//! We don't want to pull in serde for the few places that need to exchange
+//! JSON with the outside world (like the `--serve` mode), so this implements
+//! just enough of RFC 8259 to be useful: A DOM-style [`Value`] and a parser.
+//! Objects preserve the order of their keys.
+
+use std::fmt::{self, Write as _};
+
+/// Nesting deeper than this is rejected, so that we don't overflow the stack.
+const MAX_DEPTH: usize = 128;
+
+/// A parsed JSON value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Looks up `key`, if this is an object.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// Returns the number as an integer, if it is one.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Number(n) if n.fract() == 0.0 && n.abs() <= (1i64 << 53) as f64 => {
+                Some(*n as i64)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&[(String, Value)]> {
+        match self {
+            Value::Object(o) => Some(o),
+            _ => None,
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Number(value as f64)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(value.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::String(value)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::Null, Into::into)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(value: Vec<T>) -> Self {
+        Value::Array(value.into_iter().map(Into::into).collect())
+    }
+}
+
+/// Allows writing objects as `Value::from([("key", value.into()), ...])`.
+impl<const N: usize> From<[(&str, Value); N]> for Value {
+    fn from(entries: [(&str, Value); N]) -> Self {
+        Value::Object(entries.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+    }
+}
+
+/// Serializes the value as compact JSON, without any newlines.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => f.write_str("null"),
+            Value::Bool(b) => f.write_str(if *b { "true" } else { "false" }),
+            // JSON has no representation for NaN and infinities.
+            Value::Number(n) if !n.is_finite() => f.write_str("null"),
+            Value::Number(n) => write!(f, "{n}"),
+            Value::String(s) => write_string(f, s),
+            Value::Array(a) => {
+                f.write_char('[')?;
+                for (i, v) in a.iter().enumerate() {
+                    if i != 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{v}")?;
+                }
+                f.write_char(']')
+            }
+            Value::Object(o) => {
+                f.write_char('{')?;
+                for (i, (k, v)) in o.iter().enumerate() {
+                    if i != 0 {
+                        f.write_char(',')?;
+                    }
+                    write_string(f, k)?;
+                    write!(f, ":{v}")?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+
+    let mut beg = 0;
+    for (i, b) in s.bytes().enumerate() {
+        let esc = match b {
+            b'"' => "\\\"",
+            b'\\' => "\\\\",
+            b'\n' => "\\n",
+            b'\r' => "\\r",
+            b'\t' => "\\t",
+            ..0x20 | 0x7f => "",
+            _ => continue,
+        };
+
+        f.write_str(&s[beg..i])?;
+        beg = i + 1;
+
+        if esc.is_empty() {
+            write!(f, "\\u{b:04x}")?;
+        } else {
+            f.write_str(esc)?;
+        }
+    }
+
+    f.write_str(&s[beg..])?;
+    f.write_char('"')
+}
+
+/// Parses a complete JSON document. Returns `None` if it's malformed.
+///
+/// Leading and trailing whitespace is permitted, trailing garbage is not.
+pub fn parse(input: &str) -> Option<Value> {
+    let mut parser = Parser { bytes: input.as_bytes(), off: 0 };
+    let value = parser.parse_value(0)?;
+    parser.skip_whitespace();
+    if parser.off != parser.bytes.len() {
+        return None;
+    }
+    Some(value)
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    off: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.bytes.get(self.off) {
+            self.off += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.bytes.get(self.off).copied()
+    }
+
+    fn expect_literal(&mut self, lit: &[u8], value: Value) -> Option<Value> {
+        if self.bytes[self.off..].starts_with(lit) {
+            self.off += lit.len();
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    fn parse_value(&mut self, depth: usize) -> Option<Value> {
+        if depth >= MAX_DEPTH {
+            return None;
+        }
+
+        match self.peek()? {
+            b'n' => self.expect_literal(b"null", Value::Null),
+            b't' => self.expect_literal(b"true", Value::Bool(true)),
+            b'f' => self.expect_literal(b"false", Value::Bool(false)),
+            b'"' => self.parse_string().map(Value::String),
+            b'-' | b'0'..=b'9' => self.parse_number(),
+            b'[' => {
+                self.off += 1;
+                let mut array = Vec::new();
+                if self.peek()? == b']' {
+                    self.off += 1;
+                    return Some(Value::Array(array));
+                }
+                loop {
+                    array.push(self.parse_value(depth + 1)?);
+                    match self.peek()? {
+                        b',' => self.off += 1,
+                        b']' => break,
+                        _ => return None,
+                    }
+                }
+                self.off += 1;
+                Some(Value::Array(array))
+            }
+            b'{' => {
+                self.off += 1;
+                let mut object = Vec::new();
+                if self.peek()? == b'}' {
+                    self.off += 1;
+                    return Some(Value::Object(object));
+                }
+                loop {
+                    if self.peek()? != b'"' {
+                        return None;
+                    }
+                    let key = self.parse_string()?;
+                    if self.peek()? != b':' {
+                        return None;
+                    }
+                    self.off += 1;
+                    object.push((key, self.parse_value(depth + 1)?));
+                    match self.peek()? {
+                        b',' => self.off += 1,
+                        b'}' => break,
+                        _ => return None,
+                    }
+                }
+                self.off += 1;
+                Some(Value::Object(object))
+            }
+            _ => None,
+        }
+    }
+
+    fn parse_number(&mut self) -> Option<Value> {
+        let beg = self.off;
+        while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') = self.bytes.get(self.off) {
+            self.off += 1;
+        }
+
+        let str = unsafe { std::str::from_utf8_unchecked(&self.bytes[beg..self.off]) };
+        // Rust's float parser is more lenient than JSON (e.g. "1." or ".5" or "+1"),
+        // so we have to reject those cases ourselves. Leading zeros are rejected too.
+        let digits = str.strip_prefix('-').unwrap_or(str);
+        let int_len = digits.bytes().take_while(u8::is_ascii_digit).count();
+        if int_len == 0
+            || (int_len > 1 && digits.starts_with('0'))
+            || digits.contains(".e")
+            || digits.contains(".E")
+            || digits.ends_with(['.', 'e', 'E', '+', '-'])
+        {
+            return None;
+        }
+
+        str.parse::<f64>().ok().map(Value::Number)
+    }
+
+    fn parse_string(&mut self) -> Option<String> {
+        // Skip the opening quote.
+        self.off += 1;
+
+        let mut str = String::new();
+        let mut beg = self.off;
+
+        loop {
+            let b = *self.bytes.get(self.off)?;
+            match b {
+                b'"' => break,
+                ..0x20 => return None,
+                b'\\' => {}
+                _ => {
+                    self.off += 1;
+                    continue;
+                }
+            }
+
+            // The input is a `&str` and we only split at ASCII characters,
+            // so the slice is guaranteed to be valid UTF-8.
+            str.push_str(unsafe { std::str::from_utf8_unchecked(&self.bytes[beg..self.off]) });
+
+            let esc = *self.bytes.get(self.off + 1)?;
+            self.off += 2;
+
+            match esc {
+                b'"' => str.push('"'),
+                b'\\' => str.push('\\'),
+                b'/' => str.push('/'),
+                b'b' => str.push('\x08'),
+                b'f' => str.push('\x0c'),
+                b'n' => str.push('\n'),
+                b'r' => str.push('\r'),
+                b't' => str.push('\t'),
+                b'u' => {
+                    let mut c = self.parse_hex4()?;
+                    if (0xD800..0xDC00).contains(&c) {
+                        // A high surrogate must be followed by an escaped low surrogate.
+                        if !self.bytes[self.off..].starts_with(b"\\u") {
+                            return None;
+                        }
+                        self.off += 2;
+                        let lo = self.parse_hex4()?;
+                        if !(0xDC00..0xE000).contains(&lo) {
+                            return None;
+                        }
+                        c = 0x10000 + ((c - 0xD800) << 10) + (lo - 0xDC00);
+                    }
+                    str.push(char::from_u32(c)?);
+                }
+                _ => return None,
+            }
+
+            beg = self.off;
+        }
+
+        str.push_str(unsafe { std::str::from_utf8_unchecked(&self.bytes[beg..self.off]) });
+        // Skip the closing quote.
+        self.off += 1;
+        Some(str)
+    }
+
+    fn parse_hex4(&mut self) -> Option<u32> {
+        let hex = self.bytes.get(self.off..self.off + 4)?;
+        let mut val = 0;
+        for &h in hex {
+            val = val * 16 + (h as char).to_digit(16)?;
+        }
+        self.off += 4;
+        Some(val)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse(" null "), Some(Value::Null));
+        assert_eq!(parse("true"), Some(Value::Bool(true)));
+        assert_eq!(parse("-12.5e1"), Some(Value::Number(-125.0)));
+        assert_eq!(parse(r#""a\"b\u00e4\ud83d\ude00""#), Some(Value::from("a\"bä😀")));
+        assert_eq!(
+            parse(r#"{"a": [1, {}], "b": ""}"#),
+            Some(Value::Object(vec![
+                ("a".to_string(), Value::Array(vec![Value::Number(1.0), Value::Object(vec![])])),
+                ("b".to_string(), Value::from("")),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        for input in [
+            "",
+            "nul",
+            "01",
+            "1.",
+            ".5",
+            "+1",
+            "1e",
+            "[1,]",
+            "{\"a\"}",
+            "{1:2}",
+            "\"\\x\"",
+            "\"\\ud800\"",
+            "\"a\nb\"",
+            "[] []",
+            "\"unterminated",
+        ] {
+            assert_eq!(parse(input), None, "{input:?}");
+        }
+        assert_eq!(parse(&"[".repeat(MAX_DEPTH + 1)), None);
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let input = r#"{"s":"tab\t\"quote\"\u0001","n":[0,-1.5,1e+3],"b":false,"z":null}"#;
+        let value = parse(input).unwrap();
+        let output = value.to_string();
+        assert_eq!(output, r#"{"s":"tab\t\"quote\"\u0001","n":[0,-1.5,1000],"b":false,"z":null}"#);
+        assert_eq!(parse(&output), Some(value));
+    }
+}
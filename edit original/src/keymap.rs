@@ -0,0 +1,494 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Key bindings.
+//!
+//! Every action that can be bound to a key has a stable command ID, such as `"edit.undo"`.
+//! A [`Keymap`] binds sequences of keys to those IDs. Usually that's a single key, but
+//! chords like `Ctrl+K Ctrl+C` work as well. The [`Tui`](crate::tui::Tui) resolves all
+//! keyboard input through its keymap and the widgets then act on the resulting command
+//! (see [`Context::command_input()`](crate::tui::Context::command_input)) instead of raw keys.
+
+use crate::input::{InputKey, InputKeyMod, kbmod, vk};
+
+/// The IDs of the commands implemented by the textarea.
+pub mod cmd {
+    pub const CURSOR_LEFT: &str = "cursor.left";
+    pub const CURSOR_RIGHT: &str = "cursor.right";
+    pub const CURSOR_UP: &str = "cursor.up";
+    pub const CURSOR_DOWN: &str = "cursor.down";
+    pub const CURSOR_WORD_LEFT: &str = "cursor.word_left";
+    pub const CURSOR_WORD_RIGHT: &str = "cursor.word_right";
+    pub const CURSOR_HOME: &str = "cursor.home";
+    pub const CURSOR_END: &str = "cursor.end";
+    pub const CURSOR_DOCUMENT_START: &str = "cursor.document_start";
+    pub const CURSOR_DOCUMENT_END: &str = "cursor.document_end";
+    pub const CURSOR_PAGE_UP: &str = "cursor.page_up";
+    pub const CURSOR_PAGE_DOWN: &str = "cursor.page_down";
+    pub const CURSOR_ADD_ABOVE: &str = "cursor.add_above";
+    pub const CURSOR_ADD_BELOW: &str = "cursor.add_below";
+    pub const CURSOR_ADD_NEXT_MATCH: &str = "cursor.add_next_match";
+
+    pub const SELECT_LEFT: &str = "select.left";
+    pub const SELECT_RIGHT: &str = "select.right";
+    pub const SELECT_UP: &str = "select.up";
+    pub const SELECT_DOWN: &str = "select.down";
+    pub const SELECT_WORD_LEFT: &str = "select.word_left";
+    pub const SELECT_WORD_RIGHT: &str = "select.word_right";
+    pub const SELECT_HOME: &str = "select.home";
+    pub const SELECT_END: &str = "select.end";
+    pub const SELECT_DOCUMENT_START: &str = "select.document_start";
+    pub const SELECT_DOCUMENT_END: &str = "select.document_end";
+    pub const SELECT_PAGE_UP: &str = "select.page_up";
+    pub const SELECT_PAGE_DOWN: &str = "select.page_down";
+    pub const SELECT_BLOCK_LEFT: &str = "select.block_left";
+    pub const SELECT_BLOCK_RIGHT: &str = "select.block_right";
+    pub const SELECT_BLOCK_UP: &str = "select.block_up";
+    pub const SELECT_BLOCK_DOWN: &str = "select.block_down";
+    pub const SELECT_ALL: &str = "select.all";
+    pub const SELECT_CLEAR: &str = "select.clear";
+
+    pub const EDIT_DELETE_LEFT: &str = "edit.delete_left";
+    pub const EDIT_DELETE_RIGHT: &str = "edit.delete_right";
+    pub const EDIT_DELETE_WORD_LEFT: &str = "edit.delete_word_left";
+    pub const EDIT_DELETE_WORD_RIGHT: &str = "edit.delete_word_right";
+    pub const EDIT_INDENT: &str = "edit.indent";
+    pub const EDIT_UNINDENT: &str = "edit.unindent";
+    pub const EDIT_NEWLINE: &str = "edit.newline";
+    pub const EDIT_TOGGLE_OVERTYPE: &str = "edit.toggle_overtype";
+    pub const EDIT_CUT: &str = "edit.cut";
+    pub const EDIT_COPY: &str = "edit.copy";
+    pub const EDIT_PASTE: &str = "edit.paste";
+    pub const EDIT_UNDO: &str = "edit.undo";
+    pub const EDIT_REDO: &str = "edit.redo";
+
+    pub const VIEW_SCROLL_UP: &str = "view.scroll_up";
+    pub const VIEW_SCROLL_DOWN: &str = "view.scroll_down";
+    pub const VIEW_WORD_WRAP: &str = "view.word_wrap";
+}
+
+/// All commands in [`cmd`].
+pub const TEXTAREA_COMMANDS: &[&str] = &[
+    cmd::CURSOR_LEFT,
+    cmd::CURSOR_RIGHT,
+    cmd::CURSOR_UP,
+    cmd::CURSOR_DOWN,
+    cmd::CURSOR_WORD_LEFT,
+    cmd::CURSOR_WORD_RIGHT,
+    cmd::CURSOR_HOME,
+    cmd::CURSOR_END,
+    cmd::CURSOR_DOCUMENT_START,
+    cmd::CURSOR_DOCUMENT_END,
+    cmd::CURSOR_PAGE_UP,
+    cmd::CURSOR_PAGE_DOWN,
+    cmd::CURSOR_ADD_ABOVE,
+    cmd::CURSOR_ADD_BELOW,
+    cmd::CURSOR_ADD_NEXT_MATCH,
+    cmd::SELECT_LEFT,
+    cmd::SELECT_RIGHT,
+    cmd::SELECT_UP,
+    cmd::SELECT_DOWN,
+    cmd::SELECT_WORD_LEFT,
+    cmd::SELECT_WORD_RIGHT,
+    cmd::SELECT_HOME,
+    cmd::SELECT_END,
+    cmd::SELECT_DOCUMENT_START,
+    cmd::SELECT_DOCUMENT_END,
+    cmd::SELECT_PAGE_UP,
+    cmd::SELECT_PAGE_DOWN,
+    cmd::SELECT_BLOCK_LEFT,
+    cmd::SELECT_BLOCK_RIGHT,
+    cmd::SELECT_BLOCK_UP,
+    cmd::SELECT_BLOCK_DOWN,
+    cmd::SELECT_ALL,
+    cmd::SELECT_CLEAR,
+    cmd::EDIT_DELETE_LEFT,
+    cmd::EDIT_DELETE_RIGHT,
+    cmd::EDIT_DELETE_WORD_LEFT,
+    cmd::EDIT_DELETE_WORD_RIGHT,
+    cmd::EDIT_INDENT,
+    cmd::EDIT_UNINDENT,
+    cmd::EDIT_NEWLINE,
+    cmd::EDIT_TOGGLE_OVERTYPE,
+    cmd::EDIT_CUT,
+    cmd::EDIT_COPY,
+    cmd::EDIT_PASTE,
+    cmd::EDIT_UNDO,
+    cmd::EDIT_REDO,
+    cmd::VIEW_SCROLL_UP,
+    cmd::VIEW_SCROLL_DOWN,
+    cmd::VIEW_WORD_WRAP,
+];
+
+/// macOS terminals send Alt+Arrow for word navigation, since Ctrl+Arrow switches desktops.
+const KBMOD_FOR_WORD_NAV: InputKeyMod =
+    if cfg!(target_os = "macos") { kbmod::ALT } else { kbmod::CTRL };
+const KBMOD_FOR_BLOCK_SELECTION: InputKeyMod =
+    if cfg!(target_os = "macos") { kbmod::CTRL_ALT_SHIFT } else { kbmod::ALT_SHIFT };
+
+/// The names of the non-alphanumeric keys. The first name for each key is used for display.
+const KEY_NAMES: &[(&str, InputKey)] = &[
+    ("Backspace", vk::BACK),
+    ("Tab", vk::TAB),
+    ("Enter", vk::RETURN),
+    ("Return", vk::RETURN),
+    ("Esc", vk::ESCAPE),
+    ("Escape", vk::ESCAPE),
+    ("Space", vk::SPACE),
+    ("PgUp", vk::PRIOR),
+    ("PageUp", vk::PRIOR),
+    ("PgDn", vk::NEXT),
+    ("PageDown", vk::NEXT),
+    ("End", vk::END),
+    ("Home", vk::HOME),
+    ("Left", vk::LEFT),
+    ("Up", vk::UP),
+    ("Right", vk::RIGHT),
+    ("Down", vk::DOWN),
+    ("Ins", vk::INSERT),
+    ("Insert", vk::INSERT),
+    ("Del", vk::DELETE),
+    ("Delete", vk::DELETE),
+];
+
+/// A key sequence bound to a command.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Binding {
+    pub keys: Vec<InputKey>,
+    pub command: &'static str,
+}
+
+/// The result of [`Keymap::resolve()`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Resolution {
+    /// The key isn't bound to anything and should be handled as is.
+    Unbound,
+    /// The key started or continued a chord. It should be ignored.
+    Pending,
+    /// The key didn't complete the pending chord. It should be ignored.
+    Cancelled,
+    /// The key (sequence) is bound to the given command.
+    Command(&'static str),
+}
+
+pub struct Keymap {
+    commands: Vec<&'static str>,
+    bindings: Vec<Binding>,
+    pending: Vec<InputKey>,
+}
+
+impl Keymap {
+    /// Creates a keymap with the [`TEXTAREA_COMMANDS`] and their default bindings.
+    pub fn new() -> Self {
+        let mut keymap = Self::empty();
+        keymap.register(TEXTAREA_COMMANDS);
+
+        if cfg!(target_os = "macos") {
+            // On macOS, terminals commonly emit the Emacs style
+            // Alt+B/F (ESC b/f) sequences for Alt+Left/Right.
+            keymap.bind(&[kbmod::ALT | vk::B], cmd::CURSOR_WORD_LEFT);
+            keymap.bind(&[kbmod::ALT | vk::F], cmd::CURSOR_WORD_RIGHT);
+        }
+
+        // Alternative bindings for a command come first, because
+        // the last one is what's shown in menus (see `keys_for()`).
+        let word = KBMOD_FOR_WORD_NAV;
+        let block = KBMOD_FOR_BLOCK_SELECTION;
+        let defaults = [
+            (cmd::CURSOR_LEFT, vk::LEFT),
+            (cmd::CURSOR_RIGHT, vk::RIGHT),
+            (cmd::CURSOR_UP, vk::UP),
+            (cmd::CURSOR_DOWN, vk::DOWN),
+            (cmd::CURSOR_WORD_LEFT, word | vk::LEFT),
+            (cmd::CURSOR_WORD_RIGHT, word | vk::RIGHT),
+            (cmd::CURSOR_HOME, vk::HOME),
+            (cmd::CURSOR_END, vk::END),
+            (cmd::CURSOR_DOCUMENT_START, kbmod::CTRL | vk::HOME),
+            (cmd::CURSOR_DOCUMENT_END, kbmod::CTRL | vk::END),
+            (cmd::CURSOR_PAGE_UP, vk::PRIOR),
+            (cmd::CURSOR_PAGE_DOWN, vk::NEXT),
+            (cmd::CURSOR_ADD_ABOVE, kbmod::CTRL_ALT | vk::UP),
+            (cmd::CURSOR_ADD_BELOW, kbmod::CTRL_ALT | vk::DOWN),
+            (cmd::CURSOR_ADD_NEXT_MATCH, kbmod::CTRL | vk::D),
+            (cmd::SELECT_LEFT, kbmod::SHIFT | vk::LEFT),
+            (cmd::SELECT_RIGHT, kbmod::SHIFT | vk::RIGHT),
+            (cmd::SELECT_UP, kbmod::SHIFT | vk::UP),
+            (cmd::SELECT_DOWN, kbmod::SHIFT | vk::DOWN),
+            (cmd::SELECT_WORD_LEFT, word | vk::LEFT | kbmod::SHIFT),
+            (cmd::SELECT_WORD_RIGHT, word | vk::RIGHT | kbmod::SHIFT),
+            (cmd::SELECT_HOME, kbmod::SHIFT | vk::HOME),
+            (cmd::SELECT_END, kbmod::SHIFT | vk::END),
+            (cmd::SELECT_DOCUMENT_START, kbmod::CTRL_SHIFT | vk::HOME),
+            (cmd::SELECT_DOCUMENT_END, kbmod::CTRL_SHIFT | vk::END),
+            (cmd::SELECT_PAGE_UP, kbmod::SHIFT | vk::PRIOR),
+            (cmd::SELECT_PAGE_DOWN, kbmod::SHIFT | vk::NEXT),
+            (cmd::SELECT_BLOCK_LEFT, block | vk::LEFT),
+            (cmd::SELECT_BLOCK_RIGHT, block | vk::RIGHT),
+            (cmd::SELECT_BLOCK_UP, block | vk::UP),
+            (cmd::SELECT_BLOCK_DOWN, block | vk::DOWN),
+            (cmd::SELECT_ALL, kbmod::CTRL | vk::A),
+            (cmd::SELECT_CLEAR, vk::ESCAPE),
+            (cmd::EDIT_DELETE_LEFT, kbmod::SHIFT | vk::BACK),
+            (cmd::EDIT_DELETE_LEFT, vk::BACK),
+            (cmd::EDIT_DELETE_RIGHT, vk::DELETE),
+            (cmd::EDIT_DELETE_WORD_LEFT, kbmod::CTRL | vk::H),
+            (cmd::EDIT_DELETE_WORD_LEFT, kbmod::CTRL | vk::BACK),
+            (cmd::EDIT_DELETE_WORD_RIGHT, kbmod::CTRL | vk::DELETE),
+            (cmd::EDIT_INDENT, vk::TAB),
+            (cmd::EDIT_UNINDENT, kbmod::SHIFT | vk::TAB),
+            (cmd::EDIT_NEWLINE, kbmod::SHIFT | vk::RETURN),
+            (cmd::EDIT_NEWLINE, vk::RETURN),
+            (cmd::EDIT_TOGGLE_OVERTYPE, vk::INSERT),
+            (cmd::EDIT_CUT, kbmod::SHIFT | vk::DELETE),
+            (cmd::EDIT_CUT, kbmod::CTRL | vk::X),
+            (cmd::EDIT_COPY, kbmod::CTRL | vk::INSERT),
+            (cmd::EDIT_COPY, kbmod::CTRL | vk::C),
+            (cmd::EDIT_PASTE, kbmod::SHIFT | vk::INSERT),
+            (cmd::EDIT_PASTE, kbmod::CTRL | vk::V),
+            (cmd::EDIT_UNDO, kbmod::CTRL | vk::Z),
+            (cmd::EDIT_REDO, kbmod::CTRL_SHIFT | vk::Z),
+            (cmd::EDIT_REDO, kbmod::CTRL | vk::Y),
+            (cmd::VIEW_SCROLL_UP, kbmod::CTRL | vk::UP),
+            (cmd::VIEW_SCROLL_DOWN, kbmod::CTRL | vk::DOWN),
+            (cmd::VIEW_WORD_WRAP, kbmod::ALT | vk::Z),
+        ];
+        for (command, key) in defaults {
+            let removed = keymap.bind(&[key], command);
+            debug_assert!(removed.is_empty(), "{command} conflicts with another default");
+        }
+
+        keymap
+    }
+
+    /// Creates a keymap without any commands or bindings.
+    pub fn empty() -> Self {
+        Self { commands: Vec::new(), bindings: Vec::new(), pending: Vec::new() }
+    }
+
+    /// Adds commands, so that they can be looked up with [`Keymap::command()`].
+    pub fn register(&mut self, commands: &[&'static str]) {
+        for &command in commands {
+            if !self.commands.contains(&command) {
+                self.commands.push(command);
+            }
+        }
+    }
+
+    /// Returns the IDs of all registered commands.
+    pub fn commands(&self) -> &[&'static str] {
+        &self.commands
+    }
+
+    /// Looks up a registered command by its ID.
+    pub fn command(&self, id: &str) -> Option<&'static str> {
+        self.commands.iter().copied().find(|&c| c == id)
+    }
+
+    pub fn bindings(&self) -> &[Binding] {
+        &self.bindings
+    }
+
+    /// Binds `keys` to `command`.
+    ///
+    /// Existing bindings for the same keys, as well as those that can no longer be reached
+    /// (or would make this one unreachable) because one is a prefix of the other, are
+    /// removed and returned. The caller can report them as conflicts if need be.
+    pub fn bind(&mut self, keys: &[InputKey], command: &'static str) -> Vec<Binding> {
+        debug_assert!(!keys.is_empty());
+
+        let mut removed = Vec::new();
+        self.bindings.retain(|b| {
+            let len = b.keys.len().min(keys.len());
+            let overlaps = b.keys[..len] == keys[..len];
+            if overlaps && !(b.keys == keys && b.command == command) {
+                removed.push(b.clone());
+            }
+            !overlaps
+        });
+
+        self.bindings.push(Binding { keys: keys.to_vec(), command });
+        removed
+    }
+
+    /// Removes the bindings of `command`, or only the one for `keys` if given.
+    pub fn unbind(&mut self, keys: Option<&[InputKey]>, command: &str) {
+        self.bindings.retain(|b| b.command != command || keys.is_some_and(|k| k != b.keys));
+    }
+
+    /// Returns the key sequence most recently bound to `command`, if any.
+    /// This way, user bindings take precedence over the defaults for display purposes.
+    pub fn keys_for(&self, command: &str) -> Option<&[InputKey]> {
+        self.bindings.iter().rfind(|b| b.command == command).map(|b| &b.keys[..])
+    }
+
+    /// The keys of the chord entered so far.
+    pub fn pending(&self) -> &[InputKey] {
+        &self.pending
+    }
+
+    /// Discards the keys of the chord entered so far.
+    pub fn cancel(&mut self) {
+        self.pending.clear();
+    }
+
+    /// Feeds a key press into the keymap. See [`Resolution`].
+    pub fn resolve(&mut self, key: InputKey) -> Resolution {
+        self.pending.push(key);
+
+        let mut prefix = false;
+        for b in &self.bindings {
+            if b.keys == self.pending {
+                self.pending.clear();
+                return Resolution::Command(b.command);
+            }
+            prefix |= b.keys.starts_with(&self.pending);
+        }
+
+        if prefix {
+            Resolution::Pending
+        } else if self.pending.len() > 1 {
+            self.pending.clear();
+            Resolution::Cancelled
+        } else {
+            self.pending.clear();
+            Resolution::Unbound
+        }
+    }
+}
+
+/// Parses a key sequence like `"ctrl+k ctrl+shift+c"`. Case-insensitive.
+pub fn parse_keys(text: &str) -> Option<Vec<InputKey>> {
+    let keys: Option<Vec<_>> = text.split_ascii_whitespace().map(parse_key).collect();
+    keys.filter(|k| !k.is_empty())
+}
+
+fn parse_key(text: &str) -> Option<InputKey> {
+    let mut modifiers = kbmod::NONE;
+    let mut parts = text.split('+').peekable();
+
+    while let Some(part) = parts.next() {
+        if parts.peek().is_none() {
+            return parse_key_name(part).map(|key| modifiers | key);
+        }
+        modifiers |= match part.to_ascii_lowercase().as_str() {
+            "ctrl" => kbmod::CTRL,
+            "alt" => kbmod::ALT,
+            "shift" => kbmod::SHIFT,
+            _ => return None,
+        };
+    }
+
+    None
+}
+
+fn parse_key_name(name: &str) -> Option<InputKey> {
+    if let [ch] = name.as_bytes()
+        && ch.is_ascii_alphanumeric()
+    {
+        return Some(InputKey::new(ch.to_ascii_uppercase() as u32));
+    }
+
+    if let Some(num) = name.strip_prefix(['f', 'F'])
+        && let Ok(num) = num.parse::<u32>()
+        && (1..=24).contains(&num)
+    {
+        return Some(InputKey::new(vk::F1.value() + num - 1));
+    }
+
+    KEY_NAMES.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|&(_, key)| key)
+}
+
+/// Returns the display name of a key without its modifiers, e.g. "PgUp" or "F10".
+pub fn key_name(key: InputKey) -> Option<&'static str> {
+    const ALNUM: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+    const F_KEYS: [&str; 24] = [
+        "F1", "F2", "F3", "F4", "F5", "F6", "F7", "F8", "F9", "F10", "F11", "F12", "F13", "F14",
+        "F15", "F16", "F17", "F18", "F19", "F20", "F21", "F22", "F23", "F24",
+    ];
+
+    let key = key.key();
+    let value = key.value() as u8 as char;
+
+    if let Some(&(name, _)) = KEY_NAMES.iter().find(|&&(_, k)| k == key) {
+        Some(name)
+    } else if let Some(i) = ALNUM.find(value)
+        && key.value() < 0x80
+    {
+        Some(&ALNUM[i..i + 1])
+    } else if (vk::F1.value()..=vk::F24.value()).contains(&key.value()) {
+        Some(F_KEYS[(key.value() - vk::F1.value()) as usize])
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_keys() {
+        assert_eq!(parse_keys("ctrl+s").unwrap(), [kbmod::CTRL | vk::S]);
+        assert_eq!(
+            parse_keys("Ctrl+K  ctrl+shift+c").unwrap(),
+            [kbmod::CTRL | vk::K, kbmod::CTRL_SHIFT | vk::C]
+        );
+        assert_eq!(parse_keys("alt+pagedown").unwrap(), [kbmod::ALT | vk::NEXT]);
+        assert_eq!(parse_keys("f12").unwrap(), [vk::F12]);
+        assert_eq!(parse_keys("shift+Del").unwrap(), [kbmod::SHIFT | vk::DELETE]);
+        assert_eq!(parse_keys("1").unwrap(), [vk::N1]);
+        assert!(parse_keys("").is_none());
+        assert!(parse_keys("ctrl+").is_none());
+        assert!(parse_keys("hyper+a").is_none());
+        assert!(parse_keys("f25").is_none());
+        assert!(parse_keys("ctrl+k ctrl+nope").is_none());
+
+        assert_eq!(key_name(kbmod::CTRL | vk::PRIOR), Some("PgUp"));
+        assert_eq!(key_name(vk::F9), Some("F9"));
+        assert_eq!(key_name(vk::F12), Some("F12"));
+        assert_eq!(key_name(kbmod::ALT | vk::Q), Some("Q"));
+        assert_eq!(key_name(vk::NUMPAD0), None);
+    }
+
+    #[test]
+    fn test_resolve() {
+        let mut keymap = Keymap::new();
+        assert_eq!(keymap.resolve(kbmod::CTRL | vk::Z), Resolution::Command(cmd::EDIT_UNDO));
+        assert_eq!(keymap.resolve(kbmod::CTRL | vk::K), Resolution::Unbound);
+
+        // Chords are pending until completed...
+        let chord = [kbmod::CTRL | vk::K, kbmod::CTRL | vk::C];
+        assert!(keymap.bind(&chord, cmd::EDIT_COPY).is_empty());
+        assert_eq!(keymap.resolve(kbmod::CTRL | vk::K), Resolution::Pending);
+        assert_eq!(keymap.pending(), [kbmod::CTRL | vk::K]);
+        assert_eq!(keymap.resolve(kbmod::CTRL | vk::C), Resolution::Command(cmd::EDIT_COPY));
+        assert!(keymap.pending().is_empty());
+
+        // ...and a chord that isn't completed swallows the keys.
+        assert_eq!(keymap.resolve(kbmod::CTRL | vk::K), Resolution::Pending);
+        assert_eq!(keymap.resolve(vk::Q), Resolution::Cancelled);
+        assert_eq!(keymap.resolve(kbmod::CTRL | vk::C), Resolution::Command(cmd::EDIT_COPY));
+
+        // Conflicting bindings are replaced and returned.
+        let removed = keymap.bind(&[kbmod::CTRL | vk::K], cmd::EDIT_CUT);
+        assert_eq!(removed, [Binding { keys: chord.to_vec(), command: cmd::EDIT_COPY }]);
+        let removed = keymap.bind(&[kbmod::CTRL | vk::Z], cmd::EDIT_REDO);
+        assert_eq!(removed, [Binding { keys: vec![kbmod::CTRL | vk::Z], command: cmd::EDIT_UNDO }]);
+        assert_eq!(keymap.keys_for(cmd::EDIT_UNDO), None);
+
+        keymap.unbind(Some(&[kbmod::CTRL | vk::Y]), cmd::EDIT_REDO);
+        assert_eq!(keymap.resolve(kbmod::CTRL | vk::Y), Resolution::Unbound);
+        assert_eq!(keymap.keys_for(cmd::EDIT_REDO), Some(&[kbmod::CTRL | vk::Z][..]));
+        keymap.unbind(None, cmd::EDIT_REDO);
+        assert_eq!(keymap.keys_for(cmd::EDIT_REDO), None);
+    }
+
+    #[test]
+    fn test_defaults() {
+        // `Keymap::new()` asserts that the defaults don't conflict.
+        let keymap = Keymap::new();
+        for &command in TEXTAREA_COMMANDS {
+            assert!(keymap.keys_for(command).is_some(), "{command} has no binding");
+        }
+    }
+}
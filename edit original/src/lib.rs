@@ -6,7 +6,6 @@
     breakpoint,
     cold_path,
     let_chains,
-    linked_list_cursors,
     maybe_uninit_fill,
     maybe_uninit_slice,
     maybe_uninit_uninit_array_transpose
@@ -20,6 +19,7 @@ pub mod apperr;
 pub mod base64;
 pub mod buffer;
 pub mod cell;
+pub mod diff;
 pub mod document;
 pub mod framebuffer;
 pub mod fuzzy;
@@ -27,10 +27,16 @@ pub mod hash;
 pub mod helpers;
 pub mod icu;
 pub mod input;
+pub mod json;
+pub mod keymap;
 pub mod oklab;
+pub mod patch;
 pub mod path;
+pub mod plugin;
 pub mod simd;
+pub mod syntax;
 pub mod sys;
 pub mod tui;
 pub mod unicode;
 pub mod vt;
+pub mod walk;
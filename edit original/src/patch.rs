@@ -0,0 +1,250 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Parses unified diffs, like those of `diff -u` and `git diff`.
+//! See [`crate::buffer::TextBuffer::apply_patch`] for applying them.
+
+use std::fmt;
+
+use crate::diff::DiffOp;
+
+/// The changes to all files in a patch.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Patch {
+    pub files: Vec<FilePatch>,
+}
+
+/// The changes to a single file.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct FilePatch {
+    /// The path before and after the change, without the `a/` and `b/` prefixes of git.
+    /// `None` if the file is created or deleted (`/dev/null`), or if the patch only consists of hunks.
+    pub old_path: Option<String>,
+    pub new_path: Option<String>,
+    pub hunks: Vec<Hunk>,
+}
+
+impl FilePatch {
+    /// The path of the file the patch applies to, preferring the new one.
+    pub fn path(&self) -> Option<&str> {
+        self.new_path.as_deref().or(self.old_path.as_deref())
+    }
+}
+
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Hunk {
+    /// The 1-based line numbers and line counts from the `@@ -1,2 +1,3 @@` header.
+    /// For an empty range, the start is the line after which it is located.
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+    pub lines: Vec<HunkLine>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct HunkLine {
+    /// [`DiffOp::Equal`] for context lines.
+    pub op: DiffOp,
+    /// The line without its line ending.
+    pub text: Vec<u8>,
+    /// Whether it was followed by "\ No newline at end of file".
+    pub no_newline: bool,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ParseError {
+    /// The 1-based line within the patch.
+    pub line: usize,
+    pub message: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Parses a unified diff. Anything outside of the file headers and hunks,
+/// like a commit message or git's `index` lines, is ignored.
+pub fn parse(text: &[u8]) -> Result<Patch, ParseError> {
+    let mut patch = Patch::default();
+    let mut lines = text.split_inclusive(|&c| c == b'\n').enumerate().peekable();
+
+    while let Some((idx, line)) = lines.next() {
+        let line = strip_newline(line);
+        let error = |message| ParseError { line: idx + 1, message };
+
+        if let Some(rest) = line.strip_prefix(b"diff --git ") {
+            // Only used if there are no `---`/`+++` lines, e.g. for empty files.
+            let rest = String::from_utf8_lossy(rest);
+            let (old, new) = rest.split_once(" b/").unwrap_or((&rest, ""));
+            patch.files.push(FilePatch {
+                old_path: Some(old.strip_prefix("a/").unwrap_or(old).to_string()),
+                new_path: Some(new.to_string()),
+                hunks: Vec::new(),
+            });
+        } else if let Some(old) = line.strip_prefix(b"--- ")
+            && let Some((_, next)) = lines.peek()
+            && let Some(new) = strip_newline(next).strip_prefix(b"+++ ")
+        {
+            let old_path = header_path(old, "a/");
+            let new_path = header_path(new, "b/");
+            lines.next();
+
+            // Unless a `diff --git` line just started this file, this is the next one.
+            match patch.files.last_mut() {
+                Some(file) if file.hunks.is_empty() && file.old_path.is_some() => {
+                    file.old_path = old_path;
+                    file.new_path = new_path;
+                }
+                _ => patch.files.push(FilePatch { old_path, new_path, hunks: Vec::new() }),
+            }
+        } else if line.starts_with(b"@@ ") {
+            let mut hunk = parse_hunk_header(line).ok_or(error("invalid hunk header"))?;
+            let mut old_left = hunk.old_len;
+            let mut new_left = hunk.new_len;
+
+            while old_left > 0 || new_left > 0 {
+                let Some((idx, line)) = lines.next() else {
+                    return Err(ParseError { line: idx + 1, message: "unexpected end of hunk" });
+                };
+                let line = strip_newline(line);
+                let error = |message| ParseError { line: idx + 1, message };
+
+                // Some tools strip the space off of empty context lines.
+                let (op, text) = match line.split_first() {
+                    None => (DiffOp::Equal, line),
+                    Some((b' ', text)) => (DiffOp::Equal, text),
+                    Some((b'-', text)) => (DiffOp::Delete, text),
+                    Some((b'+', text)) => (DiffOp::Insert, text),
+                    Some((b'\\', _)) => {
+                        let last = hunk.lines.last_mut().ok_or(error("unexpected line"))?;
+                        last.no_newline = true;
+                        continue;
+                    }
+                    Some(_) => return Err(error("hunk ends prematurely")),
+                };
+
+                let (old, new) = match op {
+                    DiffOp::Equal => (1, 1),
+                    DiffOp::Delete => (1, 0),
+                    DiffOp::Insert => (0, 1),
+                };
+                if old > old_left || new > new_left {
+                    return Err(error("hunk is longer than its header says"));
+                }
+                old_left -= old;
+                new_left -= new;
+                hunk.lines.push(HunkLine { op, text: text.to_vec(), no_newline: false });
+            }
+
+            // The marker for the last line comes after the counts are reached.
+            if let Some((_, next)) = lines.peek()
+                && next.starts_with(b"\\")
+                && let Some(last) = hunk.lines.last_mut()
+            {
+                last.no_newline = true;
+                lines.next();
+            }
+
+            // Hunks without a file header at all apply to whatever the caller decides.
+            if patch.files.is_empty() {
+                patch.files.push(FilePatch::default());
+            }
+            patch.files.last_mut().unwrap().hunks.push(hunk);
+        }
+    }
+
+    Ok(patch)
+}
+
+fn strip_newline(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+/// Extracts the path from a `---`/`+++` line, without a trailing timestamp.
+fn header_path(text: &[u8], prefix: &str) -> Option<String> {
+    let text = String::from_utf8_lossy(text);
+    let path = text.split('\t').next().unwrap_or_default().trim_end();
+    if path == "/dev/null" {
+        return None;
+    }
+    Some(path.strip_prefix(prefix).unwrap_or(path).to_string())
+}
+
+/// Parses `@@ -1,2 +3,4 @@ optional section heading`.
+fn parse_hunk_header(line: &[u8]) -> Option<Hunk> {
+    let line = std::str::from_utf8(line).ok()?;
+    let mut parts = line.strip_prefix("@@ -")?.split(" @@").next()?.split(" +");
+    let (old_start, old_len) = parse_range(parts.next()?)?;
+    let (new_start, new_len) = parse_range(parts.next()?)?;
+    Some(Hunk { old_start, old_len, new_start, new_len, lines: Vec::new() })
+}
+
+fn parse_range(text: &str) -> Option<(usize, usize)> {
+    match text.split_once(',') {
+        Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+        None => Some((text.parse().ok()?, 1)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let patch = parse(
+            b"commit message\n\
+              diff --git a/src/x.rs b/src/x.rs\n\
+              index 1234..5678 100644\n\
+              --- a/src/x.rs\n\
+              +++ b/src/x.rs\n\
+              @@ -1,3 +1,3 @@ fn main() {\n \
+              one\n\
+              -two\n\
+              +TWO\n\
+              \n\
+              @@ -10 +10,0 @@\n\
+              -ten\n\
+              \\ No newline at end of file\n\
+              --- /dev/null\t2024-01-01 00:00:00\n\
+              +++ new.txt\n\
+              @@ -0,0 +1 @@\n\
+              +new\n",
+        )
+        .unwrap();
+
+        assert_eq!(patch.files.len(), 2);
+        let file = &patch.files[0];
+        assert_eq!(file.old_path.as_deref(), Some("src/x.rs"));
+        assert_eq!(file.new_path.as_deref(), Some("src/x.rs"));
+        assert_eq!(file.hunks.len(), 2);
+        assert_eq!((file.hunks[0].old_start, file.hunks[0].old_len), (1, 3));
+        let ops: Vec<_> = file.hunks[0].lines.iter().map(|l| l.op).collect();
+        assert_eq!(ops, [DiffOp::Equal, DiffOp::Delete, DiffOp::Insert, DiffOp::Equal]);
+        assert_eq!(file.hunks[0].lines[3].text, b"");
+        assert_eq!(
+            (file.hunks[1].old_start, file.hunks[1].old_len, file.hunks[1].new_len),
+            (10, 1, 0)
+        );
+        assert!(file.hunks[1].lines[0].no_newline);
+
+        let file = &patch.files[1];
+        assert_eq!(file.old_path, None);
+        assert_eq!(file.path(), Some("new.txt"));
+        assert_eq!(file.hunks[0].lines[0].text, b"new");
+
+        // Bare hunks are fine too.
+        let patch = parse(b"@@ -1 +1 @@\n-a\n+b\n").unwrap();
+        assert_eq!(patch.files.len(), 1);
+        assert_eq!(patch.files[0].path(), None);
+
+        // A hunk that's cut off is reported at its header.
+        assert_eq!(parse(b"x\n@@ -1,2 +1,2 @@\n a\n").unwrap_err().line, 2);
+        assert_eq!(parse(b"@@ -1 +1,2 @@\n+a\n+b\n+c\n").unwrap_err().line, 4);
+        assert_eq!(parse(b"@@ -x +1 @@\n").unwrap_err().line, 1);
+    }
+}
@@ -0,0 +1,199 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The C ABI between the editor and its plugins. `assets/edit_plugin.h` mirrors it for C.
+//!
+//! A plugin is a shared library which exports two functions:
+//! ```c
+//! uint32_t edit_plugin_api_version(void);
+//! bool edit_plugin_init(const EditApi* api, EditHost* host);
+//! ```
+//! The first returns the [`API_VERSION`] the plugin was built against. The editor refuses
+//! to load plugins that require a newer version than its own. The second gets called once
+//! after loading and registers the plugin's commands, panels and event handlers.
+//!
+//! The [`Api`] table only ever grows at the end and every addition bumps [`API_VERSION`].
+//! A plugin may check [`Api::version`] before using functions that were added later.
+//!
+//! The [`Host`] pointer is only valid for the duration of the call it was passed to.
+//! All functions must be called from the thread that called into the plugin.
+//! Strings are passed as [`Str`] and are UTF-8. They're only borrowed for the duration
+//! of a call, in either direction.
+
+use std::ffi::{CStr, c_void};
+use std::{ptr, slice, str};
+
+/// The version of the [`Api`] table.
+pub const API_VERSION: u32 = 1;
+
+/// The name of the symbol that returns the API version a plugin needs.
+pub const VERSION_SYMBOL: &CStr = c"edit_plugin_api_version";
+/// The name of the plugin's entrypoint.
+pub const INIT_SYMBOL: &CStr = c"edit_plugin_init";
+
+/// See [`VERSION_SYMBOL`].
+pub type VersionFn = unsafe extern "C" fn() -> u32;
+/// See [`INIT_SYMBOL`]. Returning `false` discards everything the plugin registered.
+pub type InitFn = unsafe extern "C" fn(api: *const Api, host: *mut Host) -> bool;
+
+/// Runs a command registered via [`Api::register_command`].
+pub type CommandFn = unsafe extern "C" fn(host: *mut Host, user: *mut c_void);
+/// Draws the contents of a panel registered via [`Api::register_panel`].
+pub type DrawFn = unsafe extern "C" fn(host: *mut Host, user: *mut c_void);
+/// Handles an event. See [`Api::subscribe`].
+pub type EventFn = unsafe extern "C" fn(host: *mut Host, event: *const Event, user: *mut c_void);
+
+/// [`Event::kind`]: The contents of a document changed.
+/// [`Event::offset`], [`Event::deleted`] and [`Event::inserted`] describe the change.
+pub const EVENT_BUFFER_CHANGED: u32 = 1;
+/// [`Event::kind`]: A document was saved to [`Event::path`].
+pub const EVENT_SAVED: u32 = 2;
+
+/// [`Api::ui_list_item`]: The item wasn't interacted with.
+pub const LIST_UNCHANGED: u32 = 0;
+/// [`Api::ui_list_item`]: The item was selected.
+pub const LIST_SELECTED: u32 = 1;
+/// [`Api::ui_list_item`]: The item was activated (Enter or double-click).
+pub const LIST_ACTIVATED: u32 = 2;
+
+/// The editor's state, as seen by a plugin. Opaque.
+#[repr(C)]
+pub struct Host {
+    _opaque: [u8; 0],
+}
+
+/// A borrowed UTF-8 string. It's not NUL-terminated.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Str {
+    pub ptr: *const u8,
+    pub len: usize,
+}
+
+impl Str {
+    pub const EMPTY: Self = Self { ptr: ptr::null(), len: 0 };
+
+    pub const fn new(s: &str) -> Self {
+        Self { ptr: s.as_ptr(), len: s.len() }
+    }
+
+    pub const fn from_bytes(s: &[u8]) -> Self {
+        Self { ptr: s.as_ptr(), len: s.len() }
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` must point to `len` valid bytes for the lifetime `'a`, or be null.
+    pub unsafe fn as_bytes<'a>(self) -> &'a [u8] {
+        if self.ptr.is_null() || self.len == 0 {
+            &[]
+        } else {
+            unsafe { slice::from_raw_parts(self.ptr, self.len) }
+        }
+    }
+
+    /// Like [`Str::as_bytes`], but returns `None` if the contents aren't valid UTF-8.
+    ///
+    /// # Safety
+    ///
+    /// See [`Str::as_bytes`].
+    pub unsafe fn as_str<'a>(self) -> Option<&'a str> {
+        str::from_utf8(unsafe { self.as_bytes() }).ok()
+    }
+}
+
+/// An event passed to the handlers registered via [`Api::subscribe`].
+#[repr(C)]
+pub struct Event {
+    /// One of the `EVENT_*` constants.
+    pub kind: u32,
+    /// The path of the affected document. Empty if it's untitled.
+    pub path: Str,
+    /// Byte offset at which the change took place.
+    pub offset: usize,
+    /// Text that was removed from the document.
+    pub deleted: Str,
+    /// Text that was added to the document.
+    pub inserted: Str,
+}
+
+/// The functions the editor provides to plugins.
+///
+/// Functions that refer to "the buffer" operate on the active document.
+/// Their offsets are in bytes and get clamped to the buffer contents.
+///
+/// The `ui_*` functions may only be called while drawing a panel.
+/// Their `name` must be unique among the siblings of the UI element.
+#[repr(C)]
+pub struct Api {
+    /// The [`API_VERSION`] of the editor.
+    pub version: u32,
+
+    /// Registers a command that can be bound to keys and run from the command palette.
+    /// The `id` should be prefixed with the plugin's name, e.g. `"wordcount.show"`.
+    /// Returns `false` if the ID is taken. Only valid during [`INIT_SYMBOL`].
+    pub register_command: unsafe extern "C" fn(
+        host: *mut Host,
+        id: Str,
+        label: Str,
+        run: CommandFn,
+        user: *mut c_void,
+    ) -> bool,
+    /// Registers a panel below the editor. It gets toggled by the command `id`,
+    /// which is registered like [`Api::register_command`]. Only valid during [`INIT_SYMBOL`].
+    pub register_panel: unsafe extern "C" fn(
+        host: *mut Host,
+        id: Str,
+        title: Str,
+        draw: DrawFn,
+        user: *mut c_void,
+    ) -> bool,
+    /// Registers a handler for all events. Events are delivered after the editor
+    /// finished processing the input that caused them. Only valid during [`INIT_SYMBOL`].
+    pub subscribe: unsafe extern "C" fn(host: *mut Host, handler: EventFn, user: *mut c_void),
+    /// Shows an error message to the user.
+    pub log_error: unsafe extern "C" fn(host: *mut Host, message: Str),
+
+    /// Returns `true` if there's an active document.
+    pub buffer_available: unsafe extern "C" fn(host: *mut Host) -> bool,
+    /// Returns the length of the buffer in bytes.
+    pub buffer_length: unsafe extern "C" fn(host: *mut Host) -> usize,
+    /// Copies up to `cap` bytes starting at `offset` into `dst`.
+    /// Returns the number of bytes copied, which is less than `cap` only at the end.
+    /// The copied text may end in the middle of a UTF-8 sequence.
+    pub buffer_read:
+        unsafe extern "C" fn(host: *mut Host, offset: usize, dst: *mut u8, cap: usize) -> usize,
+    /// Stores the selection in `beg` and `end`. Both are the cursor if there's none.
+    pub buffer_selection: unsafe extern "C" fn(host: *mut Host, beg: *mut usize, end: *mut usize),
+    /// Replaces the range `beg..end` with `text`, as a single undo step.
+    /// The cursor and selection stay where they are relative to the surrounding text.
+    /// Returns `false` if there's no buffer, or if the range is reversed or out of bounds.
+    pub buffer_replace:
+        unsafe extern "C" fn(host: *mut Host, beg: usize, end: usize, text: Str) -> bool,
+
+    /// Begins a vertical container.
+    pub ui_block_begin: unsafe extern "C" fn(host: *mut Host, name: Str),
+    /// Ends the container begun by [`Api::ui_block_begin`].
+    pub ui_block_end: unsafe extern "C" fn(host: *mut Host),
+    /// Draws a line of text.
+    pub ui_label: unsafe extern "C" fn(host: *mut Host, name: Str, text: Str),
+    /// Draws a button. Returns `true` if it was pressed.
+    pub ui_button: unsafe extern "C" fn(host: *mut Host, name: Str, text: Str) -> bool,
+    /// Begins a list of which exactly one item is selected.
+    pub ui_list_begin: unsafe extern "C" fn(host: *mut Host, name: Str),
+    /// Draws a list item. If `select` is `true`, it becomes the selected one.
+    /// Returns one of the `LIST_*` constants.
+    pub ui_list_item: unsafe extern "C" fn(host: *mut Host, select: bool, text: Str) -> u32,
+    /// Ends the list begun by [`Api::ui_list_begin`].
+    pub ui_list_end: unsafe extern "C" fn(host: *mut Host),
+    /// Draws a single-line text field editing the UTF-8 text in `buf`,
+    /// which is `*len` bytes long and can hold up to `cap` bytes.
+    /// Returns `true` if the text changed, in which case `*len` got updated.
+    pub ui_editline: unsafe extern "C" fn(
+        host: *mut Host,
+        name: Str,
+        buf: *mut u8,
+        len: *mut usize,
+        cap: usize,
+    ) -> bool,
+}
@@ -0,0 +1,1060 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The tokenizers for all supported languages.
+//!
+//! Most languages are described by a [`Grammar`] table that drives a shared tokenizer.
+//! Line-oriented formats like Markdown and diffs get a function of their own.
+//! None of this attempts to be a parser: the goal is good colors for typical code,
+//! at a speed where tokenizing an entire file on load is a non-issue.
+
+use super::{Language, LineState, Token, TokenKind};
+
+pub static LANGUAGES: &[Language] = &[
+    Language {
+        name: "Rust",
+        extensions: &["rs"],
+        filenames: &[],
+        interpreters: &[],
+        tokenize: |s, l, o| tokenize(&RUST, s, l, o),
+    },
+    Language {
+        name: "JSON",
+        extensions: &["json", "jsonc", "json5", "code-workspace"],
+        filenames: &[".babelrc", ".eslintrc"],
+        interpreters: &[],
+        tokenize: |s, l, o| tokenize(&JSON, s, l, o),
+    },
+    Language {
+        name: "TOML",
+        extensions: &["toml"],
+        filenames: &["Cargo.lock", "Pipfile", "poetry.lock"],
+        interpreters: &[],
+        tokenize: |s, l, o| tokenize(&TOML, s, l, o),
+    },
+    Language {
+        name: "YAML",
+        extensions: &["yaml", "yml"],
+        filenames: &[".clang-format", ".clang-tidy"],
+        interpreters: &[],
+        tokenize: |s, l, o| tokenize(&YAML, s, l, o),
+    },
+    Language {
+        name: "Markdown",
+        extensions: &["md", "markdown", "mdx"],
+        filenames: &[],
+        interpreters: &[],
+        tokenize: markdown,
+    },
+    Language {
+        name: "Shell",
+        extensions: &["sh", "bash", "zsh", "ksh", "bats"],
+        filenames: &[".bashrc", ".bash_profile", ".profile", ".zshrc", ".zprofile", "PKGBUILD"],
+        interpreters: &["sh", "bash", "zsh", "dash", "ksh"],
+        tokenize: |s, l, o| tokenize(&SHELL, s, l, o),
+    },
+    Language {
+        name: "Python",
+        extensions: &["py", "pyi", "pyw"],
+        filenames: &["SConstruct", "SConscript"],
+        interpreters: &["python"],
+        tokenize: |s, l, o| tokenize(&PYTHON, s, l, o),
+    },
+    Language {
+        name: "TypeScript",
+        extensions: &["ts", "tsx", "mts", "cts", "js", "jsx", "mjs", "cjs"],
+        filenames: &[],
+        interpreters: &["node", "deno", "bun", "ts-node"],
+        tokenize: |s, l, o| tokenize(&TYPESCRIPT, s, l, o),
+    },
+    Language {
+        name: "Diff",
+        extensions: &["diff", "patch", "rej"],
+        filenames: &[],
+        interpreters: &[],
+        tokenize: diff,
+    },
+    Language {
+        name: "Git Commit",
+        extensions: &[],
+        filenames: &["COMMIT_EDITMSG", "MERGE_MSG", "TAG_EDITMSG", "SQUASH_MSG"],
+        interpreters: &[],
+        tokenize: git_commit,
+    },
+];
+
+/// The table that drives [`tokenize`].
+struct Grammar {
+    line_comments: &'static [&'static str],
+    /// Whether a line comment must be preceded by whitespace, as in `echo a#b`.
+    comment_needs_space: bool,
+    block_comment: Option<(&'static str, &'static str)>,
+    nested_comments: bool,
+    quotes: &'static [u8],
+    /// Letters that may precede a quote, as in Python's `f"..."` or Rust's `b"..."`.
+    string_prefixes: &'static [u8],
+    /// The subset of `quotes` whose strings may continue on the next line.
+    multiline_quotes: &'static [u8],
+    /// The subset of `quotes` in which backslashes don't escape anything.
+    literal_quotes: &'static [u8],
+    /// Python's and TOML's `"""` and `'''`. They may always span multiple lines.
+    triple_quotes: bool,
+    /// Rust's `r#"..."#`, as well as its `'a` lifetimes next to `'a'` chars.
+    rust_literals: bool,
+    keywords: &'static [&'static str],
+    types: &'static [&'static str],
+    constants: &'static [&'static str],
+    /// Whether identifiers starting with an uppercase letter are types.
+    capitalized_types: bool,
+    /// Whether identifiers followed by `(` (or `!` for Rust) are functions.
+    calls: bool,
+    /// Whether the first word or string on a line, followed by `:` or `=`, is a property.
+    keys: bool,
+    /// Whether `[section]` at the start of a line is a header.
+    sections: bool,
+    /// Whether `$name` and `${...}` are variables.
+    variables: bool,
+    /// The prefix of decorators (`@`) or attributes (`#` in Rust), which are highlighted as meta.
+    decorator: Option<u8>,
+}
+
+const DEFAULT: Grammar = Grammar {
+    line_comments: &[],
+    comment_needs_space: false,
+    block_comment: None,
+    nested_comments: false,
+    quotes: b"\"",
+    string_prefixes: &[],
+    multiline_quotes: &[],
+    literal_quotes: &[],
+    triple_quotes: false,
+    rust_literals: false,
+    keywords: &[],
+    types: &[],
+    constants: &[],
+    capitalized_types: false,
+    calls: false,
+    keys: false,
+    sections: false,
+    variables: false,
+    decorator: None,
+};
+
+const RUST: Grammar = Grammar {
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    nested_comments: true,
+    quotes: b"\"'",
+    string_prefixes: b"b",
+    multiline_quotes: b"\"",
+    rust_literals: true,
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+        "extern", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+        "pub", "ref", "return", "self", "static", "struct", "super", "trait", "type", "union",
+        "unsafe", "use", "where", "while", "yield",
+    ],
+    types: &[
+        "bool", "char", "f32", "f64", "i8", "i16", "i32", "i64", "i128", "isize", "str", "u8",
+        "u16", "u32", "u64", "u128", "usize",
+    ],
+    constants: &["true", "false"],
+    capitalized_types: true,
+    calls: true,
+    decorator: Some(b'#'),
+    ..DEFAULT
+};
+
+const JSON: Grammar = Grammar {
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    constants: &["true", "false", "null"],
+    keys: true,
+    ..DEFAULT
+};
+
+const TOML: Grammar = Grammar {
+    line_comments: &["#"],
+    quotes: b"\"'",
+    literal_quotes: b"'",
+    triple_quotes: true,
+    constants: &["true", "false", "inf", "nan"],
+    keys: true,
+    sections: true,
+    ..DEFAULT
+};
+
+const YAML: Grammar = Grammar {
+    line_comments: &["#"],
+    comment_needs_space: true,
+    quotes: b"\"'",
+    literal_quotes: b"'",
+    constants: &["true", "false", "null", "yes", "no", "on", "off", "True", "False", "Null"],
+    keys: true,
+    decorator: Some(b'&'),
+    ..DEFAULT
+};
+
+const SHELL: Grammar = Grammar {
+    line_comments: &["#"],
+    comment_needs_space: true,
+    quotes: b"\"'`",
+    multiline_quotes: b"\"'`",
+    literal_quotes: b"'",
+    keywords: &[
+        "case", "do", "done", "elif", "else", "esac", "export", "fi", "for", "function", "if",
+        "in", "local", "readonly", "return", "select", "then", "until", "while",
+    ],
+    constants: &["true", "false"],
+    calls: true,
+    variables: true,
+    ..DEFAULT
+};
+
+const PYTHON: Grammar = Grammar {
+    line_comments: &["#"],
+    quotes: b"\"'",
+    string_prefixes: b"rRbBfFuU",
+    triple_quotes: true,
+    keywords: &[
+        "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del",
+        "elif", "else", "except", "finally", "for", "from", "global", "if", "import", "in", "is",
+        "lambda", "match", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while",
+        "with", "yield",
+    ],
+    types: &[
+        "bool",
+        "bytes",
+        "dict",
+        "float",
+        "frozenset",
+        "int",
+        "list",
+        "object",
+        "set",
+        "str",
+        "tuple",
+    ],
+    constants: &["True", "False", "None", "self", "cls"],
+    capitalized_types: true,
+    calls: true,
+    decorator: Some(b'@'),
+    ..DEFAULT
+};
+
+const TYPESCRIPT: Grammar = Grammar {
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: b"\"'`",
+    multiline_quotes: b"`",
+    keywords: &[
+        "abstract",
+        "as",
+        "async",
+        "await",
+        "break",
+        "case",
+        "catch",
+        "class",
+        "const",
+        "continue",
+        "debugger",
+        "declare",
+        "default",
+        "delete",
+        "do",
+        "else",
+        "enum",
+        "export",
+        "extends",
+        "finally",
+        "for",
+        "from",
+        "function",
+        "get",
+        "if",
+        "implements",
+        "import",
+        "in",
+        "instanceof",
+        "interface",
+        "keyof",
+        "let",
+        "namespace",
+        "new",
+        "of",
+        "private",
+        "protected",
+        "public",
+        "readonly",
+        "return",
+        "satisfies",
+        "set",
+        "static",
+        "super",
+        "switch",
+        "this",
+        "throw",
+        "try",
+        "type",
+        "typeof",
+        "var",
+        "void",
+        "while",
+        "with",
+        "yield",
+    ],
+    types: &[
+        "any", "bigint", "boolean", "never", "number", "object", "string", "symbol", "unknown",
+    ],
+    constants: &["true", "false", "null", "undefined", "NaN", "Infinity"],
+    capitalized_types: true,
+    calls: true,
+    decorator: Some(b'@'),
+    ..DEFAULT
+};
+
+// The `LineState` of `tokenize` stores the kind of construct that continues
+// on the next line in the low byte and its parameters in the upper bytes.
+const STATE_NONE: LineState = 0;
+/// Parameter: The nesting depth.
+const STATE_COMMENT: LineState = 1;
+/// Parameter: The quote character and, in bit 16, whether it's tripled.
+const STATE_STRING: LineState = 2;
+/// Parameter: The number of `#` of a Rust raw string.
+const STATE_RAW_STRING: LineState = 3;
+
+fn is_ident_start(c: u8) -> bool {
+    c.is_ascii_alphabetic() || c == b'_' || c >= 0x80
+}
+
+fn is_ident(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_' || c >= 0x80
+}
+
+fn push(out: &mut Vec<Token>, beg: usize, end: usize, kind: TokenKind) {
+    if beg < end {
+        out.push(Token { beg, end, kind });
+    }
+}
+
+fn skip_whitespace(line: &[u8], mut i: usize) -> usize {
+    while i < line.len() && line[i].is_ascii_whitespace() {
+        i += 1;
+    }
+    i
+}
+
+fn tokenize(g: &Grammar, state: &mut LineState, line: &[u8], out: &mut Vec<Token>) {
+    let mut i = 0;
+
+    // Continue whatever didn't finish on the previous line.
+    match *state & 0xff {
+        STATE_COMMENT => {
+            i = block_comment(g, state, line, 0);
+            push(out, 0, i, TokenKind::Comment);
+        }
+        STATE_STRING => {
+            let quote = (*state >> 8) as u8;
+            let triple = *state & (1 << 16) != 0;
+            i = string(g, state, line, 0, quote, triple);
+            push(out, 0, i, TokenKind::String);
+        }
+        STATE_RAW_STRING => {
+            i = raw_string(state, line, 0, (*state >> 8) as usize);
+            push(out, 0, i, TokenKind::String);
+        }
+        _ => {}
+    }
+
+    // Whether the next word or string could be a key. True at the start of a line,
+    // after the `- ` of a YAML list item, and after the `{` or `,` of a JSON object.
+    let mut expect_key = i == 0;
+
+    while i < line.len() {
+        let c = line[i];
+        let beg = i;
+        let rest = &line[i..];
+
+        if c.is_ascii_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        if g.variables && c == b'$' {
+            i = variable(line, i);
+            push(out, beg, i, TokenKind::Variable);
+            expect_key = false;
+            continue;
+        }
+
+        if g.line_comments.iter().any(|p| rest.starts_with(p.as_bytes()))
+            && (!g.comment_needs_space || i == 0 || line[i - 1].is_ascii_whitespace())
+        {
+            push(out, beg, line.len(), TokenKind::Comment);
+            break;
+        }
+
+        if let Some((open, _)) = g.block_comment
+            && rest.starts_with(open.as_bytes())
+        {
+            *state = STATE_COMMENT | (1 << 8);
+            i = block_comment(g, state, line, i + open.len());
+            push(out, beg, i, TokenKind::Comment);
+            continue;
+        }
+
+        if g.rust_literals
+            && let Some(len) = raw_string_prefix(rest)
+        {
+            let hashes = len - 1 - rest.iter().take_while(|&&c| c == b'b' || c == b'r').count();
+            i = raw_string(state, line, i + len, hashes);
+            push(out, beg, i, TokenKind::String);
+            expect_key = false;
+            continue;
+        }
+
+        if let Some(q) = string_start(g, line, i) {
+            let c = line[q];
+            let rest = &line[q..];
+
+            if g.rust_literals && c == b'\'' && !is_char_literal(rest) {
+                // A lifetime or label.
+                i += 1;
+                while i < line.len() && is_ident(line[i]) {
+                    i += 1;
+                }
+                push(out, beg, i, TokenKind::Keyword);
+                continue;
+            }
+
+            let triple = g.triple_quotes && rest.starts_with(&[c, c, c]);
+            i = string(g, state, line, q + if triple { 3 } else { 1 }, c, triple);
+            let kind = if g.keys && expect_key && is_key_end(line, i) {
+                TokenKind::Property
+            } else {
+                TokenKind::String
+            };
+            push(out, beg, i, kind);
+            expect_key = false;
+            continue;
+        }
+
+        if c.is_ascii_digit() || (c == b'.' && rest.get(1).is_some_and(u8::is_ascii_digit)) {
+            i += 1;
+            while i < line.len()
+                && (is_ident(line[i])
+                    || (line[i] == b'.' && line.get(i + 1).is_some_and(u8::is_ascii_digit))
+                    || ((line[i] == b'+' || line[i] == b'-')
+                        && matches!(line[i - 1], b'e' | b'E')
+                        && !line[beg..].starts_with(b"0x")))
+            {
+                i += 1;
+            }
+            push(out, beg, i, TokenKind::Number);
+            expect_key = false;
+            continue;
+        }
+
+        if is_ident_start(c) {
+            i += 1;
+            while i < line.len() && (is_ident(line[i]) || (g.keys && line[i] == b'-')) {
+                i += 1;
+            }
+
+            let word = &line[beg..i];
+            let kind = if g.keys && expect_key && is_key_end(line, i) {
+                Some(TokenKind::Property)
+            } else if g.keywords.iter().any(|k| k.as_bytes() == word) {
+                Some(TokenKind::Keyword)
+            } else if g.constants.iter().any(|k| k.as_bytes() == word) {
+                Some(TokenKind::Constant)
+            } else if g.types.iter().any(|k| k.as_bytes() == word)
+                || (g.capitalized_types && c.is_ascii_uppercase())
+            {
+                Some(TokenKind::Type)
+            } else if g.calls
+                && (line.get(i) == Some(&b'(')
+                    || (g.rust_literals
+                        && line.get(i) == Some(&b'!')
+                        && line.get(i + 1) != Some(&b'=')))
+            {
+                Some(TokenKind::Function)
+            } else {
+                None
+            };
+
+            if let Some(kind) = kind {
+                push(out, beg, i, kind);
+            }
+            // Dotted keys, as in TOML's `a.b = 1`.
+            expect_key = expect_key && line.get(i) == Some(&b'.');
+            continue;
+        }
+
+        if g.sections && c == b'[' && line[..i].iter().all(u8::is_ascii_whitespace) {
+            i = line[i..].iter().position(|&c| c == b']').map_or(line.len(), |p| i + p + 1);
+            push(out, beg, i, TokenKind::Meta);
+            continue;
+        }
+
+        if g.decorator == Some(c)
+            && rest.get(1).is_some_and(|&c| is_ident_start(c) || c == b'[' || c == b'!')
+        {
+            i += 1;
+            if line[i] == b'!' {
+                i += 1;
+            }
+            if line.get(i) == Some(&b'[') {
+                // Rust attributes: Skip to the matching bracket.
+                let mut depth = 0;
+                while i < line.len() {
+                    depth += (line[i] == b'[') as i32 - (line[i] == b']') as i32;
+                    i += 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+            } else {
+                while i < line.len() && (is_ident(line[i]) || line[i] == b'.') {
+                    i += 1;
+                }
+            }
+            push(out, beg, i, TokenKind::Meta);
+            continue;
+        }
+
+        // Punctuation.
+        i += 1;
+        expect_key = match c {
+            b'{' | b',' => true,
+            b'.' => expect_key,
+            b'-' => expect_key && line.get(i).is_none_or(u8::is_ascii_whitespace),
+            _ => false,
+        };
+    }
+}
+
+/// Returns the position of the quote if a string starts at `i`, including any prefix.
+fn string_start(g: &Grammar, line: &[u8], i: usize) -> Option<usize> {
+    let prefix = line[i..].iter().take(2).take_while(|c| g.string_prefixes.contains(c)).count();
+    let q = i + prefix;
+    line.get(q).is_some_and(|c| g.quotes.contains(c)).then_some(q)
+}
+
+/// Whether the word or string ending at `i` is followed by `:` or `=`.
+fn is_key_end(line: &[u8], i: usize) -> bool {
+    let i = skip_whitespace(line, i);
+    match line.get(i) {
+        Some(b'=') => line.get(i + 1) != Some(&b'='),
+        Some(b':') => line.get(i + 1) != Some(&b':'),
+        _ => false,
+    }
+}
+
+/// Skips to the end of a block comment, given its nesting depth in `state`.
+fn block_comment(g: &Grammar, state: &mut LineState, line: &[u8], mut i: usize) -> usize {
+    let (open, close) = g.block_comment.unwrap();
+    let mut depth = *state >> 8;
+
+    while i < line.len() {
+        let rest = &line[i..];
+        if rest.starts_with(close.as_bytes()) {
+            i += close.len();
+            depth -= 1;
+            if depth == 0 {
+                *state = STATE_NONE;
+                return i;
+            }
+        } else if g.nested_comments && rest.starts_with(open.as_bytes()) {
+            i += open.len();
+            depth += 1;
+        } else {
+            i += 1;
+        }
+    }
+
+    *state = STATE_COMMENT | (depth << 8);
+    i
+}
+
+/// Skips to the end of a string, which starts at `i` after the opening quote.
+fn string(
+    g: &Grammar,
+    state: &mut LineState,
+    line: &[u8],
+    mut i: usize,
+    quote: u8,
+    triple: bool,
+) -> usize {
+    let escapes = !g.literal_quotes.contains(&quote);
+
+    while i < line.len() {
+        let c = line[i];
+        if escapes && c == b'\\' {
+            i += 2;
+        } else if c == quote && (!triple || line[i..].starts_with(&[quote, quote, quote])) {
+            *state = STATE_NONE;
+            return i + if triple { 3 } else { 1 };
+        } else {
+            i += 1;
+        }
+    }
+
+    *state = if triple || g.multiline_quotes.contains(&quote) {
+        STATE_STRING | ((quote as LineState) << 8) | ((triple as LineState) << 16)
+    } else {
+        STATE_NONE
+    };
+    line.len()
+}
+
+/// Returns the length of `r"`, `br##"`, etc., if `rest` starts with one.
+fn raw_string_prefix(rest: &[u8]) -> Option<usize> {
+    let mut i = 0;
+    if rest.first() == Some(&b'b') {
+        i += 1;
+    }
+    if rest.get(i) != Some(&b'r') {
+        return None;
+    }
+    i += 1;
+    while rest.get(i) == Some(&b'#') {
+        i += 1;
+    }
+    (rest.get(i) == Some(&b'"')).then_some(i + 1)
+}
+
+/// Skips to the end of a Rust raw string with the given number of `#`.
+fn raw_string(state: &mut LineState, line: &[u8], mut i: usize, hashes: usize) -> usize {
+    while i < line.len() {
+        if line[i] == b'"'
+            && line[i + 1..].iter().take(hashes).filter(|&&c| c == b'#').count() == hashes
+        {
+            *state = STATE_NONE;
+            return i + 1 + hashes;
+        }
+        i += 1;
+    }
+
+    *state = STATE_RAW_STRING | ((hashes as LineState) << 8);
+    i
+}
+
+/// Tells `'a'` and `'\n'` apart from `'a` (a lifetime).
+fn is_char_literal(rest: &[u8]) -> bool {
+    match rest.get(1) {
+        Some(b'\\') => true,
+        Some(&c) => {
+            let len = match c {
+                0x00..0x80 => 1,
+                0xc0..0xe0 => 2,
+                0xe0..0xf0 => 3,
+                _ => 4,
+            };
+            rest.get(1 + len) == Some(&b'\'')
+        }
+        None => false,
+    }
+}
+
+/// Skips a `$name`, `${...}`, `$(` or `$1`. The `$(` of a subshell is highlighted on its own.
+fn variable(line: &[u8], mut i: usize) -> usize {
+    i += 1;
+    match line.get(i) {
+        Some(b'{') => line[i..].iter().position(|&c| c == b'}').map_or(line.len(), |p| i + p + 1),
+        Some(&c) if is_ident_start(c) => {
+            while i < line.len() && is_ident(line[i]) {
+                i += 1;
+            }
+            i
+        }
+        Some(b'(') => i,
+        Some(_) => i + 1,
+        None => i,
+    }
+}
+
+// States of `markdown`.
+const MARKDOWN_TEXT: LineState = 0;
+/// Parameter: The fence character and, in the bits above it, the fence length.
+const MARKDOWN_FENCE: LineState = 1;
+
+fn markdown(state: &mut LineState, line: &[u8], out: &mut Vec<Token>) {
+    let indent = line.iter().take_while(|&&c| c == b' ').count();
+    let rest = &line[indent..];
+    let text = rest.trim_ascii_end();
+
+    let fence_char = text.first().copied().filter(|&c| c == b'`' || c == b'~');
+    let fence_len = fence_char.map_or(0, |f| text.iter().take_while(|&&c| c == f).count());
+
+    if *state & 0xff == MARKDOWN_FENCE {
+        let open_char = (*state >> 8) as u8;
+        let open_len = (*state >> 16) as usize;
+        if fence_char == Some(open_char) && fence_len >= open_len && fence_len == text.len() {
+            *state = MARKDOWN_TEXT;
+            push(out, 0, line.len(), TokenKind::Meta);
+        } else {
+            push(out, 0, line.len(), TokenKind::Code);
+        }
+        return;
+    }
+
+    if indent < 4 && fence_len >= 3 {
+        let c = fence_char.unwrap();
+        *state = MARKDOWN_FENCE | ((c as LineState) << 8) | ((fence_len as LineState) << 16);
+        push(out, 0, line.len(), TokenKind::Meta);
+        return;
+    }
+
+    if indent >= 4 {
+        push(out, 0, line.len(), TokenKind::Code);
+        return;
+    }
+
+    let hashes = text.iter().take_while(|&&c| c == b'#').count();
+    if (1..=6).contains(&hashes) && text.get(hashes).is_none_or(|&c| c == b' ') {
+        push(out, 0, line.len(), TokenKind::Heading);
+        return;
+    }
+
+    let mut i = indent;
+    if text.starts_with(b">") {
+        push(out, i, i + 1, TokenKind::Keyword);
+        i += 1;
+    } else {
+        // List items.
+        let digits = text.iter().take_while(|c| c.is_ascii_digit()).count();
+        let marker = if matches!(text.first(), Some(b'-' | b'*' | b'+')) {
+            1
+        } else if digits > 0 && matches!(text.get(digits), Some(b'.' | b')')) {
+            digits + 1
+        } else {
+            0
+        };
+        if marker > 0 && text.get(marker).is_none_or(|&c| c == b' ') {
+            push(out, i, i + marker, TokenKind::Keyword);
+            i += marker;
+        }
+    }
+
+    markdown_inline(line, i, out);
+}
+
+/// Code spans, emphasis and links.
+fn markdown_inline(line: &[u8], mut i: usize, out: &mut Vec<Token>) {
+    let find = |from: usize, pat: &[u8]| {
+        line[from..].windows(pat.len()).position(|w| w == pat).map(|p| from + p)
+    };
+
+    while i < line.len() {
+        let c = line[i];
+        let beg = i;
+
+        match c {
+            b'\\' => i += 2,
+            b'`' => {
+                let ticks = line[i..].iter().take_while(|&&c| c == b'`').count();
+                let fence = &line[i..i + ticks];
+                match find(i + ticks, fence) {
+                    Some(end) => {
+                        i = end + ticks;
+                        push(out, beg, i, TokenKind::Code);
+                    }
+                    None => i += ticks,
+                }
+            }
+            b'*' | b'_' => {
+                let n = line[i..].iter().take_while(|&&d| d == c).count().min(3);
+                let delim = &line[i..i + n];
+                let opens = line.get(i + n).is_some_and(|c| !c.is_ascii_whitespace());
+                match find(i + n, delim) {
+                    Some(end) if opens && end > i + n && !line[end - 1].is_ascii_whitespace() => {
+                        i = end + n;
+                        push(out, beg, i, TokenKind::Emphasis);
+                    }
+                    _ => i += n,
+                }
+            }
+            b'[' | b'!' if c == b'[' || line.get(i + 1) == Some(&b'[') => {
+                let open = if c == b'!' { i + 1 } else { i };
+                let link = find(open, b"](").and_then(|close| find(close, b")"));
+                match link {
+                    Some(end) => {
+                        i = end + 1;
+                        push(out, beg, i, TokenKind::Link);
+                    }
+                    None => i = open + 1,
+                }
+            }
+            b'<' if line[i..].starts_with(b"<http") => match find(i, b">") {
+                Some(end) => {
+                    i = end + 1;
+                    push(out, beg, i, TokenKind::Link);
+                }
+                None => i += 1,
+            },
+            _ => i += 1,
+        }
+    }
+}
+
+fn diff(_state: &mut LineState, line: &[u8], out: &mut Vec<Token>) {
+    let kind = if line.starts_with(b"+++ ") || line.starts_with(b"--- ") {
+        TokenKind::Keyword
+    } else if line.starts_with(b"+") || line.starts_with(b">") {
+        TokenKind::Inserted
+    } else if line.starts_with(b"-") || line.starts_with(b"<") {
+        TokenKind::Deleted
+    } else if line.starts_with(b"@@") {
+        TokenKind::Meta
+    } else if line.starts_with(b"diff ")
+        || line.starts_with(b"index ")
+        || line.starts_with(b"new file ")
+        || line.starts_with(b"deleted file ")
+        || line.starts_with(b"rename ")
+        || line.starts_with(b"similarity ")
+    {
+        TokenKind::Keyword
+    } else {
+        return;
+    };
+    push(out, 0, line.len(), kind);
+}
+
+// States of `git_commit`.
+const COMMIT_SUBJECT: LineState = 0;
+const COMMIT_BODY: LineState = 1;
+/// Everything after the "------ >8 ------" line of `git commit -v` is a diff.
+const COMMIT_DIFF: LineState = 2;
+
+fn git_commit(state: &mut LineState, line: &[u8], out: &mut Vec<Token>) {
+    match *state {
+        COMMIT_DIFF => diff(state, line, out),
+        _ if line.starts_with(b"#") => {
+            if line.trim_ascii_end().ends_with(b" >8 ------------------------") {
+                *state = COMMIT_DIFF;
+            }
+            push(out, 0, line.len(), TokenKind::Comment);
+        }
+        COMMIT_SUBJECT => {
+            *state = COMMIT_BODY;
+            // Subjects are meant to be short. Anything past 72 columns is flagged.
+            let len = line.trim_ascii_end().len();
+            push(out, 0, len.min(72), TokenKind::Heading);
+            push(out, 72, len, TokenKind::Deleted);
+        }
+        _ => {
+            // Trailers like "Signed-off-by: ...".
+            let key = line.iter().take_while(|&&c| c.is_ascii_alphanumeric() || c == b'-').count();
+            if key > 0 && line[key..].starts_with(b": ") && line[..key].contains(&b'-') {
+                push(out, 0, key, TokenKind::Property);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::language_by_name;
+    use super::*;
+
+    /// Tokenizes `text` and returns the highlighted pieces as strings.
+    fn tokens(lang: &str, text: &str) -> Vec<(&'static str, String)> {
+        let lang = language_by_name(lang).unwrap();
+        let mut state = 0;
+        let mut out = Vec::new();
+        let mut result = Vec::new();
+
+        for line in text.split_inclusive('\n') {
+            out.clear();
+            lang.tokenize(&mut state, line.as_bytes(), &mut out);
+            for t in &out {
+                let kind = match t.kind {
+                    TokenKind::Comment => "comment",
+                    TokenKind::String => "string",
+                    TokenKind::Number => "number",
+                    TokenKind::Keyword => "keyword",
+                    TokenKind::Type => "type",
+                    TokenKind::Constant => "constant",
+                    TokenKind::Function => "function",
+                    TokenKind::Property => "property",
+                    TokenKind::Variable => "variable",
+                    TokenKind::Meta => "meta",
+                    TokenKind::Heading => "heading",
+                    TokenKind::Emphasis => "emphasis",
+                    TokenKind::Link => "link",
+                    TokenKind::Code => "code",
+                    TokenKind::Inserted => "inserted",
+                    TokenKind::Deleted => "deleted",
+                };
+                result.push((kind, line[t.beg..t.end].trim_end().to_string()));
+            }
+        }
+
+        result
+    }
+
+    fn t(kind: &'static str, text: &str) -> (&'static str, String) {
+        (kind, text.to_string())
+    }
+
+    #[test]
+    fn test_rust() {
+        assert_eq!(
+            tokens(
+                "Rust",
+                "#[derive(Debug)]\nfn foo<'a>(x: &'a str) -> Option<u8> {\n    /* a /* b */\n    c */ let c = b'\\n';\n    println!(r#\"\"x\"\"#, 0x1F, 1.5e-3);\n}\n"
+            ),
+            [
+                t("meta", "#[derive(Debug)]"),
+                t("keyword", "fn"),
+                t("keyword", "'a"),
+                t("keyword", "'a"),
+                t("type", "str"),
+                t("type", "Option"),
+                t("type", "u8"),
+                t("comment", "/* a /* b */"),
+                t("comment", "    c */"),
+                t("keyword", "let"),
+                t("string", "b'\\n'"),
+                t("function", "println"),
+                t("string", "r#\"\"x\"\"#"),
+                t("number", "0x1F"),
+                t("number", "1.5e-3"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_config_formats() {
+        assert_eq!(
+            tokens("JSON", "{\"a\": [1, \"b\", true],\n \"c\": null}\n"),
+            [
+                t("property", "\"a\""),
+                t("number", "1"),
+                t("string", "\"b\""),
+                t("constant", "true"),
+                t("property", "\"c\""),
+                t("constant", "null"),
+            ]
+        );
+        assert_eq!(
+            tokens(
+                "TOML",
+                "[package]\nname = \"edit\" # hi\nversion.workspace = true\ns = '''\nx\n'''\n"
+            ),
+            [
+                t("meta", "[package]"),
+                t("property", "name"),
+                t("string", "\"edit\""),
+                t("comment", "# hi"),
+                t("property", "workspace"),
+                t("constant", "true"),
+                t("property", "s"),
+                t("string", "'''"),
+                t("string", "x"),
+                t("string", "'''"),
+            ]
+        );
+        assert_eq!(
+            tokens("YAML", "jobs:\n  build:\n    runs-on: ubuntu#1 # c\n    - name: 'x'\n"),
+            [
+                t("property", "jobs"),
+                t("property", "build"),
+                t("property", "runs-on"),
+                t("number", "1"),
+                t("comment", "# c"),
+                t("property", "name"),
+                t("string", "'x'"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scripts() {
+        assert_eq!(
+            tokens("Shell", "if [ -n \"$x\" ]; then\n  echo ${HOME}#a # c\nfi\n"),
+            [
+                t("keyword", "if"),
+                t("string", "\"$x\""),
+                t("keyword", "then"),
+                t("variable", "${HOME}"),
+                t("comment", "# c"),
+                t("keyword", "fi"),
+            ]
+        );
+        assert_eq!(
+            tokens(
+                "Python",
+                "@cache\ndef f(x: int) -> None:\n    '''doc\n    '''\n    return f\"{x}\"\n"
+            ),
+            [
+                t("meta", "@cache"),
+                t("keyword", "def"),
+                t("function", "f"),
+                t("type", "int"),
+                t("constant", "None"),
+                t("string", "'''doc"),
+                t("string", "    '''"),
+                t("keyword", "return"),
+                t("string", "f\"{x}\""),
+            ]
+        );
+        assert_eq!(
+            tokens("TypeScript", "const s: string = `a\nb`; // c\nfoo(1);\n"),
+            [
+                t("keyword", "const"),
+                t("type", "string"),
+                t("string", "`a"),
+                t("string", "b`"),
+                t("comment", "// c"),
+                t("function", "foo"),
+                t("number", "1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_line_formats() {
+        assert_eq!(
+            tokens(
+                "Markdown",
+                "# Title\n- see `a` and [b](c)\n```rust\nlet *x*\n```\n*em* 2 * 3\n"
+            ),
+            [
+                t("heading", "# Title"),
+                t("keyword", "-"),
+                t("code", "`a`"),
+                t("link", "[b](c)"),
+                t("meta", "```rust"),
+                t("code", "let *x*"),
+                t("meta", "```"),
+                t("emphasis", "*em*"),
+            ]
+        );
+        assert_eq!(
+            tokens("Diff", "--- a/x\n+++ b/x\n@@ -1 +1 @@\n-a\n+b\n c\n"),
+            [
+                t("keyword", "--- a/x"),
+                t("keyword", "+++ b/x"),
+                t("meta", "@@ -1 +1 @@"),
+                t("deleted", "-a"),
+                t("inserted", "+b"),
+            ]
+        );
+        assert_eq!(
+            tokens(
+                "Git Commit",
+                "Fix it\n\nBody\nSigned-off-by: A <a@b>\n# comment\n# ------------------------ >8 ------------------------\n+x\n"
+            ),
+            [
+                t("heading", "Fix it"),
+                t("property", "Signed-off-by"),
+                t("comment", "# comment"),
+                t("comment", "# ------------------------ >8 ------------------------"),
+                t("inserted", "+x"),
+            ]
+        );
+    }
+}
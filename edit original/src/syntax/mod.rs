@@ -0,0 +1,351 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Syntax highlighting.
+//!
+//! A [`Language`] tokenizes one logical line at a time. The only thing that is carried
+//! over from one line to the next is a [`LineState`], for instance "inside a block comment".
+//! The [`Highlighter`] remembers the state at the start of every line it has seen,
+//! so that after an edit only the lines from the edited one onward need to be tokenized
+//! again, and only until the state at the start of a line matches what it was before.
+
+mod grammars;
+
+use std::ops::Range;
+
+use crate::framebuffer::{Attributes, IndexedColor};
+
+/// The state at the start of a line. What it means is up to the [`Language`].
+pub type LineState = u32;
+
+/// The kinds of tokens that a [`Language`] can produce.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TokenKind {
+    Comment,
+    String,
+    Number,
+    Keyword,
+    Type,
+    Constant,
+    Function,
+    Property,
+    Variable,
+    Meta,
+    Heading,
+    Emphasis,
+    Link,
+    Code,
+    Inserted,
+    Deleted,
+}
+
+/// Number of variants in [`TokenKind`].
+pub const TOKEN_KIND_COUNT: usize = 16;
+
+/// A highlighted range of bytes within a line.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Token {
+    pub beg: usize,
+    pub end: usize,
+    pub kind: TokenKind,
+}
+
+/// A supported file type.
+pub struct Language {
+    pub name: &'static str,
+    /// File extensions without the dot, matched case-insensitively.
+    extensions: &'static [&'static str],
+    /// Full file names, for files without a telling extension.
+    filenames: &'static [&'static str],
+    /// Interpreters named by a `#!` line.
+    interpreters: &'static [&'static str],
+    tokenize: fn(&mut LineState, &[u8], &mut Vec<Token>),
+}
+
+impl Language {
+    /// Appends the tokens in `line` to `out` and advances `state` to the start of the next line.
+    /// `line` may or may not include its trailing newline.
+    pub fn tokenize(&self, state: &mut LineState, line: &[u8], out: &mut Vec<Token>) {
+        (self.tokenize)(state, line, out)
+    }
+}
+
+/// All supported languages.
+pub static LANGUAGES: &[Language] = grammars::LANGUAGES;
+
+/// Looks up a language by its (case-insensitive) name.
+pub fn language_by_name(name: &str) -> Option<&'static Language> {
+    LANGUAGES.iter().find(|l| l.name.eq_ignore_ascii_case(name))
+}
+
+/// Picks the language for a file based on its name or, failing that, its `#!` line.
+pub fn detect_language(filename: &str, first_line: &[u8]) -> Option<&'static Language> {
+    if let Some(lang) = LANGUAGES.iter().find(|l| l.filenames.contains(&filename)) {
+        return Some(lang);
+    }
+
+    if let Some((_, ext)) = filename.rsplit_once('.')
+        && let Some(lang) =
+            LANGUAGES.iter().find(|l| l.extensions.iter().any(|e| e.eq_ignore_ascii_case(ext)))
+    {
+        return Some(lang);
+    }
+
+    let interpreter = shebang_interpreter(first_line)?;
+    LANGUAGES.iter().find(|l| l.interpreters.contains(&interpreter))
+}
+
+/// Turns "#!/usr/bin/env -S python3 -u" into "python".
+fn shebang_interpreter(first_line: &[u8]) -> Option<&str> {
+    let line = first_line.strip_prefix(b"#!")?;
+    let line = str::from_utf8(line).ok()?;
+    let mut args = line.split_ascii_whitespace();
+    let mut name = args.next()?.rsplit('/').next()?;
+
+    if name == "env" {
+        name = args.find(|a| !a.starts_with('-'))?;
+    }
+
+    // python3, python3.12, etc.
+    Some(name.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.'))
+}
+
+/// How a [`TokenKind`] is drawn.
+#[derive(Clone, Copy, Default)]
+pub struct Style {
+    pub fg: Option<IndexedColor>,
+    pub attr: Attributes,
+}
+
+/// Maps each [`TokenKind`] to a [`Style`].
+#[derive(Clone)]
+pub struct Theme {
+    styles: [Style; TOKEN_KIND_COUNT],
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        let fg = |color| Style { fg: Some(color), attr: Attributes::None };
+        let mut theme = Self { styles: [Style::default(); TOKEN_KIND_COUNT] };
+        theme.set_style(
+            TokenKind::Comment,
+            Style { fg: Some(IndexedColor::Green), attr: Attributes::Italic },
+        );
+        theme.set_style(TokenKind::String, fg(IndexedColor::Yellow));
+        theme.set_style(TokenKind::Number, fg(IndexedColor::BrightGreen));
+        theme.set_style(TokenKind::Keyword, fg(IndexedColor::BrightBlue));
+        theme.set_style(TokenKind::Type, fg(IndexedColor::Cyan));
+        theme.set_style(TokenKind::Constant, fg(IndexedColor::Blue));
+        theme.set_style(TokenKind::Function, fg(IndexedColor::BrightYellow));
+        theme.set_style(TokenKind::Property, fg(IndexedColor::BrightCyan));
+        theme.set_style(TokenKind::Variable, fg(IndexedColor::BrightCyan));
+        theme.set_style(TokenKind::Meta, fg(IndexedColor::Magenta));
+        theme.set_style(TokenKind::Heading, fg(IndexedColor::BrightBlue));
+        theme.set_style(TokenKind::Emphasis, Style { fg: None, attr: Attributes::Italic });
+        theme.set_style(
+            TokenKind::Link,
+            Style { fg: Some(IndexedColor::Blue), attr: Attributes::Underlined },
+        );
+        theme.set_style(TokenKind::Code, fg(IndexedColor::Yellow));
+        theme.set_style(TokenKind::Inserted, fg(IndexedColor::Green));
+        theme.set_style(TokenKind::Deleted, fg(IndexedColor::Red));
+        theme
+    }
+}
+
+impl Theme {
+    pub fn style(&self, kind: TokenKind) -> Style {
+        self.styles[kind as usize]
+    }
+
+    pub fn set_style(&mut self, kind: TokenKind, style: Style) {
+        self.styles[kind as usize] = style;
+    }
+}
+
+/// Tracks the [`LineState`] at the start of each line of a document.
+///
+/// States are computed lazily, when a line gets highlighted.
+/// Edits mark a range of lines as dirty instead of discarding everything after them.
+pub struct Highlighter {
+    language: &'static Language,
+    /// `states[y]` is the state at the start of line `y`. Lines past the end aren't known yet.
+    states: Vec<LineState>,
+    /// The lines whose state needs to be recomputed. Once the end of the range has been
+    /// reached and a state matches its previous value, the states after it are valid again.
+    dirty: Option<Range<usize>>,
+}
+
+impl Highlighter {
+    pub fn new(language: &'static Language) -> Self {
+        Self { language, states: vec![0], dirty: None }
+    }
+
+    pub fn language(&self) -> &'static Language {
+        self.language
+    }
+
+    /// Forgets all states, for when the entire document was replaced.
+    pub fn reset(&mut self) {
+        self.states.truncate(1);
+        self.dirty = None;
+    }
+
+    /// Updates the states after line `line` was edited,
+    /// which removed `removed` and added `added` newlines.
+    pub fn invalidate(&mut self, line: usize, removed: usize, added: usize) {
+        let first = line + 1;
+
+        if first + removed > self.states.len() {
+            self.states.truncate(first);
+        } else if first < self.states.len() {
+            self.states.splice(first..first + removed, std::iter::repeat_n(0, added));
+        }
+
+        // Where a line ends up after the edit.
+        let shift = |y: usize| {
+            if y <= line {
+                y
+            } else if y > line + removed {
+                y - removed + added
+            } else {
+                first + added
+            }
+        };
+
+        let mut dirty = first..first + added;
+        if let Some(d) = &self.dirty {
+            dirty.start = dirty.start.min(shift(d.start));
+            dirty.end = dirty.end.max(shift(d.end));
+        }
+        self.dirty = (dirty.start < self.states.len()).then_some(dirty);
+    }
+
+    /// Returns the next line whose state needs to be computed
+    /// (from the line before it) before `line` can be highlighted.
+    pub fn next_line_to_update(&self, line: usize) -> Option<usize> {
+        let next = match &self.dirty {
+            Some(d) => d.start,
+            None => self.states.len(),
+        };
+        (next <= line).then_some(next)
+    }
+
+    /// Computes the state of line `line` from `prev`, the text of the line before it.
+    /// `line` must have been returned by [`Highlighter::next_line_to_update`].
+    pub fn update(&mut self, line: usize, prev: &[u8], scratch: &mut Vec<Token>) {
+        let mut state = self.states[line - 1];
+        scratch.clear();
+        self.language.tokenize(&mut state, prev, scratch);
+
+        if line == self.states.len() {
+            self.states.push(state);
+            return;
+        }
+
+        let old = self.states[line];
+        self.states[line] = state;
+
+        let Some(dirty) = &mut self.dirty else {
+            return;
+        };
+        if line >= dirty.end && old == state {
+            self.dirty = None;
+        } else {
+            dirty.start = line + 1;
+            dirty.end = dirty.end.max(line + 1);
+            if dirty.start >= self.states.len() {
+                self.dirty = None;
+            }
+        }
+    }
+
+    /// Tokenizes `text`, the contents of line `line`, whose state must be known.
+    pub fn tokenize(&self, line: usize, text: &[u8], out: &mut Vec<Token>) {
+        debug_assert!(self.next_line_to_update(line).is_none());
+        let mut state = self.states[line];
+        out.clear();
+        self.language.tokenize(&mut state, text, out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(lang: Option<&Language>) -> &str {
+        lang.map_or("", |l| l.name)
+    }
+
+    #[test]
+    fn test_detect_language() {
+        assert_eq!(name(detect_language("main.rs", b"")), "Rust");
+        assert_eq!(name(detect_language("README.MD", b"")), "Markdown");
+        assert_eq!(name(detect_language("Cargo.lock", b"")), "TOML");
+        assert_eq!(name(detect_language("COMMIT_EDITMSG", b"")), "Git Commit");
+        assert_eq!(name(detect_language("fix.patch", b"")), "Diff");
+        assert_eq!(name(detect_language("run", b"#!/bin/bash")), "Shell");
+        assert_eq!(name(detect_language("run", b"#!/usr/bin/env -S python3.12 -u")), "Python");
+        assert_eq!(name(detect_language("run", b"#!/usr/bin/env node")), "TypeScript");
+        assert_eq!(name(detect_language("notes.txt", b"#!/bin/sh")), "Shell");
+        assert_eq!(name(detect_language("notes.txt", b"hello")), "");
+        assert_eq!(name(language_by_name("yaml")), "YAML");
+    }
+
+    /// Computes the states of `lines` from scratch.
+    fn expected(lines: &[&str]) -> Vec<LineState> {
+        let mut h = Highlighter::new(language_by_name("Rust").unwrap());
+        update(&mut h, lines);
+        h.states
+    }
+
+    fn update(h: &mut Highlighter, lines: &[&str]) -> usize {
+        let mut scratch = Vec::new();
+        let mut count = 0;
+        while let Some(l) = h.next_line_to_update(lines.len() - 1) {
+            h.update(l, lines[l - 1].as_bytes(), &mut scratch);
+            count += 1;
+        }
+        count
+    }
+
+    #[test]
+    fn test_incremental() {
+        let rust = language_by_name("Rust").unwrap();
+        let mut h = Highlighter::new(rust);
+        let mut lines = vec!["fn a() {}\n"; 100];
+        assert_eq!(update(&mut h, &lines), 99);
+        assert_eq!(h.states, expected(&lines));
+
+        // An edit that doesn't change the state only touches the line after it.
+        lines[10] = "fn b() {}\n";
+        h.invalidate(10, 0, 0);
+        assert_eq!(update(&mut h, &lines), 1);
+
+        // Opening a block comment changes everything after it.
+        lines[20] = "/* open\n";
+        h.invalidate(20, 0, 0);
+        assert_eq!(update(&mut h, &lines), 79);
+        assert_eq!(h.states, expected(&lines));
+
+        // Insert lines that close it again.
+        lines.splice(30..30, ["*/\n", "fn c() {}\n"]);
+        h.invalidate(30, 0, 2);
+        assert_eq!(update(&mut h, &lines), 71);
+        assert_eq!(h.states, expected(&lines));
+
+        // Two separate edits before the next update.
+        lines.drain(5..8);
+        h.invalidate(5, 3, 0);
+        lines[40] = "/* a */ fn d() {}\n";
+        h.invalidate(40, 0, 0);
+        update(&mut h, &lines);
+        assert_eq!(h.states, expected(&lines));
+
+        // Delete the comment opener.
+        lines.remove(17);
+        h.invalidate(17, 1, 0);
+        update(&mut h, &lines);
+        assert_eq!(h.states, expected(&lines));
+    }
+}
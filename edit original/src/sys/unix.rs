@@ -6,10 +6,11 @@
 //! Read the `windows` module for reference.
 //! TODO: This reminds me that the sys API should probably be a trait.
 
-use std::ffi::{CStr, c_int, c_void};
+use std::ffi::{CStr, CString, c_int, c_void};
 use std::fs::{self, File};
 use std::mem::{self, ManuallyDrop, MaybeUninit};
 use std::os::fd::{AsRawFd as _, FromRawFd as _};
+use std::os::unix::ffi::OsStrExt as _;
 use std::path::Path;
 use std::ptr::{self, NonNull, null_mut};
 use std::{thread, time};
@@ -39,6 +40,8 @@ struct State {
     stdout: libc::c_int,
     stdout_initial_termios: Option<libc::termios>,
     inject_resize: bool,
+    // The inotify descriptor of the `FileWatcher`, if any. -1 otherwise.
+    watcher: libc::c_int,
     // Buffer for incomplete UTF-8 sequences (max 4 bytes needed)
     utf8_buf: [u8; 4],
     utf8_len: usize,
@@ -50,6 +53,7 @@ static mut STATE: State = State {
     stdout: libc::STDOUT_FILENO,
     stdout_initial_termios: None,
     inject_resize: false,
+    watcher: -1,
     utf8_buf: [0; 4],
     utf8_len: 0,
 };
@@ -183,7 +187,7 @@ fn get_window_size() -> (u16, u16) {
 /// Reads from stdin.
 ///
 /// Returns `None` if there was an error reading from stdin.
-/// Returns `Some("")` if the given timeout was reached.
+/// Returns `Some("")` if the given timeout was reached or the [`FileWatcher`] saw a change.
 /// Otherwise, it returns the read, non-empty string.
 pub fn read_stdin(arena: &Arena, mut timeout: time::Duration) -> Option<ArenaString<'_>> {
     unsafe {
@@ -207,10 +211,14 @@ pub fn read_stdin(arena: &Arena, mut timeout: time::Duration) -> Option<ArenaStr
         }
 
         loop {
-            if timeout != time::Duration::MAX {
+            if timeout != time::Duration::MAX || STATE.watcher >= 0 {
                 let beg = time::Instant::now();
 
-                let mut pollfd = libc::pollfd { fd: STATE.stdin, events: libc::POLLIN, revents: 0 };
+                let mut pollfds = [
+                    libc::pollfd { fd: STATE.stdin, events: libc::POLLIN, revents: 0 },
+                    // poll() ignores negative descriptors, i.e. if there's no watcher.
+                    libc::pollfd { fd: STATE.watcher, events: libc::POLLIN, revents: 0 },
+                ];
                 let ret;
                 #[cfg(target_os = "linux")]
                 {
@@ -218,20 +226,36 @@ pub fn read_stdin(arena: &Arena, mut timeout: time::Duration) -> Option<ArenaStr
                         tv_sec: timeout.as_secs() as libc::time_t,
                         tv_nsec: timeout.subsec_nanos() as libc::c_long,
                     };
-                    ret = libc::ppoll(&mut pollfd, 1, &ts, ptr::null());
+                    let ts =
+                        if timeout == time::Duration::MAX { ptr::null() } else { &raw const ts };
+                    ret = libc::ppoll(pollfds.as_mut_ptr(), 2, ts, ptr::null());
                 }
                 #[cfg(not(target_os = "linux"))]
                 {
-                    ret = libc::poll(&mut pollfd, 1, timeout.as_millis() as libc::c_int);
+                    let timeout = if timeout == time::Duration::MAX {
+                        -1
+                    } else {
+                        timeout.as_millis() as libc::c_int
+                    };
+                    ret = libc::poll(pollfds.as_mut_ptr(), 2, timeout);
                 }
                 if ret < 0 {
-                    return None; // Error? Let's assume it's an EOF.
+                    match errno() {
+                        libc::EINTR if STATE.inject_resize => break,
+                        libc::EINTR => continue,
+                        _ => return None, // Error? Let's assume it's an EOF.
+                    }
                 }
                 if ret == 0 {
                     break; // Timeout? We can stop reading.
                 }
+                if pollfds[0].revents == 0 {
+                    break; // Only the watcher has news.
+                }
 
-                timeout = timeout.saturating_sub(beg.elapsed());
+                if timeout != time::Duration::MAX {
+                    timeout = timeout.saturating_sub(beg.elapsed());
+                }
             };
 
             // If we're asked for a non-blocking read we need
@@ -381,6 +405,121 @@ pub fn file_id(file: Option<&File>, path: &Path) -> apperr::Result<FileId> {
     }
 }
 
+/// Watches directories for changes to the files in them.
+/// While one exists, [`read_stdin`] also returns when it sees a change.
+///
+/// Only implemented for Linux (using inotify). Elsewhere [`FileWatcher::new`]
+/// returns `None` and the files need to be polled instead.
+pub struct FileWatcher {
+    #[cfg(target_os = "linux")]
+    watches: Vec<(std::path::PathBuf, c_int)>,
+}
+
+impl FileWatcher {
+    #[cfg(target_os = "linux")]
+    pub fn new() -> Option<Self> {
+        unsafe {
+            if STATE.watcher >= 0 {
+                return None; // There can only be one.
+            }
+            let fd = libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC);
+            if fd < 0 {
+                return None;
+            }
+            STATE.watcher = fd;
+            Some(Self { watches: Vec::new() })
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn new() -> Option<Self> {
+        None
+    }
+
+    /// Watches the given directories from now on and stops watching any others.
+    /// Returns false if some of them can't be watched.
+    #[cfg(target_os = "linux")]
+    pub fn set_dirs(&mut self, dirs: &[&Path]) -> bool {
+        unsafe {
+            self.watches.retain(|(path, wd)| {
+                let keep = dirs.contains(&path.as_path());
+                if !keep && *wd >= 0 {
+                    libc::inotify_rm_watch(STATE.watcher, *wd);
+                }
+                keep
+            });
+
+            for &dir in dirs {
+                if self.watches.iter().any(|(path, _)| path == dir) {
+                    continue;
+                }
+                // Writes are only reported once the file gets closed,
+                // so that a half-written file doesn't count as a change yet.
+                let mask = libc::IN_CLOSE_WRITE
+                    | libc::IN_ATTRIB
+                    | libc::IN_CREATE
+                    | libc::IN_DELETE
+                    | libc::IN_MOVED_FROM
+                    | libc::IN_MOVED_TO
+                    | libc::IN_DELETE_SELF
+                    | libc::IN_MOVE_SELF;
+                // Failures are remembered as -1, so that they aren't retried over and over.
+                let wd = match CString::new(dir.as_os_str().as_bytes()) {
+                    Ok(path) => libc::inotify_add_watch(STATE.watcher, path.as_ptr(), mask),
+                    Err(_) => -1,
+                };
+                self.watches.push((dir.to_path_buf(), wd));
+            }
+
+            self.watches.iter().all(|&(_, wd)| wd >= 0)
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn set_dirs(&mut self, _dirs: &[&Path]) -> bool {
+        false
+    }
+
+    /// Returns true if anything changed in the watched directories since the last call.
+    #[cfg(target_os = "linux")]
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+        let mut buf = [0u8; 4 * KIBI];
+        // The descriptor is non-blocking, so this stops once all events are drained.
+        while unsafe { libc::read(STATE.watcher, buf.as_mut_ptr() as *mut _, buf.len()) } > 0 {
+            changed = true;
+        }
+        changed
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn poll(&mut self) -> bool {
+        false
+    }
+}
+
+impl Drop for FileWatcher {
+    fn drop(&mut self) {
+        #[cfg(target_os = "linux")]
+        unsafe {
+            libc::close(STATE.watcher);
+            STATE.watcher = -1;
+        }
+    }
+}
+
+/// Tries to take an exclusive lock on the file, which is held until it's closed.
+/// Returns `false` if another handle holds it, even one of the same process.
+pub fn file_try_lock(file: &File) -> apperr::Result<bool> {
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    match errno() {
+        libc::EWOULDBLOCK => Ok(false),
+        no => Err(errno_to_apperr(no)),
+    }
+}
+
 /// Reserves a virtual memory region of the given size.
 /// To commit the memory, use `virtual_commit`.
 /// To release the memory, use `virtual_release`.
@@ -467,6 +606,17 @@ pub fn load_libicui18n() -> apperr::Result<NonNull<c_void>> {
     unsafe { load_library(c"libicui18n.so") }
 }
 
+/// Loads the plugin at `path`. Unlike the ICU libraries, all of its symbols
+/// are resolved right away, so that a broken plugin fails here instead of later.
+pub fn load_plugin(path: &Path) -> apperr::Result<NonNull<c_void>> {
+    let path =
+        CString::new(path.as_os_str().as_bytes()).map_err(|_| errno_to_apperr(libc::EINVAL))?;
+    unsafe {
+        NonNull::new(libc::dlopen(path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL))
+            .ok_or_else(|| errno_to_apperr(libc::ENOEXEC))
+    }
+}
+
 /// ICU, by default, adds the major version as a suffix to each exported symbol.
 /// They also recommend to disable this for system-level installations (`runConfigureICU Linux --disable-renaming`),
 /// but I found that many (most?) Linux distributions don't do this for some reason.
@@ -5,6 +5,7 @@ use std::ffi::{CStr, OsString, c_void};
 use std::fmt::Write as _;
 use std::fs::{self, File};
 use std::mem::MaybeUninit;
+use std::os::windows::ffi::OsStrExt as _;
 use std::os::windows::io::{AsRawHandle as _, FromRawHandle};
 use std::path::{Path, PathBuf};
 use std::ptr::{self, NonNull, null, null_mut};
@@ -413,6 +414,7 @@ pub fn drives() -> impl Iterator<Item = char> {
 }
 
 /// A unique identifier for a file.
+#[derive(Clone)]
 pub enum FileId {
     Id(FileSystem::FILE_ID_INFO),
     Path(PathBuf),
@@ -459,6 +461,49 @@ fn file_id_from_handle(file: &File) -> apperr::Result<FileId> {
     }
 }
 
+/// Watches directories for changes to the files in them.
+///
+/// Not implemented on Windows yet: [`FileWatcher::new`] returns `None`
+/// and the files need to be polled instead.
+pub struct FileWatcher;
+
+impl FileWatcher {
+    pub fn new() -> Option<Self> {
+        None
+    }
+
+    pub fn set_dirs(&mut self, _dirs: &[&Path]) -> bool {
+        false
+    }
+
+    pub fn poll(&mut self) -> bool {
+        false
+    }
+}
+
+/// Tries to take an exclusive lock on the file, which is held until it's closed.
+/// Returns `false` if another handle holds it, even one of the same process.
+pub fn file_try_lock(file: &File) -> apperr::Result<bool> {
+    unsafe {
+        let mut overlapped: IO::OVERLAPPED = mem::zeroed();
+        let ok = FileSystem::LockFileEx(
+            file.as_raw_handle(),
+            FileSystem::LOCKFILE_EXCLUSIVE_LOCK | FileSystem::LOCKFILE_FAIL_IMMEDIATELY,
+            0,
+            u32::MAX,
+            u32::MAX,
+            &mut overlapped,
+        );
+        if ok != 0 {
+            return Ok(true);
+        }
+        match Foundation::GetLastError() {
+            Foundation::ERROR_LOCK_VIOLATION => Ok(false),
+            gle => Err(gle_to_apperr(gle)),
+        }
+    }
+}
+
 /// Canonicalizes the given path.
 ///
 /// This differs from [`fs::canonicalize`] in that it strips the `\\?\` UNC
@@ -581,6 +626,18 @@ pub fn load_libicui18n() -> apperr::Result<NonNull<c_void>> {
     unsafe { load_library(w!("icuin.dll")) }
 }
 
+/// Loads the plugin at `path`. Its dependencies are searched for next to it.
+pub fn load_plugin(path: &Path) -> apperr::Result<NonNull<c_void>> {
+    let path: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    unsafe {
+        check_ptr_return(LibraryLoader::LoadLibraryExW(
+            path.as_ptr(),
+            null_mut(),
+            LibraryLoader::LOAD_WITH_ALTERED_SEARCH_PATH,
+        ))
+    }
+}
+
 /// Returns a list of preferred languages for the current user.
 pub fn preferred_languages(arena: &Arena) -> Vec<ArenaString, &Arena> {
     // If the GetUserPreferredUILanguages() don't fit into 512 characters,
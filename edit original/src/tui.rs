@@ -150,19 +150,20 @@ use std::fmt::Write as _;
 use std::{iter, mem, ptr, time};
 
 use crate::arena::{Arena, ArenaString, scratch_arena};
-use crate::buffer::{CursorMovement, RcTextBuffer, TextBuffer, TextBufferCell};
+use crate::buffer::{
+    CursorMovement, RcTextBuffer, RcTextBufferView, TextBuffer, TextBufferCell, TextBufferViewCell,
+};
 use crate::cell::*;
 use crate::document::WriteableDocument;
-use crate::framebuffer::{Attributes, Framebuffer, INDEXED_COLORS_COUNT, IndexedColor};
+use crate::framebuffer::{Attributes, Framebuffer, INDEXED_COLORS_COUNT, IndexedColor, Snapshot};
 use crate::hash::*;
 use crate::helpers::*;
 use crate::input::{InputKeyMod, kbmod, vk};
+use crate::keymap::{self, Keymap, Resolution, cmd};
 use crate::{apperr, arena_format, input, simd, unicode};
 
 const ROOT_ID: u64 = 0x14057B7EF767814F; // Knuth's MMIX constant
 const SHIFT_TAB: InputKey = vk::TAB.with_modifiers(kbmod::SHIFT);
-const KBMOD_FOR_WORD_NAV: InputKeyMod =
-    if cfg!(target_os = "macos") { kbmod::ALT } else { kbmod::CTRL };
 
 type Input<'input> = input::Input<'input>;
 type InputKey = input::InputKey;
@@ -175,6 +176,7 @@ type InputText<'input> = input::InputText<'input>;
 struct CachedTextBuffer {
     node_id: u64,
     editor: RcTextBuffer,
+    view: Option<RcTextBufferView>,
     seen: bool,
 }
 
@@ -182,7 +184,7 @@ struct CachedTextBuffer {
 /// do almost the same thing, this abstracts over the two.
 enum TextBufferPayload<'a> {
     Editline(&'a mut dyn WriteableDocument),
-    Textarea(RcTextBuffer),
+    Textarea(RcTextBuffer, Option<RcTextBufferView>),
 }
 
 /// In order for the TUI to show the correct Ctrl/Alt/Shift
@@ -315,6 +317,7 @@ pub struct Tui {
     framebuffer: Framebuffer,
 
     modifier_translations: ModifierTranslations,
+    keymap: Keymap,
     floater_default_bg: u32,
     floater_default_fg: u32,
     modal_default_bg: u32,
@@ -341,6 +344,8 @@ pub struct Tui {
     mouse_state: InputMouseState,
     /// Whether the mouse is currently being dragged.
     mouse_is_drag: bool,
+    /// The button that was released, while `mouse_state` is [`InputMouseState::Release`].
+    mouse_released_button: InputMouseState,
     /// The number of clicks that have happened in a row.
     /// Gets reset when the mouse was released for a while.
     mouse_click_counter: CoordType,
@@ -367,6 +372,8 @@ pub struct Tui {
     /// A counter that is incremented every time the clipboard changes.
     /// Allows for tracking clipboard changes without comparing contents.
     clipboard_generation: u32,
+    /// Whether the clipboard holds a rectangular selection.
+    clipboard_is_block: bool,
 
     settling_have: i32,
     settling_want: i32,
@@ -394,6 +401,7 @@ impl Tui {
                 alt: "Alt",
                 shift: "Shift",
             },
+            keymap: Keymap::new(),
             floater_default_bg: 0,
             floater_default_fg: 0,
             modal_default_bg: 0,
@@ -406,6 +414,7 @@ impl Tui {
             mouse_up_timestamp: std::time::Instant::now(),
             mouse_state: InputMouseState::None,
             mouse_is_drag: false,
+            mouse_released_button: InputMouseState::None,
             mouse_click_counter: 0,
             mouse_down_node_path: Vec::with_capacity(16),
             first_click_position: Point::MIN,
@@ -418,6 +427,7 @@ impl Tui {
 
             clipboard: Vec::new(),
             clipboard_generation: 0,
+            clipboard_is_block: false,
 
             settling_have: 0,
             settling_want: 0,
@@ -438,6 +448,16 @@ impl Tui {
         self.modifier_translations = translations;
     }
 
+    /// Returns the keymap that all keyboard input is resolved through.
+    pub fn keymap(&self) -> &Keymap {
+        &self.keymap
+    }
+
+    /// Replaces the keymap. Any partially entered chord is discarded.
+    pub fn set_keymap(&mut self, keymap: Keymap) {
+        self.keymap = keymap;
+    }
+
     /// Set the default background color for floaters (dropdowns, etc.).
     pub fn set_floater_default_bg(&mut self, color: u32) {
         self.floater_default_bg = color;
@@ -502,6 +522,12 @@ impl Tui {
         self.clipboard_generation
     }
 
+    /// Returns whether the clipboard contents are a rectangular selection,
+    /// which should be pasted as a column via [`TextBuffer::paste_block()`].
+    pub fn clipboard_is_block(&self) -> bool {
+        self.clipboard_is_block
+    }
+
     /// Starts a new frame and returns a [`Context`] for it.
     pub fn create_context<'a, 'input>(
         &'a mut self,
@@ -521,11 +547,13 @@ impl Tui {
             self.left_mouse_down_target = 0;
             self.mouse_state = InputMouseState::None;
             self.mouse_is_drag = false;
+            self.mouse_released_button = InputMouseState::None;
         }
 
         let now = std::time::Instant::now();
         let mut input_text = None;
         let mut input_keyboard = None;
+        let mut input_command = None;
         let mut input_mouse_modifiers = kbmod::NONE;
         let mut input_mouse_click = 0;
         let mut input_scroll_delta = Point { x: 0, y: 0 };
@@ -541,6 +569,7 @@ impl Tui {
 
         match input {
             None => {}
+            Some(Input::Focus(_)) => {}
             Some(Input::Resize(resize)) => {
                 assert!(resize.width > 0 && resize.height > 0);
                 assert!(resize.width < 32768 && resize.height < 32768);
@@ -557,10 +586,29 @@ impl Tui {
                     let ch = text.text.as_bytes()[0];
                     input_keyboard = InputKey::from_ascii(ch as char)
                 }
+
+                // Plain text only takes part in key bindings if it continues a chord.
+                if !self.keymap.pending().is_empty() {
+                    match input_keyboard {
+                        Some(key) => {
+                            input_text = None;
+                            match self.keymap.resolve(key) {
+                                Resolution::Command(command) => input_command = Some(command),
+                                _ => input_keyboard = None,
+                            }
+                        }
+                        None => self.keymap.cancel(),
+                    }
+                }
             }
-            Some(Input::Keyboard(keyboard)) => {
-                input_keyboard = Some(keyboard);
-            }
+            Some(Input::Keyboard(keyboard)) => match self.keymap.resolve(keyboard) {
+                Resolution::Unbound => input_keyboard = Some(keyboard),
+                Resolution::Pending | Resolution::Cancelled => {}
+                Resolution::Command(command) => {
+                    input_keyboard = Some(keyboard);
+                    input_command = Some(command);
+                }
+            },
             Some(Input::Mouse(mouse)) => {
                 let mut next_state = mouse.state;
                 let next_position = mouse.position;
@@ -629,6 +677,7 @@ impl Tui {
                 } else if mouse_up {
                     // Transition from some mouse input to no mouse input --> The mouse button was released.
                     next_state = InputMouseState::Release;
+                    self.mouse_released_button = self.mouse_state;
 
                     let target = focused_node.map_or(0, |n| n.borrow().id);
 
@@ -674,6 +723,7 @@ impl Tui {
 
             input_text,
             input_keyboard,
+            input_command,
             input_mouse_modifiers,
             input_mouse_click,
             input_scroll_delta,
@@ -827,12 +877,23 @@ impl Tui {
 
     /// Renders the last frame into the framebuffer and returns the VT output.
     pub fn render<'a>(&mut self, arena: &'a Arena) -> ArenaString<'a> {
+        self.render_framebuffer();
+        self.framebuffer.render(arena)
+    }
+
+    /// Renders the last frame into the framebuffer and returns an inspectable copy of it,
+    /// instead of VT output. This allows running the UI headless, for instance in tests.
+    pub fn render_snapshot(&mut self) -> Snapshot {
+        self.render_framebuffer();
+        self.framebuffer.snapshot()
+    }
+
+    fn render_framebuffer(&mut self) {
         self.framebuffer.flip(self.size);
         for child in self.prev_tree.iterate_roots() {
             let mut child = child.borrow_mut();
             self.render_node(&mut child);
         }
-        self.framebuffer.render(arena)
     }
 
     /// Recursively renders each node and its children.
@@ -943,6 +1004,10 @@ impl Tui {
             ),
             NodeContent::Textarea(tc) => {
                 let mut tb = tc.buffer.borrow_mut();
+                let detached = tc.detached_view();
+                if let Some(view) = detached {
+                    tb.view_swap(&mut view.borrow_mut());
+                }
                 let mut destination = Rect {
                     left: inner_clipped.left,
                     top: inner_clipped.top,
@@ -974,8 +1039,13 @@ impl Tui {
                         track,
                         tc.scroll_offset.y,
                         tb.visual_line_count() + inner.height() - 1,
+                        tb.search_match_lines(),
                     );
                 }
+
+                if let Some(view) = detached {
+                    tb.view_swap(&mut view.borrow_mut());
+                }
             }
             NodeContent::Scrollarea(sc) => {
                 let content = node.children.first.unwrap().borrow();
@@ -990,6 +1060,7 @@ impl Tui {
                     track,
                     sc.scroll_offset.y,
                     content.intrinsic_size.height,
+                    &[],
                 );
             }
             _ => {}
@@ -1294,6 +1365,8 @@ pub struct Context<'a, 'input> {
     input_text: Option<InputText<'input>>,
     /// Current keyboard input, if any.
     input_keyboard: Option<InputKey>,
+    /// The command that the keyboard input resolved to, if any.
+    input_command: Option<&'static str>,
     input_mouse_modifiers: InputKeyMod,
     input_mouse_click: CoordType,
     /// By how much the mouse wheel was scrolled since the last frame.
@@ -1365,10 +1438,37 @@ impl<'a> Context<'a, '_> {
         self.tui.clipboard_generation()
     }
 
+    /// Returns whether the clipboard contents are a rectangular selection.
+    /// See [`Tui::clipboard_is_block()`].
+    pub fn clipboard_is_block(&self) -> bool {
+        self.tui.clipboard_is_block()
+    }
+
     /// Sets the clipboard contents.
     pub fn set_clipboard(&mut self, data: Vec<u8>) {
+        self.set_clipboard_internal(data, false);
+    }
+
+    /// Copies the selection of `tb` into the clipboard and deletes it, if requested.
+    /// Unlike [`Context::set_clipboard()`] this remembers if it was a rectangular selection.
+    pub fn set_clipboard_from_selection(&mut self, tb: &mut TextBuffer, delete: bool) {
+        let block = tb.has_block_selection();
+        self.set_clipboard_internal(tb.extract_selection(delete), block);
+    }
+
+    /// Pastes the clipboard contents into `tb`, as a column if it's a rectangular selection.
+    pub fn paste_clipboard(&mut self, tb: &mut TextBuffer) {
+        if self.tui.clipboard_is_block {
+            tb.paste_block(&self.tui.clipboard);
+        } else {
+            tb.write(&self.tui.clipboard, true);
+        }
+    }
+
+    fn set_clipboard_internal(&mut self, data: Vec<u8>, block: bool) {
         if !data.is_empty() {
             self.tui.clipboard = data;
+            self.tui.clipboard_is_block = block;
             self.tui.clipboard_generation = self.tui.clipboard_generation.wrapping_add(1);
             self.needs_rerender();
         }
@@ -1665,6 +1765,58 @@ impl<'a> Context<'a, '_> {
         if self.input_consumed { None } else { self.input_keyboard }
     }
 
+    /// Returns the ID of the command the current keyboard input is bound to, if any.
+    /// Returns None if the input was already consumed.
+    pub fn command_input(&self) -> Option<&'static str> {
+        if self.input_consumed { None } else { self.input_command }
+    }
+
+    /// Checks if the current keyboard input is bound to the given command,
+    /// consumes it if it is and returns true in that case.
+    pub fn consume_command(&mut self, command: &str) -> bool {
+        if self.command_input() == Some(command) {
+            self.set_input_consumed();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the keymap that all keyboard input is resolved through.
+    pub fn keymap(&self) -> &Keymap {
+        &self.tui.keymap
+    }
+
+    /// Returns the keys of the chord that's being entered, if any.
+    pub fn pending_keys(&self) -> &[InputKey] {
+        self.tui.keymap.pending()
+    }
+
+    /// Formats a key sequence for display, e.g. "Ctrl+K Ctrl+C",
+    /// using the translations given to [`Tui::setup_modifier_translations()`].
+    pub fn keys_to_string(&self, keys: &[InputKey]) -> ArenaString<'a> {
+        let mut text = ArenaString::new_in(self.arena());
+        for (i, &key) in keys.iter().enumerate() {
+            if i > 0 {
+                text.push(' ');
+            }
+            if key.modifiers_contains(kbmod::CTRL) {
+                text.push_str(self.tui.modifier_translations.ctrl);
+                text.push('+');
+            }
+            if key.modifiers_contains(kbmod::ALT) {
+                text.push_str(self.tui.modifier_translations.alt);
+                text.push('+');
+            }
+            if key.modifiers_contains(kbmod::SHIFT) {
+                text.push_str(self.tui.modifier_translations.shift);
+                text.push('+');
+            }
+            text.push_str(keymap::key_name(key).unwrap_or("?"));
+        }
+        text
+    }
+
     #[inline]
     pub fn set_input_consumed(&mut self) {
         debug_assert!(!self.input_consumed);
@@ -1688,6 +1840,34 @@ impl<'a> Context<'a, '_> {
         self.tui.was_mouse_down_on_subtree(&last_node)
     }
 
+    /// Returns whether the given mouse button was pressed on the current node's subtree
+    /// and just got released. Unlike [`Context::button`], this works for nodes that can't
+    /// be focused, which makes it useful for things that shouldn't take the focus away.
+    pub fn was_clicked(&mut self, button: InputMouseState) -> bool {
+        let last_node = self.tree.last_node.borrow();
+        !self.input_consumed
+            && self.tui.mouse_state == InputMouseState::Release
+            && self.tui.mouse_released_button == button
+            && self.tui.was_mouse_down_on_subtree(&last_node)
+    }
+
+    /// If the left mouse button was pressed down on the current node and
+    /// is being dragged, returns how far it moved since then.
+    pub fn mouse_drag_delta(&mut self) -> Option<Point> {
+        let last_node = self.tree.last_node.borrow();
+        if self.tui.mouse_is_drag
+            && self.tui.mouse_state == InputMouseState::Left
+            && self.tui.was_mouse_down_on_node(last_node.id)
+        {
+            Some(Point {
+                x: self.tui.mouse_position.x - self.tui.mouse_down_position.x,
+                y: self.tui.mouse_position.y - self.tui.mouse_down_position.y,
+            })
+        } else {
+            None
+        }
+    }
+
     /// Returns whether the current node is focused.
     pub fn is_focused(&mut self) -> bool {
         let last_node = self.tree.last_node.borrow();
@@ -2047,7 +2227,19 @@ impl<'a> Context<'a, '_> {
 
     /// Creates a text area.
     pub fn textarea(&mut self, classname: &'static str, tb: RcTextBuffer) {
-        self.textarea_internal(classname, TextBufferPayload::Textarea(tb));
+        self.textarea_internal(classname, TextBufferPayload::Textarea(tb, None));
+    }
+
+    /// Creates a text area that shows `tb` through `view`, for showing a buffer more than once.
+    /// Unless the view is live, it's laid out and drawn with its own cursor, selection and width.
+    /// The view also remembers the scroll position, in case the text area gets recreated.
+    pub fn textarea_view(
+        &mut self,
+        classname: &'static str,
+        tb: RcTextBuffer,
+        view: RcTextBufferView,
+    ) {
+        self.textarea_internal(classname, TextBufferPayload::Textarea(tb, Some(view)));
     }
 
     fn textarea_internal(&mut self, classname: &'static str, payload: TextBufferPayload) -> bool {
@@ -2058,16 +2250,17 @@ impl<'a> Context<'a, '_> {
         let node = &mut *node;
         let single_line = match &payload {
             TextBufferPayload::Editline(_) => true,
-            TextBufferPayload::Textarea(_) => false,
+            TextBufferPayload::Textarea(..) => false,
         };
 
-        let buffer = {
+        let (buffer, view) = {
             let buffers = &mut self.tui.cached_text_buffers;
 
             let cached = match buffers.iter_mut().find(|t| t.node_id == node.id) {
                 Some(cached) => {
-                    if let TextBufferPayload::Textarea(tb) = &payload {
+                    if let TextBufferPayload::Textarea(tb, view) = &payload {
                         cached.editor = tb.clone();
+                        cached.view = view.clone();
                     };
                     cached.seen = true;
                     cached
@@ -2078,7 +2271,11 @@ impl<'a> Context<'a, '_> {
                         node_id: node.id,
                         editor: match &payload {
                             TextBufferPayload::Editline(_) => TextBuffer::new_rc(true).unwrap(),
-                            TextBufferPayload::Textarea(tb) => tb.clone(),
+                            TextBufferPayload::Textarea(tb, _) => tb.clone(),
+                        },
+                        view: match &payload {
+                            TextBufferPayload::Editline(_) => None,
+                            TextBufferPayload::Textarea(_, view) => view.clone(),
                         },
                         seen: true,
                     });
@@ -2089,11 +2286,12 @@ impl<'a> Context<'a, '_> {
             // SAFETY: *Assuming* that there are no duplicate node IDs in the tree that
             // would cause this cache slot to be overwritten, then this operation is safe.
             // The text buffer cache will keep the buffer alive for us long enough.
-            unsafe { mem::transmute(&*cached.editor) }
+            unsafe { (mem::transmute(&*cached.editor), mem::transmute(cached.view.as_deref())) }
         };
 
         node.content = NodeContent::Textarea(TextareaContent {
             buffer,
+            view,
             scroll_offset: Default::default(),
             scroll_offset_y_drag_start: CoordType::MIN,
             scroll_offset_x_max: 0,
@@ -2127,8 +2325,11 @@ impl<'a> Context<'a, '_> {
                     text_width -= 1;
                 }
 
-                let mut make_cursor_visible;
-                {
+                let mut make_cursor_visible = false;
+                if let Some(view) = content.detached_view() {
+                    // The buffer's width belongs to the live view. This one gets swapped in below.
+                    view.borrow_mut().set_width(text_width);
+                } else {
                     let mut tb = content.buffer.borrow_mut();
                     make_cursor_visible = tb.take_cursor_visibility_request();
                     make_cursor_visible |= tb.set_width(text_width);
@@ -2142,6 +2343,9 @@ impl<'a> Context<'a, '_> {
             } else {
                 debug_assert!(false);
             }
+        } else if let Some(view) = content.view {
+            // A new text area for an existing view continues where the old one was.
+            content.scroll_offset = view.borrow().scroll_offset;
         }
 
         let dirty;
@@ -2153,7 +2357,19 @@ impl<'a> Context<'a, '_> {
             }
         }
 
+        // A view that isn't live is scrolled within its own layout.
+        let detached = content.detached_view();
+        if let Some(view) = detached {
+            content.buffer.borrow_mut().view_swap(&mut view.borrow_mut());
+        }
         self.textarea_adjust_scroll_offset(content);
+        let visual_line_count = content.buffer.borrow().visual_line_count();
+        if let Some(view) = detached {
+            content.buffer.borrow_mut().view_swap(&mut view.borrow_mut());
+        }
+        if let Some(view) = content.view {
+            view.borrow_mut().scroll_offset = content.scroll_offset;
+        }
 
         if single_line {
             node.attributes.fg = self.indexed(IndexedColor::Foreground);
@@ -2165,7 +2381,7 @@ impl<'a> Context<'a, '_> {
         }
 
         node.attributes.focusable = true;
-        node.intrinsic_size.height = content.buffer.borrow().visual_line_count();
+        node.intrinsic_size.height = visual_line_count;
         node.intrinsic_size_set = true;
 
         dirty
@@ -2216,7 +2432,11 @@ impl<'a> Context<'a, '_> {
 
                 if text_rect.contains(self.tui.mouse_down_position) {
                     if self.tui.mouse_is_drag {
-                        tb.selection_update_visual(pos);
+                        if self.input_mouse_modifiers.contains(kbmod::ALT) {
+                            tb.block_selection_update_visual(pos);
+                        } else {
+                            tb.selection_update_visual(pos);
+                        }
                         tc.preferred_column = tb.cursor_visual_pos().x;
 
                         let height = inner.height();
@@ -2315,40 +2535,33 @@ impl<'a> Context<'a, '_> {
             write_raw = input.bracketed;
             tc.preferred_column = tb.cursor_visual_pos().x;
             make_cursor_visible = true;
-        } else if let Some(input) = &self.input_keyboard {
-            let key = input.key();
-            let modifiers = input.modifiers();
-
+        } else if let Some(command) = self.input_command {
             make_cursor_visible = true;
 
-            match key {
-                vk::BACK => {
-                    let granularity = if modifiers == kbmod::CTRL {
-                        CursorMovement::Word
-                    } else {
-                        CursorMovement::Grapheme
-                    };
-                    tb.delete(granularity, -1);
-                }
-                vk::TAB => {
+            match command {
+                cmd::EDIT_DELETE_LEFT => tb.delete(CursorMovement::Grapheme, -1),
+                cmd::EDIT_DELETE_WORD_LEFT => tb.delete(CursorMovement::Word, -1),
+                cmd::EDIT_DELETE_RIGHT => tb.delete(CursorMovement::Grapheme, 1),
+                cmd::EDIT_DELETE_WORD_RIGHT => tb.delete(CursorMovement::Word, 1),
+                cmd::EDIT_INDENT | cmd::EDIT_UNINDENT => {
                     if single_line {
                         // If this is just a simple input field, don't consume Tab (= early return).
                         return false;
                     }
-                    if modifiers == kbmod::SHIFT {
+                    if command == cmd::EDIT_UNINDENT {
                         tb.unindent();
                     } else {
                         write = b"\t";
                     }
                 }
-                vk::RETURN => {
+                cmd::EDIT_NEWLINE => {
                     if single_line {
                         // If this is just a simple input field, don't consume Enter (= early return).
                         return false;
                     }
                     write = b"\n";
                 }
-                vk::ESCAPE => {
+                cmd::SELECT_CLEAR => {
                     // If there was a selection, clear it and show the cursor (= fallthrough).
                     if !tb.clear_selection() {
                         if single_line {
@@ -2362,7 +2575,7 @@ impl<'a> Context<'a, '_> {
                         make_cursor_visible = false;
                     }
                 }
-                vk::PRIOR => {
+                cmd::CURSOR_PAGE_UP | cmd::SELECT_PAGE_UP => {
                     let height = node_prev.inner.height() - 1;
 
                     // If the cursor was already on the first line,
@@ -2371,19 +2584,15 @@ impl<'a> Context<'a, '_> {
                         tc.preferred_column = 0;
                     }
 
-                    if modifiers == kbmod::SHIFT {
-                        tb.selection_update_visual(Point {
-                            x: tc.preferred_column,
-                            y: tb.cursor_visual_pos().y - height,
-                        });
+                    let destination =
+                        Point { x: tc.preferred_column, y: tb.cursor_visual_pos().y - height };
+                    if command == cmd::SELECT_PAGE_UP {
+                        tb.selection_update_visual(destination);
                     } else {
-                        tb.cursor_move_to_visual(Point {
-                            x: tc.preferred_column,
-                            y: tb.cursor_visual_pos().y - height,
-                        });
+                        tb.cursor_move_to_visual(destination);
                     }
                 }
-                vk::NEXT => {
+                cmd::CURSOR_PAGE_DOWN | cmd::SELECT_PAGE_DOWN => {
                     let height = node_prev.inner.height() - 1;
 
                     // If the cursor was already on the last line,
@@ -2392,299 +2601,296 @@ impl<'a> Context<'a, '_> {
                         tc.preferred_column = CoordType::MAX;
                     }
 
-                    if modifiers == kbmod::SHIFT {
-                        tb.selection_update_visual(Point {
-                            x: tc.preferred_column,
-                            y: tb.cursor_visual_pos().y + height,
-                        });
+                    let destination =
+                        Point { x: tc.preferred_column, y: tb.cursor_visual_pos().y + height };
+                    if command == cmd::SELECT_PAGE_DOWN {
+                        tb.selection_update_visual(destination);
                     } else {
-                        tb.cursor_move_to_visual(Point {
-                            x: tc.preferred_column,
-                            y: tb.cursor_visual_pos().y + height,
-                        });
+                        tb.cursor_move_to_visual(destination);
                     }
 
                     if tc.preferred_column == CoordType::MAX {
                         tc.preferred_column = tb.cursor_visual_pos().x;
                     }
                 }
-                vk::END => {
-                    let logical_before = tb.cursor_logical_pos();
-                    let destination = if modifiers.contains(kbmod::CTRL) {
-                        Point::MAX
-                    } else {
-                        Point { x: CoordType::MAX, y: tb.cursor_visual_pos().y }
-                    };
+                cmd::CURSOR_END
+                | cmd::SELECT_END
+                | cmd::CURSOR_DOCUMENT_END
+                | cmd::SELECT_DOCUMENT_END => {
+                    let select = matches!(command, cmd::SELECT_END | cmd::SELECT_DOCUMENT_END);
+                    let document =
+                        matches!(command, cmd::CURSOR_DOCUMENT_END | cmd::SELECT_DOCUMENT_END);
+
+                    tb.for_each_cursor(|tb| {
+                        let logical_before = tb.cursor_logical_pos();
+                        let destination = if document {
+                            Point::MAX
+                        } else {
+                            Point { x: CoordType::MAX, y: tb.cursor_visual_pos().y }
+                        };
 
-                    if modifiers.contains(kbmod::SHIFT) {
-                        tb.selection_update_visual(destination);
-                    } else {
-                        tb.cursor_move_to_visual(destination);
-                    }
+                        if select {
+                            tb.selection_update_visual(destination);
+                        } else {
+                            tb.cursor_move_to_visual(destination);
+                        }
 
-                    if !modifiers.contains(kbmod::CTRL) {
-                        let logical_after = tb.cursor_logical_pos();
-
-                        // If word-wrap is enabled and the user presses End the first time,
-                        // it moves to the start of the visual line. The second time they
-                        // press it, it moves to the start of the logical line.
-                        if tb.is_word_wrap_enabled() && logical_after == logical_before {
-                            if modifiers == kbmod::SHIFT {
-                                tb.selection_update_logical(Point {
-                                    x: CoordType::MAX,
-                                    y: tb.cursor_logical_pos().y,
-                                });
-                            } else {
-                                tb.cursor_move_to_logical(Point {
-                                    x: CoordType::MAX,
-                                    y: tb.cursor_logical_pos().y,
-                                });
+                        if !document {
+                            let logical_after = tb.cursor_logical_pos();
+
+                            // If word-wrap is enabled and the user presses End the first time,
+                            // it moves to the start of the visual line. The second time they
+                            // press it, it moves to the start of the logical line.
+                            if tb.is_word_wrap_enabled() && logical_after == logical_before {
+                                let destination =
+                                    Point { x: CoordType::MAX, y: tb.cursor_logical_pos().y };
+                                if select {
+                                    tb.selection_update_logical(destination);
+                                } else {
+                                    tb.cursor_move_to_logical(destination);
+                                }
                             }
                         }
-                    }
+                    });
                 }
-                vk::HOME => {
-                    let logical_before = tb.cursor_logical_pos();
-                    let destination = if modifiers.contains(kbmod::CTRL) {
-                        Default::default()
-                    } else {
-                        Point { x: 0, y: tb.cursor_visual_pos().y }
-                    };
+                cmd::CURSOR_HOME
+                | cmd::SELECT_HOME
+                | cmd::CURSOR_DOCUMENT_START
+                | cmd::SELECT_DOCUMENT_START => {
+                    let select = matches!(command, cmd::SELECT_HOME | cmd::SELECT_DOCUMENT_START);
+                    let document =
+                        matches!(command, cmd::CURSOR_DOCUMENT_START | cmd::SELECT_DOCUMENT_START);
+
+                    tb.for_each_cursor(|tb| {
+                        let logical_before = tb.cursor_logical_pos();
+                        let destination = if document {
+                            Default::default()
+                        } else {
+                            Point { x: 0, y: tb.cursor_visual_pos().y }
+                        };
 
-                    if modifiers.contains(kbmod::SHIFT) {
-                        tb.selection_update_visual(destination);
-                    } else {
-                        tb.cursor_move_to_visual(destination);
-                    }
+                        if select {
+                            tb.selection_update_visual(destination);
+                        } else {
+                            tb.cursor_move_to_visual(destination);
+                        }
 
-                    if !modifiers.contains(kbmod::CTRL) {
-                        let mut logical_after = tb.cursor_logical_pos();
-
-                        // If word-wrap is enabled and the user presses Home the first time,
-                        // it moves to the start of the visual line. The second time they
-                        // press it, it moves to the start of the logical line.
-                        if tb.is_word_wrap_enabled() && logical_after == logical_before {
-                            if modifiers == kbmod::SHIFT {
-                                tb.selection_update_logical(Point {
-                                    x: 0,
-                                    y: tb.cursor_logical_pos().y,
-                                });
-                            } else {
-                                tb.cursor_move_to_logical(Point {
-                                    x: 0,
-                                    y: tb.cursor_logical_pos().y,
-                                });
+                        if !document {
+                            let mut logical_after = tb.cursor_logical_pos();
+
+                            // If word-wrap is enabled and the user presses Home the first time,
+                            // it moves to the start of the visual line. The second time they
+                            // press it, it moves to the start of the logical line.
+                            if tb.is_word_wrap_enabled() && logical_after == logical_before {
+                                let destination = Point { x: 0, y: tb.cursor_logical_pos().y };
+                                if select {
+                                    tb.selection_update_logical(destination);
+                                } else {
+                                    tb.cursor_move_to_logical(destination);
+                                }
+                                logical_after = tb.cursor_logical_pos();
                             }
-                            logical_after = tb.cursor_logical_pos();
-                        }
 
-                        // If the line has some indentation and the user pressed Home,
-                        // the first time it'll stop at the indentation. The second time
-                        // they press it, it'll move to the true start of the line.
-                        //
-                        // If the cursor is already at the start of the line,
-                        // we move it back to the end of the indentation.
-                        if logical_after.x == 0
-                            && let indent_end = tb.indent_end_logical_pos()
-                            && (logical_before > indent_end || logical_before.x == 0)
-                        {
-                            if modifiers == kbmod::SHIFT {
-                                tb.selection_update_logical(indent_end);
-                            } else {
-                                tb.cursor_move_to_logical(indent_end);
+                            // If the line has some indentation and the user pressed Home,
+                            // the first time it'll stop at the indentation. The second time
+                            // they press it, it'll move to the true start of the line.
+                            //
+                            // If the cursor is already at the start of the line,
+                            // we move it back to the end of the indentation.
+                            if logical_after.x == 0
+                                && let indent_end = tb.indent_end_logical_pos()
+                                && (logical_before > indent_end || logical_before.x == 0)
+                            {
+                                if select {
+                                    tb.selection_update_logical(indent_end);
+                                } else {
+                                    tb.cursor_move_to_logical(indent_end);
+                                }
                             }
                         }
-                    }
-                }
-                vk::LEFT => {
-                    let granularity = if modifiers.contains(KBMOD_FOR_WORD_NAV) {
-                        CursorMovement::Word
-                    } else {
-                        CursorMovement::Grapheme
-                    };
-                    if modifiers.contains(kbmod::SHIFT) {
-                        tb.selection_update_delta(granularity, -1);
-                    } else if let Some((beg, _)) = tb.selection_range() {
-                        unsafe { tb.set_cursor(beg) };
-                    } else {
-                        tb.cursor_move_delta(granularity, -1);
-                    }
+                    });
                 }
-                vk::UP => {
-                    match modifiers {
-                        kbmod::NONE => {
-                            let mut x = tc.preferred_column;
-                            let mut y = tb.cursor_visual_pos().y - 1;
-
-                            // If there's a selection we put the cursor above it.
-                            if let Some((beg, _)) = tb.selection_range() {
-                                x = beg.visual_pos.x;
-                                y = beg.visual_pos.y - 1;
-                                tc.preferred_column = x;
-                            }
-
-                            // If the cursor was already on the first line,
-                            // move it to the start of the buffer.
-                            if y < 0 {
-                                x = 0;
-                                tc.preferred_column = 0;
-                            }
+                cmd::SELECT_BLOCK_LEFT => tb.block_selection_update_delta(Point { x: -1, y: 0 }),
+                cmd::SELECT_BLOCK_RIGHT => tb.block_selection_update_delta(Point { x: 1, y: 0 }),
+                cmd::SELECT_BLOCK_UP => tb.block_selection_update_delta(Point { x: 0, y: -1 }),
+                cmd::SELECT_BLOCK_DOWN => tb.block_selection_update_delta(Point { x: 0, y: 1 }),
+                cmd::CURSOR_LEFT
+                | cmd::CURSOR_WORD_LEFT
+                | cmd::SELECT_LEFT
+                | cmd::SELECT_WORD_LEFT => {
+                    let select = matches!(command, cmd::SELECT_LEFT | cmd::SELECT_WORD_LEFT);
+                    let granularity =
+                        if matches!(command, cmd::CURSOR_WORD_LEFT | cmd::SELECT_WORD_LEFT) {
+                            CursorMovement::Word
+                        } else {
+                            CursorMovement::Grapheme
+                        };
 
-                            tb.cursor_move_to_visual(Point { x, y });
-                        }
-                        kbmod::CTRL => {
-                            tc.scroll_offset.y -= 1;
-                            make_cursor_visible = false;
+                    tb.for_each_cursor(|tb| {
+                        if select {
+                            tb.selection_update_delta(granularity, -1);
+                        } else if let Some((beg, _)) = tb.selection_range() {
+                            unsafe { tb.set_cursor(beg) };
+                        } else {
+                            tb.cursor_move_delta(granularity, -1);
                         }
-                        kbmod::SHIFT => {
-                            // If the cursor was already on the first line,
-                            // move it to the start of the buffer.
-                            if tb.cursor_visual_pos().y == 0 {
-                                tc.preferred_column = 0;
-                            }
+                    });
+                }
+                cmd::CURSOR_RIGHT
+                | cmd::CURSOR_WORD_RIGHT
+                | cmd::SELECT_RIGHT
+                | cmd::SELECT_WORD_RIGHT => {
+                    let select = matches!(command, cmd::SELECT_RIGHT | cmd::SELECT_WORD_RIGHT);
+                    let granularity =
+                        if matches!(command, cmd::CURSOR_WORD_RIGHT | cmd::SELECT_WORD_RIGHT) {
+                            CursorMovement::Word
+                        } else {
+                            CursorMovement::Grapheme
+                        };
 
-                            tb.selection_update_visual(Point {
-                                x: tc.preferred_column,
-                                y: tb.cursor_visual_pos().y - 1,
-                            });
-                        }
-                        kbmod::CTRL_ALT => {
-                            // TODO: Add cursor above
+                    tb.for_each_cursor(|tb| {
+                        if select {
+                            tb.selection_update_delta(granularity, 1);
+                        } else if let Some((_, end)) = tb.selection_range() {
+                            unsafe { tb.set_cursor(end) };
+                        } else {
+                            tb.cursor_move_delta(granularity, 1);
                         }
-                        _ => return false,
-                    }
+                    });
                 }
-                vk::RIGHT => {
-                    let granularity = if modifiers.contains(KBMOD_FOR_WORD_NAV) {
-                        CursorMovement::Word
-                    } else {
-                        CursorMovement::Grapheme
+                // With multiple cursors, each one keeps its own column.
+                cmd::CURSOR_UP if tb.cursor_count() > 1 => tb.for_each_cursor(|tb| {
+                    let pos = match tb.selection_range() {
+                        Some((beg, _)) => beg.visual_pos,
+                        None => tb.cursor_visual_pos(),
                     };
-                    if modifiers.contains(kbmod::SHIFT) {
-                        tb.selection_update_delta(granularity, 1);
-                    } else if let Some((_, end)) = tb.selection_range() {
-                        unsafe { tb.set_cursor(end) };
-                    } else {
-                        tb.cursor_move_delta(granularity, 1);
+                    tb.cursor_move_to_visual(Point { x: pos.x, y: pos.y - 1 });
+                }),
+                cmd::CURSOR_UP => {
+                    let mut x = tc.preferred_column;
+                    let mut y = tb.cursor_visual_pos().y - 1;
+
+                    // If there's a selection we put the cursor above it.
+                    if let Some((beg, _)) = tb.selection_range() {
+                        x = beg.visual_pos.x;
+                        y = beg.visual_pos.y - 1;
+                        tc.preferred_column = x;
                     }
-                }
-                vk::DOWN => match modifiers {
-                    kbmod::NONE => {
-                        let mut x = tc.preferred_column;
-                        let mut y = tb.cursor_visual_pos().y + 1;
-
-                        // If there's a selection we put the cursor below it.
-                        if let Some((_, end)) = tb.selection_range() {
-                            x = end.visual_pos.x;
-                            y = end.visual_pos.y + 1;
-                            tc.preferred_column = x;
-                        }
 
-                        // If the cursor was already on the last line,
-                        // move it to the end of the buffer.
-                        if y >= tb.visual_line_count() {
-                            x = CoordType::MAX;
-                        }
+                    // If the cursor was already on the first line,
+                    // move it to the start of the buffer.
+                    if y < 0 {
+                        x = 0;
+                        tc.preferred_column = 0;
+                    }
 
-                        tb.cursor_move_to_visual(Point { x, y });
+                    tb.cursor_move_to_visual(Point { x, y });
+                }
+                cmd::SELECT_UP => {
+                    // If the cursor was already on the first line,
+                    // move it to the start of the buffer.
+                    if tb.cursor_visual_pos().y == 0 {
+                        tc.preferred_column = 0;
+                    }
 
-                        // If we fell into the `if y >= tb.get_visual_line_count()` above, we wanted to
-                        // update the `preferred_column` but didn't know yet what it was. Now we know!
-                        if x == CoordType::MAX {
-                            tc.preferred_column = tb.cursor_visual_pos().x;
-                        }
+                    tb.selection_update_visual(Point {
+                        x: tc.preferred_column,
+                        y: tb.cursor_visual_pos().y - 1,
+                    });
+                }
+                cmd::CURSOR_DOWN if tb.cursor_count() > 1 => tb.for_each_cursor(|tb| {
+                    let pos = match tb.selection_range() {
+                        Some((_, end)) => end.visual_pos,
+                        None => tb.cursor_visual_pos(),
+                    };
+                    tb.cursor_move_to_visual(Point { x: pos.x, y: pos.y + 1 });
+                }),
+                cmd::CURSOR_DOWN => {
+                    let mut x = tc.preferred_column;
+                    let mut y = tb.cursor_visual_pos().y + 1;
+
+                    // If there's a selection we put the cursor below it.
+                    if let Some((_, end)) = tb.selection_range() {
+                        x = end.visual_pos.x;
+                        y = end.visual_pos.y + 1;
+                        tc.preferred_column = x;
                     }
-                    kbmod::CTRL => {
-                        tc.scroll_offset.y += 1;
-                        make_cursor_visible = false;
+
+                    // If the cursor was already on the last line,
+                    // move it to the end of the buffer.
+                    if y >= tb.visual_line_count() {
+                        x = CoordType::MAX;
                     }
-                    kbmod::SHIFT => {
-                        // If the cursor was already on the last line,
-                        // move it to the end of the buffer.
-                        if tb.cursor_visual_pos().y >= tb.visual_line_count() - 1 {
-                            tc.preferred_column = CoordType::MAX;
-                        }
 
-                        tb.selection_update_visual(Point {
-                            x: tc.preferred_column,
-                            y: tb.cursor_visual_pos().y + 1,
-                        });
+                    tb.cursor_move_to_visual(Point { x, y });
 
-                        if tc.preferred_column == CoordType::MAX {
-                            tc.preferred_column = tb.cursor_visual_pos().x;
-                        }
-                    }
-                    kbmod::CTRL_ALT => {
-                        // TODO: Add cursor above
-                    }
-                    _ => return false,
-                },
-                vk::INSERT => match modifiers {
-                    kbmod::SHIFT => {
-                        write = &self.tui.clipboard;
-                        write_raw = true;
-                    }
-                    kbmod::CTRL => self.set_clipboard(tb.extract_selection(false)),
-                    _ => tb.set_overtype(!tb.is_overtype()),
-                },
-                vk::DELETE => match modifiers {
-                    kbmod::SHIFT => self.set_clipboard(tb.extract_selection(true)),
-                    kbmod::CTRL => tb.delete(CursorMovement::Word, 1),
-                    _ => tb.delete(CursorMovement::Grapheme, 1),
-                },
-                vk::A => match modifiers {
-                    kbmod::CTRL => tb.select_all(),
-                    _ => return false,
-                },
-                vk::B => match modifiers {
-                    kbmod::ALT if cfg!(target_os = "macos") => {
-                        // On macOS, terminals commonly emit the Emacs style
-                        // Alt+B (ESC b) sequence for Alt+Left.
-                        tb.cursor_move_delta(CursorMovement::Word, -1);
+                    // If we fell into the `if y >= tb.get_visual_line_count()` above, we wanted to
+                    // update the `preferred_column` but didn't know yet what it was. Now we know!
+                    if x == CoordType::MAX {
+                        tc.preferred_column = tb.cursor_visual_pos().x;
                     }
-                    _ => return false,
-                },
-                vk::F => match modifiers {
-                    kbmod::ALT if cfg!(target_os = "macos") => {
-                        // On macOS, terminals commonly emit the Emacs style
-                        // Alt+F (ESC f) sequence for Alt+Right.
-                        tb.cursor_move_delta(CursorMovement::Word, 1);
+                }
+                cmd::SELECT_DOWN => {
+                    // If the cursor was already on the last line,
+                    // move it to the end of the buffer.
+                    if tb.cursor_visual_pos().y >= tb.visual_line_count() - 1 {
+                        tc.preferred_column = CoordType::MAX;
                     }
-                    _ => return false,
-                },
-                vk::H => match modifiers {
-                    kbmod::CTRL => tb.delete(CursorMovement::Word, -1),
-                    _ => return false,
-                },
-                vk::X => match modifiers {
-                    kbmod::CTRL => self.set_clipboard(tb.extract_selection(true)),
-                    _ => return false,
-                },
-                vk::C => match modifiers {
-                    kbmod::CTRL => self.set_clipboard(tb.extract_selection(false)),
-                    _ => return false,
-                },
-                vk::V => match modifiers {
-                    kbmod::CTRL => {
-                        write = &self.tui.clipboard;
-                        write_raw = true;
+
+                    tb.selection_update_visual(Point {
+                        x: tc.preferred_column,
+                        y: tb.cursor_visual_pos().y + 1,
+                    });
+
+                    if tc.preferred_column == CoordType::MAX {
+                        tc.preferred_column = tb.cursor_visual_pos().x;
                     }
-                    _ => return false,
-                },
-                vk::Y => match modifiers {
-                    kbmod::CTRL => tb.redo(),
-                    _ => return false,
-                },
-                vk::Z => match modifiers {
-                    kbmod::CTRL => tb.undo(),
-                    kbmod::CTRL_SHIFT => tb.redo(),
-                    kbmod::ALT => tb.set_word_wrap(!tb.is_word_wrap_enabled()),
-                    _ => return false,
-                },
+                }
+                cmd::VIEW_SCROLL_UP => {
+                    tc.scroll_offset.y -= 1;
+                    make_cursor_visible = false;
+                }
+                cmd::VIEW_SCROLL_DOWN => {
+                    tc.scroll_offset.y += 1;
+                    make_cursor_visible = false;
+                }
+                cmd::CURSOR_ADD_ABOVE => tb.add_cursor_vertical(-1, tc.preferred_column),
+                cmd::CURSOR_ADD_BELOW => tb.add_cursor_vertical(1, tc.preferred_column),
+                cmd::CURSOR_ADD_NEXT_MATCH => _ = tb.add_cursor_at_next_occurrence(),
+                cmd::EDIT_TOGGLE_OVERTYPE => tb.set_overtype(!tb.is_overtype()),
+                cmd::SELECT_ALL => tb.select_all(),
+                cmd::EDIT_CUT => self.set_clipboard_from_selection(tb, true),
+                cmd::EDIT_COPY => self.set_clipboard_from_selection(tb, false),
+                cmd::EDIT_PASTE if self.tui.clipboard_is_block && !single_line => {
+                    tb.paste_block(&self.tui.clipboard)
+                }
+                cmd::EDIT_PASTE => {
+                    write = &self.tui.clipboard;
+                    write_raw = true;
+                }
+                cmd::EDIT_UNDO => tb.undo(),
+                cmd::EDIT_REDO => tb.redo(),
+                cmd::VIEW_WORD_WRAP => tb.set_word_wrap(!tb.is_word_wrap_enabled()),
                 _ => return false,
             }
 
-            change_preferred_column = !matches!(key, vk::PRIOR | vk::NEXT | vk::UP | vk::DOWN);
+            change_preferred_column = !matches!(
+                command,
+                cmd::CURSOR_PAGE_UP
+                    | cmd::CURSOR_PAGE_DOWN
+                    | cmd::CURSOR_UP
+                    | cmd::CURSOR_DOWN
+                    | cmd::SELECT_PAGE_UP
+                    | cmd::SELECT_PAGE_DOWN
+                    | cmd::SELECT_UP
+                    | cmd::SELECT_DOWN
+                    | cmd::SELECT_BLOCK_UP
+                    | cmd::SELECT_BLOCK_DOWN
+                    | cmd::CURSOR_ADD_ABOVE
+                    | cmd::CURSOR_ADD_BELOW
+                    | cmd::VIEW_SCROLL_UP
+                    | cmd::VIEW_SCROLL_DOWN
+            );
         } else {
             return false;
         }
@@ -3136,13 +3342,9 @@ impl<'a> Context<'a, '_> {
     }
 
     /// Appends a button to the current menu.
-    pub fn menubar_menu_button(
-        &mut self,
-        text: &str,
-        accelerator: char,
-        shortcut: InputKey,
-    ) -> bool {
-        self.menubar_menu_checkbox(text, accelerator, shortcut, false)
+    /// The keys bound to `command` are shown next to it.
+    pub fn menubar_menu_button(&mut self, text: &str, accelerator: char, command: &str) -> bool {
+        self.menubar_menu_checkbox(text, accelerator, command, false)
     }
 
     /// Appends a checkbox to the current menu.
@@ -3151,7 +3353,7 @@ impl<'a> Context<'a, '_> {
         &mut self,
         text: &str,
         accelerator: char,
-        shortcut: InputKey,
+        command: &str,
         checked: bool,
     ) -> bool {
         self.table_next_row();
@@ -3175,7 +3377,7 @@ impl<'a> Context<'a, '_> {
             text,
             ButtonStyle::default().bracketed(false).checked(checked).accelerator(accelerator),
         );
-        self.menubar_shortcut(shortcut);
+        self.menubar_shortcut(command);
 
         if clicked {
             // TODO: This should reassign the previous focused path.
@@ -3279,24 +3481,9 @@ impl<'a> Context<'a, '_> {
         self.styled_label_end();
     }
 
-    fn menubar_shortcut(&mut self, shortcut: InputKey) {
-        let shortcut_letter = shortcut.value() as u8 as char;
-        if shortcut_letter.is_ascii_uppercase() {
-            let mut shortcut_text = ArenaString::new_in(self.arena());
-            if shortcut.modifiers_contains(kbmod::CTRL) {
-                shortcut_text.push_str(self.tui.modifier_translations.ctrl);
-                shortcut_text.push('+');
-            }
-            if shortcut.modifiers_contains(kbmod::ALT) {
-                shortcut_text.push_str(self.tui.modifier_translations.alt);
-                shortcut_text.push('+');
-            }
-            if shortcut.modifiers_contains(kbmod::SHIFT) {
-                shortcut_text.push_str(self.tui.modifier_translations.shift);
-                shortcut_text.push('+');
-            }
-            shortcut_text.push(shortcut_letter);
-
+    fn menubar_shortcut(&mut self, command: &str) {
+        if let Some(keys) = self.tui.keymap.keys_for(command) {
+            let shortcut_text = self.keys_to_string(keys);
             self.label("shortcut", &shortcut_text);
         } else {
             self.block_begin("shortcut");
@@ -3626,6 +3813,7 @@ struct TextContent<'a> {
 /// NOTE: Must not contain items that require drop().
 struct TextareaContent<'a> {
     buffer: &'a TextBufferCell,
+    view: Option<&'a TextBufferViewCell>,
 
     // Carries over between frames.
     scroll_offset: Point,
@@ -3638,6 +3826,13 @@ struct TextareaContent<'a> {
     has_focus: bool,
 }
 
+impl<'a> TextareaContent<'a> {
+    /// Returns the view, unless it's the live one (or there's none).
+    fn detached_view(&self) -> Option<&'a TextBufferViewCell> {
+        self.view.filter(|view| !view.borrow().live)
+    }
+}
+
 /// NOTE: Must not contain items that require drop().
 #[derive(Clone)]
 struct ScrollareaContent {
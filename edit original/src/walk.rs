@@ -0,0 +1,324 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Walks a directory tree the way `git` sees it: Hidden files and directories
+//! (those whose name starts with a `.`) and anything matched by a `.gitignore` are skipped.
+
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The patterns of a single `.gitignore` file.
+#[derive(Default)]
+pub struct Gitignore {
+    rules: Vec<Rule>,
+}
+
+struct Rule {
+    glob: Vec<u8>,
+    /// `!pattern`: Re-includes what a previous rule excluded.
+    negated: bool,
+    /// `pattern/`: Only matches directories.
+    dir_only: bool,
+    /// Patterns with a slash at the start or in the middle are relative to the `.gitignore`.
+    /// Others match the name of a file or directory at any depth.
+    anchored: bool,
+}
+
+impl Gitignore {
+    pub fn parse(text: &[u8]) -> Self {
+        let mut rules = Vec::new();
+
+        for line in text.split(|&c| c == b'\n') {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            let mut line = line.trim_ascii_end();
+            if line.is_empty() || line.starts_with(b"#") {
+                continue;
+            }
+
+            // `\!` and `\#` escape a leading `!` or `#`.
+            let negated = line.starts_with(b"!");
+            if negated || line.starts_with(b"\\!") || line.starts_with(b"\\#") {
+                line = &line[1..];
+            }
+
+            let dir_only = line.ends_with(b"/");
+            if dir_only {
+                line = &line[..line.len() - 1];
+            }
+            let anchored = line.contains(&b'/');
+            let line = line.strip_prefix(b"/").unwrap_or(line);
+            if line.is_empty() {
+                continue;
+            }
+
+            rules.push(Rule { glob: line.to_vec(), negated, dir_only, anchored });
+        }
+
+        Self { rules }
+    }
+
+    /// Checks the given `/`-separated path, relative to the directory of the `.gitignore`.
+    /// Returns `Some(true)` if it's ignored, `Some(false)` if a negated pattern re-included it,
+    /// and `None` if no pattern matched.
+    pub fn matched(&self, path: &str, is_dir: bool) -> Option<bool> {
+        let path = path.as_bytes();
+        let name = path.rsplit(|&c| c == b'/').next().unwrap_or(path);
+
+        // The last matching pattern wins.
+        self.rules.iter().rev().find_map(|rule| {
+            if rule.dir_only && !is_dir {
+                return None;
+            }
+            let text = if rule.anchored { path } else { name };
+            glob_match(&rule.glob, text).then_some(!rule.negated)
+        })
+    }
+}
+
+/// Matches `text` against a glob `pattern` with `*`, `?`, `[...]` and `**`.
+/// Except for `**`, wildcards don't match `/`.
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', b'/', rest @ ..] => {
+            // Zero or more directories.
+            glob_match(rest, text)
+                || text
+                    .iter()
+                    .enumerate()
+                    .any(|(i, &c)| c == b'/' && glob_match(rest, &text[i + 1..]))
+        }
+        [b'*', b'*', rest @ ..] => (0..=text.len()).any(|i| glob_match(rest, &text[i..])),
+        [b'*', rest @ ..] => {
+            let end = text.iter().position(|&c| c == b'/').unwrap_or(text.len());
+            (0..=end).any(|i| glob_match(rest, &text[i..]))
+        }
+        [b'?', rest @ ..] => match text.first() {
+            Some(&c) if c != b'/' => {
+                // Skip an entire UTF-8 sequence.
+                let len = 1 + text[1..].iter().take_while(|&&c| c & 0xC0 == 0x80).count();
+                glob_match(rest, &text[len..])
+            }
+            _ => false,
+        },
+        [b'[', class @ ..] => match match_class(class, text.first()) {
+            Some((matched, rest)) => matched && glob_match(rest, &text[1..]),
+            // An unclosed class is a literal `[`.
+            None => text.first() == Some(&b'[') && glob_match(class, &text[1..]),
+        },
+        [b'\\', c, rest @ ..] | [c, rest @ ..] => {
+            text.first() == Some(c) && glob_match(rest, &text[1..])
+        }
+    }
+}
+
+/// Matches `c` against the class at the start of `pattern` (just after the `[`).
+/// Returns whether it matched and the rest of the pattern, or `None` if the class isn't closed.
+fn match_class<'a>(pattern: &'a [u8], c: Option<&u8>) -> Option<(bool, &'a [u8])> {
+    let (negated, pattern) = match pattern {
+        [b'!' | b'^', rest @ ..] => (true, rest),
+        _ => (false, pattern),
+    };
+    // A `]` right at the start is a literal one.
+    let end = 1 + pattern.get(1..)?.iter().position(|&c| c == b']')?;
+    let (class, rest) = (&pattern[..end], &pattern[end + 1..]);
+
+    let Some(&c) = c.filter(|&&c| c != b'/') else {
+        return Some((false, rest));
+    };
+    let mut matched = false;
+    let mut i = 0;
+    while i < class.len() {
+        if i + 2 < class.len() && class[i + 1] == b'-' {
+            matched |= (class[i]..=class[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            matched |= class[i] == c;
+            i += 1;
+        }
+    }
+
+    Some((matched != negated, rest))
+}
+
+/// Iterates over all files in a directory tree, in order of their paths.
+/// Directories that can't be read are skipped.
+pub struct Walker {
+    /// The `.gitignore` files of the parent directories within the same repository.
+    parents: Vec<(PathBuf, Gitignore)>,
+    /// The directories being walked, innermost last.
+    stack: Vec<WalkDir>,
+}
+
+struct WalkDir {
+    path: PathBuf,
+    /// The remaining entries and whether they're directories, in reverse order.
+    entries: Vec<(OsString, bool)>,
+    ignore: Option<Gitignore>,
+}
+
+impl Walker {
+    pub fn new(root: &Path) -> Self {
+        // If the root is within a repository, the `.gitignore` files up to its top apply as well.
+        let mut parents = Vec::new();
+        if !root.join(".git").exists() {
+            for dir in root.ancestors().skip(1) {
+                if let Ok(text) = fs::read(dir.join(".gitignore")) {
+                    parents.push((dir.to_path_buf(), Gitignore::parse(&text)));
+                }
+                if dir.join(".git").exists() {
+                    break;
+                }
+                if dir.parent().is_none() {
+                    parents.clear();
+                }
+            }
+            parents.reverse();
+        }
+
+        let mut walker = Self { parents, stack: Vec::new() };
+        walker.push_dir(root.to_path_buf());
+        walker
+    }
+
+    fn push_dir(&mut self, path: PathBuf) {
+        let Ok(dir) = fs::read_dir(&path) else {
+            return;
+        };
+
+        let mut entries: Vec<_> = dir
+            .flatten()
+            .filter_map(|entry| {
+                let file_type = entry.file_type().ok()?;
+                // Symlinks to directories aren't followed, which avoids cycles.
+                let is_file = file_type.is_file()
+                    || (file_type.is_symlink()
+                        && fs::metadata(entry.path()).is_ok_and(|m| m.is_file()));
+                (is_file || file_type.is_dir()).then(|| (entry.file_name(), file_type.is_dir()))
+            })
+            .collect();
+        entries.sort_unstable_by(|a, b| b.0.cmp(&a.0));
+
+        let ignore = fs::read(path.join(".gitignore")).ok().map(|text| Gitignore::parse(&text));
+        self.stack.push(WalkDir { path, entries, ignore });
+    }
+
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let parents = self.parents.iter().map(|(dir, ignore)| (dir, Some(ignore)));
+        let dirs = self.stack.iter().map(|dir| (&dir.path, dir.ignore.as_ref()));
+
+        // Deeper `.gitignore` files take precedence.
+        let mut ignored = false;
+        for (dir, ignore) in parents.chain(dirs) {
+            if let Some(ignore) = ignore
+                && let Ok(rel) = path.strip_prefix(dir)
+            {
+                let rel = rel.to_string_lossy();
+                let rel = if cfg!(windows) { rel.replace('\\', "/").into() } else { rel };
+                if let Some(matched) = ignore.matched(&rel, is_dir) {
+                    ignored = matched;
+                }
+            }
+        }
+        ignored
+    }
+}
+
+impl Iterator for Walker {
+    type Item = PathBuf;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let dir = self.stack.last_mut()?;
+            let Some((name, is_dir)) = dir.entries.pop() else {
+                self.stack.pop();
+                continue;
+            };
+            if name.as_encoded_bytes().starts_with(b".") {
+                continue;
+            }
+
+            let path = dir.path.join(&name);
+            if self.is_ignored(&path, is_dir) {
+                continue;
+            }
+            if is_dir {
+                self.push_dir(path);
+            } else {
+                return Some(path);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(b"*.rs", b"main.rs"));
+        assert!(!glob_match(b"*.rs", b"src/main.rs"));
+        assert!(glob_match(b"src/*.rs", b"src/main.rs"));
+        assert!(glob_match(b"**/foo", b"foo"));
+        assert!(glob_match(b"**/foo", b"a/b/foo"));
+        assert!(glob_match(b"a/**/b", b"a/b"));
+        assert!(glob_match(b"a/**/b", b"a/x/y/b"));
+        assert!(glob_match(b"a/**", b"a/x/y"));
+        assert!(glob_match(b"?.txt", b"\xC3\xA4.txt"));
+        assert!(glob_match(b"[a-c]x", b"bx"));
+        assert!(!glob_match(b"[!a-c]x", b"bx"));
+        assert!(glob_match(b"[]]", b"]"));
+        assert!(glob_match(b"[x", b"[x"));
+        assert!(glob_match(b"\\*", b"*"));
+        assert!(!glob_match(b"\\*", b"a"));
+    }
+
+    #[test]
+    fn test_gitignore() {
+        let ignore =
+            Gitignore::parse(b"# comment\n*.log\n!keep.log\n/build/\ndocs/*.md  \r\n\\#x\n");
+        assert_eq!(ignore.matched("a.log", false), Some(true));
+        assert_eq!(ignore.matched("src/a.log", false), Some(true));
+        assert_eq!(ignore.matched("src/keep.log", false), Some(false));
+        assert_eq!(ignore.matched("build", true), Some(true));
+        assert_eq!(ignore.matched("build", false), None);
+        assert_eq!(ignore.matched("src/build", true), None);
+        assert_eq!(ignore.matched("docs/a.md", false), Some(true));
+        assert_eq!(ignore.matched("docs/x/a.md", false), None);
+        assert_eq!(ignore.matched("#x", false), Some(true));
+        assert_eq!(ignore.matched("main.rs", false), None);
+    }
+
+    #[test]
+    fn test_walker() {
+        let root = env::temp_dir().join(format!("edit-walk-{}", process::id()));
+        _ = fs::remove_dir_all(&root);
+        for dir in ["src/gen", "target", ".hidden"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for (path, text) in [
+            (".gitignore", "target/\n*.tmp\n"),
+            ("src/.gitignore", "gen/\n!keep.tmp\n"),
+            ("a.txt", ""),
+            ("b.tmp", ""),
+            ("src/main.rs", ""),
+            ("src/keep.tmp", ""),
+            ("src/gen/x.rs", ""),
+            ("target/out", ""),
+            (".hidden/x", ""),
+        ] {
+            fs::write(root.join(path), text).unwrap();
+        }
+
+        let files: Vec<_> = Walker::new(&root)
+            .map(|p| p.strip_prefix(&root).unwrap().to_string_lossy().replace('\\', "/"))
+            .collect();
+        assert_eq!(files, ["a.txt", "src/keep.tmp", "src/main.rs"]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}